@@ -0,0 +1,179 @@
+// Overview of the watched pools, candles of the selected one and the active
+// alerts, all from the service's own API. Refreshes every few seconds.
+"use strict";
+
+const REFRESH_MS = 10000;
+let selected = null;
+let chartRange = { interval: "1h", window: "7d" };
+
+function apiKey() {
+  return localStorage.getItem("poolMonitorKey") || "";
+}
+
+async function api(path) {
+  const headers = apiKey() ? { "X-API-Key": apiKey() } : {};
+  const response = await fetch(path, { headers });
+  const body = await response.json().catch(() => ({}));
+  if (!response.ok) {
+    throw new Error(body.error || response.statusText);
+  }
+  return body;
+}
+
+function element(tag, text, className) {
+  const node = document.createElement(tag);
+  if (text !== undefined && text !== null) {
+    node.textContent = text;
+  }
+  if (className) {
+    node.className = className;
+  }
+  return node;
+}
+
+function formatPrice(price) {
+  if (price === undefined || price === null) {
+    return "–";
+  }
+  return price >= 1 ? price.toFixed(4) : price.toPrecision(4);
+}
+
+function formatAge(secs) {
+  if (secs === undefined || secs === null) {
+    return "never";
+  }
+  if (secs < 120) {
+    return secs + "s";
+  }
+  return secs < 7200 ? Math.round(secs / 60) + "m" : Math.round(secs / 3600) + "h";
+}
+
+function formatTime(timestamp) {
+  return new Date(timestamp * 1000).toLocaleString();
+}
+
+async function loadPools() {
+  const note = document.getElementById("pools-note");
+  const body = document.querySelector("#pools tbody");
+  try {
+    const status = await api("/watchlist/status");
+    note.textContent = status.paused ? "Polling is paused." : "";
+    body.replaceChildren(...status.pools.map((pool) => {
+      const row = element("tr", null, pool.stale ? "stale" : "");
+      if (pool.pool_id === selected) {
+        row.classList.add("selected");
+      }
+      const snapshot = pool.snapshot || {};
+      row.append(
+        element("td", pool.label || pool.pool_id, pool.label ? "" : "mono"),
+        element("td", pool.dex || "–"),
+        element("td", formatPrice(snapshot.price)),
+        element("td", snapshot.reserve_a === undefined ? "–" : snapshot.reserve_a.value + " / " + snapshot.reserve_b.value),
+        element("td", formatAge(pool.age_secs) + (pool.last_error ? " (" + pool.last_error + ")" : "")),
+        element("td", pool.tier),
+      );
+      row.addEventListener("click", () => select(pool.pool_id));
+      return row;
+    }));
+    if (!selected && status.pools.length > 0) {
+      select(status.pools[0].pool_id);
+    }
+  } catch (e) {
+    note.textContent = e.message;
+  }
+}
+
+function chartUrl(pool) {
+  const params = new URLSearchParams({ interval: chartRange.interval, window: chartRange.window, width: 900, height: 360 });
+  if (apiKey()) {
+    params.set("api_key", apiKey());
+  }
+  return "/pool/" + pool + "/chart.png?" + params;
+}
+
+async function loadDetail() {
+  if (!selected) {
+    return;
+  }
+  document.getElementById("detail").hidden = false;
+  document.getElementById("chart").src = chartUrl(selected);
+  for (const button of document.querySelectorAll(".intervals button")) {
+    button.classList.toggle("active", button.dataset.interval === chartRange.interval);
+  }
+  const fields = document.getElementById("detail-fields");
+  try {
+    const pool = await api("/pool/" + selected);
+    document.getElementById("detail-title").textContent = pool.label || selected;
+    const rows = [
+      ["Pool", selected],
+      ["DEX", pool.dex],
+      ["Fee", pool.fee_bps === undefined ? null : pool.fee_bps + " bps"],
+      ["Price", formatPrice(pool.price)],
+      ["24h change", pool.price_change_24h === undefined || pool.price_change_24h === null ? null : pool.price_change_24h.toFixed(2) + "%"],
+      ["Reserves", pool.reserve_a === undefined ? null : pool.reserve_a.value + " / " + pool.reserve_b.value],
+      ["Tradeable", pool.tradeable === undefined ? null : String(pool.tradeable)],
+      ["Slot", pool.slot],
+      ["Age", formatAge(pool.age_secs)],
+      ["Stale", pool.stale === undefined ? null : String(pool.stale)],
+    ];
+    fields.replaceChildren(...rows.filter(([, value]) => value !== undefined && value !== null)
+      .flatMap(([name, value]) => [element("dt", name), element("dd", value)]));
+  } catch (e) {
+    document.getElementById("detail-title").textContent = selected;
+    fields.replaceChildren(element("dt", "Error"), element("dd", e.message));
+  }
+}
+
+async function loadAlerts() {
+  const list = document.getElementById("alerts");
+  const note = document.getElementById("alerts-note");
+  try {
+    const active = await api("/alerts/active");
+    note.textContent = active.alerts.length === 0 ? "Nothing firing." : "";
+    list.replaceChildren(...active.alerts.map((state) => {
+      const item = element("li");
+      const severity = state.status === "firing" ? state.alert.severity : "resolved";
+      item.append(
+        element("span", severity, "severity " + severity),
+        element("span", state.alert.message + " — since " + formatTime(state.firing_since)),
+      );
+      return item;
+    }));
+  } catch (e) {
+    list.replaceChildren();
+    note.textContent = e.message;
+  }
+}
+
+function select(pool) {
+  selected = pool;
+  for (const row of document.querySelectorAll("#pools tbody tr")) {
+    row.classList.remove("selected");
+  }
+  loadPools();
+  loadDetail();
+}
+
+function refresh() {
+  loadPools();
+  loadDetail();
+  loadAlerts();
+}
+
+document.getElementById("key").value = apiKey();
+document.getElementById("key-form").addEventListener("submit", (event) => {
+  event.preventDefault();
+  localStorage.setItem("poolMonitorKey", document.getElementById("key").value.trim());
+  refresh();
+});
+for (const button of document.querySelectorAll(".intervals button")) {
+  button.addEventListener("click", () => {
+    chartRange = { interval: button.dataset.interval, window: button.dataset.window };
+    loadDetail();
+  });
+}
+api("/solana/status")
+  .then((status) => { document.getElementById("status").textContent = status.cluster + " · slot " + status.current_slot; })
+  .catch(() => {});
+refresh();
+setInterval(refresh, REFRESH_MS);
@@ -0,0 +1,15 @@
+-- Mutating API calls with who made them and what they changed.
+CREATE TABLE audit_trail (
+    id BIGSERIAL PRIMARY KEY,
+    timestamp BIGINT NOT NULL,
+    actor TEXT NOT NULL,
+    ip TEXT,
+    method TEXT NOT NULL,
+    route TEXT NOT NULL,
+    path TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    target TEXT,
+    before JSONB NOT NULL,
+    after JSONB NOT NULL
+);
+CREATE INDEX audit_trail_timestamp ON audit_trail (timestamp);
@@ -0,0 +1,9 @@
+-- Groups of LP positions across pools, positions kept as a JSON array.
+CREATE TABLE portfolios (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    positions JSONB NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Fee growth accumulators of concentrated liquidity pools, u128 as text.
+ALTER TABLE snapshots ADD COLUMN fee_growth_a TEXT;
+ALTER TABLE snapshots ADD COLUMN fee_growth_b TEXT;
@@ -0,0 +1,28 @@
+-- Postgres has no unsigned 64 bit type, so u64 values are stored bit-for-bit
+-- as BIGINT and cast back on read.
+CREATE TABLE snapshots (
+    pool TEXT NOT NULL,
+    slot BIGINT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    reserve_a BIGINT NOT NULL,
+    reserve_b BIGINT NOT NULL,
+    price DOUBLE PRECISION NOT NULL,
+    lp_supply BIGINT,
+    authority TEXT,
+    cumulative_volume_b TEXT
+);
+CREATE INDEX snapshots_timestamp ON snapshots (timestamp);
+
+CREATE TABLE alerts (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    pool TEXT NOT NULL,
+    message TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    details JSONB NOT NULL
+);
+
+CREATE TABLE watchlist (
+    pool TEXT PRIMARY KEY
+);
@@ -0,0 +1,8 @@
+CREATE TABLE pool_index (
+    pool TEXT PRIMARY KEY,
+    dex TEXT NOT NULL,
+    mint_a TEXT NOT NULL,
+    mint_b TEXT NOT NULL,
+    slot BIGINT NOT NULL
+);
+CREATE INDEX pool_index_dex ON pool_index (dex);
@@ -0,0 +1,11 @@
+-- A watched pool whose liquidity was pulled, linked to the pool of the same
+-- pair that appeared around the same time.
+CREATE TABLE pool_links (
+    old_pool TEXT NOT NULL,
+    new_pool TEXT NOT NULL,
+    drained_at BIGINT NOT NULL,
+    created_at BIGINT NOT NULL,
+    removed_pct DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (old_pool, new_pool)
+);
+CREATE INDEX pool_links_new_pool ON pool_links (new_pool);
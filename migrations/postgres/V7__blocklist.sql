@@ -0,0 +1,7 @@
+-- Scam mints and wallets added through the admin API.
+CREATE TABLE blocklist (
+    address TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    reason TEXT,
+    added_at BIGINT NOT NULL
+);
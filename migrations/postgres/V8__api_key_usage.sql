@@ -0,0 +1,9 @@
+-- Monthly usage per API key name, month as YYYY-MM.
+CREATE TABLE api_key_usage (
+    month TEXT NOT NULL,
+    key_name TEXT NOT NULL,
+    requests BIGINT NOT NULL,
+    rpc_calls BIGINT NOT NULL,
+    stream_minutes BIGINT NOT NULL,
+    PRIMARY KEY (month, key_name)
+);
@@ -0,0 +1,17 @@
+-- API keys created through the admin API, secrets stored as SHA-256 hashes.
+CREATE TABLE api_keys (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    role TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    key_hash TEXT NOT NULL,
+    previous_hash TEXT,
+    previous_expires_at BIGINT,
+    monthly_requests BIGINT,
+    monthly_rpc_calls BIGINT,
+    monthly_stream_minutes BIGINT,
+    created_at BIGINT NOT NULL,
+    rotated_at BIGINT,
+    last_used_at BIGINT,
+    revoked_at BIGINT
+);
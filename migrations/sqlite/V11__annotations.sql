@@ -0,0 +1,11 @@
+-- Labeled notes users attach to points on a pool's timeline.
+CREATE TABLE annotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pool TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    label TEXT NOT NULL,
+    note TEXT,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX annotations_pool_timestamp ON annotations (pool, timestamp);
@@ -0,0 +1,9 @@
+-- Webhook subscriptions, pools and event kinds kept as JSON arrays.
+CREATE TABLE subscriptions (
+    id TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    pools TEXT NOT NULL,
+    events TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
@@ -0,0 +1,7 @@
+-- User defined reports, the whole template kept as JSON.
+CREATE TABLE report_templates (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
@@ -0,0 +1,29 @@
+-- Matches the schema created before migrations existed, hence IF NOT EXISTS.
+-- SQLite has no unsigned 64 bit type, so u64 values are stored bit-for-bit
+-- as i64 and cast back on read.
+CREATE TABLE IF NOT EXISTS snapshots (
+    pool TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    reserve_a INTEGER NOT NULL,
+    reserve_b INTEGER NOT NULL,
+    price REAL NOT NULL,
+    lp_supply INTEGER,
+    authority TEXT,
+    cumulative_volume_b TEXT
+);
+CREATE INDEX IF NOT EXISTS snapshots_timestamp ON snapshots (timestamp);
+
+CREATE TABLE IF NOT EXISTS alerts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    pool TEXT NOT NULL,
+    message TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    details TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS watchlist (
+    pool TEXT PRIMARY KEY
+);
@@ -0,0 +1,6 @@
+-- Alerts fired before this migration keep an empty key, they never match a
+-- tracked alert.
+ALTER TABLE alerts ADD COLUMN key TEXT NOT NULL DEFAULT '';
+ALTER TABLE alerts ADD COLUMN acked_at INTEGER;
+ALTER TABLE alerts ADD COLUMN acked_by TEXT;
+CREATE INDEX alerts_timestamp ON alerts (timestamp);
@@ -0,0 +1,3 @@
+-- Why an alert was recorded without being delivered: a mute window or
+-- maintenance mode. NULL for delivered alerts.
+ALTER TABLE alerts ADD COLUMN suppressed_by TEXT;
@@ -0,0 +1,10 @@
+-- Creation transaction of each pool looked up so far, it never changes.
+CREATE TABLE pool_creations (
+    pool TEXT PRIMARY KEY,
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    created_at INTEGER,
+    creator TEXT NOT NULL,
+    initial_reserve_a INTEGER NOT NULL,
+    initial_reserve_b INTEGER NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Monthly usage per API key name, month as YYYY-MM.
+CREATE TABLE api_key_usage (
+    month TEXT NOT NULL,
+    key_name TEXT NOT NULL,
+    requests INTEGER NOT NULL,
+    rpc_calls INTEGER NOT NULL,
+    stream_minutes INTEGER NOT NULL,
+    PRIMARY KEY (month, key_name)
+);
@@ -0,0 +1,17 @@
+-- API keys created through the admin API, secrets stored as SHA-256 hashes.
+CREATE TABLE api_keys (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    role TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    key_hash TEXT NOT NULL,
+    previous_hash TEXT,
+    previous_expires_at INTEGER,
+    monthly_requests INTEGER,
+    monthly_rpc_calls INTEGER,
+    monthly_stream_minutes INTEGER,
+    created_at INTEGER NOT NULL,
+    rotated_at INTEGER,
+    last_used_at INTEGER,
+    revoked_at INTEGER
+);
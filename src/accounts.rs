@@ -0,0 +1,57 @@
+use dashmap::DashMap;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// Raw account data seen by the poller.
+#[derive(Clone)]
+pub struct ObservedAccount {
+    pub slot: u64,
+    pub fetched_at: u64,
+    pub account: Arc<Account>,
+}
+
+// Raw account data per account and slot, so `?slot=` queries can rebuild
+// what a pool looked like at the time. Sharded by account, recording one
+// doesn't wait on lookups of the others.
+#[derive(Default)]
+pub struct AccountCache {
+    accounts: DashMap<Pubkey, BTreeMap<u64, ObservedAccount>>,
+}
+
+impl AccountCache {
+    // Record an account as seen at `slot`, keeping the newest `max_slots`
+    // observations of it.
+    pub fn record(&self, key: Pubkey, slot: u64, fetched_at: u64, account: Account, max_slots: usize) {
+        let mut slots = self.accounts.entry(key).or_default();
+        // Unchanged data shares one copy between slots
+        let account = match slots.range(..slot).next_back() {
+            Some((_, previous)) if *previous.account == account => previous.account.clone(),
+            _ => Arc::new(account),
+        };
+        slots.insert(slot, ObservedAccount { slot, fetched_at, account });
+        while slots.len() > max_slots {
+            slots.pop_first();
+        }
+    }
+
+    // Latest observation at or before `slot`. Accounts only change when
+    // written, so this is the state at `slot` unless it changed in between
+    // and nothing observed it.
+    pub fn at_or_before(&self, key: &Pubkey, slot: u64) -> Option<ObservedAccount> {
+        self.accounts.get(key)?.range(..=slot).next_back().map(|(_, observed)| observed.clone())
+    }
+
+    // Whether the observations either side of `slot` agree, which pins the
+    // state at `slot` down exactly.
+    pub fn unchanged_across(&self, key: &Pubkey, slot: u64) -> bool {
+        let Some(slots) = self.accounts.get(key) else {
+            return false;
+        };
+        match (slots.range(..=slot).next_back(), slots.range(slot..).next()) {
+            (Some((_, before)), Some((_, after))) => Arc::ptr_eq(&before.account, &after.account),
+            _ => false,
+        }
+    }
+}
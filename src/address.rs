@@ -0,0 +1,66 @@
+use actix_web::error::{InternalError, PathError};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+
+// Programs and sysvars that are never a pool, token or wallet. Asking about
+// one is a client bug, and most would cost an RPC call to find out.
+const SYSTEM_ADDRESSES: &[(&str, &str)] = &[
+    ("11111111111111111111111111111111", "the System Program"),
+    ("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", "the Token Program"),
+    ("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb", "the Token-2022 Program"),
+    ("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL", "the Associated Token Account Program"),
+    ("ComputeBudget111111111111111111111111111111", "the Compute Budget Program"),
+    ("Vote111111111111111111111111111111111111111", "the Vote Program"),
+    ("Stake11111111111111111111111111111111111111", "the Stake Program"),
+    ("BPFLoaderUpgradeab1e11111111111111111111111", "the upgradeable BPF Loader"),
+    ("SysvarC1ock11111111111111111111111111111111", "the Clock sysvar"),
+    ("SysvarRent111111111111111111111111111111111", "the Rent sysvar"),
+    ("Sysvar1nstructions1111111111111111111111111", "the Instructions sysvar"),
+];
+
+// An address taken from a path parameter. Surrounding whitespace is
+// dropped, base58 is case sensitive so that is all that can be normalized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidatedPubkey(pub Pubkey);
+
+impl FromStr for ValidatedPubkey {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let pubkey = Pubkey::from_str(text).map_err(|e| format!("Invalid address {}: {}", text, e))?;
+        if let Some((_, name)) = SYSTEM_ADDRESSES.iter().find(|(address, _)| *address == text) {
+            return Err(format!("{} is {}, not a pool, token or wallet address", text, name));
+        }
+        Ok(ValidatedPubkey(pubkey))
+    }
+}
+
+impl<'de> Deserialize<'de> for ValidatedPubkey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for ValidatedPubkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Path parameters that don't parse get a 400 with the usual error body
+// rather than actix's plain text 404.
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(|e, _| {
+        let message = match &e {
+            PathError::Deserialize(e) => e.to_string(),
+            e => e.to_string(),
+        };
+        InternalError::from_response(e, HttpResponse::BadRequest().json(json!({ "error": message }))).into()
+    })
+}
@@ -0,0 +1,730 @@
+use crate::address::ValidatedPubkey;
+use crate::alerts;
+use crate::backup;
+use crate::blocklist::{BlockKind, BlockedAddress};
+use crate::keys::{self, ApiKey, Resolved};
+use crate::limits::Priority;
+use crate::mute::Maintenance;
+use crate::config::{Quotas, ReportPeriod, Role};
+use crate::poller;
+use crate::reports::{self, ReportQuery};
+use crate::roles;
+use crate::schedule;
+use crate::state::{random_hex, unix_now, AppState};
+use crate::storage::AuditFilter;
+use crate::tasks;
+use crate::time::format_utc;
+use crate::trail;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Next};
+use actix_web::{delete, get, post, web, Error, HttpMessage, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+// Longest a rotated key's old secret keeps working.
+const MAX_ROTATION_GRACE_SECS: u64 = 30 * 24 * 60 * 60;
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+const MAX_AUDIT_LIMIT: usize = 1000;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .wrap(from_fn(require_admin))
+            .service(get_pollers)
+            .service(get_schedule)
+            .service(refresh_pool)
+            .service(evict_cache_entry)
+            .service(evict_cache)
+            .service(pause_pipeline)
+            .service(resume_pipeline)
+            .service(get_config)
+            .service(reload_config)
+            .service(get_watchlist)
+            .service(get_dormant)
+            .service(reactivate_pool)
+            .service(watch_pool)
+            .service(unwatch_pool)
+            .service(get_index)
+            .service(resync_index)
+            .service(get_maintenance)
+            .service(set_maintenance)
+            .service(get_blocklist)
+            .service(block_address)
+            .service(unblock_address)
+            .service(acknowledge_upgrade)
+            .service(generate_report)
+            .service(list_keys)
+            .service(create_key)
+            .service(revoke_key)
+            .service(rotate_key)
+            .service(get_audit_trail)
+            .service(tasks::get_tasks)
+            .service(tasks::control_task)
+            .service(backup::export_state)
+            .service(backup::import_resource()),
+    );
+}
+
+async fn require_admin(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let expected = req
+        .app_data::<web::Data<AppState>>()
+        .and_then(|state| state.config.read().unwrap().admin.token.clone());
+    // Set by usage::meter for requests with a known API key
+    let key_role = req.extensions().get::<Resolved>().map(|key| (key.name.clone(), key.role));
+
+    if let Some((name, role)) = key_role {
+        let required = roles::required_role(req.method(), &req.match_pattern().unwrap_or_default());
+        if role >= required {
+            return Ok(next.call(req).await?.map_into_left_body());
+        }
+        let response = HttpResponse::Forbidden().json(json!({
+            "error": format!("API key {} has the {} role, this route needs the {} role", name, role.as_str(), required.as_str())
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    let Some(expected) = expected else {
+        let response = HttpResponse::Forbidden().json(json!({
+            "error": "Admin API is disabled, set admin.token in the config or use an API key with the admin role"
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+
+    let provided = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    match provided {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => {
+            Ok(next.call(req).await?.map_into_left_body())
+        }
+        _ => {
+            let response = HttpResponse::Unauthorized().json(json!({
+                "error": "Missing or invalid admin token"
+            }));
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Deserialize)]
+struct MaintenanceRequest {
+    enabled: bool,
+    // Switch maintenance off again after this long, stays on until switched
+    // off when unset.
+    duration_secs: Option<u64>,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CreateKeyRequest {
+    name: String,
+    #[serde(default)]
+    role: Role,
+    #[serde(flatten)]
+    quotas: Quotas,
+}
+
+#[derive(Deserialize)]
+struct RotateKeyRequest {
+    #[serde(default)]
+    grace_secs: u64,
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    since: Option<u64>,
+    until: Option<u64>,
+    actor: Option<String>,
+    target: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct BlockRequest {
+    address: String,
+    kind: BlockKind,
+    reason: Option<String>,
+}
+
+#[get("/pollers")]
+async fn get_pollers(state: web::Data<AppState>) -> HttpResponse {
+    let pools: serde_json::Map<String, serde_json::Value> = state
+        .pollers
+        .iter()
+        .map(|entry| (entry.key().to_string(), json!(entry.value())))
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "paused": state.is_paused(),
+        "pools": pools,
+    }))
+}
+
+#[get("/schedule")]
+async fn get_schedule(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "paused": state.is_paused(),
+        "subscription_error": state.pubsub.last_error(),
+        "connections": state.pubsub.connections(),
+        "unassigned_accounts": state.pubsub.unassigned(),
+        "slot": state.slots.current(),
+        "pools": schedule::effective(&state),
+    }))
+}
+
+#[post("/pools/{pool_id}/refresh")]
+async fn refresh_pool(state: web::Data<AppState>, pool_id: web::Path<ValidatedPubkey>) -> HttpResponse {
+    let pubkey = pool_id.0;
+
+    match poller::refresh_pool(&state, pubkey, Priority::Interactive).await {
+        Ok(cached) => {
+            let encoded = cached.encoded.clone().unwrap_or_else(|| cached.encode_fields());
+            HttpResponse::Ok().json(json!({
+                "pool_id": pool_id.to_string(),
+                "account": serde_json::from_slice::<Value>(&encoded).unwrap_or(Value::Null),
+            }))
+        }
+        Err(e) => HttpResponse::BadGateway().json(json!({
+            "error": e
+        })),
+    }
+}
+
+#[delete("/cache/{pool_id}")]
+async fn evict_cache_entry(state: web::Data<AppState>, pool_id: web::Path<ValidatedPubkey>) -> HttpResponse {
+    let pubkey = pool_id.0;
+
+    let mut evicted = state.cache.remove(&pubkey).is_some();
+    for cache in state.cluster_caches.iter() {
+        evicted |= cache.remove(&pubkey).is_some();
+    }
+    HttpResponse::Ok().json(json!({
+        "pool_id": pool_id.to_string(),
+        "evicted": evicted,
+    }))
+}
+
+#[delete("/cache")]
+async fn evict_cache(state: web::Data<AppState>) -> HttpResponse {
+    let mut evicted = state.cache.clear();
+    for cache in state.cluster_caches.iter() {
+        evicted += cache.clear();
+    }
+    state.cluster_caches.clear();
+    HttpResponse::Ok().json(json!({
+        "evicted": evicted
+    }))
+}
+
+#[post("/pipeline/pause")]
+async fn pause_pipeline(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    trail::changed(&req, "pipeline", json!({ "paused": state.is_paused() }), json!({ "paused": true }));
+    state.set_paused(true);
+    println!("Ingestion pipeline paused via admin API");
+    HttpResponse::Ok().json(json!({
+        "paused": true
+    }))
+}
+
+#[post("/pipeline/resume")]
+async fn resume_pipeline(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    trail::changed(&req, "pipeline", json!({ "paused": state.is_paused() }), json!({ "paused": false }));
+    state.set_paused(false);
+    println!("Ingestion pipeline resumed via admin API");
+    HttpResponse::Ok().json(json!({
+        "paused": false
+    }))
+}
+
+#[get("/config")]
+async fn get_config(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.config().redacted())
+}
+
+#[post("/reload")]
+async fn reload_config(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let before = state.config().redacted();
+    match state.reload() {
+        Ok(changed) => {
+            // Only the sections that changed, secrets redacted
+            let after = state.config().redacted();
+            let sections = |config: &Value| -> serde_json::Map<String, Value> {
+                changed.iter().map(|section| (section.to_string(), config[*section].clone())).collect()
+            };
+            trail::changed(&req, "config", sections(&before), sections(&after));
+            HttpResponse::Ok().json(json!({
+                "reloaded": true,
+                "changed": changed,
+            }))
+        }
+        Err(e) => {
+            eprintln!("Config reload failed: {}", e);
+            HttpResponse::BadRequest().json(json!({
+                "error": format!("Config reload failed: {}", e)
+            }))
+        }
+    }
+}
+
+#[get("/watchlist")]
+async fn get_watchlist(state: web::Data<AppState>) -> HttpResponse {
+    let stored = state.stored_watchlist.read().unwrap().clone();
+    HttpResponse::Ok().json(json!({
+        "config": state.config().watchlist,
+        "runtime": stored.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+    }))
+}
+
+// Pools moved to the dormant tier for being closed or inactive. Mounted at
+// /watchlist/dormant for readers too, reactivating one stays admin only.
+#[get("/watchlist/dormant")]
+pub async fn get_dormant(state: web::Data<AppState>) -> HttpResponse {
+    let pools: Vec<_> = state
+        .schedule
+        .dormant()
+        .into_iter()
+        .map(|(pool, dormant)| json!({
+            "pool_id": pool.to_string(),
+            "reason": dormant.reason,
+            "since": dormant.since,
+        }))
+        .collect();
+    HttpResponse::Ok().json(json!({
+        "dormant_interval_secs": state.config().polling.dormant_interval_secs,
+        "pools": pools,
+    }))
+}
+
+#[post("/watchlist/dormant/{pool_id}/reactivate")]
+async fn reactivate_pool(state: web::Data<AppState>, pool_id: web::Path<ValidatedPubkey>, req: HttpRequest) -> HttpResponse {
+    let pubkey = pool_id.0;
+
+    if !state.schedule.reactivate(&pubkey) {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("Pool {} is not dormant", pool_id)
+        }));
+    }
+    trail::changed(&req, format!("watchlist/{}", pubkey), json!({ "dormant": true }), json!({ "dormant": false }));
+    println!("Pool {} reactivated via admin API", pool_id);
+    HttpResponse::Ok().json(json!({
+        "pool_id": pool_id.to_string(),
+        "reactivated": true,
+    }))
+}
+
+// Pools added here are kept by the storage backend, so they survive restarts
+// unless storage is in memory.
+#[post("/watchlist/{pool_id}")]
+async fn watch_pool(state: web::Data<AppState>, pool_id: web::Path<ValidatedPubkey>, req: HttpRequest) -> HttpResponse {
+    let pubkey = pool_id.0;
+
+    match state.with_store(move |store| store.watch(&pubkey)).await {
+        Ok(added) => {
+            {
+                let mut stored = state.stored_watchlist.write().unwrap();
+                if !stored.contains(&pubkey) {
+                    stored.push(pubkey);
+                }
+            }
+            trail::changed(&req, format!("watchlist/{}", pubkey), json!({ "watched": !added }), json!({ "watched": true }));
+            HttpResponse::Ok().json(json!({
+                "pool_id": pool_id.to_string(),
+                "added": added,
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": e
+        })),
+    }
+}
+
+#[delete("/watchlist/{pool_id}")]
+async fn unwatch_pool(state: web::Data<AppState>, pool_id: web::Path<ValidatedPubkey>, req: HttpRequest) -> HttpResponse {
+    let pubkey = pool_id.0;
+
+    match state.with_store(move |store| store.unwatch(&pubkey)).await {
+        Ok(removed) => {
+            state.stored_watchlist.write().unwrap().retain(|pool| *pool != pubkey);
+            trail::changed(&req, format!("watchlist/{}", pubkey), json!({ "watched": removed }), json!({ "watched": false }));
+            HttpResponse::Ok().json(json!({
+                "pool_id": pool_id.to_string(),
+                "removed": removed,
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": e
+        })),
+    }
+}
+
+#[get("/index")]
+async fn get_index(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "enabled": state.config().index.enabled,
+        "status": state.pool_index.status(),
+        "pools": state.pool_index.counts(),
+    }))
+}
+
+// Drops subscriptions and rescans every program, for when the index is
+// suspected to have missed pools while disconnected.
+#[post("/index/resync")]
+async fn resync_index(state: web::Data<AppState>) -> HttpResponse {
+    if !state.config().index.enabled {
+        return HttpResponse::Conflict().json(json!({
+            "error": "Pool index is disabled, set index.enabled in the config"
+        }));
+    }
+    state.pool_index.request_resync();
+    println!("Pool index resync requested via admin API");
+    HttpResponse::Accepted().json(json!({
+        "resync": true
+    }))
+}
+
+#[get("/maintenance")]
+async fn get_maintenance(state: web::Data<AppState>) -> HttpResponse {
+    let now = unix_now();
+    let muted: Vec<_> = state
+        .config()
+        .alerts
+        .mute
+        .into_iter()
+        .filter(|window| window.is_active(now))
+        .collect();
+    HttpResponse::Ok().json(json!({
+        "maintenance": state.maintenance(),
+        "muted_by": muted,
+    }))
+}
+
+// While on, non-critical alerts are recorded in the history but not
+// delivered.
+#[post("/maintenance")]
+async fn set_maintenance(state: web::Data<AppState>, body: web::Json<MaintenanceRequest>, req: HttpRequest) -> HttpResponse {
+    let body = body.into_inner();
+    let before = state.maintenance();
+    if !body.enabled {
+        trail::changed(&req, "maintenance", before, Value::Null);
+        state.set_maintenance(None);
+        println!("Maintenance mode switched off via admin API");
+        return HttpResponse::Ok().json(json!({
+            "maintenance": null
+        }));
+    }
+    if body.duration_secs == Some(0) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "duration_secs must be at least 1"
+        }));
+    }
+    let now = unix_now();
+    let maintenance = Maintenance {
+        since: now,
+        until: body.duration_secs.map(|secs| now.saturating_add(secs)),
+        reason: body.reason,
+    };
+    println!(
+        "Maintenance mode switched on via admin API until {}",
+        maintenance.until.map(format_utc).unwrap_or_else(|| "switched off".to_string())
+    );
+    trail::changed(&req, "maintenance", before, &maintenance);
+    state.set_maintenance(Some(maintenance.clone()));
+    HttpResponse::Ok().json(json!({
+        "maintenance": maintenance
+    }))
+}
+
+// Every blocked address with where it comes from.
+#[get("/blocklist")]
+async fn get_blocklist(state: web::Data<AppState>) -> HttpResponse {
+    let entries = state.blocklist.entries();
+    HttpResponse::Ok().json(json!({
+        "count": entries.len(),
+        "entries": entries,
+    }))
+}
+
+// Entries added here are kept by the storage backend and win over config
+// and community list entries for the same address.
+#[post("/blocklist")]
+async fn block_address(state: web::Data<AppState>, body: web::Json<BlockRequest>, req: HttpRequest) -> HttpResponse {
+    let body = body.into_inner();
+    let address = match Pubkey::from_str(&body.address) {
+        Ok(key) => key,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid address: {}", e)
+            }));
+        }
+    };
+    let entry = BlockedAddress {
+        address,
+        kind: body.kind,
+        reason: body.reason,
+        source: "admin".to_string(),
+        added_at: Some(unix_now()),
+    };
+    let stored = entry.clone();
+    if let Err(e) = state.with_store(move |store| store.block(&stored)).await {
+        return HttpResponse::InternalServerError().json(json!({
+            "error": e
+        }));
+    }
+    println!("Blocklisted {} {} via admin API", entry.kind.as_str(), address);
+    let before = state.blocklist.get(&address);
+    state.blocklist.add(entry.clone());
+    trail::changed(&req, format!("blocklist/{}", address), before, &entry);
+    HttpResponse::Ok().json(json!({
+        "entry": entry
+    }))
+}
+
+// Only admin entries can be removed, `still_blocked_by` names the config or
+// list that keeps the address blocked.
+#[delete("/blocklist/{address}")]
+async fn unblock_address(state: web::Data<AppState>, address: web::Path<ValidatedPubkey>, req: HttpRequest) -> HttpResponse {
+    let pubkey = address.0;
+    let before = state.blocklist.get(&pubkey);
+    let removed = match state.with_store(move |store| store.unblock(&pubkey)).await {
+        Ok(removed) => removed,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "error": e
+            }));
+        }
+    };
+    state.blocklist.remove(&pubkey);
+    trail::changed(&req, format!("blocklist/{}", pubkey), before, state.blocklist.get(&pubkey));
+    if removed {
+        println!("Removed {} from the blocklist via admin API", pubkey);
+    }
+    HttpResponse::Ok().json(json!({
+        "address": address.to_string(),
+        "removed": removed,
+        "still_blocked_by": state.blocklist.get(&pubkey).map(|entry| entry.source),
+    }))
+}
+
+// Clear a detected program upgrade once the DEX's decoder was checked
+// against it, dropping the service warning and resolving the alert.
+#[post("/programs/{dex}/acknowledge")]
+async fn acknowledge_upgrade(state: web::Data<AppState>, dex: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    let Some(before) = state.program_watch.get(&dex) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("No program deploy known for DEX {}", dex)
+        }));
+    };
+    let acknowledged = state.program_watch.acknowledge(&dex);
+    trail::changed(&req, format!("programs/{}", dex), before, state.program_watch.get(&dex));
+    if acknowledged {
+        alerts::resolve(&state, &format!("program_upgraded/{}", dex));
+        println!("Acknowledged the {} program upgrade via admin API", dex);
+    }
+    HttpResponse::Ok().json(json!({
+        "dex": dex.to_string(),
+        "acknowledged": acknowledged,
+    }))
+}
+
+// Generate and send the report of the period ending now, outside the
+// schedule. Daily unless another period is given.
+#[post("/reports/generate")]
+async fn generate_report(state: web::Data<AppState>, query: web::Query<ReportQuery>) -> HttpResponse {
+    let period = query.period.unwrap_or(ReportPeriod::Daily);
+    let state = state.into_inner();
+    match tokio::task::spawn_blocking(move || reports::publish(&state, period, unix_now())).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": format!("Task failed: {}", e)
+        })),
+    }
+}
+
+// Keys from the config file and from the admin API with their usage this
+// month. Config keys are changed in the file and take effect on reload.
+#[get("/keys")]
+async fn list_keys(state: web::Data<AppState>) -> HttpResponse {
+    let config = state.config.read().unwrap().api_keys.clone();
+    let with_usage = |name: &str, mut value: Value| {
+        value["usage"] = json!(state.usage.usage(name));
+        value["open_streams"] = json!(state.usage.open_streams(name));
+        value
+    };
+    let mut keys: Vec<Value> = config
+        .keys
+        .iter()
+        .map(|(name, key)| {
+            with_usage(name, json!({
+                "name": name,
+                "source": "config",
+                "role": key.role,
+                "quotas": key.quotas,
+            }))
+        })
+        .collect();
+    keys.extend(state.api_keys.all().into_iter().map(|key| {
+        let mut value = json!(key);
+        value["source"] = json!("admin");
+        with_usage(&key.name, value)
+    }));
+    HttpResponse::Ok().json(json!({
+        "required": config.required,
+        "anonymous_role": config.anonymous_role,
+        "keys": keys,
+    }))
+}
+
+// The secret is only ever returned here and on rotation.
+#[post("/keys")]
+async fn create_key(state: web::Data<AppState>, body: web::Json<CreateKeyRequest>, req: HttpRequest) -> HttpResponse {
+    let body = body.into_inner();
+    let name = body.name.trim().to_string();
+    if name.is_empty() || name.len() > 64 || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "name must be 1 to 64 letters, digits, -, _ or ."
+        }));
+    }
+    if state.config.read().unwrap().api_keys.keys.contains_key(&name) || state.api_keys.name_taken(&name) {
+        return HttpResponse::Conflict().json(json!({
+            "error": format!("An API key named {} already exists", name)
+        }));
+    }
+
+    let (secret, prefix, hash) = keys::generate_secret();
+    let key = ApiKey {
+        id: random_hex(8),
+        name,
+        role: body.role,
+        quotas: body.quotas,
+        prefix,
+        hash,
+        previous_hash: None,
+        previous_expires_at: None,
+        created_at: unix_now(),
+        rotated_at: None,
+        last_used_at: None,
+        revoked_at: None,
+    };
+    let saved = key.clone();
+    if let Err(e) = state.with_store(move |store| store.save_api_key(&saved)).await {
+        return HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to save API key: {}", e)
+        }));
+    }
+    state.api_keys.insert(key.clone());
+    trail::changed(&req, format!("keys/{}", key.id), Value::Null, &key);
+    println!("Created API key {} ({}) with the {} role", key.name, key.id, key.role.as_str());
+    let mut response = json!(key);
+    response["key"] = json!(secret);
+    HttpResponse::Created().json(response)
+}
+
+// Revoked keys stop working at once and stay listed.
+#[delete("/keys/{id}")]
+async fn revoke_key(state: web::Data<AppState>, id: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    let Some(mut key) = state.api_keys.get(&id) else {
+        return key_not_found(&id);
+    };
+    if key.revoked_at.is_none() {
+        let before = key.clone();
+        key.revoked_at = Some(unix_now());
+        key.previous_hash = None;
+        let saved = key.clone();
+        if let Err(e) = state.with_store(move |store| store.save_api_key(&saved)).await {
+            return HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to save API key: {}", e)
+            }));
+        }
+        state.api_keys.insert(key.clone());
+        trail::changed(&req, format!("keys/{}", key.id), before, &key);
+        println!("Revoked API key {} ({})", key.name, key.id);
+    }
+    HttpResponse::Ok().json(key)
+}
+
+// Replace a key's secret, keeping its id, name, role and usage. The old
+// secret keeps working for `grace_secs` so clients can switch over.
+#[post("/keys/{id}/rotate")]
+async fn rotate_key(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    body: Option<web::Json<RotateKeyRequest>>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(mut key) = state.api_keys.get(&id) else {
+        return key_not_found(&id);
+    };
+    if key.revoked_at.is_some() {
+        return HttpResponse::Conflict().json(json!({
+            "error": format!("API key {} is revoked", id)
+        }));
+    }
+    let grace_secs = body.map(|body| body.grace_secs).unwrap_or(0);
+    if grace_secs > MAX_ROTATION_GRACE_SECS {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("grace_secs can be at most {}", MAX_ROTATION_GRACE_SECS)
+        }));
+    }
+
+    let now = unix_now();
+    let before = key.clone();
+    let (secret, prefix, hash) = keys::generate_secret();
+    let previous_hash = std::mem::replace(&mut key.hash, hash);
+    key.previous_hash = (grace_secs > 0).then_some(previous_hash);
+    key.previous_expires_at = (grace_secs > 0).then_some(now + grace_secs);
+    key.prefix = prefix;
+    key.rotated_at = Some(now);
+    let saved = key.clone();
+    if let Err(e) = state.with_store(move |store| store.save_api_key(&saved)).await {
+        return HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to save API key: {}", e)
+        }));
+    }
+    state.api_keys.insert(key.clone());
+    trail::changed(&req, format!("keys/{}", key.id), before, &key);
+    println!("Rotated API key {} ({})", key.name, key.id);
+    let mut response = json!(key);
+    response["key"] = json!(secret);
+    HttpResponse::Ok().json(response)
+}
+
+// Mutating API calls, newest first, with who made them and what they
+// changed where the route records it.
+#[get("/audit")]
+async fn get_audit_trail(state: web::Data<AppState>, query: web::Query<AuditQuery>) -> HttpResponse {
+    let query = query.into_inner();
+    let filter = AuditFilter {
+        since: query.since,
+        until: query.until,
+        actor: query.actor,
+        target: query.target,
+        limit: query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT).min(MAX_AUDIT_LIMIT),
+    };
+    match state.with_store(move |store| store.audit_trail(&filter)).await {
+        Ok(records) => HttpResponse::Ok().json(json!({
+            "records": records,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": e
+        })),
+    }
+}
+
+fn key_not_found(id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "error": format!("No API key with id {}", id)
+    }))
+}
@@ -0,0 +1,648 @@
+use crate::config::{AlertsConfig, EmailChannel, Escalation, PagerDutyChannel, SlackChannel, WebhookChannel};
+use crate::email;
+use crate::mute::{self, MuteWindow};
+use crate::pagerduty;
+use crate::slack;
+use crate::state::{unix_now, AppState};
+use crate::trail;
+use crate::storage::{self, AlertFilter};
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+// Every kind of alert fired, to map stored ones back, e.g. on import.
+pub const KINDS: &[&str] = &[
+    "authority_changed",
+    "candle_pattern",
+    "emissions_expiring",
+    "liquidity_migrated",
+    "pool_field_changed",
+    "pool_frozen",
+    "position_near_range_edge",
+    "position_out_of_range",
+    "program_upgraded",
+    "report",
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Alert {
+    // Set once stored, matches the id in the alert history.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub kind: &'static str,
+    pub severity: Severity,
+    pub pool: String,
+    pub message: String,
+    pub timestamp: u64,
+    pub details: Value,
+    // Identifies the condition the alert is about, so repeats of it are
+    // tracked as one alert. Kind and pool unless set otherwise.
+    pub key: String,
+    // Further alerts with the same key are dropped for this long.
+    #[serde(skip)]
+    pub cooldown_secs: u64,
+    // Email recipients replacing the channels' own, when not empty.
+    #[serde(skip)]
+    pub recipients: Vec<String>,
+    // Names of the channels to deliver to, every channel when empty.
+    #[serde(skip)]
+    pub channels: Vec<String>,
+    // Windows in which this alert is recorded but not delivered.
+    #[serde(skip)]
+    pub mute: Vec<MuteWindow>,
+    // Why the alert was recorded without being delivered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suppressed_by: Option<String>,
+}
+
+impl Alert {
+    pub fn new(kind: &'static str, severity: Severity, pool: String, message: String, details: Value) -> Self {
+        Alert {
+            id: None,
+            kind,
+            severity,
+            key: format!("{}/{}", kind, pool),
+            pool,
+            message,
+            timestamp: unix_now(),
+            details,
+            cooldown_secs: 0,
+            recipients: Vec::new(),
+            channels: Vec::new(),
+            mute: Vec::new(),
+            suppressed_by: None,
+        }
+    }
+
+    pub fn with_key(mut self, key: String) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown_secs: u64) -> Self {
+        self.cooldown_secs = cooldown_secs;
+        self
+    }
+
+    pub fn with_recipients(mut self, recipients: Vec<String>) -> Self {
+        self.recipients = recipients;
+        self
+    }
+
+    pub fn with_channels(mut self, channels: Vec<String>) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    pub fn with_mute(mut self, mute: Vec<MuteWindow>) -> Self {
+        self.mute = mute;
+        self
+    }
+}
+
+// A configured delivery channel of any type.
+#[derive(Clone)]
+enum Channel {
+    Webhook(WebhookChannel),
+    Email(EmailChannel),
+    PagerDuty(PagerDutyChannel),
+    Slack(SlackChannel),
+}
+
+impl Channel {
+    fn all(config: &AlertsConfig) -> Vec<Channel> {
+        let webhooks = config.webhooks.iter().cloned().map(Channel::Webhook);
+        let emails = config.emails.iter().cloned().map(Channel::Email);
+        let pagerduty = config.pagerduty.iter().cloned().map(Channel::PagerDuty);
+        let slack = config.slack.iter().cloned().map(Channel::Slack);
+        webhooks.chain(emails).chain(pagerduty).chain(slack).collect()
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Channel::Webhook(webhook) => &webhook.name,
+            Channel::Email(email) => &email.name,
+            Channel::PagerDuty(service) => &service.name,
+            Channel::Slack(slack) => &slack.name,
+        }
+    }
+
+    fn escalation(&self) -> Option<&Escalation> {
+        match self {
+            Channel::Webhook(webhook) => webhook.escalation.as_ref(),
+            Channel::Email(email) => email.escalation.as_ref(),
+            Channel::PagerDuty(service) => service.escalation.as_ref(),
+            Channel::Slack(slack) => slack.escalation.as_ref(),
+        }
+    }
+
+    fn escalation_only(&self) -> bool {
+        match self {
+            Channel::Webhook(webhook) => webhook.escalation_only,
+            Channel::Email(email) => email.escalation_only,
+            Channel::PagerDuty(service) => service.escalation_only,
+            Channel::Slack(slack) => slack.escalation_only,
+        }
+    }
+
+    async fn send(&self, client: &reqwest::Client, alert: &Alert) -> Result<(), String> {
+        match self {
+            Channel::Webhook(webhook) => send_webhook(client, webhook, alert).await,
+            Channel::Email(email) => email::send(email, alert).await,
+            Channel::PagerDuty(service) => pagerduty::trigger(client, service, alert).await,
+            Channel::Slack(slack) => slack::send(client, slack, alert).await,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+// Lifecycle of one alert key. Firing again after a resolve starts a new
+// episode.
+#[derive(Clone, Debug, Serialize)]
+pub struct AlertState {
+    // Latest delivered occurrence.
+    pub alert: Alert,
+    pub status: AlertStatus,
+    pub firing_since: u64,
+    pub last_fired_at: u64,
+    pub resolved_at: Option<u64>,
+    pub fired: u64,
+    // Occurrences dropped by cooldown or deduplication.
+    pub suppressed: u64,
+    // Channels this episode was escalated from for still firing.
+    pub escalated_from: Vec<String>,
+    // Someone has seen this episode. Acknowledged alerts don't escalate and
+    // drop off the active list once resolved.
+    pub acknowledged: bool,
+}
+
+#[derive(Default)]
+pub struct AlertTracker {
+    alerts: Mutex<HashMap<String, AlertState>>,
+    // Message and time of the last suppressed occurrence per key.
+    suppressed: Mutex<HashMap<String, (String, u64)>>,
+}
+
+impl AlertTracker {
+    // Record an occurrence and return when its episode started, None when
+    // cooldown or deduplication drops it.
+    fn record(&self, alert: &Alert, dedupe_secs: u64) -> Option<u64> {
+        let mut alerts = self.alerts.lock().unwrap();
+        let Some(state) = alerts.get_mut(&alert.key) else {
+            alerts.insert(
+                alert.key.clone(),
+                AlertState {
+                    alert: alert.clone(),
+                    status: AlertStatus::Firing,
+                    firing_since: alert.timestamp,
+                    last_fired_at: alert.timestamp,
+                    resolved_at: None,
+                    fired: 1,
+                    suppressed: 0,
+                    escalated_from: Vec::new(),
+                    acknowledged: false,
+                },
+            );
+            return Some(alert.timestamp);
+        };
+        let since_last = alert.timestamp.saturating_sub(state.last_fired_at);
+        let duplicate = state.alert.message == alert.message && since_last < dedupe_secs;
+        if since_last < alert.cooldown_secs || duplicate {
+            state.suppressed += 1;
+            return None;
+        }
+        if state.status == AlertStatus::Resolved {
+            state.status = AlertStatus::Firing;
+            state.firing_since = alert.timestamp;
+            state.resolved_at = None;
+            state.escalated_from.clear();
+            state.acknowledged = false;
+        }
+        state.alert = alert.clone();
+        state.last_fired_at = alert.timestamp;
+        state.fired += 1;
+        Some(state.firing_since)
+    }
+
+    // Whether a suppressed occurrence should be recorded. Repeats are dropped
+    // by cooldown and deduplication the same as delivered ones, so a muted
+    // condition doesn't fill the history.
+    fn record_suppressed(&self, alert: &Alert, dedupe_secs: u64) -> bool {
+        let mut suppressed = self.suppressed.lock().unwrap();
+        if let Some((message, at)) = suppressed.get(&alert.key) {
+            let since_last = alert.timestamp.saturating_sub(*at);
+            if since_last < alert.cooldown_secs || (*message == alert.message && since_last < dedupe_secs) {
+                return false;
+            }
+        }
+        suppressed.insert(alert.key.clone(), (alert.message.clone(), alert.timestamp));
+        true
+    }
+
+    // Mark an alert resolved, returning it when it was firing.
+    fn resolve(&self, key: &str) -> Option<Alert> {
+        let mut alerts = self.alerts.lock().unwrap();
+        match alerts.get_mut(key) {
+            Some(state) if state.status == AlertStatus::Firing => {
+                state.status = AlertStatus::Resolved;
+                state.resolved_at = Some(unix_now());
+                Some(state.alert.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn stored(&self, key: &str, id: u64) {
+        if let Some(state) = self.alerts.lock().unwrap().get_mut(key) {
+            state.alert.id = Some(id);
+        }
+    }
+
+    // Acknowledge the current episode of `key` when the alert fired during
+    // it, returning its latest occurrence. Acknowledging an alert from an
+    // earlier episode changes nothing.
+    fn acknowledge(&self, key: &str, fired_at: u64) -> Option<Alert> {
+        match self.alerts.lock().unwrap().get_mut(key) {
+            Some(state) if fired_at >= state.firing_since => {
+                state.acknowledged = true;
+                Some(state.alert.clone())
+            }
+            _ => None,
+        }
+    }
+
+    // Firing alerts plus resolved ones nobody has acknowledged yet, most
+    // severe and then oldest first.
+    fn active(&self) -> Vec<AlertState> {
+        let mut active: Vec<AlertState> = self
+            .alerts
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|s| s.status == AlertStatus::Firing || !s.acknowledged)
+            .cloned()
+            .collect();
+        active.sort_by(|a, b| {
+            b.alert
+                .severity
+                .cmp(&a.alert.severity)
+                .then(a.firing_since.cmp(&b.firing_since))
+        });
+        active
+    }
+
+    // Whether the episode that started at `firing_since` is still going and
+    // hasn't been escalated from `channel` yet, marking it escalated if so.
+    // Repeats within an episode escalate once.
+    fn escalate(&self, key: &str, firing_since: u64, channel: &str) -> bool {
+        let mut alerts = self.alerts.lock().unwrap();
+        match alerts.get_mut(key) {
+            Some(state)
+                if state.status == AlertStatus::Firing
+                    && !state.acknowledged
+                    && state.firing_since == firing_since
+                    && !state.escalated_from.iter().any(|c| c == channel) =>
+            {
+                state.escalated_from.push(channel.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Log an alert and deliver it to every configured channel, unless cooldown or
+// deduplication drops it. Alerts in a mute window or maintenance mode are
+// only recorded in the history. Channels are read from the live config so
+// credential changes apply on the next alert.
+pub fn fire(state: &AppState, mut alert: Alert) {
+    let config = state.config.read().unwrap().alerts.clone();
+    if let Some(reason) = mute::suppression(state, &alert, &config.mute) {
+        if state.alerts.record_suppressed(&alert, config.dedupe_secs) {
+            println!("SUPPRESSED ({}) [{:?}] {}: {}", reason, alert.severity, alert.kind, alert.message);
+            alert.suppressed_by = Some(reason);
+            let store = state.store.clone();
+            tokio::spawn(async move {
+                if let Err(e) = storage::blocking(store, move |store| store.save_alert(&alert)).await {
+                    eprintln!("Failed to store alert: {}", e);
+                }
+            });
+        }
+        return;
+    }
+    let Some(firing_since) = state.alerts.record(&alert, config.dedupe_secs) else {
+        return;
+    };
+    println!("ALERT [{:?}] {}: {}", alert.severity, alert.kind, alert.message);
+    let store = state.store.clone();
+    let tracker = state.alerts.clone();
+    let client = state.upstream.client();
+    let channels = Arc::new(Channel::all(&config));
+    // Stored first so deliveries carry the alert's id.
+    tokio::spawn(async move {
+        let saved = alert.clone();
+        match storage::blocking(store, move |store| store.save_alert(&saved)).await {
+            Ok(id) => {
+                alert.id = Some(id);
+                tracker.stored(&alert.key, id);
+            }
+            Err(e) => eprintln!("Failed to store alert: {}", e),
+        }
+        let wanted = |channel: &&Channel| {
+            !channel.escalation_only() && (alert.channels.is_empty() || alert.channels.iter().any(|c| c == channel.name()))
+        };
+        for channel in channels.iter().filter(wanted) {
+            tokio::spawn(deliver(
+                tracker.clone(),
+                client.clone(),
+                channels.clone(),
+                channel.clone(),
+                alert.clone(),
+                firing_since,
+            ));
+        }
+    });
+}
+
+// Deliver a notification that isn't about a condition, such as a scheduled
+// report, to the channels it names or every channel but PagerDuty. It isn't
+// tracked, stored or escalated.
+pub fn notify(state: &AppState, alert: Alert) {
+    let config = state.config.read().unwrap().alerts.clone();
+    let channels = Channel::all(&config).into_iter().filter(|channel| {
+        if alert.channels.is_empty() {
+            !channel.escalation_only() && !matches!(channel, Channel::PagerDuty(_))
+        } else {
+            alert.channels.iter().any(|c| c == channel.name())
+        }
+    });
+    for channel in channels {
+        let alert = alert.clone();
+        let client = state.upstream.client();
+        tokio::spawn(async move {
+            if let Err(e) = channel.send(&client, &alert).await {
+                eprintln!("Failed to deliver {} to {}: {}", alert.kind, channel.name(), e);
+            }
+        });
+    }
+}
+
+// Mark an alert resolved once its condition has cleared, closing the
+// incidents it opened.
+pub fn resolve(state: &AppState, key: &str) {
+    let Some(alert) = state.alerts.resolve(key) else {
+        return;
+    };
+    println!("RESOLVED {}: {}", alert.kind, alert.message);
+    update_incidents(state, &alert, "resolve");
+}
+
+// Acknowledge or resolve the incident of an alert on every incident channel
+// that would have paged for it.
+fn update_incidents(state: &AppState, alert: &Alert, action: &'static str) {
+    let services = state.config.read().unwrap().alerts.pagerduty.clone();
+    for service in services.into_iter().filter(|s| alert.severity >= s.min_severity) {
+        let key = alert.key.clone();
+        let client = state.upstream.client();
+        tokio::spawn(async move {
+            if let Err(e) = pagerduty::update(&client, &service, &key, action).await {
+                eprintln!("Failed to {} incident on {}: {}", action, service.name, e);
+            }
+        });
+    }
+}
+
+// Deliver to one channel, escalating when that fails or when the alert is
+// still firing once the escalation delay has passed.
+async fn deliver(
+    tracker: Arc<AlertTracker>,
+    client: reqwest::Client,
+    channels: Arc<Vec<Channel>>,
+    channel: Channel,
+    alert: Alert,
+    firing_since: u64,
+) {
+    let failed = match channel.send(&client, &alert).await {
+        Ok(()) => false,
+        Err(e) => {
+            eprintln!("Failed to deliver alert to {}: {}", channel.name(), e);
+            true
+        }
+    };
+    let Some(escalation) = channel.escalation() else {
+        return;
+    };
+    let Some(next) = channels.iter().find(|c| c.name() == escalation.channel) else {
+        return;
+    };
+    if !failed {
+        let Some(after_secs) = escalation.after_secs else {
+            return;
+        };
+        tokio::time::sleep(Duration::from_secs(after_secs)).await;
+        if !tracker.escalate(&alert.key, firing_since, channel.name()) {
+            return;
+        }
+    }
+    println!("Escalating alert {} from {} to {}", alert.key, channel.name(), next.name());
+    // Boxed since escalation chains recurse
+    Box::pin(deliver(tracker, client, channels.clone(), next.clone(), alert, firing_since)).await;
+}
+
+async fn send_webhook(client: &reqwest::Client, channel: &WebhookChannel, alert: &Alert) -> Result<(), String> {
+    let mut request = client.post(&channel.url).json(alert);
+    if let Some(token) = &channel.bearer_token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("webhook returned {}", response.status()));
+    }
+    Ok(())
+}
+
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    since: Option<u64>,
+    until: Option<u64>,
+    pool: Option<String>,
+    kind: Option<String>,
+    acked: Option<bool>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct AckRequest {
+    by: Option<String>,
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_alert_history)
+        .service(get_active_alerts)
+        .service(ack_alert);
+}
+
+// Every fired alert with the values that triggered it, newest first.
+#[get("/alerts/history")]
+async fn get_alert_history(state: web::Data<AppState>, query: web::Query<HistoryQuery>) -> HttpResponse {
+    let query = query.into_inner();
+    let filter = AlertFilter {
+        since: query.since,
+        until: query.until,
+        pool: query.pool,
+        kind: query.kind,
+        acked: query.acked,
+        limit: query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).min(MAX_HISTORY_LIMIT),
+    };
+    match state.with_store(move |store| store.alert_history(&filter)).await {
+        Ok(alerts) => HttpResponse::Ok().json(json!({
+            "alerts": alerts,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": e
+        })),
+    }
+}
+
+// What a dashboard should show: everything firing, and resolved alerts until
+// someone acknowledges them. Tracked in memory, so empty after a restart.
+#[get("/alerts/active")]
+async fn get_active_alerts(state: web::Data<AppState>) -> HttpResponse {
+    let active = state.alerts.active();
+    HttpResponse::Ok().json(json!({
+        "firing": active.iter().filter(|s| s.status == AlertStatus::Firing).count(),
+        "alerts": active,
+    }))
+}
+
+#[post("/alerts/{id}/ack")]
+async fn ack_alert(
+    state: web::Data<AppState>,
+    id: web::Path<u64>,
+    body: Option<web::Json<AckRequest>>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let by = body.and_then(|body| body.into_inner().by).unwrap_or_else(|| "anonymous".to_string());
+    let alert_id = *id;
+    match state.with_store(move |store| store.ack_alert(alert_id, &by, unix_now())).await {
+        Ok(Some(stored)) => {
+            let after = json!({ "acked_at": stored.acked_at, "acked_by": stored.acked_by });
+            trail::changed(&req, format!("alerts/{}", id), Value::Null, after);
+            let acknowledged = state.alerts.acknowledge(&stored.key, stored.timestamp);
+            if let Some(alert) = &acknowledged {
+                update_incidents(&state, alert, "acknowledge");
+            }
+            HttpResponse::Ok().json(json!({
+                "alert": stored,
+                // Whether this silenced the alert's current episode
+                "silenced": acknowledged.is_some(),
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "error": format!("No alert with id {}", id)
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": e
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-05-01 00:00 UTC
+    const T: u64 = 1_714_521_600;
+
+    fn alert(message: &str, timestamp: u64, cooldown_secs: u64) -> Alert {
+        let mut alert = Alert::new("price_move", Severity::Warning, "pool".to_string(), message.to_string(), Value::Null)
+            .with_cooldown(cooldown_secs);
+        alert.timestamp = timestamp;
+        alert
+    }
+
+    fn suppressed(tracker: &AlertTracker, key: &str) -> u64 {
+        tracker.alerts.lock().unwrap()[key].suppressed
+    }
+
+    #[test]
+    fn cooldown_drops_repeats() {
+        let tracker = AlertTracker::default();
+        assert_eq!(tracker.record(&alert("up 5%", T, 300), 0), Some(T));
+        assert_eq!(tracker.record(&alert("up 7%", T + 299, 300), 0), None);
+        assert_eq!(suppressed(&tracker, "price_move/pool"), 1);
+        // Same episode once the cooldown passed
+        assert_eq!(tracker.record(&alert("up 9%", T + 300, 300), 0), Some(T));
+        assert_eq!(tracker.alerts.lock().unwrap()["price_move/pool"].fired, 2);
+    }
+
+    #[test]
+    fn dedupe_drops_identical_messages() {
+        let tracker = AlertTracker::default();
+        assert_eq!(tracker.record(&alert("up 5%", T, 0), 600), Some(T));
+        assert_eq!(tracker.record(&alert("up 5%", T + 599, 0), 600), None);
+        assert_eq!(tracker.record(&alert("up 7%", T + 60, 0), 600), Some(T));
+        assert_eq!(tracker.record(&alert("up 7%", T + 660, 0), 600), Some(T));
+
+        assert!(tracker.record_suppressed(&alert("down 5%", T, 0), 600));
+        assert!(!tracker.record_suppressed(&alert("down 5%", T + 599, 0), 600));
+        assert!(tracker.record_suppressed(&alert("down 5%", T + 600, 0), 600));
+    }
+
+    #[test]
+    fn episode_escalates_once_per_channel() {
+        let tracker = AlertTracker::default();
+        let firing_since = tracker.record(&alert("up 5%", T, 0), 0).unwrap();
+        for repeat in 1..=3 {
+            assert_eq!(tracker.record(&alert(&format!("up {}%", 5 + repeat), T + repeat * 60, 0), 0), Some(firing_since));
+        }
+        assert!(tracker.escalate("price_move/pool", firing_since, "slack"));
+        assert!(!tracker.escalate("price_move/pool", firing_since, "slack"));
+        assert!(tracker.escalate("price_move/pool", firing_since, "email"));
+        assert!(!tracker.escalate("price_move/pool", firing_since + 1, "webhook"));
+        assert!(!tracker.escalate("other/pool", firing_since, "slack"));
+    }
+
+    #[test]
+    fn acknowledged_or_resolved_episode_doesnt_escalate() {
+        let tracker = AlertTracker::default();
+        tracker.record(&alert("up 5%", T, 0), 0);
+        assert!(tracker.acknowledge("price_move/pool", T).is_some());
+        assert!(!tracker.escalate("price_move/pool", T, "slack"));
+
+        tracker.resolve("price_move/pool");
+        assert!(!tracker.escalate("price_move/pool", T, "slack"));
+        // Firing again starts a new episode that escalates on its own
+        assert_eq!(tracker.record(&alert("up 9%", T + 3600, 0), 0), Some(T + 3600));
+        assert!(!tracker.escalate("price_move/pool", T, "slack"));
+        assert!(tracker.escalate("price_move/pool", T + 3600, "slack"));
+    }
+}
@@ -0,0 +1,82 @@
+use crate::fixed;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+
+// A raw token amount and the decimals of its mint. Serialized with the raw
+// integer as a string, since JSON numbers past 2^53 lose precision in most
+// clients, and the same amount in whole tokens as an exact decimal string:
+// {"raw": "1500000", "decimals": 6, "value": "1.5"}.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Amount {
+    pub raw: u128,
+    pub decimals: u8,
+}
+
+impl Amount {
+    pub fn new(raw: u128, decimals: u8) -> Self {
+        Amount { raw, decimals }
+    }
+
+    // Rounded to the nearest raw unit, for amounts estimated in floating
+    // point like fees and liquidity converted at a price.
+    pub fn from_raw_f64(raw: f64, decimals: u8) -> Self {
+        Amount::new(raw.max(0.0).round() as u128, decimals)
+    }
+
+    pub fn from_tokens(tokens: f64, decimals: u8) -> Self {
+        Amount::from_raw_f64(tokens * 10f64.powi(decimals as i32), decimals)
+    }
+
+    // Whole tokens, for weights and USD values. Not for output.
+    pub fn tokens(&self) -> f64 {
+        fixed::to_tokens(self.raw, self.decimals)
+    }
+
+    pub fn value(&self) -> String {
+        decimal(self.raw, self.decimals)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut amount = serializer.serialize_struct("Amount", 3)?;
+        amount.serialize_field("raw", &self.raw.to_string())?;
+        amount.serialize_field("decimals", &self.decimals)?;
+        amount.serialize_field("value", &self.value())?;
+        amount.end()
+    }
+}
+
+// A raw amount in whole tokens without rounding, e.g. 1500000 with 6
+// decimals is "1.5". Trailing zeros of the fraction are dropped.
+pub fn decimal(raw: u128, decimals: u8) -> String {
+    let digits = format!("{:0>width$}", raw, width = decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+// The change from one raw amount to another, shaped like `Amount` with a
+// sign: {"raw": "-500000", "decimals": 6, "value": "-0.5"}.
+pub fn change(before: u128, after: u128, decimals: u8) -> Value {
+    let (sign, raw) = if after < before { ("-", before - after) } else { ("", after - before) };
+    json!({
+        "raw": format!("{}{}", sign, raw),
+        "decimals": decimals,
+        "value": format!("{}{}", sign, decimal(raw, decimals)),
+    })
+}
+
+// Raw u128 amounts without decimals at hand, like the volumes of candles,
+// as strings for the same reason as `Amount`.
+pub fn optional_u128_string<S: Serializer>(value: &Option<u128>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.serialize_str(&value.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
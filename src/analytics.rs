@@ -0,0 +1,173 @@
+use crate::history::Candle;
+use crate::state::{unix_now, AppState};
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const DEFAULT_WINDOW_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_INTERVAL_SECS: u64 = 5 * 60;
+// Keeps a request from building millions of candles out of a long window.
+const MAX_CANDLES: u64 = 10_000;
+
+#[derive(Deserialize)]
+struct CorrelationQuery {
+    pool_a: String,
+    pool_b: Option<String>,
+    // Fixed reference price standing in for pool b, e.g. 1.0 to watch a
+    // stablecoin pool for a depeg.
+    peg: Option<f64>,
+    // Seconds of history to compare.
+    window: Option<u64>,
+    // Candle size in seconds.
+    interval: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct SpreadStats {
+    current: f64,
+    mean: f64,
+    std_dev: f64,
+    min: f64,
+    max: f64,
+    // How many standard deviations the current spread is from the mean.
+    z_score: Option<f64>,
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn std_dev(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+}
+
+// Pearson correlation, None when either side doesn't move.
+fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() < 2 {
+        return None;
+    }
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let covariance: f64 = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+    let spread_a: f64 = a.iter().map(|x| (x - mean_a).powi(2)).sum();
+    let spread_b: f64 = b.iter().map(|y| (y - mean_b).powi(2)).sum();
+    if spread_a == 0.0 || spread_b == 0.0 {
+        return None;
+    }
+    Some(covariance / (spread_a * spread_b).sqrt())
+}
+
+// Log returns between consecutive closes.
+fn returns(closes: &[f64]) -> Vec<f64> {
+    closes.windows(2).map(|pair| (pair[1] / pair[0]).ln()).collect()
+}
+
+// Correlation and spread between the candle closes of two pools, or of a
+// pool and a fixed peg, over the recorded history. Both prices should be in
+// the same units, the spread is pool a relative to pool b in percent.
+#[get("/analytics/correlation")]
+async fn get_correlation(state: web::Data<AppState>, query: web::Query<CorrelationQuery>) -> HttpResponse {
+    let pool_a = match Pubkey::from_str(&query.pool_a) {
+        Ok(key) => key,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid pool_a: {}", e)
+            }));
+        }
+    };
+    let pool_b = match (query.pool_b.as_deref().map(Pubkey::from_str), query.peg) {
+        (Some(Ok(key)), None) => Some(key),
+        (None, Some(peg)) if peg.is_finite() && peg > 0.0 => None,
+        (Some(Err(e)), None) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid pool_b: {}", e)
+            }));
+        }
+        (None, Some(_)) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "peg must be a positive number"
+            }));
+        }
+        _ => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "Pass exactly one of pool_b or peg"
+            }));
+        }
+    };
+    let window = query.window.unwrap_or(DEFAULT_WINDOW_SECS);
+    let interval = query.interval.unwrap_or(DEFAULT_INTERVAL_SECS);
+    if interval == 0 || window < interval {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "interval must be positive and no longer than window"
+        }));
+    }
+    if window / interval > MAX_CANDLES {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("window covers more than {} candles, use a longer interval", MAX_CANDLES)
+        }));
+    }
+
+    let now = unix_now();
+    let since = now.saturating_sub(window);
+    let closes = |pool: &Pubkey| -> HashMap<u64, f64> {
+        state
+            .history
+            .candles(pool, interval, now)
+            .into_iter()
+            .filter(|candle: &Candle| candle.open_time >= since && candle.close > 0.0)
+            .map(|candle| (candle.open_time, candle.close))
+            .collect()
+    };
+    let closes_a = closes(&pool_a);
+    let closes_b = pool_b.map(|pool| closes(&pool));
+
+    // Only intervals both sides have a candle for are compared
+    let mut aligned: Vec<(u64, f64, f64)> = closes_a
+        .iter()
+        .filter_map(|(open_time, a)| {
+            let b = match (&closes_b, query.peg) {
+                (Some(closes_b), _) => *closes_b.get(open_time)?,
+                (None, peg) => peg?,
+            };
+            Some((*open_time, *a, b))
+        })
+        .collect();
+    aligned.sort_by_key(|(open_time, _, _)| *open_time);
+    if aligned.is_empty() {
+        return HttpResponse::NotFound().json(json!({
+            "error": "No overlapping candles in the window, the pools may not be watched"
+        }));
+    }
+
+    let prices_a: Vec<f64> = aligned.iter().map(|(_, a, _)| *a).collect();
+    let prices_b: Vec<f64> = aligned.iter().map(|(_, _, b)| *b).collect();
+    let spreads: Vec<f64> = aligned.iter().map(|(_, a, b)| (a - b) / b * 100.0).collect();
+    let current = *spreads.last().unwrap();
+    let (spread_mean, spread_std_dev) = (mean(&spreads), std_dev(&spreads));
+
+    HttpResponse::Ok().json(json!({
+        "pool_a": pool_a.to_string(),
+        "pool_b": pool_b.map(|pool| pool.to_string()),
+        "peg": query.peg,
+        "window_secs": window,
+        "interval_secs": interval,
+        "samples": aligned.len(),
+        "from": aligned.first().map(|(open_time, _, _)| *open_time),
+        "to": aligned.last().map(|(open_time, _, _)| *open_time),
+        // Of log returns, what pairs trading cares about. Null against a peg
+        // or when a side never moved.
+        "correlation": correlation(&returns(&prices_a), &returns(&prices_b)),
+        "price_correlation": correlation(&prices_a, &prices_b),
+        "spread_pct": SpreadStats {
+            current,
+            mean: spread_mean,
+            std_dev: spread_std_dev,
+            min: spreads.iter().copied().fold(f64::INFINITY, f64::min),
+            max: spreads.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            z_score: (spread_std_dev > 0.0).then(|| (current - spread_mean) / spread_std_dev),
+        },
+    }))
+}
@@ -0,0 +1,143 @@
+use crate::address::ValidatedPubkey;
+use crate::state::{unix_now, AppState};
+use crate::trail;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const MAX_LABEL_CHARS: usize = 100;
+const MAX_NOTE_CHARS: usize = 2000;
+
+// A labeled note on a point of a pool's timeline, e.g. "CEX listing".
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Annotation {
+    pub id: u64,
+    pub pool: String,
+    pub timestamp: u64,
+    pub label: String,
+    pub note: Option<String>,
+    // API key name, "jwt:<subject>", "admin_token" or "anonymous".
+    pub created_by: String,
+    pub created_at: u64,
+}
+
+#[derive(Deserialize)]
+struct CreateAnnotation {
+    // Now when omitted.
+    timestamp: Option<u64>,
+    label: String,
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+// Limits of a new annotation, also checked on the ones `POST /admin/import`
+// brings in.
+pub fn validate(label: &str, note: Option<&str>) -> Result<(), String> {
+    if label.is_empty() || label.chars().count() > MAX_LABEL_CHARS {
+        return Err(format!("label must be 1 to {} characters", MAX_LABEL_CHARS));
+    }
+    if note.is_some_and(|note| note.chars().count() > MAX_NOTE_CHARS) {
+        return Err(format!("note must be at most {} characters", MAX_NOTE_CHARS));
+    }
+    Ok(())
+}
+
+#[post("/pool/{pool_id}/annotations")]
+async fn create_annotation(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    body: web::Json<CreateAnnotation>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let body = body.into_inner();
+    let label = body.label.trim().to_string();
+    let note = body.note.map(|note| note.trim().to_string()).filter(|note| !note.is_empty());
+    if let Err(e) = validate(&label, note.as_deref()) {
+        return HttpResponse::BadRequest().json(json!({ "error": e }));
+    }
+    let now = unix_now();
+    let timestamp = body.timestamp.unwrap_or(now);
+    if timestamp > now {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "timestamp must not be in the future"
+        }));
+    }
+
+    let admin_token = state.config.read().unwrap().admin.token.clone();
+    let mut annotation = Annotation {
+        id: 0,
+        pool: pool_id.to_string(),
+        timestamp,
+        label,
+        note,
+        created_by: trail::actor(&req, admin_token.as_deref()),
+        created_at: now,
+    };
+    let saved = annotation.clone();
+    match state.with_store(move |store| store.save_annotation(&saved)).await {
+        Ok(id) => annotation.id = id,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+    state.charts.forget(&pool_id.0);
+    trail::changed(&req, target(&annotation.pool, annotation.id), Value::Null, &annotation);
+    HttpResponse::Created().json(annotation)
+}
+
+// Annotations of a pool, oldest first, all of them unless narrowed down
+// with `from` and `to`.
+#[get("/pool/{pool_id}/annotations")]
+async fn list_annotations(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<ListQuery>,
+) -> HttpResponse {
+    let pool = pool_id.to_string();
+    let (from, to) = (query.from.unwrap_or(0), query.to.unwrap_or(u64::MAX));
+    let listed = pool.clone();
+    match state.with_store(move |store| store.annotations(Some(&listed), from, to)).await {
+        Ok(annotations) => HttpResponse::Ok().json(json!({
+            "pool_id": pool,
+            "annotations": annotations,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+}
+
+#[delete("/pool/{pool_id}/annotations/{id}")]
+async fn delete_annotation(
+    state: web::Data<AppState>,
+    path: web::Path<(ValidatedPubkey, u64)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (pool_id, id) = path.into_inner();
+    let pool = pool_id.to_string();
+    let deleted = pool.clone();
+    match state.with_store(move |store| store.delete_annotation(&deleted, id)).await {
+        Ok(Some(removed)) => {
+            state.charts.forget(&pool_id.0);
+            trail::changed(&req, target(&pool, id), removed, Value::Null);
+            HttpResponse::Ok().json(json!({
+                "deleted": id
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "error": format!("Pool {} has no annotation {}", pool, id)
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+}
+
+fn target(pool: &str, id: u64) -> String {
+    format!("pool/{}/annotations/{}", pool, id)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_annotation)
+        .service(list_annotations)
+        .service(delete_annotation);
+}
@@ -0,0 +1,242 @@
+use crate::address::ValidatedPubkey;
+use crate::alerts::{self, Alert, Severity};
+use crate::dex::DecodedPool;
+use crate::state::{unix_now, AppState, CachedAccount};
+use actix_web::{get, web, HttpResponse};
+use serde::Serialize;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+// Fields whose change is a strong signal something is wrong with a pool.
+const SENSITIVE_FIELDS: &[&str] = &["authority", "vault_a", "vault_b", "lp_mint", "fee_bps"];
+// Sensitive fields alerted on by `authority_changed` rather than
+// `pool_field_changed`.
+const AUTHORITY_FIELDS: &[&str] = &["authority"];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditEntry {
+    pub slot: u64,
+    pub timestamp: u64,
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+    pub sensitive: bool,
+}
+
+// Append-only record of observed pool field changes.
+#[derive(Default)]
+pub struct AuditLog {
+    pools: RwLock<HashMap<Pubkey, Vec<AuditEntry>>>,
+}
+
+impl AuditLog {
+    pub fn entries(&self, pool: &Pubkey) -> Vec<AuditEntry> {
+        self.pools.read().unwrap().get(pool).cloned().unwrap_or_default()
+    }
+
+    fn append(&self, pool: Pubkey, entries: &[AuditEntry]) {
+        self.pools.write().unwrap().entry(pool).or_default().extend_from_slice(entries);
+    }
+}
+
+fn changed_fields(old: &DecodedPool, new: &DecodedPool) -> Vec<(&'static str, String, String)> {
+    let fields = [
+        ("mint_a", old.mint_a.to_string(), new.mint_a.to_string()),
+        ("mint_b", old.mint_b.to_string(), new.mint_b.to_string()),
+        ("vault_a", old.vault_a.to_string(), new.vault_a.to_string()),
+        ("vault_b", old.vault_b.to_string(), new.vault_b.to_string()),
+        ("lp_mint", format!("{:?}", old.lp_mint), format!("{:?}", new.lp_mint)),
+        ("fee_bps", format!("{:?}", old.fee_bps), format!("{:?}", new.fee_bps)),
+        ("authority", format!("{:?}", old.authority), format!("{:?}", new.authority)),
+        ("tradeable", old.tradeable.to_string(), new.tradeable.to_string()),
+        ("status", format!("{:?}", old.status), format!("{:?}", new.status)),
+    ];
+    fields.into_iter().filter(|(_, old, new)| old != new).collect()
+}
+
+// Compare two decoded versions of a pool, log every differing field and
+// raise an alert when a sensitive one changed or swaps were switched off.
+pub fn record_changes(state: &AppState, pool: Pubkey, slot: u64, old: &DecodedPool, new: &DecodedPool) {
+    let timestamp = unix_now();
+    let entries: Vec<AuditEntry> = changed_fields(old, new)
+        .into_iter()
+        .map(|(field, old, new)| AuditEntry {
+            slot,
+            timestamp,
+            field,
+            old,
+            new,
+            sensitive: SENSITIVE_FIELDS.contains(&field),
+        })
+        .collect();
+    if entries.is_empty() {
+        return;
+    }
+    state.audit.append(pool, &entries);
+    if old.tradeable != new.tradeable {
+        freeze_changed(state, pool, slot, old, new);
+    }
+
+    let sensitive: Vec<&AuditEntry> = entries
+        .iter()
+        .filter(|e| e.sensitive && !AUTHORITY_FIELDS.contains(&e.field))
+        .collect();
+    if !sensitive.is_empty() {
+        let fields: Vec<&str> = sensitive.iter().map(|e| e.field).collect();
+        // A fee tweak is worth a look, a new authority or vault is not routine
+        let severity = if fields.iter().all(|f| *f == "fee_bps") {
+            Severity::Warning
+        } else {
+            Severity::Critical
+        };
+        alerts::fire(
+            state,
+            Alert::new(
+                "pool_field_changed",
+                severity,
+                pool.to_string(),
+                format!("Pool {} changed sensitive fields: {}", pool, fields.join(", ")),
+                json!({ "slot": slot, "changes": sensitive }),
+            ),
+        );
+    }
+}
+
+// A frozen pool traps everyone holding its tokens against it until swaps
+// are switched back on, which ends the alert.
+fn freeze_changed(state: &AppState, pool: Pubkey, slot: u64, old: &DecodedPool, new: &DecodedPool) {
+    let key = format!("pool_frozen/{}", pool);
+    if new.tradeable {
+        println!("Pool {} is tradeable again", pool);
+        alerts::resolve(state, &key);
+        return;
+    }
+    println!("Pool {} was frozen, status {:?}", pool, new.status);
+    alerts::fire(
+        state,
+        Alert::new(
+            "pool_frozen",
+            Severity::Critical,
+            pool.to_string(),
+            format!("Swaps on {} pool {} ({}) were disabled", new.dex, pool, new.label()),
+            json!({
+                "slot": slot,
+                "dex": new.dex,
+                "old_status": old.status,
+                "status": new.status,
+            }),
+        )
+        .with_key(key),
+    );
+}
+
+// Authorities of the pool and of each of its mints that changed between two
+// fetches, as (audit field, what it is, old, new). Mints are only compared
+// while the pool still points at the same one.
+fn changed_authorities(old: &CachedAccount, new: &CachedAccount) -> Vec<(&'static str, &'static str, Option<Pubkey>, Option<Pubkey>)> {
+    let (Some(old_pool), Some(new_pool)) = (&old.pool, &new.pool) else {
+        return Vec::new();
+    };
+    let mut changed = vec![("authority", "pool_authority", old_pool.authority, new_pool.authority)];
+    let mints = [
+        (
+            ("mint_a_mint_authority", "mint_a_freeze_authority"),
+            old_pool.mint_a == new_pool.mint_a,
+            &old.token_a,
+            &new.token_a,
+        ),
+        (
+            ("mint_b_mint_authority", "mint_b_freeze_authority"),
+            old_pool.mint_b == new_pool.mint_b,
+            &old.token_b,
+            &new.token_b,
+        ),
+    ];
+    for ((mint_field, freeze_field), same_mint, old_info, new_info) in mints {
+        let (true, Some(old_info), Some(new_info)) = (same_mint, old_info, new_info) else {
+            continue;
+        };
+        changed.push((mint_field, "mint_authority", old_info.mint_authority, new_info.mint_authority));
+        changed.push((freeze_field, "freeze_authority", old_info.freeze_authority, new_info.freeze_authority));
+    }
+    changed.retain(|(_, _, old, new)| old != new);
+    changed
+}
+
+// Log changed mint authorities next to the pool's fields and alert on any
+// authority change at the severity configured for its kind. The pool's own
+// authority is logged by `record_changes`.
+pub fn record_authority_changes(state: &AppState, pool: Pubkey, old: &CachedAccount, new: &CachedAccount) {
+    let changed = changed_authorities(old, new);
+    if changed.is_empty() {
+        return;
+    }
+    let timestamp = unix_now();
+    let mint_entries: Vec<AuditEntry> = changed
+        .iter()
+        .filter(|(field, ..)| !AUTHORITY_FIELDS.contains(field))
+        .map(|(field, _, old_authority, new_authority)| AuditEntry {
+            slot: new.slot,
+            timestamp,
+            field,
+            old: format!("{:?}", old_authority),
+            new: format!("{:?}", new_authority),
+            sensitive: true,
+        })
+        .collect();
+    state.audit.append(pool, &mint_entries);
+
+    let config = state.config().alerts.authority;
+    for (field, kind, old_authority, new_authority) in changed {
+        let severity = match kind {
+            "mint_authority" => config.mint_authority,
+            "freeze_authority" => config.freeze_authority,
+            _ => config.pool_authority,
+        };
+        let Some(severity) = severity else {
+            continue;
+        };
+        if new_authority.is_none() && config.ignore_renounced {
+            continue;
+        }
+        let describe = |authority: Option<Pubkey>| authority.map_or("none".to_string(), |a| a.to_string());
+        let message = format!(
+            "Pool {} {} changed from {} to {}",
+            pool,
+            field,
+            describe(old_authority),
+            describe(new_authority)
+        );
+        println!("{}", message);
+        alerts::fire(
+            state,
+            Alert::new(
+                "authority_changed",
+                severity,
+                pool.to_string(),
+                message,
+                json!({
+                    "slot": new.slot,
+                    "field": field,
+                    "authority": kind,
+                    "old": old_authority.map(|a| a.to_string()),
+                    "new": new_authority.map(|a| a.to_string()),
+                }),
+            )
+            .with_key(format!("authority_changed/{}/{}", pool, field))
+            .with_channels(config.channels.clone()),
+        );
+    }
+}
+
+#[get("/pool/{pool_id}/audit")]
+async fn get_pool_audit(state: web::Data<AppState>, pool_id: web::Path<ValidatedPubkey>) -> HttpResponse {
+    let pubkey = pool_id.0;
+
+    HttpResponse::Ok().json(json!({
+        "pool_id": pool_id.to_string(),
+        "entries": state.audit.entries(&pubkey),
+    }))
+}
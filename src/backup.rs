@@ -0,0 +1,304 @@
+use crate::alerts::{self, Alert, Severity};
+use crate::annotations::{self, Annotation};
+use crate::blocklist::{BlockKind, BlockedAddress};
+use crate::portfolio::{self, Portfolio};
+use crate::state::{unix_now, AppState};
+use crate::storage::{AlertFilter, Store, StoredAlert};
+use crate::subscriptions::{self, Subscription};
+use crate::templates::{self, ReportTemplate};
+use crate::trail;
+use actix_web::{get, web, HttpRequest, HttpResponse, Resource};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+// Bumped when a section changes in a way older bundles can't be read as.
+const BUNDLE_VERSION: u32 = 1;
+
+// Largest bundle `POST /admin/import` reads. An export holds the whole alert
+// history, far more than `server.max_payload_bytes` allows other requests.
+const MAX_BUNDLE_BYTES: usize = 64 * 1024 * 1024;
+
+// Everything `GET /admin/export` writes. Sections missing from a bundle are
+// left alone on import.
+#[derive(Deserialize)]
+struct Bundle {
+    version: u32,
+    #[serde(default)]
+    watchlist: Vec<String>,
+    #[serde(default)]
+    alerts: Vec<StoredAlert>,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    #[serde(default)]
+    portfolios: Vec<Portfolio>,
+    #[serde(default)]
+    blocklist: Vec<BlockedEntry>,
+    #[serde(default)]
+    subscriptions: Vec<ExportedSubscription>,
+    #[serde(default)]
+    report_templates: Vec<ReportTemplate>,
+}
+
+#[derive(Deserialize)]
+struct BlockedEntry {
+    address: String,
+    kind: BlockKind,
+    reason: Option<String>,
+    added_at: Option<u64>,
+}
+
+// Subscriptions keep their signing secret, receivers go on verifying
+// deliveries from the new instance.
+#[derive(Deserialize)]
+struct ExportedSubscription {
+    #[serde(flatten)]
+    subscription: Subscription,
+    secret: String,
+}
+
+// Every alert in the history, newest first.
+fn alert_history(store: &dyn Store) -> Result<Vec<StoredAlert>, String> {
+    store.alert_history(&AlertFilter {
+        limit: i64::MAX as usize,
+        ..AlertFilter::default()
+    })
+}
+
+// All user-defined state as one JSON bundle, to move it to another instance
+// or restore it with `POST /admin/import`: pools added to the watchlist,
+// the alert history with its acknowledgements, annotations, portfolios,
+// blocklist entries added here, webhook subscriptions and report templates.
+// The config file and API keys aren't part of it, and subscription secrets
+// are, so the bundle is as sensitive as the admin token.
+#[get("/export")]
+async fn export_state(state: web::Data<AppState>) -> HttpResponse {
+    match bundle(&state).await {
+        Ok(bundle) => HttpResponse::Ok().json(bundle),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": e
+        })),
+    }
+}
+
+async fn bundle(state: &AppState) -> Result<Value, String> {
+    let watchlist: Vec<String> = state.stored_watchlist.read().unwrap().iter().map(|pool| pool.to_string()).collect();
+    let (alerts, annotations, blocklist) = state
+        .with_store(|store| Ok((alert_history(store)?, store.annotations(None, 0, u64::MAX)?, store.blocklist()?)))
+        .await?;
+    let subscriptions: Vec<Value> = state
+        .subscriptions
+        .all()
+        .into_iter()
+        .map(|subscription| {
+            let mut exported = json!(subscription);
+            exported["secret"] = json!(subscription.secret);
+            exported
+        })
+        .collect();
+    Ok(json!({
+        "version": BUNDLE_VERSION,
+        "exported_at": unix_now(),
+        "watchlist": watchlist,
+        "alerts": alerts,
+        "annotations": annotations,
+        "portfolios": state.portfolios.all(),
+        "blocklist": blocklist,
+        "subscriptions": subscriptions,
+        "report_templates": state.report_templates.all(),
+    }))
+}
+
+// Merge a bundle from `GET /admin/export` into this instance. Nothing is
+// removed: portfolios, blocklist entries, subscriptions and templates
+// replace the ones with the same id or address, alerts and annotations
+// already present are skipped, so importing twice changes nothing.
+pub fn import_resource() -> Resource {
+    web::resource("/import")
+        .app_data(web::JsonConfig::default().limit(MAX_BUNDLE_BYTES))
+        .route(web::post().to(import_state))
+}
+
+async fn import_state(state: web::Data<AppState>, body: web::Json<Bundle>, req: HttpRequest) -> HttpResponse {
+    let mut bundle = body.into_inner();
+    if bundle.version != BUNDLE_VERSION {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("Unsupported bundle version {}, expected {}", bundle.version, BUNDLE_VERSION)
+        }));
+    }
+    // Checked up front so a bad bundle changes nothing
+    let mut watchlist = Vec::new();
+    for pool in &bundle.watchlist {
+        match Pubkey::from_str(pool) {
+            Ok(pool) => watchlist.push(pool),
+            Err(e) => return invalid("watchlist", pool, e),
+        }
+    }
+    let mut blocklist = Vec::new();
+    for entry in std::mem::take(&mut bundle.blocklist) {
+        match Pubkey::from_str(&entry.address) {
+            Ok(address) => blocklist.push(BlockedAddress {
+                address,
+                kind: entry.kind,
+                reason: entry.reason,
+                source: "admin".to_string(),
+                added_at: entry.added_at,
+            }),
+            Err(e) => return invalid("blocklist", &entry.address, e),
+        }
+    }
+    // The checks of the handlers creating each of them
+    for annotation in &bundle.annotations {
+        if let Err(e) = Pubkey::from_str(&annotation.pool) {
+            return invalid("annotations", &annotation.pool, e);
+        }
+        if let Err(e) = annotations::validate(&annotation.label, annotation.note.as_deref()) {
+            return rejected("annotations", &annotation.id.to_string(), e);
+        }
+    }
+    for portfolio in &bundle.portfolios {
+        if let Err(e) = portfolio::validate(portfolio) {
+            return rejected("portfolios", &portfolio.id, e);
+        }
+    }
+    for ExportedSubscription { subscription, .. } in &bundle.subscriptions {
+        if let Err(e) = subscriptions::validate(&subscription.url, &subscription.pools) {
+            return rejected("subscriptions", &subscription.id, e);
+        }
+    }
+    for template in &bundle.report_templates {
+        if let Err(e) = templates::validate(&state, &template.name, &template.pools, &template.channels) {
+            return rejected("report_templates", &template.id, e);
+        }
+    }
+    if let Err(e) = templates::check_room(&state, bundle.report_templates.iter().map(|template| template.id.as_str())) {
+        return HttpResponse::BadRequest().json(json!({ "error": e }));
+    }
+
+    match import(&state, bundle, watchlist, blocklist).await {
+        Ok(imported) => {
+            println!("Imported service state via admin API: {}", imported);
+            trail::changed(&req, "import", Value::Null, &imported);
+            HttpResponse::Ok().json(json!({
+                "imported": imported
+            }))
+        }
+        // Sections before the failing one stay imported
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": e
+        })),
+    }
+}
+
+// Store a checked bundle and count what it added.
+async fn import(state: &AppState, bundle: Bundle, watchlist: Vec<Pubkey>, blocklist: Vec<BlockedAddress>) -> Result<Value, String> {
+    let pools = watchlist.clone();
+    let (watched, alerts, skipped_alerts, annotations) = state
+        .with_store(move |store| {
+            let mut watched = 0;
+            for pool in &pools {
+                watched += store.watch(pool)? as usize;
+            }
+            let (alerts, skipped_alerts) = import_alerts(store, bundle.alerts)?;
+            let mut annotations = 0;
+            for annotation in bundle.annotations {
+                let existing = store.annotations(Some(&annotation.pool), annotation.timestamp, annotation.timestamp)?;
+                if !existing.iter().any(|a| a.label == annotation.label && a.note == annotation.note) {
+                    store.save_annotation(&annotation)?;
+                    annotations += 1;
+                }
+            }
+            Ok((watched, alerts, skipped_alerts, annotations))
+        })
+        .await?;
+    {
+        let mut stored = state.stored_watchlist.write().unwrap();
+        for pool in watchlist {
+            if !stored.contains(&pool) {
+                stored.push(pool);
+            }
+        }
+    }
+    let portfolios = bundle.portfolios.len();
+    for portfolio in bundle.portfolios {
+        portfolio::save(state, portfolio).await?;
+    }
+    let blocked = blocklist.len();
+    let entries = blocklist.clone();
+    state
+        .with_store(move |store| entries.iter().try_for_each(|entry| store.block(entry)))
+        .await?;
+    for entry in blocklist {
+        state.blocklist.add(entry);
+    }
+    let subscriptions = bundle.subscriptions.len();
+    for ExportedSubscription { mut subscription, secret } in bundle.subscriptions {
+        subscription.secret = secret;
+        subscriptions::save(state, subscription).await?;
+    }
+    let templates = bundle.report_templates.len();
+    for template in bundle.report_templates {
+        templates::save(state, template).await?;
+    }
+    Ok(json!({
+        "watchlist": watched,
+        "alerts": alerts,
+        "alerts_skipped": skipped_alerts,
+        "annotations": annotations,
+        "portfolios": portfolios,
+        "blocklist": blocked,
+        "subscriptions": subscriptions,
+        "report_templates": templates,
+    }))
+}
+
+fn invalid(section: &str, address: &str, e: impl std::fmt::Display) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "error": format!("Invalid address {} in {}: {}", address, section, e)
+    }))
+}
+
+fn rejected(section: &str, id: &str, e: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "error": format!("Invalid entry {} in {}: {}", id, section, e)
+    }))
+}
+
+// Store alerts not in the history yet, oldest first so ids keep their order,
+// with their acknowledgements. Alerts of kinds this version doesn't fire
+// are skipped and counted.
+fn import_alerts(store: &dyn Store, mut stored: Vec<StoredAlert>) -> Result<(usize, usize), String> {
+    stored.sort_by_key(|alert| (alert.timestamp, alert.id));
+    let (mut imported, mut skipped) = (0, 0);
+    for alert in stored {
+        let (Some(kind), Ok(severity)) = (
+            alerts::KINDS.iter().find(|kind| **kind == alert.kind),
+            serde_json::from_value::<Severity>(json!(alert.severity)),
+        ) else {
+            skipped += 1;
+            continue;
+        };
+        let existing = store.alert_history(&AlertFilter {
+            since: Some(alert.timestamp),
+            until: Some(alert.timestamp + 1),
+            pool: Some(alert.pool.clone()),
+            kind: Some(alert.kind.clone()),
+            limit: i64::MAX as usize,
+            ..AlertFilter::default()
+        })?;
+        if existing.iter().any(|a| a.key == alert.key && a.message == alert.message) {
+            continue;
+        }
+        let mut new = Alert::new(kind, severity, alert.pool, alert.message, alert.details);
+        new.timestamp = alert.timestamp;
+        new.key = alert.key;
+        new.suppressed_by = alert.suppressed_by;
+        let id = store.save_alert(&new)?;
+        if let (Some(at), Some(by)) = (alert.acked_at, &alert.acked_by) {
+            store.ack_alert(id, by, at)?;
+        }
+        imported += 1;
+    }
+    Ok((imported, skipped))
+}
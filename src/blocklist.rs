@@ -0,0 +1,223 @@
+use crate::config::BlocklistConfig;
+use crate::dex::pubkey_string;
+use crate::periodic::{self, FETCH_TIMEOUT};
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Mint,
+    Wallet,
+}
+
+impl BlockKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BlockKind::Mint => "mint",
+            BlockKind::Wallet => "wallet",
+        }
+    }
+}
+
+impl FromStr for BlockKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, String> {
+        match kind {
+            "mint" => Ok(BlockKind::Mint),
+            "wallet" => Ok(BlockKind::Wallet),
+            _ => Err(format!("Unknown blocklist kind {}, expected mint or wallet", kind)),
+        }
+    }
+}
+
+// A known scam mint or wallet.
+#[derive(Clone, Debug, Serialize)]
+pub struct BlockedAddress {
+    #[serde(serialize_with = "pubkey_string")]
+    pub address: Pubkey,
+    pub kind: BlockKind,
+    pub reason: Option<String>,
+    // "admin", "config" or the name of the community list.
+    pub source: String,
+    // When it was added through the admin API, lists are refreshed wholesale.
+    pub added_at: Option<u64>,
+}
+
+// Community list entries are bare addresses or objects with a reason.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListEntry {
+    Address(String),
+    Entry { address: String, reason: Option<String> },
+}
+
+// Blocked addresses by where they come from. Admin entries are backed by the
+// store, config and community list entries are rebuilt on every refresh.
+#[derive(Default)]
+pub struct Blocklist {
+    admin: RwLock<HashMap<Pubkey, BlockedAddress>>,
+    config: RwLock<HashMap<Pubkey, BlockedAddress>>,
+    lists: RwLock<HashMap<Pubkey, BlockedAddress>>,
+}
+
+impl Blocklist {
+    pub fn load(&self, entries: Vec<BlockedAddress>) {
+        self.admin.write().unwrap().extend(entries.into_iter().map(|entry| (entry.address, entry)));
+    }
+
+    // Admin entries win over config ones, which win over community lists.
+    pub fn get(&self, address: &Pubkey) -> Option<BlockedAddress> {
+        [&self.admin, &self.config, &self.lists]
+            .into_iter()
+            .find_map(|entries| entries.read().unwrap().get(address).cloned())
+    }
+
+    // Every blocked address, the entry that wins for each.
+    pub fn entries(&self) -> Vec<BlockedAddress> {
+        let mut entries: HashMap<Pubkey, BlockedAddress> = HashMap::new();
+        for source in [&self.lists, &self.config, &self.admin] {
+            entries.extend(source.read().unwrap().iter().map(|(address, entry)| (*address, entry.clone())));
+        }
+        let mut entries: Vec<BlockedAddress> = entries.into_values().collect();
+        entries.sort_by_key(|entry| entry.address.to_string());
+        entries
+    }
+
+    pub fn add(&self, entry: BlockedAddress) {
+        self.admin.write().unwrap().insert(entry.address, entry);
+    }
+
+    pub fn remove(&self, address: &Pubkey) {
+        self.admin.write().unwrap().remove(address);
+    }
+}
+
+// Blocked addresses among `involved`, each with the role it plays there.
+// Responses carry these as `blocklisted`, empty when nothing is blocked.
+pub fn flags(state: &AppState, involved: &[(&str, Pubkey)]) -> Vec<Value> {
+    involved
+        .iter()
+        .filter_map(|(role, address)| {
+            let entry = state.blocklist.get(address)?;
+            Some(json!({
+                "role": role,
+                "address": address.to_string(),
+                "kind": entry.kind,
+                "reason": entry.reason,
+                "source": entry.source,
+            }))
+        })
+        .collect()
+}
+
+// Flags of a pool and, once it has been decoded, its mints.
+pub fn pool_flags(state: &AppState, pool: &str) -> Vec<Value> {
+    let Ok(pubkey) = Pubkey::from_str(pool) else {
+        return Vec::new();
+    };
+    let mut involved = vec![("pool", pubkey)];
+    if let Some(decoded) = state.cache.get(&pubkey).and_then(|cached| cached.pool.clone()) {
+        involved.extend([("mint_a", decoded.mint_a), ("mint_b", decoded.mint_b)]);
+    }
+    flags(state, &involved)
+}
+
+// `value` with the flags of `involved` added, when it is an object.
+pub fn annotate(state: &AppState, involved: &[(&str, Pubkey)], mut value: Value) -> Value {
+    if let Value::Object(fields) = &mut value {
+        fields.insert("blocklisted".to_string(), json!(flags(state, involved)));
+    }
+    value
+}
+
+// Background task rebuilding the config entries and refetching community
+// lists every `blocklist.refresh_secs`, and whenever the config is reloaded.
+pub async fn run(state: Arc<AppState>) {
+    periodic::every(&state, "Blocklist refresh", None, || async {
+        let config = state.config().blocklist;
+        *state.blocklist.config.write().unwrap() = config_entries(&config);
+        if config.lists.is_empty() {
+            state.blocklist.lists.write().unwrap().clear();
+            return None;
+        }
+        let result = refresh(&state, &config).await.map(|entries| {
+            println!("Loaded {} blocked addresses from {} community lists", entries.len(), config.lists.len());
+            *state.blocklist.lists.write().unwrap() = entries;
+        });
+        Some((Duration::from_secs(config.refresh_secs), result))
+    })
+    .await
+}
+
+// Addresses are checked when the config is validated.
+fn config_entries(config: &BlocklistConfig) -> HashMap<Pubkey, BlockedAddress> {
+    let mut entries = HashMap::new();
+    for entry in &config.entries {
+        if let Ok(address) = Pubkey::from_str(&entry.address) {
+            entries.insert(
+                address,
+                BlockedAddress {
+                    address,
+                    kind: entry.kind,
+                    reason: entry.reason.clone(),
+                    source: "config".to_string(),
+                    added_at: None,
+                },
+            );
+        }
+    }
+    entries
+}
+
+// All community lists merged. Any list failing fails the refresh so an
+// address doesn't drop out for a transient error. Fetches are recorded
+// against the list URLs in the provider health.
+async fn refresh(state: &AppState, config: &BlocklistConfig) -> Result<HashMap<Pubkey, BlockedAddress>, String> {
+    let client = state.upstream.client();
+    let providers_config = state.config().providers;
+    let mut entries = HashMap::new();
+    for list in &config.lists {
+        let listed = fetch(&client, &list.name, &list.url).await;
+        let outcome = listed.as_ref().map(|_| ()).map_err(|e| e.clone());
+        state.providers.record(&list.url, outcome, &providers_config);
+        for entry in listed? {
+            let (address, reason) = match entry {
+                ListEntry::Address(address) => (address, None),
+                ListEntry::Entry { address, reason } => (address, reason),
+            };
+            let Ok(address) = Pubkey::from_str(&address) else {
+                continue;
+            };
+            entries.entry(address).or_insert_with(|| BlockedAddress {
+                address,
+                kind: list.kind,
+                reason,
+                source: list.name.clone(),
+                added_at: None,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+async fn fetch(client: &reqwest::Client, name: &str, url: &str) -> Result<Vec<ListEntry>, String> {
+    client
+        .get(url)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        // The URL may carry an API key
+        .map_err(|e| format!("Failed to fetch list {}: {}", name, e.without_url()))?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse list {}: {}", name, e.without_url()))
+}
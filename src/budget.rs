@@ -0,0 +1,94 @@
+use crate::config::{rpc_host, RpcBudgetConfig};
+use crate::state::unix_now;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
+
+const DAY_SECS: u64 = 86_400;
+
+#[derive(Default)]
+struct DailyUsage {
+    // Days since the epoch, UTC.
+    day: u64,
+    credits: u64,
+    calls: BTreeMap<String, u64>,
+    // Whether exhaustion was logged today.
+    warned: bool,
+}
+
+// How far a provider is into its daily budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Standing {
+    Within,
+    Throttled,
+    Exhausted,
+}
+
+// Estimated RPC credits spent today, by endpoint host.
+#[derive(Default)]
+pub struct RpcBudget {
+    usage: Mutex<HashMap<String, DailyUsage>>,
+    // Background calls that were held back for the budget.
+    pub throttled: AtomicU64,
+}
+
+impl RpcBudget {
+    pub fn charge(&self, url: &str, method: &str, config: &RpcBudgetConfig) {
+        let host = rpc_host(url);
+        let today = unix_now() / DAY_SECS;
+        let mut all = self.usage.lock().unwrap();
+        let usage = all.entry(host.clone()).or_default();
+        if usage.day != today {
+            *usage = DailyUsage {
+                day: today,
+                ..DailyUsage::default()
+            };
+        }
+        usage.credits += config.cost(method);
+        *usage.calls.entry(method.to_string()).or_default() += 1;
+        if let Some(&budget) = config.daily_credits.get(&host) {
+            if usage.credits >= budget && !usage.warned {
+                usage.warned = true;
+                eprintln!(
+                    "RPC budget of {} spent ({} of {} credits), background work waits for the next UTC day",
+                    host, usage.credits, budget
+                );
+            }
+        }
+    }
+
+    // Credits spent today on a host.
+    pub fn used(&self, host: &str) -> u64 {
+        let today = unix_now() / DAY_SECS;
+        let all = self.usage.lock().unwrap();
+        all.get(host).filter(|usage| usage.day == today).map_or(0, |usage| usage.credits)
+    }
+
+    pub fn standing(&self, url: &str, config: &RpcBudgetConfig) -> Standing {
+        let host = rpc_host(url);
+        let Some(&budget) = config.daily_credits.get(&host) else {
+            return Standing::Within;
+        };
+        let used = self.used(&host);
+        if used >= budget {
+            Standing::Exhausted
+        } else if used.saturating_mul(100) >= budget.saturating_mul(config.throttle_percent) {
+            Standing::Throttled
+        } else {
+            Standing::Within
+        }
+    }
+
+    // Today's credits and calls by method of every host that was called.
+    pub fn usage(&self) -> Vec<(String, u64, BTreeMap<String, u64>)> {
+        let today = unix_now() / DAY_SECS;
+        let all = self.usage.lock().unwrap();
+        let mut usage: Vec<_> = all
+            .iter()
+            .filter(|(_, usage)| usage.day == today)
+            .map(|(host, usage)| (host.clone(), usage.credits, usage.calls.clone()))
+            .collect();
+        usage.sort();
+        usage
+    }
+}
@@ -0,0 +1,189 @@
+use crate::config::{RecordingConfig, RecordingMode};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use solana_client::client_error::{ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// Upstream responses kept on disk, one file per distinct request holding
+// the different responses it got in the order they came. Recording writes
+// them as calls return, replaying serves them in that order, the last one
+// over and over once they run out, so handlers can be tested end to end
+// without network access.
+pub struct Cassette {
+    mode: RecordingMode,
+    dir: PathBuf,
+    max_responses: usize,
+    tapes: Mutex<HashMap<String, Tape>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tape {
+    request: Value,
+    responses: Vec<Value>,
+    // Responses replayed so far.
+    #[serde(skip)]
+    played: usize,
+}
+
+// File name of a request, so the same call always maps to the same file.
+fn name(request: &Value) -> String {
+    Sha256::digest(request.to_string())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Cassette {
+    // None when recording is off. Recording adds to what is already in the
+    // directory, so an interrupted run can be picked up again.
+    pub fn open(config: &RecordingConfig) -> Result<Option<Arc<Cassette>>, String> {
+        if config.mode == RecordingMode::Off {
+            return Ok(None);
+        }
+        let dir = &config.dir;
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let mut tapes = HashMap::new();
+        let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let tape: Tape = std::fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+                .map_err(|e| format!("Failed to load recording {}: {}", path.display(), e))?;
+            tapes.insert(name(&tape.request), tape);
+        }
+        match config.mode {
+            RecordingMode::Replay => println!("Replaying {} recorded requests from {}", tapes.len(), dir.display()),
+            _ => println!("Recording upstream responses to {}", dir.display()),
+        }
+        Ok(Some(Arc::new(Cassette {
+            mode: config.mode,
+            dir: dir.clone(),
+            max_responses: config.max_responses,
+            tapes: Mutex::new(tapes),
+        })))
+    }
+
+    // The next response to `request` when replaying, an error when it was
+    // never recorded. None when recording, the call goes upstream then.
+    pub fn replay(&self, request: &Value) -> Option<Result<Value, String>> {
+        if self.mode != RecordingMode::Replay {
+            return None;
+        }
+        let mut tapes = self.tapes.lock().unwrap();
+        let response = tapes.get_mut(&name(request)).and_then(|tape| {
+            let response = tape.responses.get(tape.played.min(tape.responses.len().saturating_sub(1)))?;
+            tape.played += 1;
+            Some(response.clone())
+        });
+        Some(response.ok_or_else(|| format!("No recorded response to {}", request)))
+    }
+
+    // Save a response when recording. Repeats of the previous response to
+    // the same request aren't kept, a pool polled while nothing trades is
+    // recorded once.
+    pub fn record(&self, request: &Value, response: &Value) {
+        if self.mode != RecordingMode::Record {
+            return;
+        }
+        let name = name(request);
+        let mut tapes = self.tapes.lock().unwrap();
+        let tape = tapes.entry(name.clone()).or_insert_with(|| Tape {
+            request: request.clone(),
+            responses: Vec::new(),
+            played: 0,
+        });
+        if tape.responses.last() == Some(response) || tape.responses.len() >= self.max_responses {
+            return;
+        }
+        tape.responses.push(response.clone());
+        let path = self.dir.join(format!("{}.json", name));
+        let written = serde_json::to_vec_pretty(tape)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| std::fs::write(&path, bytes).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("Failed to record response to {}: {}", path.display(), e);
+        }
+    }
+}
+
+// An RPC call by method and parameters. The endpoint is left out, so a
+// recording replays against any URL and never holds an API key.
+fn rpc_request(request: RpcRequest, params: &Value) -> Value {
+    json!({ "rpc": request.to_string(), "params": params })
+}
+
+// A GET of a third-party API, see `Upstream::get_json`.
+pub fn http_request(url: &str, params: &[(&str, &str)]) -> Value {
+    json!({ "get": url, "query": params })
+}
+
+// RPC transport going through the cassette when recording is on.
+pub struct RecordingSender<S> {
+    pub inner: S,
+    pub cassette: Option<Arc<Cassette>>,
+}
+
+#[async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for RecordingSender<S> {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let Some(cassette) = &self.cassette else {
+            return self.inner.send(request, params).await;
+        };
+        let recorded = rpc_request(request, &params);
+        if let Some(replayed) = cassette.replay(&recorded) {
+            return replayed.map_err(|e| ClientErrorKind::Custom(e).into());
+        }
+        let result = self.inner.send(request, params).await;
+        if let Ok(response) = &result {
+            cassette.record(&recorded, response);
+        }
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_in_recorded_order() {
+        let dir = std::env::temp_dir().join(format!("pool-monitor-cassette-{}", std::process::id()));
+        let config = |mode| RecordingConfig {
+            mode,
+            dir: dir.clone(),
+            max_responses: 3,
+        };
+        let request = http_request("https://api.example/prices", &[("token", "SOL")]);
+        let other = http_request("https://api.example/prices", &[("token", "USDC")]);
+
+        let recording = Cassette::open(&config(RecordingMode::Record)).unwrap().unwrap();
+        assert!(recording.replay(&request).is_none());
+        for price in [1, 1, 2, 1, 3] {
+            recording.record(&request, &json!({ "price": price }));
+        }
+
+        let replaying = Cassette::open(&config(RecordingMode::Replay)).unwrap().unwrap();
+        let replayed: Vec<Value> = (0..5).map(|_| replaying.replay(&request).unwrap().unwrap()).collect();
+        let prices: Vec<u64> = replayed.iter().map(|response| response["price"].as_u64().unwrap()).collect();
+        // Repeats aren't kept and the fourth response is past max_responses
+        assert_eq!(prices, [1, 2, 1, 1, 1]);
+        assert!(replaying.replay(&other).unwrap().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
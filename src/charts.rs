@@ -0,0 +1,227 @@
+use crate::address::ValidatedPubkey;
+use crate::history::Candle;
+use crate::time::parse_duration;
+use crate::state::{unix_now, AppState};
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse};
+use plotters::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+
+const DEFAULT_INTERVAL_SECS: u64 = 60 * 60;
+const DEFAULT_WINDOW_SECS: u64 = 7 * 24 * 60 * 60;
+const DEFAULT_SIZE: (u32, u32) = (800, 400);
+const MAX_SIZE: (u32, u32) = (2000, 2000);
+const MAX_CANDLES: u64 = 2000;
+const GRID_LINES: usize = 4;
+
+#[derive(Deserialize)]
+struct ChartQuery {
+    interval: Option<String>,
+    window: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ChartKey {
+    pool: Pubkey,
+    interval: u64,
+    window: u64,
+    size: (u32, u32),
+}
+
+#[derive(Clone)]
+struct CachedChart {
+    png: Bytes,
+    rendered_at: u64,
+}
+
+// Rendered charts, so a chart embedded in an alert that many people open is
+// drawn once.
+#[derive(Default)]
+pub struct ChartCache {
+    charts: Mutex<HashMap<ChartKey, CachedChart>>,
+}
+
+impl ChartCache {
+    fn get(&self, key: &ChartKey, max_age: u64) -> Option<CachedChart> {
+        let charts = self.charts.lock().unwrap();
+        charts.get(key).filter(|chart| unix_now().saturating_sub(chart.rendered_at) < max_age).cloned()
+    }
+
+    // Evicts the oldest charts once there are `max_entries`.
+    fn insert(&self, key: ChartKey, chart: CachedChart, max_entries: usize) {
+        let mut charts = self.charts.lock().unwrap();
+        while charts.len() >= max_entries {
+            let Some(oldest) = charts.iter().min_by_key(|(_, chart)| chart.rendered_at).map(|(key, _)| key.clone()) else {
+                break;
+            };
+            charts.remove(&oldest);
+        }
+        charts.insert(key, chart);
+    }
+
+    // Drop the charts of a pool, e.g. once its annotations changed.
+    pub fn forget(&self, pool: &Pubkey) {
+        self.charts.lock().unwrap().retain(|key, _| key.pool != *pool);
+    }
+}
+
+// Candlesticks over volume bars, with a vertical line at each annotation.
+// There are no axis labels, fonts can't be relied on where the server runs,
+// the chart goes next to text that gives the numbers.
+fn render(candles: &[Candle], annotations: &[u64], interval: u64, (width, height): (u32, u32)) -> Result<Vec<u8>, String> {
+    let mut buffer = vec![0u8; width as usize * height as usize * 3];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (width, height)).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| e.to_string())?;
+        let (prices, volumes) = root.split_vertically((height * 3 / 4) as i32);
+
+        let start = candles[0].open_time;
+        let end = candles[candles.len() - 1].open_time + interval;
+        let low = candles.iter().map(|c| c.low).fold(f64::INFINITY, f64::min);
+        let high = candles.iter().map(|c| c.high).fold(f64::NEG_INFINITY, f64::max);
+        // Keep a flat price off the edges
+        let pad = if high > low { (high - low) * 0.05 } else { high.abs() * 0.01 + f64::EPSILON };
+        let (low, high) = (low - pad, high + pad);
+
+        let mut chart = ChartBuilder::on(&prices)
+            .margin(8)
+            .build_cartesian_2d(start..end, low..high)
+            .map_err(|e| e.to_string())?;
+        let grid = RGBColor(230, 230, 230);
+        chart
+            .draw_series((1..GRID_LINES).map(|i| {
+                let price = low + (high - low) * i as f64 / GRID_LINES as f64;
+                PathElement::new(vec![(start, price), (end, price)], grid)
+            }))
+            .map_err(|e| e.to_string())?;
+        let plot_width = width.saturating_sub(16) as u64;
+        let bar_width = (plot_width * interval / (end - start).max(1)).saturating_sub(2).max(1) as u32;
+        chart
+            .draw_series(candles.iter().map(|c| {
+                CandleStick::new(c.open_time + interval / 2, c.open, c.high, c.low, c.close, GREEN.filled(), RED.filled(), bar_width)
+            }))
+            .map_err(|e| e.to_string())?;
+        chart
+            .draw_series(
+                annotations
+                    .iter()
+                    .filter(|at| (start..end).contains(*at))
+                    .map(|at| PathElement::new(vec![(*at, low), (*at, high)], BLUE.mix(0.6).stroke_width(2))),
+            )
+            .map_err(|e| e.to_string())?;
+
+        let max_volume = candles.iter().filter_map(|c| c.volume_b).max().unwrap_or(0);
+        if max_volume > 0 {
+            let mut chart = ChartBuilder::on(&volumes)
+                .margin(8)
+                .build_cartesian_2d(start..end, 0f64..max_volume as f64)
+                .map_err(|e| e.to_string())?;
+            chart
+                .draw_series(candles.iter().filter_map(|c| {
+                    let color = if c.is_red() { RED.mix(0.5) } else { GREEN.mix(0.5) };
+                    let volume = c.volume_b? as f64;
+                    Some(Rectangle::new([(c.open_time, 0.0), (c.open_time + interval, volume)], color.filled()))
+                }))
+                .map_err(|e| e.to_string())?;
+        }
+        root.present().map_err(|e| e.to_string())?;
+    }
+
+    let image = image::RgbImage::from_raw(width, height, buffer).ok_or("Chart buffer has the wrong size")?;
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    Ok(png)
+}
+
+// Price and volume chart of a pool's recorded history as a PNG, for
+// embedding in chat alerts and emails. The candle still in progress is
+// included.
+#[get("/pool/{pool_id}/chart.png")]
+async fn get_pool_chart(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<ChartQuery>,
+) -> HttpResponse {
+    let pool = pool_id.0;
+    let retention = state.config.read().unwrap().history_retention_secs;
+    let parse = |value: Option<&str>, default| value.map_or(Ok(default), |value| parse_duration(value, retention));
+    let (interval, window) = match (parse(query.interval.as_deref(), DEFAULT_INTERVAL_SECS), parse(query.window.as_deref(), DEFAULT_WINDOW_SECS)) {
+        (Ok(interval), Ok(window)) => (interval, window),
+        (Err(e), _) | (_, Err(e)) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    if window < interval || window / interval > MAX_CANDLES {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("window must cover between 1 and {} intervals", MAX_CANDLES)
+        }));
+    }
+    let size = (
+        query.width.unwrap_or(DEFAULT_SIZE.0),
+        query.height.unwrap_or(DEFAULT_SIZE.1),
+    );
+    if size.0 < 100 || size.1 < 100 || size.0 > MAX_SIZE.0 || size.1 > MAX_SIZE.1 {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("width and height must be between 100 and {}x{}", MAX_SIZE.0, MAX_SIZE.1)
+        }));
+    }
+
+    let config = state.config.read().unwrap().charts.clone();
+    let key = ChartKey { pool, interval, window, size };
+    let png = match state.charts.get(&key, config.cache_secs) {
+        Some(chart) => chart.png,
+        None => {
+            let now = unix_now();
+            let since = now.saturating_sub(window);
+            let candles: Vec<Candle> = state
+                .history
+                .candles(&pool, interval, now + interval)
+                .into_iter()
+                .filter(|candle| candle.open_time + interval > since)
+                .collect();
+            if candles.is_empty() {
+                return HttpResponse::NotFound().json(json!({
+                    "error": format!("No recorded history for {} in the window", pool)
+                }));
+            }
+            let listed = pool.to_string();
+            let annotations = state.with_store(move |store| store.annotations(Some(&listed), since, now)).await;
+            let annotations: Vec<u64> = match annotations {
+                Ok(annotations) => annotations.iter().map(|annotation| annotation.timestamp).collect(),
+                Err(e) => {
+                    eprintln!("Failed to load annotations of {}: {}", pool, e);
+                    Vec::new()
+                }
+            };
+            let rendered = tokio::task::spawn_blocking(move || render(&candles, &annotations, interval, size)).await;
+            let png = match rendered {
+                Ok(Ok(png)) => Bytes::from(png),
+                Ok(Err(e)) => {
+                    eprintln!("Failed to render chart for {}: {}", pool, e);
+                    return HttpResponse::InternalServerError().json(json!({
+                        "error": format!("Failed to render chart: {}", e)
+                    }));
+                }
+                Err(e) => {
+                    return HttpResponse::InternalServerError().json(json!({
+                        "error": format!("Task failed: {}", e)
+                    }));
+                }
+            };
+            let chart = CachedChart { png: png.clone(), rendered_at: now };
+            state.charts.insert(key, chart, config.cache_entries);
+            png
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header(("Cache-Control", format!("public, max-age={}", config.cache_secs)))
+        .body(png)
+}
@@ -0,0 +1,136 @@
+use crate::admin::constant_time_eq;
+use crate::config::Config;
+use crate::state::AppState;
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::{web, Error, FromRequest, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use std::future::{ready, Ready};
+
+pub const CLUSTER_HEADER: &str = "X-Solana-Cluster";
+pub const RPC_URL_HEADER: &str = "X-RPC-URL";
+
+#[derive(Deserialize)]
+struct ClusterQuery {
+    cluster: Option<String>,
+}
+
+// Cluster a request targets, from `?cluster=` or the X-Solana-Cluster header,
+// falling back to the default cluster. Resolved against the config when the
+// request arrives so a reload mid-request can't change its endpoints.
+pub struct Cluster {
+    pub name: String,
+    pub rpc_urls: Vec<String>,
+    default: bool,
+    overridden: bool,
+}
+
+impl Cluster {
+    // The default cluster, for work not tied to a request.
+    pub fn default_for(config: &Config) -> Cluster {
+        Cluster {
+            name: config.default_cluster.clone(),
+            rpc_urls: config.rpc_urls.clone(),
+            default: true,
+            overridden: false,
+        }
+    }
+
+    // Only the default cluster is polled, indexed and kept in history.
+    pub fn is_default(&self) -> bool {
+        self.default
+    }
+
+    // Set when the client routed the request through its own endpoint with
+    // X-RPC-URL. Such requests neither read nor fill the cache.
+    pub fn is_overridden(&self) -> bool {
+        self.overridden
+    }
+}
+
+impl FromRequest for Cluster {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(resolve(req))
+    }
+}
+
+fn resolve(req: &HttpRequest) -> Result<Cluster, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>() else {
+        return Err(actix_web::error::ErrorInternalServerError("App state missing"));
+    };
+    let requested = web::Query::<ClusterQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.into_inner().cluster)
+        .or_else(|| {
+            req.headers()
+                .get(CLUSTER_HEADER)
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_string)
+        });
+
+    let config = state.config.read().unwrap();
+    let name = requested.unwrap_or_else(|| config.default_cluster.clone());
+    let Some(rpc_urls) = config.cluster_rpc_urls(&name) else {
+        return Err(reject(
+            HttpResponse::BadRequest().json(json!({
+                "error": format!("Unknown cluster {}", name),
+                "clusters": config.cluster_names(),
+            })),
+            format!("Unknown cluster {}", name),
+        ));
+    };
+    let rpc_override = rpc_override(req, &config)?;
+
+    Ok(Cluster {
+        default: name == config.default_cluster,
+        overridden: rpc_override.is_some(),
+        rpc_urls: rpc_override.map(|url| vec![url]).unwrap_or_else(|| rpc_urls.to_vec()),
+        name,
+    })
+}
+
+// Endpoint from X-RPC-URL, once the caller is authenticated and the URL is
+// allowlisted.
+fn rpc_override(req: &HttpRequest, config: &Config) -> Result<Option<String>, Error> {
+    let Some(url) = req.headers().get(RPC_URL_HEADER) else {
+        return Ok(None);
+    };
+    let Some(expected) = &config.rpc_override.token else {
+        return Err(reject(
+            HttpResponse::Forbidden().json(json!({
+                "error": "RPC overrides are disabled, set rpc_override.token in the config"
+            })),
+            "RPC overrides are disabled",
+        ));
+    };
+    let provided = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    if !provided.is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes())) {
+        return Err(reject(
+            HttpResponse::Unauthorized().json(json!({
+                "error": "X-RPC-URL requires a valid rpc_override token"
+            })),
+            "Missing or invalid RPC override token",
+        ));
+    }
+    match url.to_str() {
+        Ok(url) if config.rpc_override.allows(url) => Ok(Some(url.to_string())),
+        _ => Err(reject(
+            HttpResponse::Forbidden().json(json!({
+                "error": "X-RPC-URL is not in rpc_override.allowed_urls"
+            })),
+            "RPC override not allowed",
+        )),
+    }
+}
+
+fn reject(response: HttpResponse, reason: impl Into<String>) -> Error {
+    InternalError::from_response(reason.into(), response).into()
+}
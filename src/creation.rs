@@ -0,0 +1,208 @@
+use crate::dex::{pubkey_string, DecodedPool};
+use crate::state::{unix_now, AppState};
+use serde::Serialize;
+use solana_client::client_error::ClientError;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, UiLoadedAddresses, UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+
+const SIGNATURE_PAGE: usize = 1000;
+// Pools with longer histories are left unresolved, the busiest ones have
+// hundreds of millions of transactions.
+const MAX_SIGNATURE_PAGES: usize = 500;
+// A failed lookup is tried again after this long.
+const RETRY_AFTER_SECS: u64 = 60 * 60;
+
+// The transaction that created a pool.
+#[derive(Clone, Debug, Serialize)]
+pub struct PoolCreation {
+    pub signature: String,
+    pub slot: u64,
+    // None when the node has no block time for the slot.
+    pub created_at: Option<u64>,
+    // Fee payer of the creation transaction.
+    #[serde(serialize_with = "pubkey_string")]
+    pub creator: Pubkey,
+    // Raw vault balances the creation transaction left. Concentrated
+    // liquidity pools are created empty and funded by later positions.
+    pub initial_reserve_a: u64,
+    pub initial_reserve_b: u64,
+}
+
+// What is known about a pool's creation.
+pub enum Lookup {
+    Resolved(PoolCreation),
+    Pending,
+    Failed(String),
+}
+
+// Resolved creations, backed by the store since they never change, plus
+// the lookups running or recently failed.
+#[derive(Default)]
+pub struct Creations {
+    resolved: RwLock<HashMap<Pubkey, PoolCreation>>,
+    pending: Mutex<HashSet<Pubkey>>,
+    failed: Mutex<HashMap<Pubkey, (String, u64)>>,
+}
+
+// Creation of a pool of the default cluster. Paging back to the first
+// signature can take hundreds of calls, so an unknown pool gets a
+// background lookup and is reported pending until it finishes.
+pub async fn lookup(state: &Arc<AppState>, pool: Pubkey, decoded: &DecodedPool) -> Lookup {
+    if let Some(creation) = state.creations.resolved.read().unwrap().get(&pool) {
+        return Lookup::Resolved(creation.clone());
+    }
+    match state.with_store(move |store| store.pool_creation(&pool)).await {
+        Ok(Some(creation)) => {
+            state.creations.resolved.write().unwrap().insert(pool, creation.clone());
+            return Lookup::Resolved(creation);
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("Failed to load creation of {}: {}", pool, e),
+    }
+    if let Some((error, at)) = state.creations.failed.lock().unwrap().get(&pool) {
+        if unix_now().saturating_sub(*at) < RETRY_AFTER_SECS {
+            return Lookup::Failed(error.clone());
+        }
+    }
+    if !state.creations.pending.lock().unwrap().insert(pool) {
+        return Lookup::Pending;
+    }
+
+    let state = state.clone();
+    let vaults = (decoded.vault_a, decoded.vault_b);
+    tokio::spawn(async move {
+        let result = resolve(&state, pool, vaults).await;
+        match result {
+            Ok(creation) => {
+                println!("Pool {} was created in {} by {}", pool, creation.signature, creation.creator);
+                let stored = creation.clone();
+                if let Err(e) = state.with_store(move |store| store.save_pool_creation(&pool, &stored)).await {
+                    eprintln!("Failed to store creation of {}: {}", pool, e);
+                }
+                state.creations.resolved.write().unwrap().insert(pool, creation);
+                state.creations.failed.lock().unwrap().remove(&pool);
+            }
+            Err(e) => {
+                eprintln!("Failed to resolve creation of {}: {}", pool, e);
+                state.creations.failed.lock().unwrap().insert(pool, (e, unix_now()));
+            }
+        }
+        state.creations.pending.lock().unwrap().remove(&pool);
+    });
+    Lookup::Pending
+}
+
+async fn resolve(state: &AppState, pool: Pubkey, vaults: (Pubkey, Pubkey)) -> Result<PoolCreation, String> {
+    // The newest page is on every node, older ones and the transaction
+    // itself may only be on archival ones. Each client is let go before the
+    // next is taken so a lookup never holds two upstream slots.
+    let rpc_client = state.background_rpc_client().await;
+    let (mut oldest, mut before) = run_blocking(move || page_back(&rpc_client, &pool, None, 1)).await?;
+    if before.is_some() {
+        let archival_client = state.background_history_rpc_client().await;
+        let (older, next) =
+            run_blocking(move || page_back(&archival_client, &pool, before, MAX_SIGNATURE_PAGES - 1)).await?;
+        oldest = older.or(oldest);
+        before = next;
+    }
+    if before.is_some() {
+        return Err(format!(
+            "Pool has more than {} signatures, its creation is too far back",
+            MAX_SIGNATURE_PAGES * SIGNATURE_PAGE
+        ));
+    }
+    let Some(signature) = oldest else {
+        return Err("Pool has no successful transactions".to_string());
+    };
+
+    let archival_client = state.background_history_rpc_client().await;
+    let tx = run_blocking(move || {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(archival_client.commitment()),
+            max_supported_transaction_version: Some(0),
+        };
+        archival_client.get_transaction_with_config(&signature, config)
+    })
+    .await?;
+    creation(&signature, &tx, vaults)
+}
+
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, ClientError> + Send + 'static,
+) -> Result<T, String> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(e) => Err(format!("Task failed: {}", e)),
+    }
+}
+
+// Page back through the signatures of `pool` from `before`, at most `pages`
+// pages. Returns the oldest successful signature seen and where to go on
+// from, None once the history is exhausted.
+fn page_back(
+    client: &RpcClient,
+    pool: &Pubkey,
+    mut before: Option<Signature>,
+    pages: usize,
+) -> Result<(Option<Signature>, Option<Signature>), ClientError> {
+    let mut oldest = None;
+    for _ in 0..pages {
+        let page = client.get_signatures_for_address_with_config(
+            pool,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                limit: Some(SIGNATURE_PAGE),
+                ..GetConfirmedSignaturesForAddress2Config::default()
+            },
+        )?;
+        // Newest first, so the last successful entry is the oldest so far
+        if let Some(entry) = page.iter().rev().find(|entry| entry.err.is_none()) {
+            oldest = Signature::from_str(&entry.signature).ok().or(oldest);
+        }
+        before = page.last().and_then(|entry| Signature::from_str(&entry.signature).ok());
+        if page.len() < SIGNATURE_PAGE || before.is_none() {
+            return Ok((oldest, None));
+        }
+    }
+    Ok((oldest, before))
+}
+
+fn creation(
+    signature: &Signature,
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+    (vault_a, vault_b): (Pubkey, Pubkey),
+) -> Result<PoolCreation, String> {
+    let meta = tx.transaction.meta.as_ref().ok_or("Creation transaction has no status")?;
+    let message = tx.transaction.transaction.decode().ok_or("Failed to decode creation transaction")?.message;
+    // Token balances index into the static keys followed by the ones loaded
+    // from lookup tables
+    let mut keys = message.static_account_keys().to_vec();
+    if let Some(loaded) = Option::<UiLoadedAddresses>::from(meta.loaded_addresses.clone()) {
+        keys.extend(loaded.writable.iter().chain(&loaded.readonly).filter_map(|key| Pubkey::from_str(key).ok()));
+    }
+    let creator = *keys.first().ok_or("Creation transaction has no accounts")?;
+
+    let balances = Option::<Vec<UiTransactionTokenBalance>>::from(meta.post_token_balances.clone()).unwrap_or_default();
+    let reserve = |vault: Pubkey| {
+        let index = keys.iter().position(|key| *key == vault)?;
+        let balance = balances.iter().find(|balance| balance.account_index as usize == index)?;
+        balance.ui_token_amount.amount.parse::<u64>().ok()
+    };
+    Ok(PoolCreation {
+        signature: signature.to_string(),
+        slot: tx.slot,
+        created_at: tx.block_time.map(|t| t as u64),
+        creator,
+        initial_reserve_a: reserve(vault_a).unwrap_or(0),
+        initial_reserve_b: reserve(vault_b).unwrap_or(0),
+    })
+}
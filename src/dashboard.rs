@@ -0,0 +1,57 @@
+use crate::state::AppState;
+use actix_web::http::header;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use rust_embed::RustEmbed;
+use serde_json::json;
+
+// The single-page dashboard, compiled into the binary.
+#[derive(RustEmbed)]
+#[folder = "dashboard/"]
+struct Assets;
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit('.').next() {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+// An embedded file, revalidated by its hash so a new binary's files are
+// picked up straight away.
+fn serve(state: &AppState, req: &HttpRequest, path: &str) -> HttpResponse {
+    let file = Some(path).filter(|_| state.config.read().unwrap().dashboard.enabled).and_then(Assets::get);
+    let Some(file) = file else {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("No dashboard file {}", path)
+        }));
+    };
+    let etag = format!("\"{}\"", file.metadata.sha256_hash().iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    let cached = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value == etag);
+    if cached {
+        return HttpResponse::NotModified().insert_header((header::ETAG, etag)).finish();
+    }
+    HttpResponse::Ok()
+        .content_type(content_type(path))
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .body(file.data.into_owned())
+}
+
+// Watched pools, candles and alerts, drawn from the API in the browser.
+#[get("/")]
+async fn index(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    serve(&state, &req, "index.html")
+}
+
+#[get("/dashboard/{file:.*}")]
+async fn asset(state: web::Data<AppState>, file: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    serve(&state, &req, &file)
+}
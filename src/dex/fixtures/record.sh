@@ -0,0 +1,35 @@
+#!/bin/sh
+# Replace fixtures with recordings of their addresses:
+#
+#   src/dex/fixtures/record.sh [RPC_URL] [FIXTURE...]
+#
+# Every fixture when none are named. Needs jq, and the Solana CLI or curl.
+set -eu
+cd "$(dirname "$0")"
+url=${1:-https://api.mainnet-beta.solana.com}
+[ $# -gt 0 ] && shift
+[ $# -eq 0 ] && set -- *.json
+# A failed fetch leaves the fixture as it was
+trap 'rm -f ./*.recording' EXIT
+
+# The JSON `solana account --output json` writes, from getAccountInfo.
+fetch() {
+    response=$(curl -sSf "$url" -H 'Content-Type: application/json' -d "{
+        \"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"getAccountInfo\",
+        \"params\": [\"$1\", {\"encoding\": \"base64\"}]
+    }")
+    echo "$response" | jq -e --arg pubkey "$1" '.result.value // error("no account at \($pubkey)")
+        | {pubkey: $pubkey, account: {lamports, data, owner, executable, rentEpoch, space}}'
+}
+
+for fixture in "$@"; do
+    fixture=${fixture%.json}.json
+    pubkey=$(jq -r .pubkey "$fixture")
+    if command -v solana > /dev/null; then
+        solana account "$pubkey" --url "$url" --output json > "$fixture.recording"
+    else
+        fetch "$pubkey" > "$fixture.recording"
+    fi
+    mv "$fixture.recording" "$fixture"
+    echo "Recorded $pubkey to $fixture"
+done
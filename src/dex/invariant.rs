@@ -0,0 +1,101 @@
+use super::{cast, check_layout, DecodedPool, PoolLayout};
+use crate::fixed;
+use bytemuck::{Pod, Zeroable};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::mem::{offset_of, size_of};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("HyaB3W9q6XdA5xwpU4XnSZV94htfmbmqJXZcEbRaJutt");
+
+// Size of the Pool account, including the Anchor discriminator.
+pub const ACCOUNT_LEN: usize = 400;
+
+// The Pool account as laid out on chain, read in place. Invariant keeps its
+// numbers as decimals with a fixed scale rather than Q64.64.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+struct Pool {
+    _discriminator: [u8; 8],
+    token_x: [u8; 32],
+    token_y: [u8; 32],
+    token_x_reserve: [u8; 32],
+    token_y_reserve: [u8; 32],
+    _position_iterator: u128,
+    _tick_spacing: u16,
+    // FEE_SCALE, 10^12 is 100%.
+    fee: u128,
+    _protocol_fee: u128,
+    _liquidity: u128,
+    // PRICE_SCALE.
+    sqrt_price: u128,
+    _current_tick_index: i32,
+    _tickmap: [u8; 32],
+    // FEE_GROWTH_SCALE.
+    fee_growth_global_x: u128,
+    fee_growth_global_y: u128,
+    fee_protocol_token_x: u64,
+    fee_protocol_token_y: u64,
+    _seconds_per_liquidity_global: u128,
+    _start_timestamp: u64,
+    _last_timestamp: u64,
+    // fee_receiver and oracle_address
+    _accounts: [[u8; 32]; 2],
+    _oracle_initialized: u8,
+    _bump: u8,
+}
+
+const _: () = assert!(size_of::<Pool>() == ACCOUNT_LEN);
+
+const FEE_SCALE: u128 = 10u128.pow(12);
+const PRICE_SCALE: u128 = 10u128.pow(24);
+const FEE_GROWTH_SCALE: u128 = 10u128.pow(28);
+
+pub const LAYOUT: PoolLayout = PoolLayout {
+    dex: "invariant",
+    program_id: PROGRAM_ID,
+    account_len: ACCOUNT_LEN,
+    discriminator: Some("Pool"),
+    mint_a: offset_of!(Pool, token_x),
+    mint_b: offset_of!(Pool, token_y),
+};
+
+// A decimal of the given scale as Q64.64, saturating when it doesn't fit.
+fn to_x64(value: u128, scale: u128) -> u128 {
+    fixed::mul_div(value, 1 << 64, scale).unwrap_or(u128::MAX)
+}
+
+pub fn decode(data: &[u8]) -> Result<DecodedPool, String> {
+    let warnings = check_layout(data, &LAYOUT);
+    let pool: &Pool = cast(data)?;
+
+    Ok(DecodedPool {
+        dex: "invariant",
+        mint_a: Pubkey::new_from_array(pool.token_x),
+        mint_b: Pubkey::new_from_array(pool.token_y),
+        vault_a: Pubkey::new_from_array(pool.token_x_reserve),
+        vault_b: Pubkey::new_from_array(pool.token_y_reserve),
+        // Not stored, the poller reads them from the mints
+        decimals_a: 0,
+        decimals_b: 0,
+        lp_mint: None,
+        lp_supply: None,
+        lp_decimals: 0,
+        fee_bps: fixed::mul_div(pool.fee, 10_000, FEE_SCALE).and_then(|fee| u64::try_from(fee).ok()),
+        fee_account: None,
+        authority: None,
+        tradeable: true,
+        status: None,
+        pending_a: pool.fee_protocol_token_x,
+        pending_b: pool.fee_protocol_token_y,
+        cumulative_volume_b: None,
+        sqrt_price_x64: Some(to_x64(pool.sqrt_price, PRICE_SCALE)),
+        oracle_price: None,
+        fee_growth_global: Some((
+            to_x64(pool.fee_growth_global_x, FEE_GROWTH_SCALE),
+            to_x64(pool.fee_growth_global_y, FEE_GROWTH_SCALE),
+        )),
+        // Liquidity mining runs through the separate staking program
+        rewards: Vec::new(),
+        warnings,
+    })
+}
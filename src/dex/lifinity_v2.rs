@@ -0,0 +1,100 @@
+use super::{cast, check_layout, DecodedPool, PoolLayout};
+use bytemuck::{Pod, Zeroable};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::mem::{offset_of, size_of};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("2wT8Yq49kHgDzXuPxZSaeLaH1qbmGXtEyPy64bL7aD3c");
+
+// Size of the Amm account, including the Anchor discriminator.
+pub const ACCOUNT_LEN: usize = 911;
+
+// The Amm account as laid out on chain, read in place.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+struct Amm {
+    _discriminator: [u8; 8],
+    initializer_key: [u8; 32],
+    // initializer_deposit_token_account and initializer_receive_token_account
+    _initializer_accounts: [[u8; 32]; 2],
+    // initializer_amount and taker_amount
+    _amounts: [u64; 2],
+    _is_initialized: u8,
+    _bump_seed: u8,
+    freeze_trade: u8,
+    _freeze_deposit: u8,
+    _freeze_withdraw: u8,
+    base_decimals: u8,
+    _token_program_id: [u8; 32],
+    token_a_account: [u8; 32],
+    token_b_account: [u8; 32],
+    pool_mint: [u8; 32],
+    token_a_mint: [u8; 32],
+    token_b_mint: [u8; 32],
+    // fee_account and the main, sub and pc oracle accounts
+    _accounts: [[u8; 32]; 4],
+    trade_fee_numerator: u64,
+    trade_fee_denominator: u64,
+    // owner trade, owner withdraw and host fee numerators and denominators
+    _fees: [u64; 6],
+    _curve_type: u8,
+    _curve_parameters: u64,
+    last_price: u64,
+    _last_balanced_price: u64,
+    config_denominator: u64,
+    // volume_x through config_temp8
+    _config: [u64; 26],
+    _temp_accounts: [[u8; 32]; 5],
+}
+
+const _: () = assert!(size_of::<Amm>() == ACCOUNT_LEN);
+
+pub const LAYOUT: PoolLayout = PoolLayout {
+    dex: "lifinity_v2",
+    program_id: PROGRAM_ID,
+    account_len: ACCOUNT_LEN,
+    discriminator: Some("Amm"),
+    mint_a: offset_of!(Amm, token_a_mint),
+    mint_b: offset_of!(Amm, token_b_mint),
+};
+
+pub fn decode(data: &[u8]) -> Result<DecodedPool, String> {
+    let warnings = check_layout(data, &LAYOUT);
+    let pool: &Amm = cast(data)?;
+    let freeze_trade = pool.freeze_trade;
+    let (last_price, config_denominator) = (pool.last_price, pool.config_denominator);
+
+    Ok(DecodedPool {
+        dex: "lifinity_v2",
+        mint_a: Pubkey::new_from_array(pool.token_a_mint),
+        mint_b: Pubkey::new_from_array(pool.token_b_mint),
+        vault_a: Pubkey::new_from_array(pool.token_a_account),
+        vault_b: Pubkey::new_from_array(pool.token_b_account),
+        // Only the base decimals are stored, the poller reads both from the
+        // mints
+        decimals_a: pool.base_decimals,
+        decimals_b: 0,
+        lp_mint: Some(Pubkey::new_from_array(pool.pool_mint)),
+        // The LP mint holds the supply
+        lp_supply: None,
+        lp_decimals: 0,
+        fee_bps: pool
+            .trade_fee_numerator
+            .checked_mul(10_000)
+            .and_then(|n| n.checked_div(pool.trade_fee_denominator)),
+        fee_account: None,
+        authority: Some(Pubkey::new_from_array(pool.initializer_key)),
+        tradeable: freeze_trade == 0,
+        status: Some(freeze_trade as u64),
+        pending_a: 0,
+        pending_b: 0,
+        cumulative_volume_b: None,
+        sqrt_price_x64: None,
+        // Swaps are priced off the oracle and the vaults are rebalanced
+        // towards it, so their ratio isn't the pool's price
+        oracle_price: (config_denominator > 0).then(|| last_price as f64 / config_denominator as f64),
+        fee_growth_global: None,
+        rewards: Vec::new(),
+        warnings,
+    })
+}
@@ -0,0 +1,346 @@
+pub mod invariant;
+pub mod lifinity_v2;
+pub mod orca_whirlpool;
+pub mod raydium_amm;
+pub mod raydium_clmm;
+pub mod raydium_cpmm;
+#[cfg(test)]
+mod tests;
+
+use crate::state::AppState;
+use crate::token;
+use actix_web::{get, web, HttpResponse};
+use bytemuck::Pod;
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::mem::size_of;
+
+#[cfg(target_endian = "big")]
+compile_error!("Pool layouts are read in place and assume a little-endian target");
+
+// Known DEX programs. Pools owned by a program without a decoder are still
+// labelled with the DEX name.
+const PROGRAMS: &[(Pubkey, &str)] = &[
+    (raydium_amm::PROGRAM_ID, "raydium_amm"),
+    (raydium_clmm::PROGRAM_ID, "raydium_clmm"),
+    (raydium_cpmm::PROGRAM_ID, "raydium_cpmm"),
+    (orca_whirlpool::PROGRAM_ID, "orca_whirlpool"),
+    (pubkey!("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo"), "meteora_dlmm"),
+    (pubkey!("Eo7WjKq67rjJQSZxS6z95bz3BfCpeVM3jYyE8iT7tpHy"), "meteora_amm"),
+    (pubkey!("PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY"), "phoenix"),
+    (pubkey!("opnb2LAfJYbRMAHHvqjCwQxanZn7ReEHp1k81EohpZb"), "openbook_v2"),
+    (lifinity_v2::PROGRAM_ID, "lifinity_v2"),
+    (invariant::PROGRAM_ID, "invariant"),
+];
+
+// Pool fields every supported DEX can provide, decoded from the pool account.
+// Token "a" is the base and "b" the quote, so prices are b per a.
+#[derive(Clone, Debug, Serialize)]
+pub struct DecodedPool {
+    pub dex: &'static str,
+    #[serde(serialize_with = "pubkey_string")]
+    pub mint_a: Pubkey,
+    #[serde(serialize_with = "pubkey_string")]
+    pub mint_b: Pubkey,
+    #[serde(serialize_with = "pubkey_string")]
+    pub vault_a: Pubkey,
+    #[serde(serialize_with = "pubkey_string")]
+    pub vault_b: Pubkey,
+    pub decimals_a: u8,
+    pub decimals_b: u8,
+    #[serde(serialize_with = "optional_pubkey_string")]
+    pub lp_mint: Option<Pubkey>,
+    // Sent as an `Amount` by `CachedAccount::encode_fields`.
+    #[serde(skip)]
+    pub lp_supply: Option<u64>,
+    #[serde(skip)]
+    pub lp_decimals: u8,
+    // Swap fee in basis points.
+    pub fee_bps: Option<u64>,
+    // Account holding the fee rate, for DEXes that keep it outside the pool.
+    #[serde(skip)]
+    pub fee_account: Option<Pubkey>,
+    // Admin key able to change pool parameters, where the DEX has one.
+    #[serde(serialize_with = "optional_pubkey_string")]
+    pub authority: Option<Pubkey>,
+    // Whether the pool's own state lets swaps through. Pools of DEXes with
+    // no way to pause them always do.
+    pub tradeable: bool,
+    // Raw status the DEX keeps for pausing the pool, where it has one.
+    pub status: Option<u64>,
+    // Fees owed to the protocol that still sit in the vaults and are not
+    // part of the tradeable reserves.
+    #[serde(skip)]
+    pub pending_a: u64,
+    #[serde(skip)]
+    pub pending_b: u64,
+    // Running total of token b swapped through the pool, raw units.
+    #[serde(skip)]
+    pub cumulative_volume_b: Option<u128>,
+    // Concentrated liquidity pools price from the Q64.64 square root price
+    // rather than the vault ratio.
+    #[serde(skip)]
+    pub sqrt_price_x64: Option<u128>,
+    // Price of token a in token b for pools that trade at an oracle's price
+    // rather than along their reserves.
+    #[serde(skip)]
+    pub oracle_price: Option<f64>,
+    // Fees earned per unit of liquidity since the pool opened, Q64.64, in
+    // tokens a and b. Concentrated liquidity pools only.
+    #[serde(skip)]
+    pub fee_growth_global: Option<(u128, u128)>,
+    // Liquidity mining rewards the pool emits to its positions.
+    pub rewards: Vec<Reward>,
+    // Checks the account failed while decoding, see `DecodeWarning`.
+    #[serde(skip)]
+    pub warnings: Vec<DecodeWarning>,
+}
+
+// A token a pool emits to liquidity providers on top of swap fees.
+#[derive(Clone, Debug, Serialize)]
+pub struct Reward {
+    #[serde(serialize_with = "pubkey_string")]
+    pub mint: Pubkey,
+    #[serde(serialize_with = "pubkey_string")]
+    pub vault: Pubkey,
+    // Raw reward tokens emitted per second across the pool's liquidity,
+    // Q64.64 as the programs store it.
+    #[serde(serialize_with = "u128_string")]
+    pub emissions_per_second_x64: u128,
+    // Filled in from the mint by the poller.
+    pub decimals: Option<u8>,
+    pub open_time: Option<u64>,
+    // When emissions stop. Raydium stores it, for Orca the poller estimates
+    // it from what is left in the reward vault.
+    pub end_time: Option<u64>,
+    pub end_estimated: bool,
+}
+
+impl Reward {
+    // Raw reward tokens emitted per second.
+    pub fn emissions_per_second(&self) -> f64 {
+        self.emissions_per_second_x64 as f64 / 2f64.powi(64)
+    }
+
+    // Whether the reward is being emitted at `timestamp`.
+    pub fn active(&self, timestamp: u64) -> bool {
+        self.emissions_per_second_x64 > 0
+            && self.open_time.is_none_or(|open| open <= timestamp)
+            && self.end_time.is_none_or(|end| timestamp < end)
+    }
+}
+
+// A sanity check a decoded pool failed. Layout warnings mean fields may have
+// been read from the wrong offsets, typically after a program upgrade, so
+// the pool is served with the warning but kept out of history and alerts.
+#[derive(Clone, Debug, Serialize)]
+pub struct DecodeWarning {
+    pub code: &'static str,
+    pub message: String,
+    pub layout: bool,
+}
+
+impl DecodeWarning {
+    pub fn layout(code: &'static str, message: String) -> Self {
+        DecodeWarning { code, message, layout: true }
+    }
+
+    pub fn value(code: &'static str, message: String) -> Self {
+        DecodeWarning { code, message, layout: false }
+    }
+}
+
+impl DecodedPool {
+    // Canonical pair label such as "SOL/USDC 0.05%".
+    pub fn label(&self) -> String {
+        let pair = format!("{}/{}", token::symbol(&self.mint_a), token::symbol(&self.mint_b));
+        match self.fee_bps {
+            Some(fee_bps) => format!("{} {}%", pair, fee_bps as f64 / 100.0),
+            None => pair,
+        }
+    }
+}
+
+// Where a DEX keeps the fields pool discovery needs, so program scans can
+// filter on the RPC node and fetch only those bytes.
+pub struct PoolLayout {
+    pub dex: &'static str,
+    pub program_id: Pubkey,
+    pub account_len: usize,
+    // Anchor account name, for programs whose accounts carry a discriminator.
+    pub discriminator: Option<&'static str>,
+    pub mint_a: usize,
+    pub mint_b: usize,
+}
+
+impl PoolLayout {
+    // Smallest (offset, length) data slice covering both mints.
+    pub fn slice(&self) -> (usize, usize) {
+        let start = self.mint_a.min(self.mint_b);
+        (start, self.mint_a.max(self.mint_b) + 32 - start)
+    }
+
+    // Decode the mints from a slice fetched with `slice()`.
+    pub fn mints(&self, sliced: &[u8]) -> Result<(Pubkey, Pubkey), String> {
+        let (start, _) = self.slice();
+        Ok((read_pubkey(sliced, self.mint_a - start)?, read_pubkey(sliced, self.mint_b - start)?))
+    }
+}
+
+// Layouts for every DEX with a decoder.
+pub const LAYOUTS: &[PoolLayout] = &[
+    raydium_amm::LAYOUT,
+    raydium_clmm::LAYOUT,
+    raydium_cpmm::LAYOUT,
+    orca_whirlpool::LAYOUT,
+    lifinity_v2::LAYOUT,
+    invariant::LAYOUT,
+];
+
+pub fn layout(dex: &str) -> Option<&'static PoolLayout> {
+    LAYOUTS.iter().find(|layout| layout.dex == dex)
+}
+
+pub fn dex_name(program: &Pubkey) -> Option<&'static str> {
+    PROGRAMS.iter().find(|(id, _)| id == program).map(|(_, name)| *name)
+}
+
+// Decode a pool account based on the program that owns it. Returns Ok(None)
+// for programs we have no decoder for.
+pub fn decode(owner: &Pubkey, data: &[u8]) -> Result<Option<DecodedPool>, String> {
+    if *owner == raydium_amm::PROGRAM_ID {
+        return raydium_amm::decode(data).map(Some);
+    }
+    if *owner == raydium_clmm::PROGRAM_ID {
+        return raydium_clmm::decode(data).map(Some);
+    }
+    if *owner == raydium_cpmm::PROGRAM_ID {
+        return raydium_cpmm::decode(data).map(Some);
+    }
+    if *owner == orca_whirlpool::PROGRAM_ID {
+        return orca_whirlpool::decode(data).map(Some);
+    }
+    if *owner == lifinity_v2::PROGRAM_ID {
+        return lifinity_v2::decode(data).map(Some);
+    }
+    if *owner == invariant::PROGRAM_ID {
+        return invariant::decode(data).map(Some);
+    }
+    Ok(None)
+}
+
+// Fill in the fee from the pool's separate fee account, see `fee_account`.
+pub fn apply_fee_account(pool: &mut DecodedPool, data: &[u8]) -> Result<(), String> {
+    match pool.dex {
+        "raydium_clmm" => pool.fee_bps = Some(raydium_clmm::decode_amm_config_fee(data)?),
+        "raydium_cpmm" => pool.fee_bps = Some(raydium_cpmm::decode_amm_config_fee(data)?),
+        _ => {}
+    }
+    Ok(())
+}
+
+// The 8 byte Anchor account discriminator, sha256("account:<name>").
+pub fn discriminator(name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("account:{}", name));
+    hash[..8].try_into().unwrap()
+}
+
+pub(crate) fn check_discriminator(data: &[u8], name: &str) -> Result<(), String> {
+    if data.get(..8) != Some(&discriminator(name)[..]) {
+        return Err(format!("Account is not a {} account", name));
+    }
+    Ok(())
+}
+
+// Size and discriminator checks of a pool account. A mismatch is reported
+// rather than refused, the fields are still read and the reads fail on
+// their own when the account is too short for them.
+pub(crate) fn check_layout(data: &[u8], layout: &PoolLayout) -> Vec<DecodeWarning> {
+    let mut warnings = Vec::new();
+    if data.len() != layout.account_len {
+        warnings.push(DecodeWarning::layout(
+            "unexpected_size",
+            format!("{} account is {} bytes, expected {}", layout.dex, data.len(), layout.account_len),
+        ));
+    }
+    if let Some(name) = layout.discriminator {
+        if let Err(e) = check_discriminator(data, name) {
+            warnings.push(DecodeWarning::layout("discriminator_mismatch", e));
+        }
+    }
+    warnings
+}
+
+#[get("/dexes")]
+async fn list_dexes(state: web::Data<AppState>) -> HttpResponse {
+    let dexes: Vec<_> = PROGRAMS
+        .iter()
+        .map(|(program, name)| {
+            // Deploys are only tracked for the programs with a decoder
+            let deploy = state.program_watch.get(name);
+            json!({
+                "dex": name,
+                "program_id": program.to_string(),
+                "decoded": LAYOUTS.iter().any(|layout| layout.program_id == *program),
+                "deploy_slot": deploy.as_ref().map(|deploy| deploy.deploy_slot),
+                "upgrade_authority": deploy.as_ref().and_then(|deploy| deploy.upgrade_authority).map(|a| a.to_string()),
+                "upgrade": deploy.and_then(|deploy| deploy.upgrade),
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(json!({ "dexes": dexes }))
+}
+
+// Raw token amount held by an SPL token account.
+pub fn token_account_amount(data: &[u8]) -> Result<u64, String> {
+    read_u64(data, 64)
+}
+
+// View the start of an account as one of the packed layouts above without
+// copying it. Packed structs have an alignment of 1, so only the size can
+// fail. Their integers are read as stored, little-endian like Solana.
+pub(crate) fn cast<T: Pod>(data: &[u8]) -> Result<&T, String> {
+    let bytes = data
+        .get(..size_of::<T>())
+        .ok_or_else(|| format!("Account data too short, {} bytes for a {} byte layout", data.len(), size_of::<T>()))?;
+    bytemuck::try_from_bytes(bytes).map_err(|e| format!("Failed to read account layout: {:?}", e))
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| format!("Account data too short to read u32 at {}", offset))
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> Result<u64, String> {
+    data.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| format!("Account data too short to read u64 at {}", offset))
+}
+
+pub(crate) fn read_pubkey(data: &[u8], offset: usize) -> Result<Pubkey, String> {
+    data.get(offset..offset + 32)
+        .map(|b| Pubkey::new_from_array(b.try_into().unwrap()))
+        .ok_or_else(|| format!("Account data too short to read pubkey at {}", offset))
+}
+
+pub fn pubkey_string<S: Serializer>(key: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&key.to_string())
+}
+
+fn u128_string<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+pub fn optional_pubkey_string<S: Serializer>(
+    key: &Option<Pubkey>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match key {
+        Some(key) => serializer.serialize_str(&key.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
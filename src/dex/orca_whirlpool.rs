@@ -0,0 +1,107 @@
+use super::{cast, check_layout, DecodedPool, PoolLayout, Reward};
+use bytemuck::{Pod, Zeroable};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::mem::{offset_of, size_of};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc");
+
+// Size of the Whirlpool account, including the Anchor discriminator.
+pub const ACCOUNT_LEN: usize = 653;
+
+// The Whirlpool account as laid out on chain, read in place.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+struct Whirlpool {
+    _discriminator: [u8; 8],
+    // whirlpools_config, whirlpool_bump, tick_spacing and tick_spacing_seed
+    _config: [u8; 37],
+    // Hundredths of a basis point.
+    fee_rate: u16,
+    _protocol_fee_rate: u16,
+    _liquidity: u128,
+    sqrt_price: u128,
+    _tick_current_index: i32,
+    protocol_fee_owed_a: u64,
+    protocol_fee_owed_b: u64,
+    token_mint_a: [u8; 32],
+    token_vault_a: [u8; 32],
+    fee_growth_global_a: u128,
+    token_mint_b: [u8; 32],
+    token_vault_b: [u8; 32],
+    fee_growth_global_b: u128,
+    _reward_last_updated_timestamp: u64,
+    reward_infos: [WhirlpoolRewardInfo; 3],
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+struct WhirlpoolRewardInfo {
+    mint: [u8; 32],
+    vault: [u8; 32],
+    _authority: [u8; 32],
+    emissions_per_second_x64: u128,
+    _growth_global_x64: u128,
+}
+
+const _: () = assert!(size_of::<Whirlpool>() == ACCOUNT_LEN);
+
+pub const LAYOUT: PoolLayout = PoolLayout {
+    dex: "orca_whirlpool",
+    program_id: PROGRAM_ID,
+    account_len: ACCOUNT_LEN,
+    discriminator: Some("Whirlpool"),
+    mint_a: offset_of!(Whirlpool, token_mint_a),
+    mint_b: offset_of!(Whirlpool, token_mint_b),
+};
+
+pub fn decode(data: &[u8]) -> Result<DecodedPool, String> {
+    let warnings = check_layout(data, &LAYOUT);
+    let pool: &Whirlpool = cast(data)?;
+
+    // Decimals aren't stored in the pool, the poller takes them from the mints
+    Ok(DecodedPool {
+        dex: "orca_whirlpool",
+        mint_a: Pubkey::new_from_array(pool.token_mint_a),
+        mint_b: Pubkey::new_from_array(pool.token_mint_b),
+        vault_a: Pubkey::new_from_array(pool.token_vault_a),
+        vault_b: Pubkey::new_from_array(pool.token_vault_b),
+        decimals_a: 0,
+        decimals_b: 0,
+        lp_mint: None,
+        lp_supply: None,
+        lp_decimals: 0,
+        fee_bps: Some(pool.fee_rate as u64 / 100),
+        fee_account: None,
+        authority: None,
+        tradeable: true,
+        status: None,
+        pending_a: pool.protocol_fee_owed_a,
+        pending_b: pool.protocol_fee_owed_b,
+        cumulative_volume_b: None,
+        sqrt_price_x64: Some(pool.sqrt_price),
+        oracle_price: None,
+        fee_growth_global: Some((pool.fee_growth_global_a, pool.fee_growth_global_b)),
+        rewards: decode_rewards(pool),
+        warnings,
+    })
+}
+
+// Initialized reward slots. Orca keeps no end time, emissions run until the
+// vault is empty.
+fn decode_rewards(pool: &Whirlpool) -> Vec<Reward> {
+    let reward_infos = pool.reward_infos;
+    reward_infos
+        .iter()
+        .filter(|info| info.mint != [0; 32])
+        .map(|info| Reward {
+            mint: Pubkey::new_from_array(info.mint),
+            vault: Pubkey::new_from_array(info.vault),
+            emissions_per_second_x64: info.emissions_per_second_x64,
+            decimals: None,
+            open_time: None,
+            end_time: None,
+            end_estimated: false,
+        })
+        .collect()
+}
@@ -0,0 +1,102 @@
+use super::{cast, check_layout, DecodedPool, PoolLayout};
+use bytemuck::{Pod, Zeroable};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::mem::{offset_of, size_of};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+
+// Size of the AmmInfo (liquidity state v4) account.
+pub const ACCOUNT_LEN: usize = 752;
+
+// The AmmInfo account as laid out on chain, read in place.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+struct AmmInfo {
+    status: u64,
+    // nonce, order_num and depth
+    _order_params: [u64; 3],
+    base_decimal: u64,
+    quote_decimal: u64,
+    // state through sys_decimal_value
+    _market_params: [u64; 10],
+    // min_separate, trade_fee and pnl numerators and denominators
+    _fees: [u64; 6],
+    swap_fee_numerator: u64,
+    swap_fee_denominator: u64,
+    base_need_take_pnl: u64,
+    quote_need_take_pnl: u64,
+    // total_pnl_pc through orderbook_to_init_time
+    _state: [u64; 6],
+    _swap_base_in_amount: u128,
+    swap_quote_out_amount: u128,
+    _swap_acc_quote_fee: u64,
+    swap_quote_in_amount: u128,
+    _swap_base_out_amount: u128,
+    _swap_acc_base_fee: u64,
+    base_vault: [u8; 32],
+    quote_vault: [u8; 32],
+    base_mint: [u8; 32],
+    quote_mint: [u8; 32],
+    lp_mint: [u8; 32],
+    // open_orders, market, market_program, target_orders, withdraw_queue
+    // and lp_vault
+    _market_accounts: [[u8; 32]; 6],
+    amm_owner: [u8; 32],
+    lp_reserve: u64,
+    // client_order_id, recent_epoch and padding
+    _tail: [u64; 3],
+}
+
+const _: () = assert!(size_of::<AmmInfo>() == ACCOUNT_LEN);
+
+// AmmStatus values that let swaps through: Initialized, SwapOnly and
+// WaitingTrade, which opens at the pool's open time.
+const SWAP_STATUSES: &[u64] = &[1, 6, 7];
+
+pub const LAYOUT: PoolLayout = PoolLayout {
+    dex: "raydium_amm",
+    program_id: PROGRAM_ID,
+    account_len: ACCOUNT_LEN,
+    discriminator: None,
+    mint_a: offset_of!(AmmInfo, base_mint),
+    mint_b: offset_of!(AmmInfo, quote_mint),
+};
+
+pub fn decode(data: &[u8]) -> Result<DecodedPool, String> {
+    let warnings = check_layout(data, &LAYOUT);
+    let pool: &AmmInfo = cast(data)?;
+    let status = pool.status;
+
+    Ok(DecodedPool {
+        dex: "raydium_amm",
+        mint_a: Pubkey::new_from_array(pool.base_mint),
+        mint_b: Pubkey::new_from_array(pool.quote_mint),
+        vault_a: Pubkey::new_from_array(pool.base_vault),
+        vault_b: Pubkey::new_from_array(pool.quote_vault),
+        decimals_a: pool.base_decimal as u8,
+        decimals_b: pool.quote_decimal as u8,
+        lp_mint: Some(Pubkey::new_from_array(pool.lp_mint)),
+        lp_supply: Some(pool.lp_reserve),
+        // The LP mint takes the decimals of the base token
+        lp_decimals: pool.base_decimal as u8,
+        fee_bps: pool
+            .swap_fee_numerator
+            .checked_mul(10_000)
+            .and_then(|n| n.checked_div(pool.swap_fee_denominator)),
+        fee_account: None,
+        authority: Some(Pubkey::new_from_array(pool.amm_owner)),
+        tradeable: SWAP_STATUSES.contains(&status),
+        status: Some(status),
+        pending_a: pool.base_need_take_pnl,
+        pending_b: pool.quote_need_take_pnl,
+        cumulative_volume_b: Some(pool.swap_quote_out_amount.saturating_add(pool.swap_quote_in_amount)),
+        sqrt_price_x64: None,
+        oracle_price: None,
+        fee_growth_global: None,
+        // Farms are separate accounts of the farm programs, the pool doesn't
+        // point to them
+        rewards: Vec::new(),
+        warnings,
+    })
+}
@@ -0,0 +1,147 @@
+use super::{cast, check_discriminator, check_layout, read_u32, DecodedPool, PoolLayout, Reward};
+use bytemuck::{Pod, Zeroable};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::mem::{offset_of, size_of};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK");
+
+// Size of the PoolState account, including the Anchor discriminator.
+pub const ACCOUNT_LEN: usize = 1544;
+
+// The PoolState account as laid out on chain, read in place.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+struct PoolState {
+    _discriminator: [u8; 8],
+    _bump: u8,
+    amm_config: [u8; 32],
+    _owner: [u8; 32],
+    token_mint_0: [u8; 32],
+    token_mint_1: [u8; 32],
+    token_vault_0: [u8; 32],
+    token_vault_1: [u8; 32],
+    _observation_key: [u8; 32],
+    mint_decimals_0: u8,
+    mint_decimals_1: u8,
+    _tick_spacing: u16,
+    _liquidity: u128,
+    sqrt_price_x64: u128,
+    _tick_current: i32,
+    _padding: [u16; 2],
+    fee_growth_global_0_x64: u128,
+    fee_growth_global_1_x64: u128,
+    protocol_fees_token_0: u64,
+    protocol_fees_token_1: u64,
+    _swap_in_amount_token_0: u128,
+    swap_out_amount_token_1: u128,
+    swap_in_amount_token_1: u128,
+    _swap_out_amount_token_0: u128,
+    status: u8,
+    _status_padding: [u8; 7],
+    reward_infos: [RewardInfo; 3],
+    _tick_array_bitmap: [u64; 16],
+    // total fees and fees claimed of each token
+    _total_fees: [u64; 4],
+    fund_fees_token_0: u64,
+    fund_fees_token_1: u64,
+    _open_time: u64,
+    _recent_epoch: u64,
+    _reserved: [u64; 56],
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RewardInfo {
+    reward_state: u8,
+    open_time: u64,
+    end_time: u64,
+    _last_update_time: u64,
+    emissions_per_second_x64: u128,
+    _reward_total_emissioned: u64,
+    _reward_claimed: u64,
+    token_mint: [u8; 32],
+    token_vault: [u8; 32],
+    _authority: [u8; 32],
+    _reward_growth_global_x64: u128,
+}
+
+const _: () = assert!(size_of::<PoolState>() == ACCOUNT_LEN);
+
+// Status bit set when the admin has disabled swaps, the lower bits cover
+// liquidity and fee operations.
+const STATUS_SWAP_DISABLED: u8 = 1 << 4;
+
+// Reward state of a slot that was never set up.
+const REWARD_UNINITIALIZED: u8 = 0;
+
+// AmmConfig, shared by every pool in a fee tier.
+const AMM_CONFIG_TRADE_FEE_RATE: usize = 47;
+
+pub const LAYOUT: PoolLayout = PoolLayout {
+    dex: "raydium_clmm",
+    program_id: PROGRAM_ID,
+    account_len: ACCOUNT_LEN,
+    discriminator: Some("PoolState"),
+    mint_a: offset_of!(PoolState, token_mint_0),
+    mint_b: offset_of!(PoolState, token_mint_1),
+};
+
+pub fn decode(data: &[u8]) -> Result<DecodedPool, String> {
+    let warnings = check_layout(data, &LAYOUT);
+    let pool: &PoolState = cast(data)?;
+    let status = pool.status;
+
+    Ok(DecodedPool {
+        dex: "raydium_clmm",
+        mint_a: Pubkey::new_from_array(pool.token_mint_0),
+        mint_b: Pubkey::new_from_array(pool.token_mint_1),
+        vault_a: Pubkey::new_from_array(pool.token_vault_0),
+        vault_b: Pubkey::new_from_array(pool.token_vault_1),
+        decimals_a: pool.mint_decimals_0,
+        decimals_b: pool.mint_decimals_1,
+        lp_mint: None,
+        lp_supply: None,
+        lp_decimals: 0,
+        // Filled in from the AmmConfig account
+        fee_bps: None,
+        fee_account: Some(Pubkey::new_from_array(pool.amm_config)),
+        authority: None,
+        tradeable: status & STATUS_SWAP_DISABLED == 0,
+        status: Some(status as u64),
+        pending_a: pool.protocol_fees_token_0.saturating_add(pool.fund_fees_token_0),
+        pending_b: pool.protocol_fees_token_1.saturating_add(pool.fund_fees_token_1),
+        cumulative_volume_b: Some(pool.swap_out_amount_token_1.saturating_add(pool.swap_in_amount_token_1)),
+        sqrt_price_x64: Some(pool.sqrt_price_x64),
+        oracle_price: None,
+        fee_growth_global: Some((pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)),
+        rewards: decode_rewards(pool),
+        warnings,
+    })
+}
+
+// Reward slots that were set up, including ended ones, whose end time is in
+// the past.
+fn decode_rewards(pool: &PoolState) -> Vec<Reward> {
+    let reward_infos = pool.reward_infos;
+    reward_infos
+        .iter()
+        .filter(|info| info.reward_state != REWARD_UNINITIALIZED)
+        .map(|info| Reward {
+            mint: Pubkey::new_from_array(info.token_mint),
+            vault: Pubkey::new_from_array(info.token_vault),
+            emissions_per_second_x64: info.emissions_per_second_x64,
+            decimals: None,
+            open_time: Some(info.open_time),
+            end_time: Some(info.end_time),
+            end_estimated: false,
+        })
+        .collect()
+}
+
+// Trade fee in basis points from an AmmConfig account. The rate is stored in
+// millionths.
+pub fn decode_amm_config_fee(data: &[u8]) -> Result<u64, String> {
+    check_discriminator(data, "AmmConfig")?;
+    Ok(read_u32(data, AMM_CONFIG_TRADE_FEE_RATE)? as u64 / 100)
+}
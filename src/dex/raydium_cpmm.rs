@@ -0,0 +1,112 @@
+use super::{cast, check_discriminator, check_layout, read_u64, DecodedPool, PoolLayout};
+use bytemuck::{Pod, Zeroable};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::mem::{offset_of, size_of};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C");
+
+// Size of the PoolState account, including the Anchor discriminator.
+pub const ACCOUNT_LEN: usize = 637;
+
+// The PoolState account as laid out on chain, read in place. Unlike AMM v4
+// the pool needs no OpenBook market and either token may be a Token-2022
+// mint.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+struct PoolState {
+    _discriminator: [u8; 8],
+    amm_config: [u8; 32],
+    _pool_creator: [u8; 32],
+    token_0_vault: [u8; 32],
+    token_1_vault: [u8; 32],
+    lp_mint: [u8; 32],
+    token_0_mint: [u8; 32],
+    token_1_mint: [u8; 32],
+    // token_0_program, token_1_program and observation_key
+    _accounts: [[u8; 32]; 3],
+    _auth_bump: u8,
+    status: u8,
+    lp_mint_decimals: u8,
+    mint_0_decimals: u8,
+    mint_1_decimals: u8,
+    lp_supply: u64,
+    protocol_fees_token_0: u64,
+    protocol_fees_token_1: u64,
+    fund_fees_token_0: u64,
+    fund_fees_token_1: u64,
+    _open_time: u64,
+    _recent_epoch: u64,
+    // creator_fee_on, enable_creator_fee and padding, zero on pools created
+    // before creator fees
+    _creator_fee_flags: [u8; 8],
+    creator_fees_token_0: u64,
+    creator_fees_token_1: u64,
+    _padding: [u64; 28],
+}
+
+const _: () = assert!(size_of::<PoolState>() == ACCOUNT_LEN);
+
+// Status bit set when the admin has disabled swaps, the lower two cover
+// deposits and withdrawals.
+const STATUS_SWAP_DISABLED: u8 = 1 << 2;
+
+// AmmConfig, shared by every pool in a fee tier.
+const AMM_CONFIG_TRADE_FEE_RATE: usize = 12;
+
+pub const LAYOUT: PoolLayout = PoolLayout {
+    dex: "raydium_cpmm",
+    program_id: PROGRAM_ID,
+    account_len: ACCOUNT_LEN,
+    discriminator: Some("PoolState"),
+    mint_a: offset_of!(PoolState, token_0_mint),
+    mint_b: offset_of!(PoolState, token_1_mint),
+};
+
+pub fn decode(data: &[u8]) -> Result<DecodedPool, String> {
+    let warnings = check_layout(data, &LAYOUT);
+    let pool: &PoolState = cast(data)?;
+    let status = pool.status;
+
+    Ok(DecodedPool {
+        dex: "raydium_cpmm",
+        mint_a: Pubkey::new_from_array(pool.token_0_mint),
+        mint_b: Pubkey::new_from_array(pool.token_1_mint),
+        vault_a: Pubkey::new_from_array(pool.token_0_vault),
+        vault_b: Pubkey::new_from_array(pool.token_1_vault),
+        decimals_a: pool.mint_0_decimals,
+        decimals_b: pool.mint_1_decimals,
+        lp_mint: Some(Pubkey::new_from_array(pool.lp_mint)),
+        lp_supply: Some(pool.lp_supply),
+        lp_decimals: pool.lp_mint_decimals,
+        // Filled in from the AmmConfig account
+        fee_bps: None,
+        fee_account: Some(Pubkey::new_from_array(pool.amm_config)),
+        // Pools are administered by the program-wide admin, not per pool
+        authority: None,
+        tradeable: status & STATUS_SWAP_DISABLED == 0,
+        status: Some(status as u64),
+        pending_a: pool
+            .protocol_fees_token_0
+            .saturating_add(pool.fund_fees_token_0)
+            .saturating_add(pool.creator_fees_token_0),
+        pending_b: pool
+            .protocol_fees_token_1
+            .saturating_add(pool.fund_fees_token_1)
+            .saturating_add(pool.creator_fees_token_1),
+        // The pool keeps no swap totals
+        cumulative_volume_b: None,
+        sqrt_price_x64: None,
+        oracle_price: None,
+        fee_growth_global: None,
+        rewards: Vec::new(),
+        warnings,
+    })
+}
+
+// Trade fee in basis points from an AmmConfig account. The rate is stored in
+// millionths.
+pub fn decode_amm_config_fee(data: &[u8]) -> Result<u64, String> {
+    check_discriminator(data, "AmmConfig")?;
+    Ok(read_u64(data, AMM_CONFIG_TRADE_FEE_RATE)? / 100)
+}
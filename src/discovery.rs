@@ -0,0 +1,188 @@
+use crate::blocklist;
+use crate::cluster::Cluster;
+use crate::dex::{self, PoolLayout};
+use crate::state::AppState;
+use crate::token;
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::client_error::ClientError;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+#[derive(Deserialize)]
+struct DiscoverQuery {
+    mint: String,
+    // Only pools pairing `mint` with this one.
+    quote: Option<String>,
+    dex: Option<String>,
+    // Hide pools with a token on none of the token lists.
+    #[serde(default)]
+    verified: bool,
+}
+
+// getProgramAccounts/programSubscribe config for pools of `layout` with each
+// (offset, mint) pair matched, fetching only the bytes holding the mints.
+pub fn scan_config(layout: &PoolLayout, mints: &[(usize, &Pubkey)], commitment: CommitmentConfig) -> RpcProgramAccountsConfig {
+    let mut filters = vec![RpcFilterType::DataSize(layout.account_len as u64)];
+    for (offset, mint) in mints {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(*offset, mint.as_ref())));
+    }
+    if let Some(name) = layout.discriminator {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, &dex::discriminator(name))));
+    }
+    let (offset, length) = layout.slice();
+    RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            data_slice: Some(UiDataSliceConfig { offset, length }),
+            commitment: Some(commitment),
+            min_context_slot: None,
+        },
+        ..RpcProgramAccountsConfig::default()
+    }
+}
+
+fn pool_json(state: &AppState, pool: Pubkey, dex: &str, mint_a: &Pubkey, mint_b: &Pubkey) -> Value {
+    let involved = [("pool", pool), ("mint_a", *mint_a), ("mint_b", *mint_b)];
+    json!({
+        "pool_id": pool.to_string(),
+        "dex": dex,
+        "mint_a": mint_a.to_string(),
+        "mint_b": mint_b.to_string(),
+        "pair": format!("{}/{}", token::symbol(mint_a), token::symbol(mint_b)),
+        "blocklisted": blocklist::flags(state, &involved),
+    })
+}
+
+// Pools holding a mint, from the pool index when it is enabled and synced
+// and the default cluster is asked for, otherwise by scanning every
+// supported DEX. The node filters by size and
+// mint and returns only the bytes around the mints, a full Raydium scan would
+// otherwise pull gigabytes. With `verified=true` only pools of two listed
+// tokens are kept.
+#[get("/pools/discover")]
+async fn discover_pools(state: web::Data<AppState>, cluster: Cluster, query: web::Query<DiscoverQuery>) -> HttpResponse {
+    let mint = match Pubkey::from_str(&query.mint) {
+        Ok(key) => key,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid mint address: {}", e)
+            }));
+        }
+    };
+    let quote = match query.quote.as_deref().map(Pubkey::from_str) {
+        None => None,
+        Some(Ok(key)) => Some(key),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid quote mint: {}", e)
+            }));
+        }
+    };
+    let layouts: Vec<&PoolLayout> = match query.dex.as_deref() {
+        None => dex::LAYOUTS.iter().collect(),
+        Some(name) => match dex::layout(name) {
+            Some(layout) => vec![layout],
+            None => {
+                return HttpResponse::BadRequest().json(json!({
+                    "error": format!("No decoder for DEX {}, see /dexes", name)
+                }));
+            }
+        },
+    };
+
+    if query.verified && !state.token_lists.is_loaded() {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": "Token lists are not loaded yet"
+        }));
+    }
+    let verified_only = query.verified;
+    let listed = |mint_a: &Pubkey, mint_b: &Pubkey| {
+        !verified_only || (state.token_lists.is_listed(mint_a) && state.token_lists.is_listed(mint_b))
+    };
+
+    if cluster.is_default() && !cluster.is_overridden() && state.pool_index.is_ready() {
+        let pools: Vec<Value> = state
+            .pool_index
+            .find(&mint, quote.as_ref())
+            .into_iter()
+            .filter(|pool| layouts.iter().any(|layout| layout.dex == pool.dex))
+            .filter(|pool| listed(&pool.mint_a, &pool.mint_b))
+            .map(|pool| pool_json(&state, pool.pool, pool.dex, &pool.mint_a, &pool.mint_b))
+            .collect();
+        return HttpResponse::Ok().json(json!({
+            "mint": mint.to_string(),
+            "quote": quote.map(|q| q.to_string()),
+            "cluster": cluster.name,
+            "source": "index",
+            "pool_count": pools.len(),
+            "pools": pools,
+        }));
+    }
+
+    // Each DEX is scanned twice, with the mint as token a and as token b
+    let mut scans = Vec::new();
+    for layout in layouts {
+        let mut as_a = vec![(layout.mint_a, &mint)];
+        let mut as_b = vec![(layout.mint_b, &mint)];
+        if let Some(quote) = &quote {
+            as_a.push((layout.mint_b, quote));
+            as_b.push((layout.mint_a, quote));
+        }
+        let commitment = CommitmentConfig::confirmed();
+        scans.push((layout, scan_config(layout, &as_a, commitment)));
+        scans.push((layout, scan_config(layout, &as_b, commitment)));
+    }
+
+    let rpc_client = state.cluster_rpc_client(&cluster).await;
+    let result = tokio::task::spawn_blocking(move || {
+        let mut found = Vec::new();
+        for (layout, config) in scans {
+            let accounts = rpc_client.get_program_accounts_with_config(&layout.program_id, config)?;
+            found.extend(accounts.into_iter().map(|(pool, account)| (layout, pool, account.data)));
+        }
+        Ok::<_, ClientError>(found)
+    })
+    .await;
+
+    let found = match result {
+        Ok(Ok(found)) => found,
+        Ok(Err(e)) => {
+            eprintln!("RPC error scanning for pools: {}", e);
+            return HttpResponse::BadGateway().json(json!({
+                "error": format!("Failed to scan programs: {}", e)
+            }));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "error": format!("Task failed: {}", e)
+            }));
+        }
+    };
+
+    let pools: Vec<Value> = found
+        .into_iter()
+        .filter_map(|(layout, pool, data)| {
+            let (mint_a, mint_b) = layout
+                .mints(&data)
+                .map_err(|e| eprintln!("Failed to read mints of {}: {}", pool, e))
+                .ok()?;
+            listed(&mint_a, &mint_b).then(|| pool_json(&state, pool, layout.dex, &mint_a, &mint_b))
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "mint": mint.to_string(),
+        "quote": quote.map(|q| q.to_string()),
+        "cluster": cluster.name,
+        "source": "scan",
+        "pool_count": pools.len(),
+        "pools": pools,
+    }))
+}
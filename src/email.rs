@@ -0,0 +1,70 @@
+use crate::alerts::Alert;
+use crate::config::{EmailChannel, SmtpTls};
+use crate::time::format_utc;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+// Send an alert to the rule's recipients, or the channel's own when the rule
+// lists none. A new connection per alert, alerts are rare enough.
+pub async fn send(channel: &EmailChannel, alert: &Alert) -> Result<(), String> {
+    let recipients = if alert.recipients.is_empty() {
+        &channel.to
+    } else {
+        &alert.recipients
+    };
+    if recipients.is_empty() {
+        return Err("no recipients".to_string());
+    }
+
+    let mut message = Message::builder()
+        .from(parse_mailbox(&channel.from)?)
+        .subject(render(&channel.subject, alert).replace(['\r', '\n'], " "));
+    for recipient in recipients {
+        message = message.to(parse_mailbox(recipient)?);
+    }
+    let message = message
+        .body(render(&channel.body, alert))
+        .map_err(|e| format!("failed to build email: {}", e))?;
+
+    let builder = match channel.tls {
+        SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&channel.host),
+        SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&channel.host),
+        SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&channel.host)),
+    };
+    let mut builder = builder.map_err(|e| format!("invalid SMTP host {}: {}", channel.host, e))?;
+    if let Some(port) = channel.port {
+        builder = builder.port(port);
+    }
+    if let (Some(username), Some(password)) = (&channel.username, &channel.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    builder
+        .build()
+        .send(message)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("SMTP delivery failed: {}", e))
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, String> {
+    address
+        .parse()
+        .map_err(|e| format!("invalid address {}: {}", address, e))
+}
+
+// Fill the {placeholders} of a subject or body template.
+fn render(template: &str, alert: &Alert) -> String {
+    template
+        .replace("{severity}", alert.severity.as_str())
+        .replace("{kind}", alert.kind)
+        .replace("{pool}", &alert.pool)
+        .replace("{message}", &alert.message)
+        .replace("{key}", &alert.key)
+        .replace("{id}", &alert.id.map(|id| id.to_string()).unwrap_or_default())
+        .replace("{time}", &format_utc(alert.timestamp))
+        .replace(
+            "{details}",
+            &serde_json::to_string_pretty(&alert.details).unwrap_or_default(),
+        )
+}
@@ -0,0 +1,105 @@
+use crate::amount::{self, Amount};
+use crate::history::{Decimals, PoolSnapshot};
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Snapshot,
+    Trade,
+    Liquidity,
+}
+
+impl EventKind {
+    // Public names used by the streaming endpoints.
+    pub fn channel(self) -> &'static str {
+        match self {
+            EventKind::Snapshot => "price",
+            EventKind::Trade => "trades",
+            EventKind::Liquidity => "liquidity",
+        }
+    }
+
+    pub fn from_channel(name: &str) -> Option<EventKind> {
+        match name {
+            "price" | "snapshot" => Some(EventKind::Snapshot),
+            "trades" | "trade" => Some(EventKind::Trade),
+            "liquidity" => Some(EventKind::Liquidity),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoolEvent {
+    pub kind: EventKind,
+    pub pool: String,
+    pub slot: u64,
+    pub timestamp: u64,
+    pub data: Value,
+}
+
+// Derive events from two consecutive snapshots of a pool and publish them.
+pub fn publish_snapshot(
+    state: &AppState,
+    pool: Pubkey,
+    decimals: Decimals,
+    previous: Option<&PoolSnapshot>,
+    current: &PoolSnapshot,
+) {
+    state.ticker.record(pool, previous, current);
+    for event in derive(pool, decimals, previous, current) {
+        // No receivers is fine, nobody is listening yet
+        let _ = state.events.send(event);
+    }
+}
+
+// Events between two consecutive snapshots of a pool. Trades are aggregated
+// over the poll interval, so one event may cover several swaps.
+pub fn derive(pool: Pubkey, decimals: Decimals, previous: Option<&PoolSnapshot>, current: &PoolSnapshot) -> Vec<PoolEvent> {
+    let mut events = vec![event(EventKind::Snapshot, pool, current, current.json(decimals))];
+
+    if let Some(previous) = previous {
+        let volume = match (previous.cumulative_volume_b, current.cumulative_volume_b) {
+            (Some(before), Some(after)) if after > before => Some(after - before),
+            _ => None,
+        };
+        if let Some(volume) = volume {
+            events.push(event(EventKind::Trade, pool, current, json!({
+                // Raw, as a string like the volumes of candles
+                "volume_b": volume.to_string(),
+                "price_before": previous.price,
+                "price_after": current.price,
+                "reserve_a_delta": amount::change(previous.reserve_a as u128, current.reserve_a as u128, decimals.a),
+                "reserve_b_delta": amount::change(previous.reserve_b as u128, current.reserve_b as u128, decimals.b),
+            })));
+        }
+
+        if let (Some(before), Some(after)) = (previous.lp_supply, current.lp_supply) {
+            if before != after {
+                events.push(event(EventKind::Liquidity, pool, current, json!({
+                    "action": if after > before { "add" } else { "remove" },
+                    "lp_supply_before": Amount::new(before as u128, decimals.lp),
+                    "lp_supply_after": Amount::new(after as u128, decimals.lp),
+                    "reserve_a": Amount::new(current.reserve_a as u128, decimals.a),
+                    "reserve_b": Amount::new(current.reserve_b as u128, decimals.b),
+                })));
+            }
+        }
+    }
+
+    events
+}
+
+fn event(kind: EventKind, pool: Pubkey, snapshot: &PoolSnapshot, data: Value) -> PoolEvent {
+    PoolEvent {
+        kind,
+        pool: pool.to_string(),
+        slot: snapshot.slot,
+        timestamp: snapshot.timestamp,
+        data,
+    }
+}
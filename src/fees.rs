@@ -0,0 +1,226 @@
+use crate::amount::Amount;
+use crate::dex::DecodedPool;
+use crate::fixed::{self, U256, Q64};
+use crate::history::PoolSnapshot;
+use crate::time::parse_duration;
+use crate::portfolio::{self, Position};
+use crate::state::{unix_now, AppState};
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+
+const DEFAULT_PERIOD_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_COMPOUND_SECS: u64 = 24 * 60 * 60;
+const MAX_PERIODS: u64 = 1000;
+const YEAR_SECS: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+#[derive(Deserialize)]
+struct FeesQuery {
+    // Length of the periods fees are broken down into, 1d when omitted.
+    period: Option<String>,
+    // How often fees are assumed to be reinvested for the APY, 1d when
+    // omitted, "none" for no reinvesting.
+    compound: Option<String>,
+}
+
+// Fees earned during one period, in each token and in USD. The USD value is
+// null when a token had no USD price at some point of the period. Token
+// fees are summed in fractions of raw units and rounded for output.
+struct PeriodFees {
+    start: u64,
+    fees_a: f64,
+    fees_b: f64,
+    fees_usd: Option<f64>,
+}
+
+impl PeriodFees {
+    fn json(&self, pool: &DecodedPool) -> Value {
+        json!({
+            "start": self.start,
+            "fees_a": Amount::from_raw_f64(self.fees_a, pool.decimals_a),
+            "fees_b": Amount::from_raw_f64(self.fees_b, pool.decimals_b),
+            "fees_usd": self.fees_usd,
+        })
+    }
+}
+
+// Fees a position earned between two consecutive snapshots of its pool, in
+// raw units of tokens a and b.
+fn earned(position: &Position, previous: &PoolSnapshot, current: &PoolSnapshot) -> (f64, f64) {
+    match position.range {
+        // Fee growth inside the range matches the pool-wide growth while the
+        // price stays in it. Accumulators wrap by design.
+        Some(range) => {
+            let (Some((before_a, before_b)), Some((after_a, after_b))) = (previous.fee_growth, current.fee_growth) else {
+                return (0.0, 0.0);
+            };
+            if !range.contains(previous.price) {
+                return (0.0, 0.0);
+            }
+            // Fee growth accumulators are Q64.64
+            let fees = |growth: u128| fixed::ratio(U256::mul(range.liquidity, growth), Q64);
+            (fees(after_a.wrapping_sub(before_a)), fees(after_b.wrapping_sub(before_b)))
+        }
+        // Fees of a constant-product pool grow the product of the reserves
+        // behind each LP token, see `portfolio::value`.
+        None => {
+            let (Some(supply_before), Some(supply_after)) = (previous.lp_supply, current.lp_supply) else {
+                return (0.0, 0.0);
+            };
+            if supply_before == 0 || supply_after == 0 {
+                return (0.0, 0.0);
+            }
+            let invariant = |snapshot: &PoolSnapshot, supply: u64| {
+                (snapshot.reserve_a as f64).sqrt() * (snapshot.reserve_b as f64).sqrt() / supply as f64
+            };
+            let growth = invariant(current, supply_after) / invariant(previous, supply_before);
+            if !growth.is_finite() || growth <= 1.0 {
+                return (0.0, 0.0);
+            }
+            let fees = position.lp_amount as f64 / supply_after as f64 * (1.0 - 1.0 / growth);
+            (fees * current.reserve_a as f64, fees * current.reserve_b as f64)
+        }
+    }
+}
+
+// Tokens a and b the position holds at a snapshot.
+fn holdings(position: &Position, pool: &DecodedPool, snapshot: &PoolSnapshot) -> Option<(f64, f64)> {
+    let (scale_a, scale_b) = (10f64.powi(pool.decimals_a as i32), 10f64.powi(pool.decimals_b as i32));
+    match position.range {
+        Some(range) => {
+            // Square roots of raw prices, the price clamped to the range
+            // since outside it the position is all one token
+            let sqrt = |price: f64| (price * scale_b / scale_a).sqrt();
+            let (lower, upper) = (sqrt(range.lower_price), sqrt(range.upper_price));
+            let current = sqrt(snapshot.price).clamp(lower, upper);
+            let liquidity = range.liquidity as f64;
+            Some((liquidity * (1.0 / current - 1.0 / upper) / scale_a, liquidity * (current - lower) / scale_b))
+        }
+        None => {
+            let supply = snapshot.lp_supply.filter(|supply| *supply > 0)? as u128;
+            let held = |reserve: u64, decimals: u8| {
+                fixed::mul_div(position.lp_amount as u128, reserve as u128, supply).map(|raw| fixed::to_tokens(raw, decimals))
+            };
+            Some((held(snapshot.reserve_a, pool.decimals_a)?, held(snapshot.reserve_b, pool.decimals_b)?))
+        }
+    }
+}
+
+// Estimated fees of a position by period since it was opened, or since the
+// oldest recorded snapshot after that, with the APR they make against its
+// current value and the APY if they were reinvested every `compound`.
+// Range positions are estimated from pool-wide fee growth while the price
+// was in range, so fees already collected are counted too.
+#[get("/position/{id}/fees")]
+async fn get_position_fees(state: web::Data<AppState>, id: web::Path<String>, query: web::Query<FeesQuery>) -> HttpResponse {
+    let Some((portfolio, position)) = state.portfolios.position(&id) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("Position {} not found", id)
+        }));
+    };
+    if position.range.is_some_and(|range| range.liquidity == 0) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("Position {} has no liquidity set, its fees can't be estimated", id)
+        }));
+    }
+    let retention = state.config.read().unwrap().history_retention_secs;
+    let period = match query.period.as_deref().map_or(Ok(DEFAULT_PERIOD_SECS), |period| parse_duration(period, retention)) {
+        Ok(period) => period,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    let compound = match query.compound.as_deref() {
+        None => Some(DEFAULT_COMPOUND_SECS),
+        Some("none") => None,
+        Some(compound) => match parse_duration(compound, YEAR_SECS as u64) {
+            Ok(compound) => Some(compound),
+            Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+        },
+    };
+    let Ok(pool) = position.pool.parse::<Pubkey>() else {
+        return HttpResponse::InternalServerError().json(json!({
+            "error": format!("Invalid pool {} in position {}", position.pool, id)
+        }));
+    };
+    let Some(decoded) = state.cache.get(&pool).and_then(|cached| cached.pool.clone()) else {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": format!("Pool {} has not been polled yet", pool)
+        }));
+    };
+
+    let snapshots = state.history.range(&pool, position.opened_at, unix_now());
+    let (from, to) = match (snapshots.first(), snapshots.last()) {
+        (Some(first), Some(last)) => (first.timestamp, last.timestamp),
+        _ => (position.opened_at, position.opened_at),
+    };
+    if (to - from) / period >= MAX_PERIODS {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("period is too short for the history covered, at most {} periods", MAX_PERIODS)
+        }));
+    }
+
+    // Fees are in fractions of raw units
+    let (unit_a, unit_b) = (fixed::to_tokens(1, decoded.decimals_a), fixed::to_tokens(1, decoded.decimals_b));
+    let mut periods: Vec<PeriodFees> = Vec::new();
+    for pair in snapshots.windows(2) {
+        let (previous, current) = (&pair[0], &pair[1]);
+        let (fees_a, fees_b) = earned(&position, previous, current);
+        let fees_usd = portfolio::usd_prices(&state, &decoded, current)
+            .map(|(usd_a, usd_b)| fees_a * unit_a * usd_a + fees_b * unit_b * usd_b);
+        let start = current.timestamp / period * period;
+        match periods.last_mut() {
+            Some(last) if last.start == start => {
+                last.fees_a += fees_a;
+                last.fees_b += fees_b;
+                last.fees_usd = last.fees_usd.zip(fees_usd).map(|(sum, fees)| sum + fees);
+            }
+            _ => periods.push(PeriodFees {
+                start,
+                fees_a,
+                fees_b,
+                fees_usd,
+            }),
+        }
+    }
+
+    let fees_a: f64 = periods.iter().map(|p| p.fees_a).sum();
+    let fees_b: f64 = periods.iter().map(|p| p.fees_b).sum();
+    let fees_usd: Option<f64> = periods.iter().map(|p| p.fees_usd).sum();
+    let value_usd = snapshots.last().and_then(|snapshot| {
+        let (amount_a, amount_b) = holdings(&position, &decoded, snapshot)?;
+        let (usd_a, usd_b) = portfolio::usd_prices(&state, &decoded, snapshot)?;
+        Some(amount_a * usd_a + amount_b * usd_b)
+    });
+    let apr = match (fees_usd, value_usd) {
+        (Some(fees), Some(value)) if value > 0.0 && to > from => Some(fees / value * YEAR_SECS / (to - from) as f64),
+        _ => None,
+    };
+    // Null rather than infinite for returns too large to compound
+    let apy = apr
+        .map(|apr| match compound {
+            Some(compound) => {
+                let times = YEAR_SECS / compound as f64;
+                (1.0 + apr / times).powf(times) - 1.0
+            }
+            None => apr,
+        })
+        .filter(|apy| apy.is_finite());
+
+    HttpResponse::Ok().json(json!({
+        "position_id": position.id,
+        "portfolio_id": portfolio.id,
+        "pool": position.pool,
+        "label": decoded.label(),
+        "from": from,
+        "to": to,
+        "period_secs": period,
+        "fees_a": Amount::from_raw_f64(fees_a, decoded.decimals_a),
+        "fees_b": Amount::from_raw_f64(fees_b, decoded.decimals_b),
+        "fees_usd": fees_usd,
+        "value_usd": value_usd,
+        "apr_pct": apr.map(|apr| apr * 100.0),
+        "apy_pct": apy.map(|apy| apy * 100.0),
+        "compound_secs": compound,
+        "periods": periods.iter().map(|period| period.json(&decoded)).collect::<Vec<_>>(),
+    }))
+}
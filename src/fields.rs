@@ -0,0 +1,116 @@
+use actix_web::body::{self, EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::future::{ready, Ready};
+
+#[derive(Deserialize)]
+struct FieldsQuery {
+    fields: Option<String>,
+}
+
+// Fields a client asked for with `?fields=price,reserve_a,pool.authority`,
+// None when it wants everything. Dotted paths reach into nested objects and
+// apply to every element of an array on the way.
+pub struct Fields(Option<Vec<String>>);
+
+impl Fields {
+    fn parse(query: &str) -> Fields {
+        let fields = web::Query::<FieldsQuery>::from_query(query)
+            .ok()
+            .and_then(|query| query.into_inner().fields)
+            .map(|fields| {
+                fields
+                    .split(',')
+                    .map(|field| field.trim().to_string())
+                    .filter(|field| !field.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|fields| !fields.is_empty());
+        Fields(fields)
+    }
+
+    // Whether the top-level `field` ends up in the response, for handlers to
+    // skip work nobody will see.
+    pub fn wants(&self, field: &str) -> bool {
+        match &self.0 {
+            None => true,
+            Some(fields) => fields
+                .iter()
+                .any(|f| f == field || f.strip_prefix(field).is_some_and(|rest| rest.starts_with('.'))),
+        }
+    }
+}
+
+impl FromRequest for Fields {
+    type Error = Error;
+    type Future = Ready<Result<Fields, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Fields::parse(req.query_string())))
+    }
+}
+
+// Keep only the `paths` of `value`. Paths reaching past a scalar keep it
+// whole.
+fn select(value: Value, paths: &[Vec<&str>]) -> Value {
+    if paths.iter().any(|path| path.is_empty()) {
+        return value;
+    }
+    match value {
+        Value::Array(items) => Value::Array(items.into_iter().map(|item| select(item, paths)).collect()),
+        Value::Object(fields) => {
+            let mut selected = Map::new();
+            for (key, field) in fields {
+                let rest: Vec<Vec<&str>> = paths
+                    .iter()
+                    .filter(|path| path[0] == key)
+                    .map(|path| path[1..].to_vec())
+                    .collect();
+                if !rest.is_empty() {
+                    selected.insert(key, select(field, &rest));
+                }
+            }
+            Value::Object(selected)
+        }
+        scalar => scalar,
+    }
+}
+
+// Strip successful JSON responses down to the requested fields. Errors are
+// left whole so their message isn't lost.
+pub async fn filter(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Fields(Some(fields)) = Fields::parse(req.query_string()) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let response = next.call(req).await?;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if !response.status().is_success() || !is_json {
+        return Ok(response.map_into_left_body());
+    }
+
+    let (req, response) = response.into_parts();
+    let (head, payload) = response.into_parts();
+    let bytes = body::to_bytes(payload)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into().to_string()))?;
+    let selected = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(value) => {
+            let paths: Vec<Vec<&str>> = fields.iter().map(|field| field.split('.').collect()).collect();
+            select(value, &paths).to_string().into_bytes()
+        }
+        // Not really JSON, send it as it was
+        Err(_) => bytes.to_vec(),
+    };
+    Ok(ServiceResponse::new(req, head.set_body(selected).map_into_boxed_body()).map_into_right_body())
+}
@@ -0,0 +1,310 @@
+// Fixed-point math for prices, quotes and amounts. Raw amounts and Q64.64
+// values stay integers through multiplications and divisions and are rounded
+// to f64 once, at the end, rather than each operand on its way in.
+
+// An unsigned 256-bit integer, wide enough for the product of two u128s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct U256 {
+    // Field order makes the derived ordering numeric.
+    hi: u128,
+    lo: u128,
+}
+
+// 2^64 and 2^128, the scales of Q64.64 values and of their squares.
+pub const Q64: U256 = U256 { hi: 0, lo: 1 << 64 };
+const Q128: U256 = U256 { hi: 1, lo: 0 };
+
+impl U256 {
+    pub fn from_u128(value: u128) -> Self {
+        U256 { hi: 0, lo: value }
+    }
+
+    // The full product of two u128s.
+    pub fn mul(a: u128, b: u128) -> Self {
+        const MASK: u128 = u64::MAX as u128;
+        let (a1, a0, b1, b0) = (a >> 64, a & MASK, b >> 64, b & MASK);
+        let (p00, p01, p10, p11) = (a0 * b0, a0 * b1, a1 * b0, a1 * b1);
+        let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+        U256 {
+            hi: p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64),
+            lo: (p00 & MASK) | (mid << 64),
+        }
+    }
+
+    pub fn checked_mul(self, m: u128) -> Option<Self> {
+        let (low, high) = (U256::mul(self.lo, m), U256::mul(self.hi, m));
+        if high.hi != 0 {
+            return None;
+        }
+        Some(U256 { hi: high.lo.checked_add(low.hi)?, lo: low.lo })
+    }
+
+    pub fn to_u128(self) -> Option<u128> {
+        (self.hi == 0).then_some(self.lo)
+    }
+
+    fn is_zero(self) -> bool {
+        self.hi == 0 && self.lo == 0
+    }
+
+    fn bits(self) -> u32 {
+        match self.hi {
+            0 => 128 - self.lo.leading_zeros(),
+            hi => 256 - hi.leading_zeros(),
+        }
+    }
+
+    fn bit(self, n: u32) -> bool {
+        match n {
+            0..128 => self.lo >> n & 1 == 1,
+            _ => self.hi >> (n - 128) & 1 == 1,
+        }
+    }
+
+    fn shl(self, n: u32) -> Self {
+        match n {
+            0 => self,
+            1..128 => U256 { hi: self.hi << n | self.lo >> (128 - n), lo: self.lo << n },
+            128..256 => U256 { hi: self.lo << (n - 128), lo: 0 },
+            _ => U256::default(),
+        }
+    }
+
+    fn shr(self, n: u32) -> Self {
+        match n {
+            0 => self,
+            1..128 => U256 { hi: self.hi >> n, lo: self.lo >> n | self.hi << (128 - n) },
+            128..256 => U256 { hi: 0, lo: self.hi >> (n - 128) },
+            _ => U256::default(),
+        }
+    }
+
+    // `self - other` for `other <= self`.
+    fn sub(self, other: Self) -> Self {
+        let (lo, borrow) = self.lo.overflowing_sub(other.lo);
+        U256 { hi: self.hi - other.hi - borrow as u128, lo }
+    }
+
+    // Quotient and remainder, bit by bit. The divisor stays below 2^255 so
+    // the doubled remainder can't overflow.
+    fn div_rem(self, divisor: Self) -> (Self, Self) {
+        let (mut quotient, mut remainder) = (U256::default(), U256::default());
+        for n in (0..self.bits()).rev() {
+            remainder = remainder.shl(1);
+            remainder.lo |= self.bit(n) as u128;
+            if remainder >= divisor {
+                remainder = remainder.sub(divisor);
+                quotient = quotient.shl(1);
+                quotient.lo |= 1;
+            } else {
+                quotient = quotient.shl(1);
+            }
+        }
+        (quotient, remainder)
+    }
+}
+
+// `numerator / denominator` rounded to the nearest f64. Infinite for a zero
+// denominator.
+pub fn ratio(numerator: U256, denominator: U256) -> f64 {
+    if denominator.is_zero() {
+        return f64::INFINITY;
+    }
+    if numerator.is_zero() {
+        return 0.0;
+    }
+    // Low bits of huge denominators, far beyond f64 precision, are dropped
+    // to leave room to shift the numerator into. Made up for in the exponent
+    let excess = denominator.bits().saturating_sub(190);
+    let denominator = denominator.shr(excess);
+    // Scaled so the quotient has 66 or 67 bits, with a sticky bit for any
+    // remainder so it rounds to nearest like the exact ratio would
+    let shift = 66 + denominator.bits() as i32 - numerator.bits() as i32;
+    let (scaled, mut inexact) = match shift {
+        0.. => (numerator.shl(shift as u32), false),
+        _ => {
+            let scaled = numerator.shr(-shift as u32);
+            (scaled, scaled.shl(-shift as u32) != numerator)
+        }
+    };
+    let (quotient, remainder) = scaled.div_rem(denominator);
+    inexact |= !remainder.is_zero();
+    (quotient.lo | inexact as u128) as f64 * 2f64.powi(-shift - excess as i32)
+}
+
+// `a * b / denominator` rounded down, None when it overflows a u128 or the
+// denominator is zero.
+pub fn mul_div(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+    U256::mul(a, b).div_rem(U256::from_u128(denominator)).0.to_u128()
+}
+
+// `a * b / denominator` rounded up.
+pub fn mul_div_ceil(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+    let (quotient, remainder) = U256::mul(a, b).div_rem(U256::from_u128(denominator));
+    quotient.to_u128()?.checked_add(!remainder.is_zero() as u128)
+}
+
+// A raw amount times 10^decimals, None past 38 decimals which no mint has.
+fn scaled(raw: u128, decimals: u8) -> Option<U256> {
+    Some(U256::mul(raw, 10u128.checked_pow(decimals as u32)?))
+}
+
+// A raw amount in whole tokens.
+pub fn to_tokens(raw: u128, decimals: u8) -> f64 {
+    scaled(1, decimals).map_or(0.0, |scale| ratio(U256::from_u128(raw), scale))
+}
+
+// Constant-product spot price of a in terms of b.
+pub fn price_from_reserves(reserve_a: u64, decimals_a: u8, reserve_b: u64, decimals_b: u8) -> f64 {
+    if reserve_a == 0 {
+        return 0.0;
+    }
+    match (scaled(reserve_b as u128, decimals_a), scaled(reserve_a as u128, decimals_b)) {
+        (Some(numerator), Some(denominator)) => ratio(numerator, denominator),
+        _ => 0.0,
+    }
+}
+
+// Concentrated liquidity spot price of a in terms of b from a Q64.64 square
+// root price: sqrt_price^2 / 2^128, adjusted for decimals.
+pub fn price_from_sqrt_x64(sqrt_price_x64: u128, decimals_a: u8, decimals_b: u8) -> f64 {
+    let square = U256::mul(sqrt_price_x64, sqrt_price_x64);
+    let exponent = decimals_a as i32 - decimals_b as i32;
+    let exact = match exponent {
+        0.. => 10u128.checked_pow(exponent as u32).and_then(|scale| square.checked_mul(scale)).map(|n| ratio(n, Q128)),
+        _ => 10u128.checked_pow(-exponent as u32).map(|scale| ratio(square, U256 { hi: scale, lo: 0 })),
+    };
+    // Near the top of the price range with far more decimals on a
+    exact.unwrap_or_else(|| ratio(square, Q128) * 10f64.powi(exponent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+    use num_traits::{ToPrimitive, Zero};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const CASES: usize = 2000;
+
+    fn big(value: U256) -> BigUint {
+        (BigUint::from(value.hi) << 128) + BigUint::from(value.lo)
+    }
+
+    // Random values spread over every magnitude, not just the top bits.
+    fn any_u128(rng: &mut StdRng) -> u128 {
+        rng.gen::<u128>() >> rng.gen_range(0..128)
+    }
+
+    fn any_u256(rng: &mut StdRng) -> U256 {
+        let value = U256 { hi: rng.gen(), lo: rng.gen() };
+        value.shr(rng.gen_range(0..256))
+    }
+
+    // The exact ratio to f64 through big integers: 80 bits of quotient
+    // carry the result well past f64 precision.
+    fn reference_ratio(numerator: &BigUint, denominator: &BigUint) -> f64 {
+        if numerator.is_zero() {
+            return 0.0;
+        }
+        let shift = 80 + denominator.bits() as i32 - numerator.bits() as i32;
+        let quotient = if shift >= 0 {
+            (numerator << shift as u32) / denominator
+        } else {
+            numerator / (denominator << (-shift) as u32)
+        };
+        quotient.to_f64().unwrap() * 2f64.powi(-shift)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        let tolerance = expected.abs() * 2.0 * f64::EPSILON;
+        assert!((actual - expected).abs() <= tolerance, "{} vs {}", actual, expected);
+    }
+
+    #[test]
+    fn mul_matches_big_integers() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..CASES {
+            let (a, b) = (any_u128(&mut rng), any_u128(&mut rng));
+            assert_eq!(big(U256::mul(a, b)), BigUint::from(a) * BigUint::from(b));
+        }
+        assert_eq!(big(U256::mul(u128::MAX, u128::MAX)), BigUint::from(u128::MAX) * BigUint::from(u128::MAX));
+    }
+
+    #[test]
+    fn mul_div_matches_big_integers() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..CASES {
+            let (a, b, d) = (any_u128(&mut rng), any_u128(&mut rng), any_u128(&mut rng).max(1));
+            let product = BigUint::from(a) * BigUint::from(b);
+            let (floor, rem) = (&product / d, &product % d);
+            assert_eq!(mul_div(a, b, d), floor.to_u128());
+            let ceil = if rem.is_zero() { floor } else { floor + 1u32 };
+            assert_eq!(mul_div_ceil(a, b, d), ceil.to_u128());
+        }
+        assert_eq!(mul_div(1, 1, 0), None);
+    }
+
+    #[test]
+    fn ratio_matches_big_integers() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..CASES {
+            let (numerator, denominator) = (any_u256(&mut rng), any_u256(&mut rng));
+            if denominator.is_zero() {
+                continue;
+            }
+            assert_close(ratio(numerator, denominator), reference_ratio(&big(numerator), &big(denominator)));
+        }
+        assert_eq!(ratio(U256::from_u128(1), U256::default()), f64::INFINITY);
+    }
+
+    #[test]
+    fn ratio_is_exact_when_representable() {
+        assert_eq!(ratio(U256::from_u128(3), U256::from_u128(4)), 0.75);
+        assert_eq!(ratio(U256::mul(u128::MAX, 2), U256::from_u128(u128::MAX)), 2.0);
+        assert_eq!(to_tokens(1_500_000, 6), 1.5);
+        assert_eq!(to_tokens(0, 9), 0.0);
+    }
+
+    #[test]
+    fn prices_from_reserves_match_big_integers() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..CASES {
+            let (reserve_a, reserve_b) = (rng.gen::<u64>() >> rng.gen_range(0..64), rng.gen::<u64>() >> rng.gen_range(0..64));
+            let (decimals_a, decimals_b) = (rng.gen_range(0..=18u8), rng.gen_range(0..=18u8));
+            let expected = match reserve_a {
+                0 => 0.0,
+                _ => reference_ratio(
+                    &(BigUint::from(reserve_b) * BigUint::from(10u32).pow(decimals_a as u32)),
+                    &(BigUint::from(reserve_a) * BigUint::from(10u32).pow(decimals_b as u32)),
+                ),
+            };
+            assert_close(price_from_reserves(reserve_a, decimals_a, reserve_b, decimals_b), expected);
+        }
+    }
+
+    #[test]
+    fn prices_from_sqrt_match_big_integers() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..CASES {
+            // Orca and Raydium keep square root prices within 2^32 and 2^96
+            let sqrt_price = rng.gen_range(1u128 << 32..1u128 << 96);
+            let (decimals_a, decimals_b) = (rng.gen_range(0..=18u8), rng.gen_range(0..=18u8));
+            let square = BigUint::from(sqrt_price).pow(2);
+            let (numerator, denominator) = (
+                square * BigUint::from(10u32).pow(decimals_a as u32),
+                (BigUint::from(1u32) << 128) * BigUint::from(10u32).pow(decimals_b as u32),
+            );
+            assert_close(price_from_sqrt_x64(sqrt_price, decimals_a, decimals_b), reference_ratio(&numerator, &denominator));
+        }
+        // 1.0 as Q64.64 between tokens of equal decimals
+        assert_eq!(price_from_sqrt_x64(1 << 64, 6, 6), 1.0);
+    }
+}
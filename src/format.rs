@@ -0,0 +1,117 @@
+use actix_web::body::{self, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+// Endpoints returning enough data for JSON parsing to matter to bots. The
+// rest always answer in JSON.
+const MSGPACK_ROUTES: &[&str] = &[
+    "/pool/{pool_id}",
+    "/pool/{pool_id}/diff",
+    "/pool/{pool_id}/price-at",
+    "/pool/{pool_id}/indicators",
+    "/pool/{pool_id}/audit",
+    "/pool/{pool_id}/quote",
+    "/token/{mint}/price",
+    "/token-pair/{token_a}/{token_b}",
+    "/transactions/{token}",
+    "/tokens/metadata",
+    "/pools/discover",
+    "/wallet/{address}/tax-export",
+    "/alerts/history",
+];
+
+// Whether the client asked for MessagePack, through the Accept header or a
+// `format=msgpack` query parameter for clients that can't set headers.
+pub fn wants_msgpack(req: &HttpRequest) -> bool {
+    let query = req
+        .query_string()
+        .split('&')
+        .any(|pair| pair == "format=msgpack");
+    let accept = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| {
+            accept.split(',').any(|media| {
+                let media = media.split(';').next().unwrap_or("").trim();
+                media == MSGPACK_CONTENT_TYPE || media == "application/x-msgpack"
+            })
+        });
+    query || accept
+}
+
+// Map keys stay field names so the payload reads the same as the JSON one.
+pub fn to_msgpack<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    rmp_serde::to_vec_named(value).map_err(|e| format!("Failed to encode MessagePack: {}", e))
+}
+
+// Join serialized JSON objects into one, for responses built partly from
+// fields serialized ahead of time. Keys must not repeat across them.
+pub fn merge_objects(objects: &[&[u8]]) -> Bytes {
+    let mut merged = BytesMut::with_capacity(objects.iter().map(|object| object.len()).sum::<usize>() + 2);
+    merged.put_u8(b'{');
+    for object in objects {
+        let fields = object.get(1..object.len().saturating_sub(1)).unwrap_or_default();
+        if fields.is_empty() {
+            continue;
+        }
+        if merged.len() > 1 {
+            merged.put_u8(b',');
+        }
+        merged.extend_from_slice(fields);
+    }
+    merged.put_u8(b'}');
+    merged.freeze()
+}
+
+// Re-encode JSON responses of the MessagePack routes for clients asking for
+// it. Other content types, such as CSV exports, pass through untouched.
+pub async fn negotiate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let listed = req.match_pattern().is_some_and(|p| MSGPACK_ROUTES.contains(&p.as_str()));
+    if !listed || !wants_msgpack(req.request()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let response = next.call(req).await?;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if !is_json {
+        return Ok(response.map_into_left_body());
+    }
+
+    let (req, response) = response.into_parts();
+    let (head, payload) = response.into_parts();
+    let bytes = body::to_bytes(payload)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into().to_string()))?;
+    let encoded = serde_json::from_slice::<Value>(&bytes)
+        .map_err(|e| format!("Failed to parse response: {}", e))
+        .and_then(|value| to_msgpack(&value));
+    let mut response = match encoded {
+        Ok(encoded) => {
+            let mut response = head.set_body(encoded);
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(MSGPACK_CONTENT_TYPE));
+            response.map_into_boxed_body()
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": e }))
+        }
+    };
+    response.headers_mut().insert(header::VARY, HeaderValue::from_static("Accept"));
+    Ok(ServiceResponse::new(req, response).map_into_right_body())
+}
@@ -0,0 +1,260 @@
+use crate::address::ValidatedPubkey;
+use crate::history::PoolSnapshot;
+use crate::poller::{self, PoolSource};
+use crate::pubsub;
+use crate::schedule::PollTier;
+use crate::state::{unix_now, AppState};
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillStatus {
+    // Not tried yet.
+    Pending,
+    // Every snapshot asked for was found.
+    Filled,
+    // Some were found, the cache and archival endpoints had no state within
+    // the gap for the rest.
+    Partial,
+    Unavailable,
+}
+
+// A stretch of a pool's history with fewer snapshots than its tier's
+// interval calls for.
+#[derive(Clone, Serialize)]
+pub struct Gap {
+    // Timestamps and slots of the snapshots either side.
+    pub from: u64,
+    pub to: u64,
+    pub from_slot: u64,
+    pub to_slot: u64,
+    pub duration_secs: u64,
+    // Snapshots the interval calls for that aren't there.
+    pub missing: u64,
+    pub backfill: BackfillStatus,
+}
+
+// A backfill of one gap. Gaps left inside it afterwards aren't tried again.
+struct Attempt {
+    from: u64,
+    to: u64,
+    status: BackfillStatus,
+}
+
+#[derive(Default)]
+pub struct Gaps {
+    attempts: Mutex<HashMap<Pubkey, Vec<Attempt>>>,
+    pub backfilled: AtomicU64,
+}
+
+impl Gaps {
+    fn status(&self, pool: &Pubkey, from: u64, to: u64) -> BackfillStatus {
+        let attempts = self.attempts.lock().unwrap();
+        attempts
+            .get(pool)
+            .and_then(|attempts| attempts.iter().find(|a| a.from <= from && to <= a.to))
+            .map_or(BackfillStatus::Pending, |attempt| attempt.status)
+    }
+
+    fn record(&self, pool: Pubkey, from: u64, to: u64, status: BackfillStatus) {
+        self.attempts.lock().unwrap().entry(pool).or_default().push(Attempt { from, to, status });
+    }
+
+    // Forget attempts on gaps that have aged out of the history.
+    fn prune(&self, cutoff: u64) {
+        let mut attempts = self.attempts.lock().unwrap();
+        for pool_attempts in attempts.values_mut() {
+            pool_attempts.retain(|attempt| attempt.to >= cutoff);
+        }
+        attempts.retain(|_, pool_attempts| !pool_attempts.is_empty());
+    }
+}
+
+// The interval a pool's snapshots are expected at, None for dormant pools
+// that aren't polled. Pools off the watchlist count as standard ones.
+fn expected_interval(state: &AppState, pool: &Pubkey) -> Option<u64> {
+    let config = state.config();
+    let tier = state
+        .watchlist()
+        .into_iter()
+        .find(|(watched, _)| watched == pool)
+        .map_or(PollTier::Standard, |(_, tier)| tier);
+    Some(tier.interval_secs(&config)).filter(|interval| *interval > 0)
+}
+
+// Gaps between consecutive snapshots, oldest first.
+fn find(state: &AppState, pool: &Pubkey, points: &[PoolSnapshot], interval: u64) -> Vec<Gap> {
+    let threshold = interval * state.config.read().unwrap().gaps.gap_after_intervals;
+    points
+        .windows(2)
+        .filter(|pair| pair[1].timestamp - pair[0].timestamp > threshold)
+        .map(|pair| {
+            let (before, after) = (&pair[0], &pair[1]);
+            let duration_secs = after.timestamp - before.timestamp;
+            Gap {
+                from: before.timestamp,
+                to: after.timestamp,
+                from_slot: before.slot,
+                to_slot: after.slot,
+                duration_secs,
+                missing: (duration_secs / interval).saturating_sub(1),
+                backfill: state.gaps.status(pool, before.timestamp, after.timestamp),
+            }
+        })
+        .collect()
+}
+
+// Backfill the gaps found in every watched pool's history every
+// `gaps.backfill_interval_secs`. The first pass runs one interval after
+// startup, once the poller has recorded the snapshots closing the gaps
+// left by the downtime.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let config = state.config().gaps;
+        if config.backfill_interval_secs == 0 {
+            state.reloaded.notified().await;
+            continue;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(config.backfill_interval_secs)) => {}
+            _ = state.reloaded.notified() => continue,
+        }
+        if state.is_paused() {
+            continue;
+        }
+        state.gaps.prune(unix_now().saturating_sub(state.config().history_retention_secs));
+        for (pool, _) in state.watchlist() {
+            let Some(interval) = expected_interval(&state, &pool) else {
+                continue;
+            };
+            let points = state.history.range(&pool, 0, u64::MAX);
+            for gap in find(&state, &pool, &points, interval) {
+                if gap.backfill != BackfillStatus::Pending {
+                    continue;
+                }
+                // RPC errors are retried on the next pass
+                match backfill(&state, pool, &gap, config.max_backfill_snapshots).await {
+                    Ok(status) => state.gaps.record(pool, gap.from, gap.to, status),
+                    Err(e) => eprintln!("Failed to backfill {} between {} and {}: {}", pool, gap.from, gap.to, e),
+                }
+            }
+        }
+    }
+}
+
+// Fill a gap with the pool's state at the slots the missing snapshots would
+// have been taken at, estimated from the slots either side. States from
+// outside the gap, like the current one an archival endpoint without
+// history returns, are dropped.
+async fn backfill(state: &AppState, pool: Pubkey, gap: &Gap, max: usize) -> Result<BackfillStatus, String> {
+    let count = (gap.missing as usize).min(max);
+    if count == 0 {
+        return Ok(BackfillStatus::Filled);
+    }
+    let step = gap.duration_secs / (count as u64 + 1);
+    let slot_at = |timestamp: u64| {
+        if gap.to_slot > gap.from_slot {
+            gap.from_slot + (gap.to_slot - gap.from_slot) * (timestamp - gap.from) / gap.duration_secs
+        } else {
+            gap.from_slot + pubsub::slots_in(timestamp - gap.from)
+        }
+    };
+    let max_deviation_pct = state.config.read().unwrap().outliers.max_deviation_pct;
+    let mut last_slot = gap.from_slot;
+    let mut filled = 0;
+    for i in 1..=count as u64 {
+        let target = gap.from + step * i;
+        let Some((cached, source, _)) = poller::get_pool_at(state, pool, slot_at(target)).await? else {
+            // No account cache that far back and no archival endpoint
+            break;
+        };
+        // The cache hands back the same observation for targets between two
+        // of its slots
+        if cached.slot <= last_slot || cached.slot >= gap.to_slot || cached.has_layout_warnings() {
+            continue;
+        }
+        let Some(mut snapshot) = cached.snapshot.clone() else {
+            continue;
+        };
+        snapshot.timestamp = match source {
+            PoolSource::Cache => cached.fetched_at,
+            PoolSource::Archival => target,
+        };
+        if snapshot.timestamp <= gap.from || snapshot.timestamp >= gap.to {
+            continue;
+        }
+        // Kept out like an outlier when out of line with the snapshots on
+        // both sides
+        let (before, after) = state.history.around(&pool, snapshot.timestamp, |p| p.timestamp);
+        let out_of_line = |neighbour: Option<PoolSnapshot>| {
+            neighbour.is_some_and(|p| p.price > 0.0 && (snapshot.price - p.price).abs() / p.price * 100.0 > max_deviation_pct)
+        };
+        if out_of_line(before) && out_of_line(after) {
+            continue;
+        }
+        last_slot = cached.slot;
+        state.history.insert(pool, snapshot.clone());
+        if let Err(e) = state.with_store(move |store| store.save_snapshot(&pool, &snapshot)).await {
+            eprintln!("Failed to store backfilled snapshot for {}: {}", pool, e);
+        }
+        filled += 1;
+    }
+    state.gaps.backfilled.fetch_add(filled as u64, Ordering::Relaxed);
+    if filled > 0 {
+        println!("Backfilled {} of {} snapshots of {} between {} and {}", filled, count, pool, gap.from, gap.to);
+    }
+    Ok(match filled {
+        0 => BackfillStatus::Unavailable,
+        n if n == count => BackfillStatus::Filled,
+        _ => BackfillStatus::Partial,
+    })
+}
+
+#[derive(Deserialize)]
+struct GapsQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+// Gaps in a pool's recorded history and how far they were backfilled, with
+// the cadence the snapshots were expected and found at.
+#[get("/pool/{pool_id}/history/gaps")]
+async fn get_history_gaps(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<GapsQuery>,
+) -> HttpResponse {
+    let pool = pool_id.0;
+    let (from, to) = (query.from.unwrap_or(0), query.to.unwrap_or(u64::MAX));
+    if from > to {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "from must not be after to"
+        }));
+    }
+    let points = state.history.range(&pool, from, to);
+    let interval = expected_interval(&state, &pool);
+    let gaps = interval.map_or_else(Vec::new, |interval| find(&state, &pool, &points, interval));
+
+    let mut intervals: Vec<u64> = points.windows(2).map(|pair| pair[1].timestamp - pair[0].timestamp).collect();
+    intervals.sort_unstable();
+    let span = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => last.timestamp - first.timestamp,
+        _ => 0,
+    };
+    let missing_secs: u64 = gaps.iter().map(|gap| gap.duration_secs).sum();
+    HttpResponse::Ok().json(json!({
+        "pool": pool.to_string(),
+        "expected_interval_secs": interval,
+        "median_interval_secs": intervals.get(intervals.len() / 2),
+        "snapshots": points.len(),
+        "coverage_pct": (span > 0).then(|| (span - missing_secs) as f64 / span as f64 * 100.0),
+        "gaps": gaps,
+    }))
+}
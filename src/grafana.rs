@@ -0,0 +1,259 @@
+use crate::dex::DecodedPool;
+use crate::fixed;
+use crate::state::AppState;
+use crate::storage::AlertFilter;
+use crate::time::parse_rfc3339;
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+// Alerts returned as annotations per request.
+const MAX_ANNOTATIONS: usize = 1000;
+// Kind user annotations are tagged and selected with.
+const USER_ANNOTATION_KIND: &str = "annotation";
+const METRICS: [&str; 5] = ["price", "tvl", "volume", "reserve_a", "reserve_b"];
+
+#[derive(Deserialize)]
+struct Range {
+    from: String,
+    to: String,
+}
+
+impl Range {
+    fn parse(&self) -> Result<(u64, u64), String> {
+        let time = |text: &str| parse_rfc3339(text).ok_or(format!("Invalid time {}", text));
+        let (from, to) = (time(&self.from)?, time(&self.to)?);
+        if from > to {
+            return Err("range.from is after range.to".to_string());
+        }
+        Ok((from, to))
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchRequest {
+    #[serde(default)]
+    target: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryRequest {
+    range: Range,
+    interval_ms: Option<u64>,
+    max_data_points: Option<u64>,
+    #[serde(default)]
+    targets: Vec<QueryTarget>,
+}
+
+#[derive(Deserialize)]
+struct QueryTarget {
+    // "<pool id>.<metric>", as listed by /grafana/search.
+    target: Option<String>,
+    // "timeserie" or "table".
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    hide: bool,
+}
+
+#[derive(Deserialize)]
+struct AnnotationsRequest {
+    range: Range,
+    annotation: Value,
+}
+
+fn target_name(pool: &Pubkey, metric: &str) -> String {
+    format!("{}.{}", pool, metric)
+}
+
+// One value per step of `step` seconds, the last snapshot of each step, or
+// for volume the token b traded during it. Amounts are in whole tokens.
+fn series(state: &AppState, pool: &Pubkey, metric: &str, from: u64, to: u64, step: u64) -> Vec<(u64, f64)> {
+    let decoded: Option<DecodedPool> = state.cache.get(pool).and_then(|cached| cached.pool.clone());
+    let scale = |raw: u128, decimals: fn(&DecodedPool) -> u8| {
+        decoded.as_ref().map(|pool| fixed::to_tokens(raw, decimals(pool)))
+    };
+    let mut last_volume = state.history.at_or_before(pool, from.saturating_sub(1)).and_then(|s| s.cumulative_volume_b);
+    let mut points: Vec<(u64, f64)> = Vec::new();
+    for snapshot in state.history.range(pool, from, to) {
+        let value = match metric {
+            "price" => Some(snapshot.price),
+            "tvl" => scale(2 * snapshot.reserve_b as u128, |pool| pool.decimals_b),
+            "reserve_a" => scale(snapshot.reserve_a as u128, |pool| pool.decimals_a),
+            "reserve_b" => scale(snapshot.reserve_b as u128, |pool| pool.decimals_b),
+            _ => last_volume
+                .zip(snapshot.cumulative_volume_b)
+                .and_then(|(before, after)| scale(after.saturating_sub(before), |pool| pool.decimals_b)),
+        };
+        last_volume = snapshot.cumulative_volume_b;
+        let Some(value) = value else {
+            continue;
+        };
+        let time = snapshot.timestamp - snapshot.timestamp % step;
+        match points.last_mut() {
+            Some((last, total)) if *last == time && metric == "volume" => *total += value,
+            Some((last, latest)) if *last == time => *latest = value,
+            _ => points.push((time, value)),
+        }
+    }
+    points
+}
+
+// Grafana's connection test.
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+// Metric names for the query editor, "<pool id>.<metric>" for every watched
+// pool, filtered by the text typed so far.
+#[post("/grafana/search")]
+async fn search(state: web::Data<AppState>, body: web::Json<SearchRequest>) -> HttpResponse {
+    let filter = body.target.to_lowercase();
+    let mut targets = Vec::new();
+    for (pool, _) in state.watchlist() {
+        let label = state.cache.get(&pool).and_then(|cached| cached.pool.as_ref().map(|pool| pool.label()));
+        for metric in METRICS {
+            let text = match &label {
+                Some(label) => format!("{} {} ({})", label, metric, pool),
+                None => target_name(&pool, metric),
+            };
+            if text.to_lowercase().contains(&filter) {
+                targets.push(json!({ "text": text, "value": target_name(&pool, metric) }));
+            }
+        }
+    }
+    HttpResponse::Ok().json(targets)
+}
+
+// Time series, or tables of time and value, for the requested targets over
+// the dashboard's range, from the recorded history of the default cluster.
+#[post("/grafana/query")]
+async fn query(state: web::Data<AppState>, body: web::Json<QueryRequest>) -> HttpResponse {
+    let (from, to) = match body.range.parse() {
+        Ok(range) => range,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    // Fewer points than Grafana asked for rather than more
+    let mut step = (body.interval_ms.unwrap_or(0) / 1000).max(1);
+    if let Some(max_points) = body.max_data_points.filter(|max| *max > 0) {
+        step = step.max((to - from).div_ceil(max_points));
+    }
+
+    let mut results = Vec::new();
+    for target in body.targets.iter().filter(|target| !target.hide) {
+        let Some(name) = target.target.as_deref().filter(|name| !name.is_empty()) else {
+            continue;
+        };
+        let parsed = name
+            .rsplit_once('.')
+            .filter(|(_, metric)| METRICS.contains(metric))
+            .and_then(|(pool, metric)| Some((Pubkey::from_str(pool).ok()?, metric)));
+        let Some((pool, metric)) = parsed else {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid target {}, expected <pool id>.<metric> with metric one of {}", name, METRICS.join(", "))
+            }));
+        };
+        let points = series(&state, &pool, metric, from, to, step);
+        if target.kind.as_deref() == Some("table") {
+            results.push(json!({
+                "type": "table",
+                "columns": [{ "text": "Time", "type": "time" }, { "text": name, "type": "number" }],
+                "rows": points.iter().map(|(time, value)| json!([time * 1000, value])).collect::<Vec<_>>(),
+            }));
+        } else {
+            results.push(json!({
+                "target": name,
+                "datapoints": points.iter().map(|(time, value)| json!([value, time * 1000])).collect::<Vec<_>>(),
+            }));
+        }
+    }
+    HttpResponse::Ok().json(results)
+}
+
+// Stored alerts and user annotations over the dashboard's range. The
+// annotation's query narrows them down with space separated pool=<id> and
+// kind=<kind>, where kind=annotation selects only user annotations.
+#[post("/grafana/annotations")]
+async fn annotations(state: web::Data<AppState>, body: web::Json<AnnotationsRequest>) -> HttpResponse {
+    let (from, to) = match body.range.parse() {
+        Ok(range) => range,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    let mut filter = AlertFilter {
+        since: Some(from),
+        until: Some(to + 1),
+        limit: MAX_ANNOTATIONS,
+        ..AlertFilter::default()
+    };
+    for term in body.annotation["query"].as_str().unwrap_or_default().split_whitespace() {
+        match term.split_once('=') {
+            Some(("pool", pool)) => filter.pool = Some(pool.to_string()),
+            Some(("kind", kind)) => filter.kind = Some(kind.to_string()),
+            _ => {
+                return HttpResponse::BadRequest().json(json!({
+                    "error": format!("Invalid annotation query term {}, use pool=<id> or kind=<kind>", term)
+                }));
+            }
+        }
+    }
+    let notes = match filter.kind.as_deref() {
+        None | Some(USER_ANNOTATION_KIND) => {
+            let pool = filter.pool.clone();
+            state.with_store(move |store| store.annotations(pool.as_deref(), from, to)).await
+        }
+        Some(_) => Ok(Vec::new()),
+    };
+    let notes = match notes {
+        Ok(notes) => notes,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to read annotations: {}", e)
+            }));
+        }
+    };
+    let alerts = match filter.kind.as_deref() {
+        Some(USER_ANNOTATION_KIND) => Ok(Vec::new()),
+        _ => state.with_store(move |store| store.alert_history(&filter)).await,
+    };
+    let alerts = match alerts {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to read alert history: {}", e)
+            }));
+        }
+    };
+    let mut annotations: Vec<Value> = alerts
+        .into_iter()
+        .filter(|alert| alert.suppressed_by.is_none())
+        .map(|alert| {
+            json!({
+                "annotation": body.annotation,
+                "time": alert.timestamp * 1000,
+                "title": alert.kind,
+                "text": alert.message,
+                "tags": [alert.severity, alert.kind, alert.pool],
+            })
+        })
+        .collect();
+    annotations.extend(notes.into_iter().take(MAX_ANNOTATIONS).map(|note| {
+        json!({
+            "annotation": body.annotation,
+            "time": note.timestamp * 1000,
+            "title": note.label,
+            "text": note.note.unwrap_or_default(),
+            "tags": [USER_ANNOTATION_KIND, note.pool],
+        })
+    }));
+    HttpResponse::Ok().json(annotations)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource(["/grafana", "/grafana/"]).route(web::get().to(health)))
+        .service(search)
+        .service(query)
+        .service(annotations);
+}
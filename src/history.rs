@@ -0,0 +1,392 @@
+use crate::address::ValidatedPubkey;
+use crate::amount::{self, optional_u128_string, Amount};
+use crate::dex::DecodedPool;
+use crate::state::AppState;
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use dashmap::DashMap;
+use std::collections::VecDeque;
+
+// Point-in-time view of a pool as seen by the poller.
+#[derive(Clone, Debug)]
+pub struct PoolSnapshot {
+    pub slot: u64,
+    pub timestamp: u64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    // Token b per token a, adjusted for decimals.
+    pub price: f64,
+    pub lp_supply: Option<u64>,
+    pub authority: Option<Pubkey>,
+    pub cumulative_volume_b: Option<u128>,
+    // The pool's fee growth accumulators of tokens a and b, see
+    // `DecodedPool::fee_growth_global`.
+    pub fee_growth: Option<(u128, u128)>,
+}
+
+// Decimals of a pool's tokens and LP mint. Snapshots are stored without
+// them, so they come from the pool when a snapshot is sent out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Decimals {
+    pub a: u8,
+    pub b: u8,
+    pub lp: u8,
+}
+
+impl Decimals {
+    pub fn of(pool: &DecodedPool) -> Self {
+        Decimals {
+            a: pool.decimals_a,
+            b: pool.decimals_b,
+            lp: pool.lp_decimals,
+        }
+    }
+
+    // Of a pool polled already.
+    pub fn cached(state: &AppState, pool: &Pubkey) -> Option<Self> {
+        state.cache.get(pool)?.pool.as_ref().map(Decimals::of)
+    }
+}
+
+impl PoolSnapshot {
+    // Amounts as `Amount`s, see there.
+    pub fn json(&self, decimals: Decimals) -> Value {
+        json!({
+            "slot": self.slot,
+            "timestamp": self.timestamp,
+            "reserve_a": Amount::new(self.reserve_a as u128, decimals.a),
+            "reserve_b": Amount::new(self.reserve_b as u128, decimals.b),
+            "price": self.price,
+            "lp_supply": self.lp_supply.map(|supply| Amount::new(supply as u128, decimals.lp)),
+            "authority": self.authority.map(|authority| authority.to_string()),
+        })
+    }
+}
+
+// OHLC of the pool price over one interval, built from snapshots.
+#[derive(Clone, Debug, Serialize)]
+pub struct Candle {
+    pub open_time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    // Token b traded during the candle, raw units. None when the pool has no
+    // swap counters.
+    #[serde(serialize_with = "optional_u128_string")]
+    pub volume_b: Option<u128>,
+}
+
+impl Candle {
+    pub fn is_red(&self) -> bool {
+        self.close < self.open
+    }
+
+    pub fn is_green(&self) -> bool {
+        self.close > self.open
+    }
+}
+
+// In-memory snapshot history per pool, oldest first. Sharded by pool, the
+// poller appending to one doesn't hold up reads of the others.
+#[derive(Default)]
+pub struct History {
+    pools: DashMap<Pubkey, VecDeque<PoolSnapshot>>,
+}
+
+impl History {
+    // Append a snapshot and drop everything older than the retention window.
+    pub fn record(&self, pool: Pubkey, snapshot: PoolSnapshot, retention_secs: u64) {
+        let mut points = self.pools.entry(pool).or_default();
+        let cutoff = snapshot.timestamp.saturating_sub(retention_secs);
+        points.push_back(snapshot);
+        while points.front().is_some_and(|p| p.timestamp < cutoff) {
+            points.pop_front();
+        }
+    }
+
+    // Put a snapshot taken in the past in its place, e.g. one backfilled into
+    // a gap.
+    pub fn insert(&self, pool: Pubkey, snapshot: PoolSnapshot) {
+        let mut points = self.pools.entry(pool).or_default();
+        let index = points.partition_point(|p| (p.timestamp, p.slot) <= (snapshot.timestamp, snapshot.slot));
+        points.insert(index, snapshot);
+    }
+
+    // Seed the history from storage at startup. Snapshots must be oldest first.
+    pub fn load(&self, snapshots: Vec<(Pubkey, PoolSnapshot)>) {
+        for (pool, snapshot) in snapshots {
+            self.pools.entry(pool).or_default().push_back(snapshot);
+        }
+    }
+
+    pub fn latest(&self, pool: &Pubkey) -> Option<PoolSnapshot> {
+        self.pools.get(pool).and_then(|p| p.back().cloned())
+    }
+
+    // The last `count` snapshots, oldest first.
+    pub fn recent(&self, pool: &Pubkey, count: usize) -> Vec<PoolSnapshot> {
+        let Some(points) = self.pools.get(pool) else {
+            return Vec::new();
+        };
+        points.range(points.len().saturating_sub(count)..).cloned().collect()
+    }
+
+    // Most recent snapshot taken at or before `timestamp`.
+    pub fn at_or_before(&self, pool: &Pubkey, timestamp: u64) -> Option<PoolSnapshot> {
+        let points = self.pools.get(pool)?;
+        let index = points.partition_point(|p| p.timestamp <= timestamp);
+        index.checked_sub(1).and_then(|i| points.get(i).cloned())
+    }
+
+    // Most recent snapshot taken at or before `slot`.
+    pub fn at_or_before_slot(&self, pool: &Pubkey, slot: u64) -> Option<PoolSnapshot> {
+        let points = self.pools.get(pool)?;
+        let index = points.partition_point(|p| p.slot <= slot);
+        index.checked_sub(1).and_then(|i| points.get(i).cloned())
+    }
+
+    // When the reserves last moved, as the first snapshot showing the current
+    // ones. The oldest snapshot when they never moved within the history.
+    pub fn unchanged_since(&self, pool: &Pubkey) -> Option<u64> {
+        let points = self.pools.get(pool)?;
+        let latest = points.back()?;
+        let unchanged = points
+            .iter()
+            .rev()
+            .take_while(|p| p.reserve_a == latest.reserve_a && p.reserve_b == latest.reserve_b)
+            .last()?;
+        Some(unchanged.timestamp)
+    }
+
+    // Snapshots either side of `target`, measured by `key` (timestamp or
+    // slot). Both are the same snapshot on an exact hit.
+    pub fn around(
+        &self,
+        pool: &Pubkey,
+        target: u64,
+        key: fn(&PoolSnapshot) -> u64,
+    ) -> (Option<PoolSnapshot>, Option<PoolSnapshot>) {
+        let Some(points) = self.pools.get(pool) else {
+            return (None, None);
+        };
+        let index = points.partition_point(|p| key(p) <= target);
+        let before = index.checked_sub(1).and_then(|i| points.get(i).cloned());
+        let after = match &before {
+            Some(before) if key(before) == target => Some(before.clone()),
+            _ => points.get(index).cloned(),
+        };
+        (before, after)
+    }
+
+    // Snapshots taken between `from` and `to`, inclusive, oldest first.
+    pub fn range(&self, pool: &Pubkey, from: u64, to: u64) -> Vec<PoolSnapshot> {
+        let Some(points) = self.pools.get(pool) else {
+            return Vec::new();
+        };
+        let start = points.partition_point(|p| p.timestamp < from);
+        points.range(start..).take_while(|p| p.timestamp <= to).cloned().collect()
+    }
+
+    // Candles of `interval_secs` that closed by `until`, oldest first.
+    // Intervals without snapshots are skipped rather than filled.
+    pub fn candles(&self, pool: &Pubkey, interval_secs: u64, until: u64) -> Vec<Candle> {
+        let Some(points) = self.pools.get(pool) else {
+            return Vec::new();
+        };
+        let mut candles: Vec<Candle> = Vec::new();
+        // Volume is counted from the last snapshot before each candle
+        let mut last_volume = None;
+        for point in points.iter() {
+            let open_time = point.timestamp - point.timestamp % interval_secs;
+            if open_time + interval_secs > until {
+                break;
+            }
+            let volume = match (last_volume, point.cumulative_volume_b) {
+                (Some(before), Some(after)) => Some(after.saturating_sub(before)),
+                _ => None,
+            };
+            match candles.last_mut() {
+                Some(candle) if candle.open_time == open_time => {
+                    candle.high = candle.high.max(point.price);
+                    candle.low = candle.low.min(point.price);
+                    candle.close = point.price;
+                    candle.volume_b = match (candle.volume_b, volume) {
+                        (Some(total), Some(volume)) => Some(total + volume),
+                        (total, volume) => total.or(volume),
+                    };
+                }
+                _ => candles.push(Candle {
+                    open_time,
+                    open: point.price,
+                    high: point.price,
+                    low: point.price,
+                    close: point.price,
+                    volume_b: volume.or(point.cumulative_volume_b.map(|_| 0)),
+                }),
+            }
+            last_volume = point.cumulative_volume_b;
+        }
+        candles
+    }
+}
+
+// Percentage price change between the snapshot `window_secs` before `current`
+// and `current`. None when history doesn't reach back that far.
+pub fn price_change(history: &History, pool: &Pubkey, current: &PoolSnapshot, window_secs: u64) -> Option<f64> {
+    let past = history.at_or_before(pool, current.timestamp.checked_sub(window_secs)?)?;
+    if past.price == 0.0 {
+        return None;
+    }
+    Some((current.price - past.price) / past.price * 100.0)
+}
+
+// Token b volume traded over the window, derived from the pool's cumulative
+// swap counters. Returns raw units.
+pub fn volume(history: &History, pool: &Pubkey, current: &PoolSnapshot, window_secs: u64) -> Option<u128> {
+    let past = history.at_or_before(pool, current.timestamp.checked_sub(window_secs)?)?;
+    Some(current.cumulative_volume_b?.saturating_sub(past.cumulative_volume_b?))
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Interpolation {
+    // Closest snapshot on either side.
+    #[default]
+    Nearest,
+    // Last snapshot at or before the target, the price actually in effect.
+    Previous,
+    // Straight line between the snapshots either side.
+    Linear,
+}
+
+#[derive(Deserialize)]
+struct PriceAtQuery {
+    timestamp: Option<u64>,
+    slot: Option<u64>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+#[derive(Deserialize)]
+struct DiffQuery {
+    from_slot: u64,
+    to_slot: u64,
+}
+
+#[get("/pool/{pool_id}/diff")]
+async fn get_pool_diff(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<DiffQuery>,
+) -> HttpResponse {
+    let pubkey = pool_id.0;
+    if query.from_slot > query.to_slot {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "from_slot must not be after to_slot"
+        }));
+    }
+
+    let Some(decimals) = Decimals::cached(&state, &pubkey) else {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": format!("Pool {} has not been polled yet", pubkey)
+        }));
+    };
+    let (Some(from), Some(to)) = (
+        state.history.at_or_before_slot(&pubkey, query.from_slot),
+        state.history.at_or_before_slot(&pubkey, query.to_slot),
+    ) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("No stored snapshots for {} at or before both slots", pubkey)
+        }));
+    };
+
+    let price_change_pct = if from.price != 0.0 {
+        Some((to.price - from.price) / from.price * 100.0)
+    } else {
+        None
+    };
+    let lp_supply_delta = match (from.lp_supply, to.lp_supply) {
+        (Some(a), Some(b)) => Some(amount::change(a as u128, b as u128, decimals.lp)),
+        _ => None,
+    };
+
+    HttpResponse::Ok().json(json!({
+        "pool_id": pool_id.to_string(),
+        "from": from.json(decimals),
+        "to": to.json(decimals),
+        "elapsed_secs": to.timestamp.saturating_sub(from.timestamp),
+        "reserve_a_delta": amount::change(from.reserve_a as u128, to.reserve_a as u128, decimals.a),
+        "reserve_b_delta": amount::change(from.reserve_b as u128, to.reserve_b as u128, decimals.b),
+        "price_change": to.price - from.price,
+        "price_change_pct": price_change_pct,
+        "lp_supply_delta": lp_supply_delta,
+        "authority_changed": from.authority != to.authority,
+    }))
+}
+
+// Pool price at a past timestamp or slot from recorded history, e.g. for PNL
+// and tax tooling. `distance` tells how far the snapshots used are from the
+// target, in the unit asked for.
+#[get("/pool/{pool_id}/price-at")]
+async fn get_price_at(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<PriceAtQuery>,
+) -> HttpResponse {
+    let pubkey = pool_id.0;
+    let (target, key, unit): (u64, fn(&PoolSnapshot) -> u64, &str) = match (query.timestamp, query.slot) {
+        (Some(timestamp), None) => (timestamp, |p| p.timestamp, "timestamp"),
+        (None, Some(slot)) => (slot, |p| p.slot, "slot"),
+        _ => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "Pass exactly one of timestamp or slot"
+            }));
+        }
+    };
+
+    let Some(decimals) = Decimals::cached(&state, &pubkey) else {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": format!("Pool {} has not been polled yet", pubkey)
+        }));
+    };
+    let (before, after) = state.history.around(&pubkey, target, key);
+    let (price, used) = match (query.interpolation, before, after) {
+        (Interpolation::Previous, Some(before), _) => (before.price, vec![before]),
+        (Interpolation::Nearest, Some(before), Some(after)) => {
+            if target - key(&before) <= key(&after) - target {
+                (before.price, vec![before])
+            } else {
+                (after.price, vec![after])
+            }
+        }
+        (Interpolation::Nearest, Some(only), None) | (Interpolation::Nearest, None, Some(only)) => (only.price, vec![only]),
+        (Interpolation::Linear, Some(before), Some(after)) => {
+            let span = key(&after) - key(&before);
+            let price = if span == 0 {
+                before.price
+            } else {
+                let weight = (target - key(&before)) as f64 / span as f64;
+                before.price + (after.price - before.price) * weight
+            };
+            (price, vec![before, after])
+        }
+        _ => {
+            return HttpResponse::NotFound().json(json!({
+                "error": format!("No stored snapshots for {} around {} {}", pubkey, unit, target)
+            }));
+        }
+    };
+    let distance = used.iter().map(|p| key(p).abs_diff(target)).max().unwrap_or(0);
+
+    HttpResponse::Ok().json(json!({
+        "pool_id": pool_id.to_string(),
+        unit: target,
+        "interpolation": query.interpolation,
+        "price": price,
+        "distance": distance,
+        "snapshots": used.iter().map(|snapshot| snapshot.json(decimals)).collect::<Vec<_>>(),
+    }))
+}
@@ -0,0 +1,79 @@
+use crate::dex::DecodedPool;
+use crate::fixed;
+use crate::history::PoolSnapshot;
+use crate::token;
+use actix_web::dev::Payload;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::future::{ready, Ready};
+
+#[derive(Deserialize)]
+struct HumanQuery {
+    #[serde(default)]
+    human: bool,
+}
+
+// Whether a client asked for `?human=true`: display strings of the amounts
+// in a response, like "1.23M USDC", under `human` next to the raw integers.
+pub struct Human(pub bool);
+
+impl FromRequest for Human {
+    type Error = Error;
+    type Future = Ready<Result<Human, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let human = web::Query::<HumanQuery>::from_query(req.query_string()).is_ok_and(|query| query.human);
+        ready(Ok(Human(human)))
+    }
+}
+
+// A number for display: thousands and up abbreviated to K, M or B with two
+// decimals, smaller ones with two decimals or, below one, three significant
+// digits.
+pub fn number(value: f64) -> String {
+    let abs = value.abs();
+    let text = if abs >= 1e9 {
+        format!("{:.2}B", value / 1e9)
+    } else if abs >= 1e6 {
+        format!("{:.2}M", value / 1e6)
+    } else if abs >= 1e3 {
+        format!("{:.2}K", value / 1e3)
+    } else if abs >= 1.0 || abs == 0.0 {
+        format!("{:.2}", value)
+    } else {
+        let decimals = 2 - abs.log10().floor() as i32;
+        format!("{:.*}", decimals.max(0) as usize, value)
+    };
+    trim(text)
+}
+
+// Drop trailing zeros of the fraction, keeping any suffix.
+fn trim(text: String) -> String {
+    let digits = text.trim_end_matches(char::is_alphabetic);
+    let suffix = &text[digits.len()..];
+    if !digits.contains('.') {
+        return text;
+    }
+    format!("{}{}", digits.trim_end_matches('0').trim_end_matches('.'), suffix)
+}
+
+// A raw token amount in whole tokens of `mint`, e.g. "0.0532 SOL".
+pub fn amount(raw: u128, decimals: u8, mint: &Pubkey) -> String {
+    format!("{} {}", number(fixed::to_tokens(raw, decimals)), token::symbol(mint))
+}
+
+// Reserves, LP supply and price of a pool. The price is in token b per
+// token a.
+pub fn pool_fields(pool: &DecodedPool, snapshot: &PoolSnapshot) -> Value {
+    let mut fields = json!({
+        "reserve_a": amount(snapshot.reserve_a as u128, pool.decimals_a, &pool.mint_a),
+        "reserve_b": amount(snapshot.reserve_b as u128, pool.decimals_b, &pool.mint_b),
+        "price": format!("{} {}", number(snapshot.price), token::symbol(&pool.mint_b)),
+    });
+    if let Some(lp_supply) = snapshot.lp_supply {
+        fields["lp_supply"] = json!(format!("{} LP", number(fixed::to_tokens(lp_supply as u128, pool.lp_decimals))));
+    }
+    fields
+}
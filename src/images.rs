@@ -0,0 +1,381 @@
+use crate::address::ValidatedPubkey;
+use crate::config::{redact_url, HttpClientConfig, TokenImagesConfig};
+use crate::metadata;
+use crate::state::{unix_now, AppState};
+use crate::upstream;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse};
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// A failed lookup is answered from memory for this long, so a page full of
+// broken images doesn't hammer the gateways.
+const FAILURE_TTL_SECS: u64 = 5 * 60;
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Clone)]
+struct CachedImage {
+    content_type: &'static str,
+    body: Bytes,
+    fetched_at: u64,
+}
+
+#[derive(Clone)]
+enum ImageError {
+    // The mint has no metadata or image, or points somewhere we won't go.
+    NotFound(String),
+    // The RPC node, a gateway or the image host failed us.
+    Upstream(String),
+}
+
+impl ImageError {
+    fn response(&self) -> HttpResponse {
+        match self {
+            ImageError::NotFound(e) => HttpResponse::NotFound().json(json!({ "error": e })),
+            ImageError::Upstream(e) => HttpResponse::BadGateway().json(json!({ "error": e })),
+        }
+    }
+}
+
+// Images by mint, plus recent failures.
+#[derive(Default)]
+pub struct ImageCache {
+    images: Mutex<HashMap<Pubkey, CachedImage>>,
+    failures: Mutex<HashMap<Pubkey, (ImageError, u64)>>,
+}
+
+impl ImageCache {
+    fn get(&self, mint: &Pubkey, max_age: u64) -> Option<CachedImage> {
+        let images = self.images.lock().unwrap();
+        images.get(mint).filter(|image| unix_now().saturating_sub(image.fetched_at) < max_age).cloned()
+    }
+
+    // Evicts the oldest images until the new one fits in `max_bytes`.
+    fn insert(&self, mint: Pubkey, image: CachedImage, max_bytes: usize) {
+        if image.body.len() > max_bytes {
+            return;
+        }
+        let mut images = self.images.lock().unwrap();
+        images.remove(&mint);
+        let mut total: usize = images.values().map(|image| image.body.len()).sum();
+        while total + image.body.len() > max_bytes {
+            let Some(oldest) = images.iter().min_by_key(|(_, image)| image.fetched_at).map(|(mint, _)| *mint) else {
+                break;
+            };
+            total -= images.remove(&oldest).map_or(0, |image| image.body.len());
+        }
+        images.insert(mint, image);
+    }
+
+    fn failure(&self, mint: &Pubkey) -> Option<ImageError> {
+        let failures = self.failures.lock().unwrap();
+        failures
+            .get(mint)
+            .filter(|(_, at)| unix_now().saturating_sub(*at) < FAILURE_TTL_SECS)
+            .map(|(error, _)| error.clone())
+    }
+
+    fn record_failure(&self, mint: Pubkey, error: ImageError) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, (_, at)| unix_now().saturating_sub(*at) < FAILURE_TTL_SECS);
+        failures.insert(mint, (error, unix_now()));
+    }
+}
+
+// A token's image, found through its metadata URI and served from here so
+// frontends don't depend on IPFS gateways or arbitrary hosts. Only raster
+// formats are passed on, SVG can carry scripts.
+#[get("/token/{mint}/image")]
+async fn get_token_image(state: web::Data<AppState>, mint: web::Path<ValidatedPubkey>) -> HttpResponse {
+    let mint = mint.0;
+    let config = state.config().token_images;
+
+    if let Some(image) = state.images.get(&mint, config.cache_secs) {
+        return image_response(&image, &config);
+    }
+    if let Some(error) = state.images.failure(&mint) {
+        return error.response();
+    }
+    match fetch_image(&state, mint, &config).await {
+        Ok(image) => {
+            state.images.insert(mint, image.clone(), config.cache_max_bytes);
+            image_response(&image, &config)
+        }
+        Err(error) => {
+            if let ImageError::Upstream(e) = &error {
+                eprintln!("Failed to fetch image of {}: {}", mint, e);
+            }
+            state.images.record_failure(mint, error.clone());
+            error.response()
+        }
+    }
+}
+
+fn image_response(image: &CachedImage, config: &TokenImagesConfig) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(image.content_type)
+        .insert_header(("Cache-Control", format!("public, max-age={}", config.cache_secs)))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .body(image.body.clone())
+}
+
+async fn fetch_image(state: &AppState, mint: Pubkey, config: &TokenImagesConfig) -> Result<CachedImage, ImageError> {
+    let metadata = metadata::get(state, mint)
+        .await
+        .map_err(ImageError::Upstream)?
+        .ok_or_else(|| ImageError::NotFound(format!("Mint {} has no metadata", mint)))?;
+    if metadata.uri.is_empty() {
+        return Err(ImageError::NotFound(format!("Metadata of {} has no URI", mint)));
+    }
+    let http_config = state.config.read().unwrap().http_client.clone();
+    let client = http_client(&http_config, config)?;
+
+    let url = resolve_uri(&metadata.uri, config).map_err(ImageError::NotFound)?;
+    let (declared, body) = fetch_limited(&client, &url, config.max_image_bytes.max(config.max_metadata_bytes)).await?;
+    // Some mints point their URI straight at the image
+    if let Some(content_type) = image_type(&body) {
+        return image(content_type, body, config);
+    }
+    if body.len() > config.max_metadata_bytes {
+        return Err(ImageError::Upstream(format!(
+            "Metadata JSON is over {} bytes",
+            config.max_metadata_bytes
+        )));
+    }
+    let json: Value = serde_json::from_slice(&body).map_err(|e| {
+        ImageError::Upstream(format!(
+            "Metadata at {} is not JSON ({}): {}",
+            redact_url(url.as_str()),
+            declared.as_deref().unwrap_or("no content type"),
+            e
+        ))
+    })?;
+    let image_uri = json["image"]
+        .as_str()
+        .filter(|uri| !uri.is_empty())
+        .ok_or_else(|| ImageError::NotFound(format!("Metadata of {} has no image", mint)))?;
+
+    let url = resolve_uri(image_uri, config).map_err(ImageError::NotFound)?;
+    let (declared, body) = fetch_limited(&client, &url, config.max_image_bytes).await?;
+    let content_type = image_type(&body).ok_or_else(|| {
+        ImageError::Upstream(format!(
+            "{} is not a PNG, JPEG, GIF, WebP or AVIF image ({})",
+            redact_url(url.as_str()),
+            declared.as_deref().unwrap_or("no content type")
+        ))
+    })?;
+    image(content_type, body, config)
+}
+
+fn image(content_type: &'static str, body: Bytes, config: &TokenImagesConfig) -> Result<CachedImage, ImageError> {
+    if body.len() > config.max_image_bytes {
+        return Err(ImageError::Upstream(format!("Image is over {} bytes", config.max_image_bytes)));
+    }
+    Ok(CachedImage {
+        content_type,
+        body,
+        fetched_at: unix_now(),
+    })
+}
+
+// Redirects are followed only to hosts a metadata URI could point at
+// directly, or to the gateways.
+fn http_client(http_config: &HttpClientConfig, config: &TokenImagesConfig) -> Result<reqwest::Client, ImageError> {
+    let gateways: Vec<String> = [&config.ipfs_gateway, &config.arweave_gateway]
+        .into_iter()
+        .filter_map(|gateway| Url::parse(gateway).ok()?.host_str().map(str::to_string))
+        .collect();
+    upstream::builder(http_config)
+        .map_err(ImageError::Upstream)?
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .redirect(redirect_policy(gateways.clone()))
+        .dns_resolver(PublicResolver::new(gateways))
+        .build()
+        .map_err(|e| ImageError::Upstream(format!("Failed to build HTTP client: {}", e)))
+}
+
+// Follows up to MAX_REDIRECTS redirects to public hosts or the `trusted`
+// ones, and stops at the first pointing anywhere else.
+pub fn redirect_policy(trusted: Vec<String>) -> Policy {
+    Policy::custom(move |attempt| {
+        let trusted = attempt.url().host_str().is_some_and(|host| trusted.iter().any(|t| t == host));
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if trusted || is_public(attempt.url()) {
+            attempt.follow()
+        } else {
+            attempt.stop()
+        }
+    })
+}
+
+// Where to fetch a metadata or image URI from. ipfs:// and ar:// go through
+// the configured gateways, and so do links into other IPFS gateways, many of
+// which are slow or gone. Anything else has to be a public http(s) URL.
+fn resolve_uri(uri: &str, config: &TokenImagesConfig) -> Result<Url, String> {
+    let uri = uri.trim();
+    if let Some(path) = uri.strip_prefix("ipfs://") {
+        return gateway_url(&config.ipfs_gateway, &format!("ipfs/{}", path.trim_start_matches("ipfs/")));
+    }
+    if let Some(id) = uri.strip_prefix("ar://") {
+        return gateway_url(&config.arweave_gateway, id);
+    }
+    let url = Url::parse(uri).map_err(|e| format!("Invalid URI {}: {}", uri, e))?;
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return Err(format!("Unsupported URI scheme {}", url.scheme()));
+    }
+    if let Some(path) = url.path().strip_prefix("/ipfs/") {
+        let path = match url.query() {
+            Some(query) => format!("ipfs/{}?{}", path, query),
+            None => format!("ipfs/{}", path),
+        };
+        return gateway_url(&config.ipfs_gateway, &path);
+    }
+    if !is_public(&url) {
+        return Err(format!("{} is not a public address", url.host_str().unwrap_or(uri)));
+    }
+    Ok(url)
+}
+
+// `path` below the gateway URL, keeping any access token in its query.
+fn gateway_url(gateway: &str, path: &str) -> Result<Url, String> {
+    let mut url = Url::parse(gateway).map_err(|e| format!("Invalid gateway {}: {}", redact_url(gateway), e))?;
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    url.set_path(&format!("{}/{}", url.path().trim_end_matches('/'), path));
+    if let Some(query) = query {
+        let query = match url.query() {
+            Some(token) => format!("{}&{}", query, token),
+            None => query.to_string(),
+        };
+        url.set_query(Some(&query));
+    }
+    Ok(url)
+}
+
+// Keeps URLs from outside, like metadata URIs, which anyone minting a token
+// controls, from reaching this host or its private network. Only looks at
+// the URL, names resolving to private addresses are caught by
+// `PublicResolver` when connecting.
+pub fn is_public(url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+    match IpAddr::from_str(&host) {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => !(host == "localhost" || host.ends_with(".localhost") || host.ends_with(".local") || host.ends_with(".internal")),
+    }
+}
+
+// Resolves names like the system resolver but drops private addresses, so
+// a public name can't point the client at this host or its network. The
+// client connects to the addresses checked here, a second lookup can't swap
+// in another. Names in `trusted` resolve as usual. Requests through a proxy
+// are resolved by the proxy instead.
+pub struct PublicResolver {
+    trusted: Vec<String>,
+}
+
+impl PublicResolver {
+    pub fn new(trusted: Vec<String>) -> Arc<Self> {
+        Arc::new(PublicResolver { trusted })
+    }
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let trusted = self.trusted.iter().any(|t| t.eq_ignore_ascii_case(&host));
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| trusted || is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64;
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast() || shared)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+            let unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00;
+            let link_local = (ip.segments()[0] & 0xffc0) == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+        }
+    }
+}
+
+// GET `url`, failing once the body passes `max_bytes`. Returns the declared
+// content type with the body.
+async fn fetch_limited(client: &reqwest::Client, url: &Url, max_bytes: usize) -> Result<(Option<String>, Bytes), ImageError> {
+    let shown = redact_url(url.as_str());
+    let mut response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|e| ImageError::Upstream(format!("Failed to fetch {}: {}", shown, e)))?;
+    if !response.status().is_success() {
+        return Err(ImageError::Upstream(format!("{} returned {}", shown, response.status())));
+    }
+    if response.content_length().is_some_and(|length| length > max_bytes as u64) {
+        return Err(ImageError::Upstream(format!("{} is over {} bytes", shown, max_bytes)));
+    }
+    let declared = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| ImageError::Upstream(format!("Failed to read {}: {}", shown, e)))?
+    {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(ImageError::Upstream(format!("{} is over {} bytes", shown, max_bytes)));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((declared, Bytes::from(body)))
+}
+
+// Content type from the leading bytes. Gateways often serve images as
+// application/octet-stream, so the declared type isn't trusted either way.
+fn image_type(body: &[u8]) -> Option<&'static str> {
+    if body.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if body.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if body.starts_with(b"GIF87a") || body.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if body.len() >= 12 && &body[..4] == b"RIFF" && &body[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if body.len() >= 12 && &body[4..8] == b"ftyp" && (&body[8..12] == b"avif" || &body[8..12] == b"avis") {
+        Some("image/avif")
+    } else {
+        None
+    }
+}
@@ -0,0 +1,273 @@
+use crate::dex::{self, pubkey_string, PoolLayout};
+use crate::discovery::scan_config;
+use crate::migration;
+use crate::pubsub_client::PubsubClient;
+use crate::state::{unix_now, AppState};
+use futures::StreamExt;
+use serde::Serialize;
+use solana_client::client_error::ClientError;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// A pool found by scanning or by a program subscription.
+#[derive(Clone, Debug, Serialize)]
+pub struct IndexedPool {
+    #[serde(serialize_with = "pubkey_string")]
+    pub pool: Pubkey,
+    pub dex: &'static str,
+    #[serde(serialize_with = "pubkey_string")]
+    pub mint_a: Pubkey,
+    #[serde(serialize_with = "pubkey_string")]
+    pub mint_b: Pubkey,
+    // Last slot the pool was seen at.
+    pub slot: u64,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct IndexStatus {
+    // Set once the index holds a complete scan, loaded or fresh.
+    pub ready: bool,
+    pub syncing: bool,
+    pub subscribed: bool,
+    pub last_sync: Option<u64>,
+    // Account updates received over the subscriptions.
+    pub updates: u64,
+    pub last_error: Option<String>,
+}
+
+// Every pool of the supported DEXes. Built by one full scan, persisted, and
+// then kept current from program subscriptions so discovery never has to
+// rescan.
+#[derive(Default)]
+pub struct PoolIndex {
+    pools: RwLock<HashMap<Pubkey, IndexedPool>>,
+    status: Mutex<IndexStatus>,
+    resync: Notify,
+}
+
+impl PoolIndex {
+    pub fn is_ready(&self) -> bool {
+        self.status.lock().unwrap().ready
+    }
+
+    pub fn status(&self) -> IndexStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    // Indexed pools per DEX.
+    pub fn counts(&self) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for pool in self.pools.read().unwrap().values() {
+            *counts.entry(pool.dex).or_default() += 1;
+        }
+        counts
+    }
+
+    // Pools with `mint` on either side, paired with `quote` when given.
+    pub fn find(&self, mint: &Pubkey, quote: Option<&Pubkey>) -> Vec<IndexedPool> {
+        self.pools
+            .read()
+            .unwrap()
+            .values()
+            .filter(|pool| {
+                let other = if pool.mint_a == *mint {
+                    pool.mint_b
+                } else if pool.mint_b == *mint {
+                    pool.mint_a
+                } else {
+                    return false;
+                };
+                quote.is_none_or(|quote| *quote == other)
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn request_resync(&self) {
+        self.resync.notify_one();
+    }
+
+    // Returns true for pools not indexed before.
+    fn upsert(&self, pool: IndexedPool) -> bool {
+        self.pools.write().unwrap().insert(pool.pool, pool).is_none()
+    }
+
+    fn replace_dex(&self, dex: &str, pools: Vec<IndexedPool>) {
+        let mut indexed = self.pools.write().unwrap();
+        indexed.retain(|_, pool| pool.dex != dex);
+        indexed.extend(pools.into_iter().map(|pool| (pool.pool, pool)));
+    }
+
+    fn set_error(&self, error: String) {
+        eprintln!("Pool index: {}", error);
+        self.status.lock().unwrap().last_error = Some(error);
+    }
+}
+
+// Background task maintaining the index while `index.enabled` is set.
+pub async fn run(state: Arc<AppState>) {
+    if !state.config().index.enabled {
+        return;
+    }
+
+    let index = &state.pool_index;
+    match state.with_store(|store| store.load_pool_index()).await {
+        Ok(pools) if !pools.is_empty() => {
+            println!("Loaded {} indexed pools", pools.len());
+            for pool in pools {
+                index.upsert(pool);
+            }
+            index.status.lock().unwrap().ready = true;
+        }
+        Ok(_) => resync(&state).await,
+        Err(e) => {
+            index.set_error(format!("Failed to load stored index: {}", e));
+            resync(&state).await;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            result = follow(&state) => {
+                index.status.lock().unwrap().subscribed = false;
+                if let Err(e) = result {
+                    index.set_error(e);
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+            _ = index.resync.notified() => {
+                index.status.lock().unwrap().subscribed = false;
+                resync(&state).await;
+            }
+        }
+    }
+}
+
+// Full scan of every supported program, replacing what is indexed.
+async fn resync(state: &Arc<AppState>) {
+    let index = &state.pool_index;
+    index.status.lock().unwrap().syncing = true;
+    let mut complete = true;
+    for layout in dex::LAYOUTS {
+        match scan(state, layout).await {
+            Ok(pools) => {
+                println!("Indexed {} {} pools", pools.len(), layout.dex);
+                let stored = pools.clone();
+                if let Err(e) = state.with_store(move |store| store.replace_pool_index(layout.dex, &stored)).await {
+                    index.set_error(format!("Failed to store {} pools: {}", layout.dex, e));
+                }
+                index.replace_dex(layout.dex, pools);
+            }
+            Err(e) => {
+                index.set_error(format!("Failed to scan {}: {}", layout.dex, e));
+                complete = false;
+            }
+        }
+    }
+    let mut status = index.status.lock().unwrap();
+    status.syncing = false;
+    if complete {
+        status.ready = true;
+        status.last_sync = Some(unix_now());
+    }
+}
+
+async fn scan(state: &AppState, layout: &'static PoolLayout) -> Result<Vec<IndexedPool>, String> {
+    let rpc_client = state.background_rpc_client().await;
+    let result = tokio::task::spawn_blocking(move || {
+        let slot = rpc_client.get_slot()?;
+        let config = scan_config(layout, &[], rpc_client.commitment());
+        let accounts = rpc_client.get_program_accounts_with_config(&layout.program_id, config)?;
+        Ok::<_, ClientError>((slot, accounts))
+    })
+    .await;
+    let (slot, accounts) = match result {
+        Ok(Ok(scanned)) => scanned,
+        Ok(Err(e)) => return Err(e.to_string()),
+        Err(e) => return Err(format!("Task failed: {}", e)),
+    };
+
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(pool, account)| {
+            let (mint_a, mint_b) = layout.mints(&account.data).ok()?;
+            Some(IndexedPool {
+                pool,
+                dex: layout.dex,
+                mint_a,
+                mint_b,
+                slot,
+            })
+        })
+        .collect())
+}
+
+// Subscribe to every supported program and index pools as their accounts
+// change. Only returns on error.
+async fn follow(state: &AppState) -> Result<(), String> {
+    let url = {
+        let config = state.config.read().unwrap();
+        config.index.ws_url.clone().unwrap_or_else(|| websocket_url(&config.rpc_urls[0]))
+    };
+    let client = PubsubClient::connect(&state.upstream, &url)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", crate::config::redact_url(&url), e))?;
+
+    let mut streams = Vec::new();
+    for layout in dex::LAYOUTS {
+        let config = scan_config(layout, &[], CommitmentConfig::confirmed());
+        let (stream, _unsubscribe) = client
+            .program_subscribe(&layout.program_id, Some(config))
+            .await
+            .map_err(|e| format!("Failed to subscribe to {}: {}", layout.dex, e))?;
+        streams.push(stream.map(move |update| (layout, update)));
+    }
+    state.pool_index.status.lock().unwrap().subscribed = true;
+
+    let mut updates = futures::stream::select_all(streams);
+    while let Some((layout, update)) = updates.next().await {
+        let (Ok(pool), Some(data)) = (Pubkey::from_str(&update.value.pubkey), update.value.account.data.decode()) else {
+            continue;
+        };
+        let Ok((mint_a, mint_b)) = layout.mints(&data) else {
+            continue;
+        };
+        let indexed = IndexedPool {
+            pool,
+            dex: layout.dex,
+            mint_a,
+            mint_b,
+            slot: update.context.slot,
+        };
+        state.pool_index.status.lock().unwrap().updates += 1;
+        // Existing pools update on every swap, only new ones are worth a write
+        if state.pool_index.upsert(indexed.clone()) {
+            println!("Indexed new {} pool {}", layout.dex, pool);
+            let saved = indexed.clone();
+            if let Err(e) = state.with_store(move |store| store.save_indexed_pools(&[saved])).await {
+                state.pool_index.set_error(format!("Failed to store pool {}: {}", pool, e));
+            }
+            migration::pool_created(state, &indexed).await;
+        }
+    }
+    Err("Program subscription closed".to_string())
+}
+
+// The PubSub endpoint of an HTTP RPC URL, for providers serving both on one
+// host. Set `index.ws_url` or `polling.ws_url` when they differ.
+pub fn websocket_url(rpc_url: &str) -> String {
+    if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    }
+}
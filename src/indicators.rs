@@ -0,0 +1,209 @@
+use crate::address::ValidatedPubkey;
+use crate::history::Candle;
+use crate::state::{unix_now, AppState};
+use crate::time::parse_duration;
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+const DEFAULT_SET: &str = "sma20,ema50,rsi14";
+const DEFAULT_INTERVAL_SECS: u64 = 60 * 60;
+const DEFAULT_POINTS: usize = 50;
+const MAX_PERIOD: usize = 1000;
+const MAX_POINTS: usize = 1000;
+// Standard deviations between the Bollinger middle band and the outer ones.
+const BOLLINGER_WIDTH: f64 = 2.0;
+
+#[derive(Deserialize)]
+struct IndicatorsQuery {
+    set: Option<String>,
+    interval: Option<String>,
+    // How many of the most recent values of each indicator to return.
+    points: Option<usize>,
+}
+
+#[derive(Clone, Copy)]
+enum Indicator {
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+    Bollinger(usize),
+    // 12/26 EMA difference with its 9 EMA signal line.
+    Macd,
+}
+
+impl Indicator {
+    fn parse(name: &str) -> Result<Indicator, String> {
+        if name == "macd" {
+            return Ok(Indicator::Macd);
+        }
+        let split = name.find(|c: char| c.is_ascii_digit()).unwrap_or(name.len());
+        let (kind, period) = name.split_at(split);
+        let period: usize = period
+            .parse()
+            .map_err(|_| format!("Indicator {} needs a period, e.g. {}20", name, kind))?;
+        if period == 0 || period > MAX_PERIOD {
+            return Err(format!("Indicator {} period must be between 1 and {}", name, MAX_PERIOD));
+        }
+        match kind {
+            "sma" => Ok(Indicator::Sma(period)),
+            "ema" => Ok(Indicator::Ema(period)),
+            "rsi" => Ok(Indicator::Rsi(period)),
+            "bb" => Ok(Indicator::Bollinger(period)),
+            _ => Err(format!("Unknown indicator {}, supported are sma, ema, rsi, bb and macd", name)),
+        }
+    }
+
+    // Value for every close, None while there aren't enough candles yet.
+    fn series(self, closes: &[f64]) -> Vec<Option<Value>> {
+        let numbers = |values: Vec<Option<f64>>| values.into_iter().map(|v| v.map(|v| json!(v))).collect();
+        match self {
+            Indicator::Sma(period) => numbers(sma(closes, period)),
+            Indicator::Ema(period) => numbers(ema(closes, period)),
+            Indicator::Rsi(period) => numbers(rsi(closes, period)),
+            Indicator::Bollinger(period) => sma(closes, period)
+                .into_iter()
+                .enumerate()
+                .map(|(i, middle)| {
+                    let middle = middle?;
+                    let window = &closes[i + 1 - period..=i];
+                    let deviation = (window.iter().map(|c| (c - middle).powi(2)).sum::<f64>() / period as f64).sqrt();
+                    Some(json!({
+                        "middle": middle,
+                        "upper": middle + BOLLINGER_WIDTH * deviation,
+                        "lower": middle - BOLLINGER_WIDTH * deviation,
+                    }))
+                })
+                .collect(),
+            Indicator::Macd => {
+                let (fast, slow) = (ema(closes, 12), ema(closes, 26));
+                let macd: Vec<Option<f64>> = fast.iter().zip(&slow).map(|(f, s)| Some((*f)? - (*s)?)).collect();
+                // The signal line starts once there are 9 MACD values
+                let start = macd.iter().position(Option::is_some).unwrap_or(macd.len());
+                let defined: Vec<f64> = macd[start..].iter().flatten().copied().collect();
+                let mut signal = vec![None; start];
+                signal.extend(ema(&defined, 9));
+                macd.into_iter()
+                    .zip(signal)
+                    .map(|(macd, signal)| {
+                        let macd = macd?;
+                        Some(json!({
+                            "macd": macd,
+                            "signal": signal,
+                            "histogram": signal.map(|signal| macd - signal),
+                        }))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+fn sma(closes: &[f64], period: usize) -> Vec<Option<f64>> {
+    (0..closes.len())
+        .map(|i| (i + 1 >= period).then(|| closes[i + 1 - period..=i].iter().sum::<f64>() / period as f64))
+        .collect()
+}
+
+// Seeded with the simple average of the first `period` closes.
+fn ema(closes: &[f64], period: usize) -> Vec<Option<f64>> {
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut previous: Option<f64> = None;
+    (0..closes.len())
+        .map(|i| {
+            previous = match previous {
+                Some(previous) => Some(previous + alpha * (closes[i] - previous)),
+                None if i + 1 == period => Some(closes[..period].iter().sum::<f64>() / period as f64),
+                None => None,
+            };
+            previous
+        })
+        .collect()
+}
+
+// Wilder's RSI, averages of gains and losses smoothed over `period` changes.
+fn rsi(closes: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut values = vec![None; closes.len()];
+    if closes.len() <= period {
+        return values;
+    }
+    let changes: Vec<f64> = closes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let mut gain = changes[..period].iter().map(|c| c.max(0.0)).sum::<f64>() / period as f64;
+    let mut loss = changes[..period].iter().map(|c| (-c).max(0.0)).sum::<f64>() / period as f64;
+    let index = |gain: f64, loss: f64| if loss == 0.0 { 100.0 } else { 100.0 - 100.0 / (1.0 + gain / loss) };
+    values[period] = Some(index(gain, loss));
+    for (i, change) in changes.iter().enumerate().skip(period) {
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+        values[i + 1] = Some(index(gain, loss));
+    }
+    values
+}
+
+// Technical indicators over the closed candles of a pool's recorded history,
+// for clients without a TA library of their own. Indicators without enough
+// candles yet have a null value.
+#[get("/pool/{pool_id}/indicators")]
+async fn get_pool_indicators(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<IndicatorsQuery>,
+) -> HttpResponse {
+    let pubkey = pool_id.0;
+    let retention = state.config.read().unwrap().history_retention_secs;
+    let interval = match query.interval.as_deref().map(|interval| parse_duration(interval, retention)) {
+        None => DEFAULT_INTERVAL_SECS,
+        Some(Ok(interval)) => interval,
+        Some(Err(e)) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    let names: Vec<&str> = query
+        .set
+        .as_deref()
+        .unwrap_or(DEFAULT_SET)
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    let indicators = match names.iter().map(|name| Indicator::parse(name)).collect::<Result<Vec<_>, _>>() {
+        Ok(indicators) if !indicators.is_empty() => indicators,
+        Ok(_) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "set must name at least one indicator"
+            }));
+        }
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    let points = query.points.unwrap_or(DEFAULT_POINTS).clamp(1, MAX_POINTS);
+
+    let candles: Vec<Candle> = state.history.candles(&pubkey, interval, unix_now());
+    if candles.is_empty() {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("No closed candles recorded for {} at this interval", pubkey)
+        }));
+    }
+    let closes: Vec<f64> = candles.iter().map(|candle| candle.close).collect();
+    let skip = candles.len().saturating_sub(points);
+
+    let mut values = Map::new();
+    for (name, indicator) in names.iter().zip(indicators) {
+        let series = indicator.series(&closes);
+        values.insert(name.to_string(), json!({
+            "value": series.last().cloned().flatten(),
+            "series": candles
+                .iter()
+                .zip(series)
+                .skip(skip)
+                .map(|(candle, value)| json!({ "open_time": candle.open_time, "value": value }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+    let last = candles.last().unwrap();
+    HttpResponse::Ok().json(json!({
+        "pool_id": pool_id.to_string(),
+        "interval_secs": interval,
+        "candle_count": candles.len(),
+        "last_open_time": last.open_time,
+        "close": last.close,
+        "indicators": values,
+    }))
+}
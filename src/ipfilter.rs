@@ -0,0 +1,76 @@
+use crate::state::AppState;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use ipnet::IpNet;
+use serde_json::json;
+use std::net::IpAddr;
+
+// Address a request is attributed to, set by `filter`. The connecting peer
+// unless it is a trusted proxy.
+#[derive(Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+// A CIDR range, or a single address as a range of one.
+pub fn parse_range(text: &str) -> Result<IpNet, String> {
+    text.parse::<IpNet>()
+        .or_else(|_| text.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("Invalid IP address or CIDR range {}", text))
+}
+
+// Ranges are checked when the config is validated.
+fn contains(ranges: &[String], ip: IpAddr) -> bool {
+    ranges.iter().filter_map(|range| parse_range(range).ok()).any(|range| range.contains(&ip))
+}
+
+// The peer, or when it is a trusted proxy the address it forwarded for.
+// X-Forwarded-For is read from the right, each trusted proxy appends the
+// address it got the request from, so anything left of the first untrusted
+// address could be made up by the client.
+fn client_ip(req: &ServiceRequest, trusted_proxies: &[String]) -> Option<IpAddr> {
+    let mut ip = req.peer_addr()?.ip().to_canonical();
+    let forwarded: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    for hop in forwarded.into_iter().rev() {
+        if !contains(trusted_proxies, ip) {
+            break;
+        }
+        // The last trusted proxy is all we know of a garbled header
+        let Ok(hop) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        ip = hop.to_canonical();
+    }
+    Some(ip)
+}
+
+// Refuse clients outside `ip_filter.allow` or inside `ip_filter.deny`
+// before anything else looks at the request. Clients of a Unix socket have
+// no address, the socket's permissions decide who connects there.
+pub async fn filter(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let config = state.config.read().unwrap().ip_filter.clone();
+    let Some(ip) = client_ip(&req, &config.trusted_proxies) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    req.extensions_mut().insert(ClientIp(ip));
+    let allowed = config.allow.is_empty() || contains(&config.allow, ip);
+    if !allowed || contains(&config.deny, ip) {
+        let response = HttpResponse::Forbidden().json(json!({
+            "error": format!("Access from {} is not allowed", ip)
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
@@ -0,0 +1,144 @@
+use crate::config::{JwtConfig, Role};
+use crate::keys::Resolved;
+use crate::periodic::{self, FETCH_TIMEOUT};
+use crate::state::AppState;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// Only public key signatures, a shared HMAC secret would have to be handed
+// to the browser as well.
+const ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+// Signing keys of the configured identity provider.
+#[derive(Default)]
+pub struct Jwks {
+    keys: RwLock<Option<JwkSet>>,
+    // Woken by a token signed by an unknown key, the provider may have
+    // rotated its keys.
+    refetch: Notify,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+// Whether a bearer token is meant for us rather than being the admin token.
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+// Highest known role in a claim that is a role name or a list of them.
+fn role_of(claim: &Value) -> Option<Role> {
+    match claim {
+        Value::String(name) => Role::from_str(name).ok(),
+        Value::Array(names) => names.iter().filter_map(|name| Role::from_str(name.as_str()?).ok()).max(),
+        _ => None,
+    }
+}
+
+// Check a token's signature against the provider's keys and its issuer,
+// audience and expiry, and resolve it like an API key named after the
+// subject.
+pub fn validate(state: &AppState, config: &JwtConfig, token: &str) -> Result<Resolved, String> {
+    let header = decode_header(token).map_err(|e| format!("Invalid token: {}", e))?;
+    if !ALGORITHMS.contains(&header.alg) {
+        return Err(format!("Invalid token: {:?} signatures aren't accepted", header.alg));
+    }
+    let key = {
+        let keys = state.jwks.keys.read().unwrap();
+        let Some(set) = keys.as_ref() else {
+            return Err("Signing keys of the identity provider haven't been fetched yet".to_string());
+        };
+        let jwk = match &header.kid {
+            Some(kid) => set.find(kid),
+            None if set.keys.len() == 1 => set.keys.first(),
+            None => None,
+        };
+        let Some(jwk) = jwk else {
+            state.jwks.refetch.notify_one();
+            return Err("Invalid token: signed by an unknown key".to_string());
+        };
+        DecodingKey::from_jwk(jwk).map_err(|e| format!("Unusable signing key: {}", e))?
+    };
+
+    let mut validation = Validation::new(header.alg);
+    validation.leeway = config.leeway_secs;
+    validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+    if let Some(issuer) = &config.issuer {
+        validation.set_issuer(&[issuer]);
+    }
+    match &config.audience {
+        Some(audience) => validation.set_audience(&[audience]),
+        None => validation.validate_aud = false,
+    }
+    let claims = decode::<Claims>(token, &key, &validation)
+        .map_err(|e| format!("Invalid token: {}", e))?
+        .claims;
+    let role = claims.other.get(&config.role_claim).and_then(role_of).unwrap_or(config.default_role);
+    Ok(Resolved {
+        name: format!("jwt:{}", claims.sub),
+        role,
+        quotas: config.quotas,
+    })
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<JwkSet, String> {
+    client
+        .get(url)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to fetch signing keys: {}", e.without_url()))?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse signing keys: {}", e.without_url()))
+}
+
+// Background task fetching the identity provider's signing keys every
+// `jwt.refresh_secs`, whenever the config is reloaded and when a token
+// names a key we don't have.
+pub async fn run(state: Arc<AppState>) {
+    let mut fetched_from: Option<String> = None;
+    periodic::every(&state, "JWT signing key refresh", Some(&state.jwks.refetch), || {
+        let config = state.config().jwt;
+        // Keys of another provider mustn't outlive a config change
+        if config.jwks_url != fetched_from {
+            *state.jwks.keys.write().unwrap() = None;
+        }
+        fetched_from = config.jwks_url.clone();
+        let state = &state;
+        async move {
+            let url = config.jwks_url?;
+            let result = fetch(&state.upstream.client(), &url).await;
+            let outcome = result.as_ref().map(|_| ()).map_err(|e| e.clone());
+            state.providers.record(&url, outcome, &state.config().providers);
+            let result = result.map(|set| {
+                println!("Loaded {} JWT signing keys", set.keys.len());
+                *state.jwks.keys.write().unwrap() = Some(set);
+            });
+            Some((Duration::from_secs(config.refresh_secs), result))
+        }
+    })
+    .await
+}
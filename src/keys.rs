@@ -0,0 +1,135 @@
+use crate::admin::constant_time_eq;
+use crate::config::{ApiKeysConfig, Quotas, Role};
+use crate::state::{random_hex, AppState};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, RwLock};
+
+// Generated secrets start with this, so leaked ones are easy to scan for.
+const SECRET_PREFIX: &str = "pm_";
+// Characters of the secret kept in the clear.
+const PREFIX_LEN: usize = 10;
+
+// An API key created through the admin API. Only a hash of its secret is
+// kept, the secret itself is shown once when created or rotated.
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub role: Role,
+    pub quotas: Quotas,
+    // Start of the secret, to tell keys apart.
+    pub prefix: String,
+    #[serde(skip)]
+    pub hash: String,
+    // Hash of the secret before the last rotation, still accepted until
+    // `previous_expires_at`.
+    #[serde(skip)]
+    pub previous_hash: Option<String>,
+    pub previous_expires_at: Option<u64>,
+    pub created_at: u64,
+    pub rotated_at: Option<u64>,
+    pub last_used_at: Option<u64>,
+    pub revoked_at: Option<u64>,
+}
+
+impl ApiKey {
+    fn matches(&self, hash: &str, now: u64) -> bool {
+        if self.revoked_at.is_some() {
+            return false;
+        }
+        let previous = match (&self.previous_hash, self.previous_expires_at) {
+            (Some(previous), Some(expires_at)) if now < expires_at => constant_time_eq(previous.as_bytes(), hash.as_bytes()),
+            _ => false,
+        };
+        constant_time_eq(self.hash.as_bytes(), hash.as_bytes()) || previous
+    }
+}
+
+pub fn hash(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// A new secret with its prefix and hash.
+pub fn generate_secret() -> (String, String, String) {
+    let secret = format!("{}{}", SECRET_PREFIX, random_hex(24));
+    let prefix = secret[..PREFIX_LEN].to_string();
+    let hash = hash(&secret);
+    (secret, prefix, hash)
+}
+
+// Keys created through the admin API, by id, loaded from storage at
+// startup and written back on every change.
+#[derive(Default)]
+pub struct ApiKeys {
+    keys: RwLock<HashMap<String, ApiKey>>,
+    // Keys whose `last_used_at` moved since they were last saved.
+    used: Mutex<HashSet<String>>,
+}
+
+impl ApiKeys {
+    pub fn load(&self, keys: Vec<ApiKey>) {
+        *self.keys.write().unwrap() = keys.into_iter().map(|key| (key.id.clone(), key)).collect();
+    }
+
+    // Oldest first.
+    pub fn all(&self) -> Vec<ApiKey> {
+        let mut keys: Vec<ApiKey> = self.keys.read().unwrap().values().cloned().collect();
+        keys.sort_by_key(|key| key.created_at);
+        keys
+    }
+
+    pub fn get(&self, id: &str) -> Option<ApiKey> {
+        self.keys.read().unwrap().get(id).cloned()
+    }
+
+    pub fn insert(&self, key: ApiKey) {
+        self.keys.write().unwrap().insert(key.id.clone(), key);
+    }
+
+    // Whether a key that isn't revoked goes by `name`.
+    pub fn name_taken(&self, name: &str) -> bool {
+        self.keys.read().unwrap().values().any(|key| key.name == name && key.revoked_at.is_none())
+    }
+
+    // Keys used since the last call, for saving their `last_used_at`.
+    pub fn take_used(&self) -> Vec<ApiKey> {
+        let used = std::mem::take(&mut *self.used.lock().unwrap());
+        let keys = self.keys.read().unwrap();
+        used.iter().filter_map(|id| keys.get(id).cloned()).collect()
+    }
+
+    fn find(&self, secret: &str, now: u64) -> Option<ApiKey> {
+        let hash = hash(secret);
+        let mut keys = self.keys.write().unwrap();
+        let key = keys.values_mut().find(|key| key.matches(&hash, now))?;
+        key.last_used_at = Some(now);
+        self.used.lock().unwrap().insert(key.id.clone());
+        Some(key.clone())
+    }
+}
+
+// What a presented key may do, whether it came from the config or the
+// admin API. Usage is metered by name. Set on requests by usage::meter.
+#[derive(Clone)]
+pub struct Resolved {
+    pub name: String,
+    pub role: Role,
+    pub quotas: Quotas,
+}
+
+pub fn resolve(state: &AppState, config: &ApiKeysConfig, secret: &str, now: u64) -> Option<Resolved> {
+    if let Some((name, key)) = config.keys.iter().find(|(_, key)| constant_time_eq(key.key.as_bytes(), secret.as_bytes())) {
+        return Some(Resolved {
+            name: name.clone(),
+            role: key.role,
+            quotas: key.quotas,
+        });
+    }
+    state.api_keys.find(secret, now).map(|key| Resolved {
+        name: key.name,
+        role: key.role,
+        quotas: key.quotas,
+    })
+}
@@ -0,0 +1,225 @@
+use crate::config::LimitsConfig;
+use crate::state::AppState;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use serde_json::json;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Semaphore};
+
+// Routes doing heavy upstream work on every request.
+const EXPENSIVE_ROUTES: &[&str] = &[
+    "/simulate",
+    "/solana/send",
+    "/token-pair/{token_a}/{token_b}",
+    "/transactions/{token}",
+    "/pools/discover",
+    "/wallet/{address}/tax-export",
+];
+// Streams would hold a permit for as long as they stay open, and metrics
+// must stay scrapeable while the service is overloaded.
+const UNLIMITED_ROUTES: &[&str] = &["/ws", "/sse/stream", "/pool/{pool_id}/replay", "/metrics"];
+
+const RETRY_AFTER_SECS: u64 = 1;
+
+// How often each event loop is checked for lag.
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+thread_local! {
+    // Whether this worker's event loop is being probed.
+    static PROBED: Cell<bool> = const { Cell::new(false) };
+}
+
+// Which upstream work goes first when every RPC slot is taken.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    // API requests someone is waiting on.
+    Interactive,
+    // Polling, index scans and other ingestion, runs on whatever interactive
+    // requests leave free.
+    Background,
+}
+
+// RPC slots, handed to interactive waiters before background ones and in
+// arrival order within each.
+#[derive(Default)]
+struct RpcQueue {
+    available: usize,
+    interactive: VecDeque<oneshot::Sender<RpcPermit>>,
+    background: VecDeque<oneshot::Sender<RpcPermit>>,
+}
+
+impl RpcQueue {
+    fn waiting(queue: &VecDeque<oneshot::Sender<RpcPermit>>) -> usize {
+        queue.iter().filter(|waiter| !waiter.is_closed()).count()
+    }
+}
+
+// One upstream RPC slot, passed on to the next waiter when dropped.
+pub struct RpcPermit {
+    queue: Arc<Mutex<RpcQueue>>,
+}
+
+impl Drop for RpcPermit {
+    fn drop(&mut self) {
+        let next = {
+            let mut queue = self.queue.lock().unwrap();
+            match queue.interactive.pop_front().or_else(|| queue.background.pop_front()) {
+                Some(next) => next,
+                None => {
+                    queue.available += 1;
+                    return;
+                }
+            }
+        };
+        // A waiter that gave up drops the permit again, which moves it on to
+        // the one after
+        let _ = next.send(RpcPermit {
+            queue: self.queue.clone(),
+        });
+    }
+}
+
+pub struct Limits {
+    rpc: Arc<Mutex<RpcQueue>>,
+    rpc_capacity: usize,
+    requests: Arc<Semaphore>,
+    expensive: Arc<Semaphore>,
+    shed_lag: Duration,
+    // Smoothed lag of each event loop, by the thread running it.
+    lags: Mutex<HashMap<ThreadId, Duration>>,
+    pub rejected: AtomicU64,
+    pub rejected_expensive: AtomicU64,
+    pub shed: AtomicU64,
+}
+
+impl Limits {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Limits {
+            rpc: Arc::new(Mutex::new(RpcQueue {
+                available: config.max_rpc_in_flight,
+                ..RpcQueue::default()
+            })),
+            rpc_capacity: config.max_rpc_in_flight,
+            requests: Arc::new(Semaphore::new(config.max_requests)),
+            expensive: Arc::new(Semaphore::new(config.max_expensive_requests)),
+            shed_lag: Duration::from_millis(config.shed_lag_ms),
+            lags: Mutex::default(),
+            rejected: AtomicU64::new(0),
+            rejected_expensive: AtomicU64::new(0),
+            shed: AtomicU64::new(0),
+        }
+    }
+
+    // Wait for an upstream RPC slot. The wait is bounded because handlers are
+    // capped by the request limits and the poller runs one pool at a time,
+    // background work only waits longer while interactive requests keep
+    // every slot busy.
+    pub async fn rpc_permit(&self, priority: Priority) -> RpcPermit {
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut queue = self.rpc.lock().unwrap();
+            if queue.available > 0 {
+                queue.available -= 1;
+                return RpcPermit { queue: self.rpc.clone() };
+            }
+            match priority {
+                Priority::Interactive => queue.interactive.push_back(sender),
+                Priority::Background => queue.background.push_back(sender),
+            }
+        }
+        receiver.await.expect("queued RPC waiters are always handed a permit")
+    }
+
+    pub fn rpc_in_flight(&self) -> usize {
+        self.rpc_capacity - self.rpc.lock().unwrap().available
+    }
+
+    // Interactive and background work waiting for an RPC slot.
+    pub fn rpc_queued(&self) -> (usize, usize) {
+        let queue = self.rpc.lock().unwrap();
+        (RpcQueue::waiting(&queue.interactive), RpcQueue::waiting(&queue.background))
+    }
+
+    // Lag of the event loop furthest behind.
+    pub fn event_loop_lag(&self) -> Duration {
+        self.lags.lock().unwrap().values().max().copied().unwrap_or_default()
+    }
+
+    fn sheds(&self, expensive: bool) -> bool {
+        if self.shed_lag.is_zero() {
+            return false;
+        }
+        let threshold = if expensive { self.shed_lag } else { self.shed_lag * 2 };
+        self.event_loop_lag() >= threshold
+    }
+}
+
+// Measure how late the current event loop wakes a timer, smoothed over the
+// last few probes so a single slow poll doesn't shed requests. Runs on the
+// main loop, where pools are polled and alerts evaluated, and on each HTTP
+// worker from its first request.
+pub async fn probe(state: Arc<AppState>) {
+    let thread = std::thread::current().id();
+    let mut lag = Duration::ZERO;
+    loop {
+        let start = Instant::now();
+        tokio::time::sleep(PROBE_INTERVAL).await;
+        lag = (lag * 3 + start.elapsed().saturating_sub(PROBE_INTERVAL)) / 4;
+        state.limits.lags.lock().unwrap().insert(thread, lag);
+    }
+}
+
+fn unavailable(message: &str) -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header(("Retry-After", RETRY_AFTER_SECS.to_string()))
+        .json(json!({
+            "error": message,
+            "hint": format!("The service is at capacity, retry in {} second(s)", RETRY_AFTER_SECS),
+        }))
+}
+
+// Reject requests with 503 once their class of route is at capacity rather
+// than letting them queue, or while the event loops are falling behind.
+pub async fn limit_concurrency(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if !PROBED.replace(true) {
+        actix_web::rt::spawn(probe(state.clone().into_inner()));
+    }
+    let pattern = req.match_pattern();
+    if pattern.as_deref().is_some_and(|p| UNLIMITED_ROUTES.contains(&p)) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let expensive = pattern.as_deref().is_some_and(|p| EXPENSIVE_ROUTES.contains(&p));
+
+    let limits = &state.limits;
+    // Operators need /admin most when the service is overloaded
+    let admin = pattern.as_deref().is_some_and(|p| p.starts_with("/admin"));
+    if !admin && limits.sheds(expensive) {
+        limits.shed.fetch_add(1, Ordering::Relaxed);
+        let response = unavailable("Service overloaded");
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    let (semaphore, rejected, message) = if expensive {
+        (&limits.expensive, &limits.rejected_expensive, "Too many expensive requests in flight")
+    } else {
+        (&limits.requests, &limits.rejected, "Too many requests in flight")
+    };
+    let Ok(_permit) = semaphore.clone().try_acquire_owned() else {
+        rejected.fetch_add(1, Ordering::Relaxed);
+        let response = unavailable(message);
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+    Ok(next.call(req).await?.map_into_left_body())
+}
@@ -1,33 +1,187 @@
-use actix_web::{web, App, HttpServer, HttpResponse, get};
+// ClientError is large, but spawn_blocking closures returning it directly is the
+// pattern every RPC handler uses, so boxing it everywhere buys nothing.
+#![allow(clippy::result_large_err)]
+
+mod accounts;
+mod address;
+mod amount;
+mod admin;
+mod alerts;
+mod annotations;
+mod analytics;
+mod audit;
+mod backup;
+mod blocklist;
+mod budget;
+mod cassette;
+mod charts;
+mod cluster;
+mod config;
+mod creation;
+mod dashboard;
+mod dex;
+mod discovery;
+mod email;
+mod events;
+mod fields;
+mod fees;
+mod fixed;
+mod format;
+mod gaps;
+mod grafana;
+mod history;
+mod human;
+mod images;
+mod index;
+mod ipfilter;
+mod jwt;
+mod keys;
+mod indicators;
+mod limits;
+mod metadata;
+mod metrics;
+mod migration;
+mod mute;
+mod outliers;
+mod pagerduty;
+mod periodic;
+mod poller;
+mod portfolio;
+mod pricing;
+mod providers;
+mod public;
+mod pubsub;
+mod pubsub_client;
+mod quote;
+mod reports;
+mod rewards;
+mod roles;
+mod rules;
+mod schedule;
+mod send;
+mod simulate;
+mod slack;
+mod sse;
+mod staleness;
+mod state;
+mod storage;
+mod streaming;
+mod subscriptions;
+mod tasks;
+mod tax;
+mod templates;
+#[cfg(test)]
+mod tests;
+mod ticker;
+mod time;
+mod token;
+mod tokenlist;
+mod trail;
+mod upgrades;
+mod upstream;
+mod usage;
+mod ws;
+
+use actix_web::http::header::ContentType;
+use actix_web::{middleware, web, App, HttpServer, HttpResponse, get};
 use actix_cors::Cors;
-use serde::{Deserialize, Serialize};
+use address::ValidatedPubkey;
+use amount::Amount;
+use cluster::Cluster;
+use config::Config;
+use creation::Lookup;
+use fields::Fields;
+use history::Decimals;
+use human::Human;
+use schedule::PollTier;
+use serde::Deserialize;
 use serde_json::json;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
-use std::str::FromStr;
+use state::AppState;
+use std::sync::Arc;
+use std::time::Duration;
 
-// Basic data structure for pool info
-#[derive(Serialize, Deserialize)]
-struct PoolInfo {
-    address: String,
-    message: String,
-}
+// Last snapshot and freshness of every watched pool in one response, for
+// dashboards rendering an overview.
+#[get("/watchlist/status")]
+async fn get_watchlist_status(state: web::Data<AppState>, human: Human) -> HttpResponse {
+    let config = state.config();
+    let cluster = Cluster::default_for(&config);
+    let mut stale_count = 0;
+    let pools: Vec<_> = state
+        .watchlist()
+        .into_iter()
+        .map(|(pool, tier)| {
+            let cached = state.cache.get(&pool);
+            let cached = cached.as_deref();
+            let freshness = cached.map(|cached| staleness::freshness(&state, &cluster, &pool, cached));
+            // Never fetched at all is stale unless it isn't being polled
+            let polled = tier != PollTier::Dormant || config.polling.dormant_interval_secs > 0;
+            let stale = freshness.map_or(polled, |freshness| freshness.stale);
+            if stale {
+                stale_count += 1;
+            }
+            let poller = state.pollers.get(&pool).map(|status| status.clone());
+            let poller = poller.as_ref();
+            let formatted = cached
+                .filter(|_| human.0)
+                .and_then(|cached| Some(human::pool_fields(cached.pool.as_ref()?, cached.snapshot.as_ref()?)));
+            let mut entry = json!({
+                "pool_id": pool.to_string(),
+                "tier": tier,
+                "dex": cached.and_then(|cached| cached.dex),
+                "label": cached.and_then(|cached| cached.pool.as_ref()).map(|pool| pool.label()),
+                "snapshot": cached
+                    .and_then(|cached| Some(cached.snapshot.as_ref()?.json(Decimals::of(cached.pool.as_ref()?)))),
+                "last_update": cached.map(|cached| cached.fetched_at),
+                "age_secs": freshness.map(|freshness| freshness.age_secs),
+                "slot_lag": freshness.map(|freshness| freshness.slot_lag),
+                "stale": stale,
+                "decode_warnings": cached.map(|cached| &cached.decode_warnings),
+                // Only while failing, the poller keeps the last error after recovering
+                "last_error": poller
+                    .filter(|status| status.consecutive_failures > 0)
+                    .and_then(|status| status.last_error.clone()),
+                "consecutive_failures": poller.map_or(0, |status| status.consecutive_failures),
+            });
+            if let Some(formatted) = formatted {
+                entry["human"] = formatted;
+            }
+            entry
+        })
+        .collect();
 
-fn create_rpc_client() -> RpcClient {
-    let url = "https://api.mainnet-beta.solana.com".to_string();
-    RpcClient::new_with_commitment(url, CommitmentConfig::confirmed())
+    HttpResponse::Ok().json(json!({
+        "paused": state.is_paused(),
+        "stale": stale_count,
+        "pools": pools,
+    }))
 }
 
 #[get("/solana/status")]
-async fn get_solana_status() -> HttpResponse {
-    let rpc_client = create_rpc_client();
+async fn get_solana_status(state: web::Data<AppState>, cluster: Cluster) -> HttpResponse {
+    let rpc_client = state.cluster_rpc_client(&cluster).await;
 
     match tokio::task::spawn_blocking(move || rpc_client.get_slot()).await {
         Ok(Ok(slot)) => {
+            // Service-level problems of the default cluster's data
+            let mut warnings = Vec::new();
+            if cluster.is_default() {
+                for program in state.program_watch.all() {
+                    if let Some(upgrade) = program.upgrade {
+                        warnings.push(json!({
+                            "code": "program_upgraded",
+                            "dex": program.dex,
+                            "message": format!("The {} program was redeployed at slot {}, decoding is unverified", program.dex, upgrade.deploy_slot),
+                        }));
+                    }
+                }
+            }
             HttpResponse::Ok().json(json!({
                 "status": "connected",
-                "current_slot": slot
+                "cluster": cluster.name,
+                "current_slot": slot,
+                "warnings": warnings,
             }))
         },
         Ok(Err(e)) => {
@@ -45,67 +199,135 @@ async fn get_solana_status() -> HttpResponse {
     }
 }
 
+#[derive(Deserialize)]
+struct PoolQuery {
+    // Serve the pool as it was at this slot instead of now.
+    slot: Option<u64>,
+}
+
 #[get("/pool/{pool_id}")]
-async fn get_pool_info(pool_id: web::Path<String>) -> HttpResponse {
-    let rpc_client = create_rpc_client();
+async fn get_pool_info(
+    state: web::Data<AppState>,
+    cluster: Cluster,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<PoolQuery>,
+    fields: Fields,
+    human: Human,
+) -> HttpResponse {
+    let pubkey = pool_id.0;
 
-    let pubkey = match Pubkey::from_str(&pool_id){
-        Ok(key) => key,
-        Err(e) => {
+    let (result, historical) = match query.slot {
+        Some(_) if !cluster.is_default() || cluster.is_overridden() => {
             return HttpResponse::BadRequest().json(json!({
-                "error": format!("Invalid pool ID: {}", e)
+                "error": "Past pool state is only kept for the default cluster"
             }));
         }
-    };
-
-    match tokio::task::spawn_blocking(move || rpc_client.get_account(&pubkey)).await {
-        Ok(Ok(account)) => {
-            HttpResponse::Ok().json(json!({
-                "pool_id": pool_id.to_string(),
-                "lamports": account.lamports,
-                "data_size": account.data.len(),
-            }))
-        },
-        Ok(Err(e)) => {
-            eprintln!("RPC error getting account: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to get account: {}", e)
-            }))
+        Some(slot) => match poller::get_pool_at(&state, pubkey, slot).await {
+            Ok(Some((cached, source, exact))) => (Ok(Arc::new(cached)), Some((slot, source, exact))),
+            Ok(None) => {
+                return HttpResponse::NotFound().json(json!({
+                    "error": format!("No state of pool {} cached at or before slot {} and no archival RPC configured", pubkey, slot)
+                }));
+            }
+            Err(e) => (Err(e), None),
         },
+        None => (poller::get_pool(&state, &cluster, pubkey).await, None),
+    };
+    let cached = match result {
+        Ok(cached) => cached,
         Err(e) => {
-            eprintln!("Task error: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": format!("Task failed: {}", e)
-            }))
+            eprintln!("RPC error getting account: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": e
+            }));
         }
+    };
+
+    // What only depends on the cached state comes serialized already, the
+    // rest is built per request and merged in
+    let mut body = json!({
+        "pool_id": pool_id.to_string(),
+        "cluster": cluster.name,
+    });
+    // `slot` is when the state was observed, `exact` whether it is known to
+    // be the state at the requested slot too
+    if let Some((requested_slot, source, exact)) = historical {
+        body["requested_slot"] = json!(requested_slot);
+        body["source"] = json!(source);
+        body["exact"] = json!(exact);
+    } else {
+        let freshness = staleness::freshness(&state, &cluster, &pubkey, &cached);
+        body["age_secs"] = json!(freshness.age_secs);
+        body["slot_lag"] = json!(freshness.slot_lag);
+        body["stale"] = json!(freshness.stale);
     }
+    let mut involved = vec![("pool", pubkey)];
+    if let (Some(pool), Some(snapshot)) = (&cached.pool, &cached.snapshot) {
+        involved.extend([("mint_a", pool.mint_a), ("mint_b", pool.mint_b)]);
+        // History is only kept for the default cluster
+        let stats = ["price_change_5m", "price_change_1h", "price_change_24h", "volume_24h"];
+        let mut volume_24h = None;
+        if cluster.is_default() && stats.iter().any(|field| fields.wants(field)) {
+            volume_24h = history::volume(&state.history, &pubkey, snapshot, 24 * 60 * 60)
+                .map(|raw| Amount::new(raw, pool.decimals_b));
+            body["price_change_5m"] = json!(history::price_change(&state.history, &pubkey, snapshot, 5 * 60));
+            body["price_change_1h"] = json!(history::price_change(&state.history, &pubkey, snapshot, 60 * 60));
+            body["price_change_24h"] = json!(history::price_change(&state.history, &pubkey, snapshot, 24 * 60 * 60));
+            body["volume_24h"] = json!(volume_24h);
+        }
+        if cluster.is_default() && (fields.wants("migrated_to") || fields.wants("migrated_from")) {
+            match state.with_store(move |store| store.pool_links(&pubkey)).await {
+                Ok(links) => {
+                    let (to, from): (Vec<_>, Vec<_>) = links.into_iter().partition(|link| link.old_pool == pubkey);
+                    body["migrated_to"] = json!(to);
+                    body["migrated_from"] = json!(from);
+                }
+                Err(e) => eprintln!("Failed to load links of {}: {}", pubkey, e),
+            }
+        }
+        // Looked up once in the background, null until then. The creator is
+        // checked against the blocklist too.
+        let wants_creation = ["creation", "creation_error", "blocklisted"].iter().any(|field| fields.wants(field));
+        if cluster.is_default() && !cluster.is_overridden() && wants_creation {
+            body["creation"] = json!(null);
+            match creation::lookup(&state.clone().into_inner(), pubkey, pool).await {
+                Lookup::Resolved(creation) => {
+                    involved.push(("creator", creation.creator));
+                    body["creation"] = json!(creation);
+                }
+                Lookup::Pending => {}
+                Lookup::Failed(error) => body["creation_error"] = json!(error),
+            }
+        }
+        body["token_a"] = tokenlist::annotate(&state, &pool.mint_a, json!(cached.token_a));
+        body["token_b"] = tokenlist::annotate(&state, &pool.mint_b, json!(cached.token_b));
+        if human.0 {
+            let mut formatted = human::pool_fields(pool, snapshot);
+            if let Some(volume) = volume_24h {
+                formatted["volume_24h"] = json!(human::amount(volume.raw, volume.decimals, &pool.mint_b));
+            }
+            body["human"] = formatted;
+        }
+    }
+    body["blocklisted"] = json!(blocklist::flags(&state, &involved));
+    // Pools fetched for a past slot or an overridden cluster aren't cached
+    let encoded = cached.encoded.clone().unwrap_or_else(|| cached.encode_fields());
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(format::merge_objects(&[&encoded, body.to_string().as_bytes()]))
 }
 
 #[get("/token-pair/{token_a}/{token_b}")]
-async fn get_token_pair_info(path: web::Path<(String, String)>) -> HttpResponse {
+async fn get_token_pair_info(
+    state: web::Data<AppState>,
+    cluster: Cluster,
+    path: web::Path<(ValidatedPubkey, ValidatedPubkey)>,
+) -> HttpResponse {
     let (token_a, token_b) = path.into_inner();
     println!("Analyzing token pair: {} and {}", token_a, token_b);
     
-    let rpc_client = create_rpc_client();
-    
-    // Convert strings to pubkeys
-    let token_a_pubkey = match Pubkey::from_str(&token_a) {
-        Ok(key) => key,
-        Err(e) => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": format!("Invalid token A address: {}", e)
-            }));
-        }
-    };
-
-    let token_b_pubkey = match Pubkey::from_str(&token_b) {
-        Ok(key) => key,
-        Err(e) => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": format!("Invalid token B address: {}", e)
-            }));
-        }
-    };
+    let rpc_client = state.cluster_rpc_client(&cluster).await;
+    let (token_a_pubkey, token_b_pubkey) = (token_a.0, token_b.0);
 
     // Get token accounts info (wrapped in spawn_blocking)
     match tokio::task::spawn_blocking(move || {
@@ -114,15 +336,21 @@ async fn get_token_pair_info(path: web::Path<(String, String)>) -> HttpResponse
         Ok::<_, solana_client::client_error::ClientError>((token_a_info, token_b_info))
     }).await {
         Ok(Ok((token_a_info, token_b_info))) => {
+            // Null when neither a pool of the pair nor a route through SOL or
+            // USDC is cached
+            let pair = pricing::pair_price(&state, &cluster, &token_a_pubkey, &token_b_pubkey);
             HttpResponse::Ok().json(json!({
                 "token_a": {
-                    "address": token_a,
+                    "address": token_a.to_string(),
                     "data_size": token_a_info.data.len(),
                 },
                 "token_b": {
-                    "address": token_b,
+                    "address": token_b.to_string(),
                     "data_size": token_b_info.data.len(),
-                }
+                },
+                "price": pair.as_ref().map(|pair| pair.price),
+                "route": pair,
+                "blocklisted": blocklist::flags(&state, &[("token_a", token_a_pubkey), ("token_b", token_b_pubkey)]),
             }))
         },
         Ok(Err(e)) => {
@@ -141,55 +369,248 @@ async fn get_token_pair_info(path: web::Path<(String, String)>) -> HttpResponse
 }
 
 #[get("/transactions/{token}")]
-async fn get_token_transactions(token: web::Path<String>) -> HttpResponse {
+async fn get_token_transactions(state: web::Data<AppState>, token: web::Path<ValidatedPubkey>) -> HttpResponse {
     println!("Fetching Solscan transactions for token: {}", token);
     
-    let client = reqwest::Client::new();
-    let url = format!("https://public-api.solscan.io/token/transfers?token={}&limit=50", token);
-    
-    match client.get(&url).send().await {
-        Ok(response) => {
-            match response.json::<serde_json::Value>().await {
-                Ok(data) => {
-                    println!("Successfully got transaction data");
-                    HttpResponse::Ok().json(data)
-                },
-                Err(e) => {
-                    eprintln!("Error parsing response: {}", e);
-                    HttpResponse::InternalServerError().json(json!({
-                        "error": format!("Failed to parse response: {}", e)
-                    }))
+    let mint = token.0;
+    let token = mint.to_string();
+    let providers_config = state.config.read().unwrap().providers.clone();
+    if !state.providers.available(upstream::SOLSCAN, &providers_config) {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": "Solscan is failing, calls are paused, see GET /providers/status"
+        }));
+    }
+    let result = state.upstream.get_json(upstream::SOLSCAN_TRANSFERS_URL, &[("token", &token), ("limit", "50")]).await;
+    let outcome = result.as_ref().map(|_| ()).map_err(|e| e.clone());
+    state.providers.record(upstream::SOLSCAN, outcome, &providers_config);
+    match result {
+        Ok(mut data) => {
+            println!("Successfully got transaction data");
+            // Flag each transfer's wallets, and the token itself at the top
+            if let Some(transfers) = data["data"].as_array_mut() {
+                for transfer in transfers {
+                    let involved: Vec<(&str, Pubkey)> = ["from_address", "to_address"]
+                        .into_iter()
+                        .filter_map(|field| Some((field, transfer[field].as_str()?.parse().ok()?)))
+                        .collect();
+                    *transfer = blocklist::annotate(&state, &involved, transfer.take());
                 }
             }
-        },
+            HttpResponse::Ok().json(blocklist::annotate(&state, &[("mint", mint)], data))
+        }
         Err(e) => {
             eprintln!("Error fetching from Solscan: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to fetch from Solscan: {}", e)
+            HttpResponse::BadGateway().json(json!({
+                "error": e
             }))
         }
     }
 }
 
+#[cfg(unix)]
+async fn reload_on_sighup(state: std::sync::Arc<AppState>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Failed to install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        if let Err(e) = state.reload() {
+            eprintln!("Config reload failed: {}", e);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn reload_on_sighup(_state: std::sync::Arc<AppState>) {}
+
+// Every route, shared by the server and the replay tests.
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_pool_info)
+        .service(get_solana_status)
+        .service(get_watchlist_status)
+        .service(admin::get_dormant)
+        .service(get_token_pair_info)
+        .service(get_token_transactions)
+        .service(pricing::get_token_price)
+        .service(images::get_token_image)
+        .service(metadata::get_tokens_metadata)
+        .service(history::get_pool_diff)
+        .service(history::get_price_at)
+        .service(gaps::get_history_gaps)
+        .service(analytics::get_correlation)
+        .service(indicators::get_pool_indicators)
+        .service(charts::get_pool_chart)
+        .service(reports::get_latest_report)
+        .service(audit::get_pool_audit)
+        .service(quote::get_pool_quote)
+        .service(simulate::simulate_transaction)
+        .service(send::get_blockhash)
+        .service(send::send_transaction)
+        .service(send::get_transaction_status)
+        .service(tax::get_tax_export)
+        .service(sse::stream)
+        .service(sse::replay)
+        .service(ws::connect)
+        .service(metrics::get_metrics)
+        .service(usage::get_usage)
+        .service(providers::get_providers_status)
+        .service(fees::get_position_fees)
+        .service(rewards::get_pool_apr)
+        .service(outliers::get_pool_outliers)
+        .service(dex::list_dexes)
+        .service(dashboard::index)
+        .service(dashboard::asset)
+        .service(discovery::discover_pools)
+        .configure(alerts::configure)
+        .configure(annotations::configure)
+        .configure(portfolio::configure)
+        .configure(subscriptions::configure)
+        .configure(templates::configure)
+        .configure(grafana::configure)
+        .configure(admin::configure);
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    println!("Starting server at http://127.0.0.1:3000");
+    let config_path = Config::path();
+    let config = Config::load(&config_path)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let dead_letter_file = config.subscriptions.dead_letter_file.clone();
+    let store = storage::open(&config.storage)
+        .map_err(std::io::Error::other)?;
+    // Lets deployments run schema changes as a separate step before starting
+    // the new version
+    if std::env::args().any(|arg| arg == "--migrate-only") {
+        println!("Migrations complete, exiting");
+        return Ok(());
+    }
+    let since = state::unix_now().saturating_sub(config.history_retention_secs);
+    let snapshots = store
+        .load_snapshots(since)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if !snapshots.is_empty() {
+        println!("Loaded {} stored snapshots", snapshots.len());
+    }
+    let state = web::Data::new(AppState::new(config, config_path, store));
+    state.history.load(snapshots);
+    let blocked = state
+        .store
+        .blocklist()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    state.blocklist.load(blocked);
+    let watchlist = state
+        .store
+        .watchlist()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    *state.stored_watchlist.write().unwrap() = watchlist;
+    let api_keys = state
+        .store
+        .api_keys()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    state.api_keys.load(api_keys);
+    let portfolios = state
+        .store
+        .portfolios()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    state.portfolios.load(portfolios);
+    let subscriptions = state
+        .store
+        .subscriptions()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    state.subscriptions.load(subscriptions);
+    let report_templates = state
+        .store
+        .report_templates()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    state.report_templates.load(report_templates);
+    state.usage.roll(&state);
+    if let Some(path) = dead_letter_file {
+        state
+            .subscriptions
+            .load_dead_letters(&path)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    }
+
+    tasks::install_panic_hook();
+    let supervised = state.clone().into_inner();
+    tasks::supervise(supervised.clone(), "polling", "poller", poller::run);
+    tasks::supervise(supervised.clone(), "webhooks", "subscriptions", subscriptions::run);
+    tasks::supervise(supervised.clone(), "indexer", "index", index::run);
+    tasks::supervise(supervised.clone(), "polling", "pubsub", pubsub::run);
+    tasks::supervise(supervised.clone(), "polling", "follow_slots", pubsub::follow_slots);
+    tasks::supervise(supervised.clone(), "lists", "token_lists", tokenlist::run);
+    tasks::supervise(supervised.clone(), "lists", "blocklist", blocklist::run);
+    tasks::supervise(supervised.clone(), "polling", "upgrades", upgrades::run);
+    tasks::supervise(supervised.clone(), "reports", "reports", reports::run);
+    tasks::supervise(supervised.clone(), "reports", "usage", usage::run);
+    tasks::supervise(supervised.clone(), "auth", "jwt", jwt::run);
+    tasks::supervise(supervised.clone(), "streaming", "ticker", ticker::run);
+    tasks::supervise(supervised.clone(), "polling", "gaps", gaps::run);
+    tasks::supervise(supervised.clone(), "server", "event_loop_probe", limits::probe);
+    tasks::supervise(supervised, "server", "reload_on_sighup", reload_on_sighup);
 
-    HttpServer::new(|| {
-        // Set up CORS to allow requests from your JavaScript frontend
+    let server_config = state.config().server;
+
+    let server = HttpServer::new(move || {
+        // Set up CORS to allow requests from your JavaScript frontend. Origins are
+        // checked against the live config so a reload can change them.
+        let cors_state = state.clone();
         let cors = Cors::default()
-            .allow_any_origin()
+            .allowed_origin_fn(move |origin, _| {
+                origin
+                    .to_str()
+                    .map(|origin| cors_state.config.read().unwrap().allows_origin(origin))
+                    .unwrap_or(false)
+            })
             .allow_any_method()
             .allow_any_header();
+        let max_payload = state.config().server.max_payload_bytes;
 
         App::new()
+            .wrap(middleware::from_fn(fields::filter))
+            .wrap(middleware::from_fn(format::negotiate))
+            .wrap(middleware::from_fn(limits::limit_concurrency))
+            .wrap(middleware::from_fn(usage::meter))
+            .wrap(middleware::from_fn(trail::record))
             .wrap(cors)
-            .service(get_pool_info)
-            .service(get_solana_status)
-            .service(get_token_pair_info)
-            .service(get_token_transactions)
+            .wrap(middleware::from_fn(ipfilter::filter))
+            .app_data(state.clone())
+            .app_data(address::path_config())
+            .app_data(web::PayloadConfig::new(max_payload))
+            .app_data(web::JsonConfig::default().limit(max_payload))
+            .configure(routes)
     })
-    .bind(("127.0.0.1", 3000))?
-    .run()
-    .await
-}
\ No newline at end of file
+    .keep_alive(Duration::from_secs(server_config.keep_alive_secs))
+    .client_request_timeout(Duration::from_millis(server_config.client_request_timeout_ms));
+
+    let server = match server_config.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+
+    let server = match &server_config.unix_socket {
+        #[cfg(unix)]
+        Some(path) => {
+            println!("Starting server on unix socket {}", path.display());
+            server.bind_uds(path)?
+        }
+        #[cfg(not(unix))]
+        Some(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "server.unix_socket is only supported on Unix",
+            ));
+        }
+        None => {
+            println!("Starting server at http://{}:{}", server_config.host, server_config.port);
+            server.bind((server_config.host.as_str(), server_config.port))?
+        }
+    };
+
+    server.run().await
+}
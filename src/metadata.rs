@@ -0,0 +1,139 @@
+use crate::blocklist;
+use crate::state::{unix_now, AppState};
+use crate::token::{self, TokenMetadata, METADATA_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use crate::tokenlist;
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use solana_client::client_error::ClientError;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+// Metadata marked mutable can be updated, so it is read again after this.
+const METADATA_TTL_SECS: u64 = 60 * 60;
+const MAX_BATCH_MINTS: usize = 100;
+// getMultipleAccounts takes at most 100 keys, a mint and its metadata
+// account each.
+const MINTS_PER_CALL: usize = 50;
+
+// On-chain metadata by mint, including mints found to have none.
+#[derive(Default)]
+pub struct MetadataCache {
+    entries: RwLock<HashMap<Pubkey, (Option<TokenMetadata>, u64)>>,
+}
+
+impl MetadataCache {
+    fn cached(&self, mint: &Pubkey) -> Option<Option<TokenMetadata>> {
+        let entries = self.entries.read().unwrap();
+        let (metadata, fetched_at) = entries.get(mint)?;
+        (unix_now().saturating_sub(*fetched_at) < METADATA_TTL_SECS).then(|| metadata.clone())
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    mints: Vec<String>,
+}
+
+// Metadata of a mint of the default cluster, None when it isn't a mint or
+// has none.
+pub async fn get(state: &AppState, mint: Pubkey) -> Result<Option<TokenMetadata>, String> {
+    Ok(get_many(state, &[mint]).await?.remove(&mint).flatten())
+}
+
+// Same as `get` for several mints, fetching the ones not cached in as few
+// calls as possible.
+pub async fn get_many(state: &AppState, mints: &[Pubkey]) -> Result<HashMap<Pubkey, Option<TokenMetadata>>, String> {
+    let mut found = HashMap::new();
+    let mut missing = Vec::new();
+    for mint in mints {
+        match state.token_metadata.cached(mint) {
+            Some(metadata) => {
+                found.insert(*mint, metadata);
+            }
+            None if !missing.contains(mint) => missing.push(*mint),
+            None => {}
+        }
+    }
+    if missing.is_empty() {
+        return Ok(found);
+    }
+
+    let rpc_client = state.rpc_client().await;
+    let keys = missing.clone();
+    let accounts = match tokio::task::spawn_blocking(move || {
+        let mut accounts = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(MINTS_PER_CALL) {
+            let metadata_accounts = chunk.iter().map(token::metadata_pda);
+            let batch: Vec<Pubkey> = chunk.iter().copied().chain(metadata_accounts).collect();
+            let fetched = rpc_client.get_multiple_accounts(&batch)?;
+            let (mint_accounts, metadata_accounts) = fetched.split_at(chunk.len());
+            accounts.extend(mint_accounts.iter().cloned().zip(metadata_accounts.iter().cloned()));
+        }
+        Ok::<_, ClientError>(accounts)
+    })
+    .await
+    {
+        Ok(Ok(accounts)) => accounts,
+        Ok(Err(e)) => return Err(format!("Failed to fetch token metadata: {}", e)),
+        Err(e) => return Err(format!("Task failed: {}", e)),
+    };
+
+    let now = unix_now();
+    let mut entries = state.token_metadata.entries.write().unwrap();
+    for (mint, (mint_account, metaplex)) in missing.into_iter().zip(accounts) {
+        let metadata = decode(mint_account.as_ref(), metaplex.as_ref());
+        entries.insert(mint, (metadata.clone(), now));
+        found.insert(mint, metadata);
+    }
+    Ok(found)
+}
+
+// A Token-2022 metadata extension wins over a Metaplex account.
+fn decode(mint_account: Option<&Account>, metaplex: Option<&Account>) -> Option<TokenMetadata> {
+    let mint_account = mint_account.filter(|account| account.owner == TOKEN_PROGRAM_ID || account.owner == TOKEN_2022_PROGRAM_ID)?;
+    token::token_2022_metadata(&mint_account.data).or_else(|| {
+        metaplex
+            .filter(|account| account.owner == METADATA_PROGRAM_ID)
+            .and_then(|account| token::decode_metaplex_metadata(&account.data).ok())
+    })
+}
+
+// Metadata of up to 100 mints by address with their token list flags, null
+// for the ones that have none, so a pool list can be rendered with one call.
+#[post("/tokens/metadata")]
+async fn get_tokens_metadata(state: web::Data<AppState>, body: web::Json<BatchRequest>) -> HttpResponse {
+    if body.mints.len() > MAX_BATCH_MINTS {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("At most {} mints can be requested at once", MAX_BATCH_MINTS)
+        }));
+    }
+    let mut mints = Vec::with_capacity(body.mints.len());
+    for mint in &body.mints {
+        match Pubkey::from_str(mint) {
+            Ok(key) => mints.push(key),
+            Err(e) => {
+                return HttpResponse::BadRequest().json(json!({
+                    "error": format!("Invalid mint address {}: {}", mint, e)
+                }));
+            }
+        }
+    }
+
+    match get_many(&state, &mints).await {
+        Ok(found) => {
+            let metadata: Map<String, Value> = mints
+                .iter()
+                .map(|mint| {
+                    let metadata = tokenlist::annotate(&state, mint, json!(found.get(mint).cloned().flatten()));
+                    (mint.to_string(), blocklist::annotate(&state, &[("mint", *mint)], metadata))
+                })
+                .collect();
+            HttpResponse::Ok().json(json!({ "metadata": metadata }))
+        }
+        Err(e) => HttpResponse::BadGateway().json(json!({ "error": e })),
+    }
+}
@@ -0,0 +1,91 @@
+use crate::state::AppState;
+use actix_web::{get, web, HttpResponse};
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+// Prometheus text exposition of internal counters.
+#[get("/metrics")]
+async fn get_metrics(state: web::Data<AppState>) -> HttpResponse {
+    let mut out = String::new();
+    let streams = &state.stream_metrics;
+    metric(&mut out, "pool_monitor_stream_clients", "gauge", "Connected WebSocket and SSE clients", streams.clients.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_stream_dropped_total", "counter", "Messages dropped for slow streaming clients", streams.dropped.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_stream_coalesced_total", "counter", "Price updates replaced by a newer one before sending", streams.coalesced.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_stream_patches_total", "counter", "Price updates sent to delta-encoded clients as patches", streams.patches.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_stream_disconnected_total", "counter", "Streaming clients disconnected for overflowing their buffer", streams.disconnected.load(Ordering::Relaxed));
+
+    let pubsub = &state.pubsub;
+    let connections = pubsub.connections();
+    metric(&mut out, "pool_monitor_pubsub_connections", "gauge", "Upstream PubSub connections open for realtime pools", connections.iter().filter(|c| c.connected).count());
+    metric(&mut out, "pool_monitor_pubsub_subscriptions", "gauge", "Accounts with a live upstream subscription", pubsub.subscription_count());
+    metric(&mut out, "pool_monitor_pubsub_unassigned_accounts", "gauge", "Realtime accounts that didn't fit on any connection", pubsub.unassigned());
+    metric(&mut out, "pool_monitor_pubsub_reconnects_total", "counter", "Upstream PubSub connections lost and reopened", connections.iter().map(|c| c.reconnects).sum::<u64>());
+    metric(&mut out, "pool_monitor_pubsub_notifications_total", "counter", "Account change notifications received", pubsub.notifications.load(Ordering::Relaxed));
+    let _ = writeln!(out, "# HELP pool_monitor_pubsub_connection_subscriptions Live subscriptions per upstream PubSub connection");
+    let _ = writeln!(out, "# TYPE pool_monitor_pubsub_connection_subscriptions gauge");
+    for (index, connection) in connections.iter().enumerate() {
+        let _ = writeln!(out, "pool_monitor_pubsub_connection_subscriptions{{connection=\"{}\"}} {}", index, connection.subscribed);
+    }
+
+    if let Some(slot) = state.slots.current() {
+        metric(&mut out, "pool_monitor_chain_slot", "gauge", "Latest slot reported by the slot subscription", slot);
+    }
+    metric(&mut out, "pool_monitor_outliers_rejected_total", "counter", "Snapshots kept out of price series as outliers", state.outliers.rejected.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_snapshots_backfilled_total", "counter", "Snapshots filled into gaps in pool history", state.gaps.backfilled.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_public_requests_total", "counter", "Requests let through without a key in public mode", state.public.requests.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_public_rate_limited_total", "counter", "Requests without a key refused over the public rate limit", state.public.limited.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_polls_skipped_total", "counter", "Polls left out because the chain hadn't advanced", state.slots.polls_skipped.load(Ordering::Relaxed));
+
+    let limits = &state.limits;
+    metric(&mut out, "pool_monitor_rpc_in_flight", "gauge", "Upstream RPC clients currently checked out", limits.rpc_in_flight());
+    let (interactive, background) = limits.rpc_queued();
+    metric(&mut out, "pool_monitor_rpc_queued_interactive", "gauge", "API requests waiting for an upstream RPC slot", interactive);
+    metric(&mut out, "pool_monitor_rpc_queued_background", "gauge", "Background work waiting for an upstream RPC slot", background);
+    metric(&mut out, "pool_monitor_requests_rejected_total", "counter", "Requests rejected with 503 at the request limit", limits.rejected.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_expensive_requests_rejected_total", "counter", "Expensive requests rejected with 503 at their limit", limits.rejected_expensive.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_requests_shed_total", "counter", "Requests rejected with 503 while the event loops lagged", limits.shed.load(Ordering::Relaxed));
+    metric(&mut out, "pool_monitor_event_loop_lag_seconds", "gauge", "Smoothed timer lag of the event loop furthest behind", limits.event_loop_lag().as_secs_f64());
+
+    let tasks = state.tasks.summary();
+    let _ = writeln!(out, "# HELP pool_monitor_task_up Whether a supervised background task is running");
+    let _ = writeln!(out, "# TYPE pool_monitor_task_up gauge");
+    for (task, subsystem, running, _) in &tasks {
+        let _ = writeln!(out, "pool_monitor_task_up{{task=\"{}\",subsystem=\"{}\"}} {}", task, subsystem, u8::from(*running));
+    }
+    let _ = writeln!(out, "# HELP pool_monitor_task_restarts_total Automatic restarts of a supervised background task");
+    let _ = writeln!(out, "# TYPE pool_monitor_task_restarts_total counter");
+    for (task, subsystem, _, restarts) in &tasks {
+        let _ = writeln!(out, "pool_monitor_task_restarts_total{{task=\"{}\",subsystem=\"{}\"}} {}", task, subsystem, restarts);
+    }
+
+    let budget = state.config.read().unwrap().rpc_budget.clone();
+    metric(&mut out, "pool_monitor_rpc_budget_throttled_total", "counter", "Background RPC work held back for the daily budget", state.rpc_budget.throttled.load(Ordering::Relaxed));
+    let usage = state.rpc_budget.usage();
+    let _ = writeln!(out, "# HELP pool_monitor_rpc_credits_used Estimated RPC credits spent today (UTC) per provider");
+    let _ = writeln!(out, "# TYPE pool_monitor_rpc_credits_used gauge");
+    for (provider, credits, _) in &usage {
+        let _ = writeln!(out, "pool_monitor_rpc_credits_used{{provider=\"{}\"}} {}", provider, credits);
+    }
+    let _ = writeln!(out, "# HELP pool_monitor_rpc_credits_budget Daily RPC credit budget per provider");
+    let _ = writeln!(out, "# TYPE pool_monitor_rpc_credits_budget gauge");
+    for (provider, credits) in &budget.daily_credits {
+        let _ = writeln!(out, "pool_monitor_rpc_credits_budget{{provider=\"{}\"}} {}", provider, credits);
+    }
+    let _ = writeln!(out, "# HELP pool_monitor_rpc_calls_today RPC calls made today (UTC) per provider and method");
+    let _ = writeln!(out, "# TYPE pool_monitor_rpc_calls_today gauge");
+    for (provider, _, calls) in &usage {
+        for (method, count) in calls {
+            let _ = writeln!(out, "pool_monitor_rpc_calls_today{{provider=\"{}\",method=\"{}\"}} {}", provider, method, count);
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(out)
+}
@@ -0,0 +1,155 @@
+use crate::alerts::{self, Alert, Severity};
+use crate::history::PoolSnapshot;
+use crate::index::IndexedPool;
+use crate::state::{unix_now, AppState};
+use crate::storage::PoolLink;
+use crate::token;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// A watched pool whose liquidity was pulled, waiting for a successor.
+#[derive(Clone)]
+struct Drain {
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+    at: u64,
+    removed_pct: f64,
+}
+
+// Drained pools and new pools of the last `migration.window_secs`, so either
+// can be matched with the other whichever is noticed first.
+#[derive(Default)]
+pub struct Migrations {
+    drained: Mutex<HashMap<Pubkey, Drain>>,
+    // Pools the index saw for the first time, with when.
+    created: Mutex<Vec<(IndexedPool, u64)>>,
+}
+
+fn same_pair(a: (Pubkey, Pubkey), b: (Pubkey, Pubkey)) -> bool {
+    a == b || a == (b.1, b.0)
+}
+
+// Treat a watched pool as drained when both reserves fell by at least
+// `migration.min_removed_pct` since its previous snapshot. A swap moves them
+// in opposite directions, only a withdrawal shrinks both.
+pub async fn check_snapshot(state: &AppState, pool: Pubkey, mints: (Pubkey, Pubkey), previous: &PoolSnapshot, current: &PoolSnapshot) {
+    if previous.reserve_a == 0 || previous.reserve_b == 0 {
+        return;
+    }
+    let removed = |before: u64, after: u64| before.saturating_sub(after) as f64 / before as f64 * 100.0;
+    let removed_pct = removed(previous.reserve_a, current.reserve_a).min(removed(previous.reserve_b, current.reserve_b));
+    if removed_pct >= state.config.read().unwrap().migration.min_removed_pct {
+        drained(state, pool, mints, removed_pct).await;
+    }
+}
+
+// A watched pool whose account was closed, with its mints from the last
+// decoded state.
+pub async fn pool_closed(state: &AppState, pool: Pubkey) {
+    let mints = state
+        .cache
+        .get(&pool)
+        .and_then(|cached| cached.pool.as_ref().map(|decoded| (decoded.mint_a, decoded.mint_b)));
+    if let Some(mints) = mints {
+        drained(state, pool, mints, 100.0).await;
+    }
+}
+
+async fn drained(state: &AppState, pool: Pubkey, (mint_a, mint_b): (Pubkey, Pubkey), removed_pct: f64) {
+    let window = state.config.read().unwrap().migration.window_secs;
+    if window == 0 {
+        return;
+    }
+    let now = unix_now();
+    let drain = Drain { mint_a, mint_b, at: now, removed_pct };
+    {
+        let mut drained = state.migrations.drained.lock().unwrap();
+        drained.retain(|_, d| now.saturating_sub(d.at) <= window);
+        if drained.contains_key(&pool) {
+            return;
+        }
+        drained.insert(pool, drain.clone());
+    }
+    println!("Pool {} drained ({:.1}% of its reserves removed)", pool, removed_pct);
+
+    let created: Vec<(Pubkey, u64)> = state
+        .migrations
+        .created
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(new, at)| {
+            new.pool != pool && now.saturating_sub(*at) <= window && same_pair((new.mint_a, new.mint_b), (mint_a, mint_b))
+        })
+        .map(|(new, at)| (new.pool, *at))
+        .collect();
+    for (new_pool, created_at) in created {
+        link(state, pool, &drain, new_pool, created_at).await;
+    }
+}
+
+// A pool the index saw for the first time.
+pub async fn pool_created(state: &AppState, pool: &IndexedPool) {
+    let window = state.config.read().unwrap().migration.window_secs;
+    if window == 0 {
+        return;
+    }
+    let now = unix_now();
+    {
+        let mut created = state.migrations.created.lock().unwrap();
+        created.retain(|(_, at)| now.saturating_sub(*at) <= window);
+        created.push((pool.clone(), now));
+    }
+    let drained: Vec<(Pubkey, Drain)> = state
+        .migrations
+        .drained
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(old, d)| {
+            **old != pool.pool && now.saturating_sub(d.at) <= window && same_pair((d.mint_a, d.mint_b), (pool.mint_a, pool.mint_b))
+        })
+        .map(|(old, d)| (*old, d.clone()))
+        .collect();
+    for (old_pool, drain) in drained {
+        link(state, old_pool, &drain, pool.pool, now).await;
+    }
+}
+
+async fn link(state: &AppState, old_pool: Pubkey, drain: &Drain, new_pool: Pubkey, created_at: u64) {
+    let link = PoolLink {
+        old_pool,
+        new_pool,
+        drained_at: drain.at,
+        created_at,
+        removed_pct: drain.removed_pct,
+    };
+    match state.with_store(move |store| store.save_pool_link(&link)).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => eprintln!("Failed to store link from {} to {}: {}", old_pool, new_pool, e),
+    }
+    let pair = format!("{}/{}", token::symbol(&drain.mint_a), token::symbol(&drain.mint_b));
+    println!("Liquidity of {} pool {} migrated to {}", pair, old_pool, new_pool);
+    alerts::fire(
+        state,
+        Alert::new(
+            "liquidity_migrated",
+            Severity::Warning,
+            old_pool.to_string(),
+            format!("Liquidity of {} pool {} migrated to new pool {}", pair, old_pool, new_pool),
+            json!({
+                "pair": pair,
+                "mint_a": drain.mint_a.to_string(),
+                "mint_b": drain.mint_b.to_string(),
+                "new_pool": new_pool.to_string(),
+                "drained_at": drain.at,
+                "created_at": created_at,
+                "removed_pct": drain.removed_pct,
+            }),
+        )
+        .with_key(format!("liquidity_migrated/{}/{}", old_pool, new_pool)),
+    );
+}
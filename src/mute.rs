@@ -0,0 +1,230 @@
+use crate::alerts::{Alert, Severity};
+use crate::state::AppState;
+use crate::time::civil;
+use serde::{Deserialize, Serialize};
+
+// Recurring window in which alerts are muted, opening whenever a cron
+// expression (UTC) matches and lasting `duration_mins`, e.g. Sundays
+// 02:00-04:00 as {"cron": "0 2 * * 0", "duration_mins": 120}.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MuteWindow {
+    pub cron: String,
+    pub duration_mins: u64,
+}
+
+// Longest window accepted, checking walks back minute by minute.
+const MAX_DURATION_MINS: u64 = 7 * 24 * 60;
+
+impl MuteWindow {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.duration_mins == 0 || self.duration_mins > MAX_DURATION_MINS {
+            return Err(format!("mute window duration_mins must be between 1 and {}", MAX_DURATION_MINS));
+        }
+        Cron::parse(&self.cron).map(|_| ())
+    }
+
+    // Whether `now` falls in a window that opened within the last
+    // `duration_mins`.
+    pub fn is_active(&self, now: u64) -> bool {
+        let Ok(cron) = Cron::parse(&self.cron) else {
+            return false;
+        };
+        let minute = now - now % 60;
+        (0..self.duration_mins).any(|back| minute.checked_sub(back * 60).is_some_and(|t| cron.matches(t)))
+    }
+}
+
+// Standard five field cron expression: minute hour day-of-month month
+// day-of-week, each a `*`, value, range, list or `/` step.
+struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    // Cron matches either day field when both are restricted.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Cron, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("cron expression {} must have 5 fields", expression));
+        };
+        let mut weekday_set = parse_field(weekdays, 0, 7)?;
+        // 7 is Sunday too
+        weekday_set[0] |= weekday_set[7];
+        weekday_set.truncate(7);
+        Ok(Cron {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekday_set,
+            days_restricted: days != "*",
+            weekdays_restricted: weekdays != "*",
+        })
+    }
+
+    fn matches(&self, timestamp: u64) -> bool {
+        let (_, month, day) = civil(timestamp);
+        let secs = timestamp % 86_400;
+        // 1970-01-01 was a Thursday
+        let weekday = ((timestamp / 86_400 + 4) % 7) as usize;
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => self.days[day as usize] || self.weekdays[weekday],
+            _ => self.days[day as usize] && self.weekdays[weekday],
+        };
+        self.minutes[(secs % 3600 / 60) as usize]
+            && self.hours[(secs / 3600) as usize]
+            && self.months[month as usize]
+            && day_matches
+    }
+}
+
+// Values of one field as a lookup table indexed by value.
+fn parse_field(field: &str, min: usize, max: usize) -> Result<Vec<bool>, String> {
+    let mut set = vec![false; max + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().ok().filter(|s| *s > 0)),
+            None => (part, Some(1)),
+        };
+        let Some(step) = step else {
+            return Err(format!("invalid step in cron field {}", field));
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (parse_value(start, field)?, parse_value(end, field)?),
+                None => {
+                    let value = parse_value(range, field)?;
+                    // "5/15" runs from 5 to the end of the range
+                    (value, if part.contains('/') { max } else { value })
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("cron field {} is out of range {}-{}", field, min, max));
+        }
+        for value in (start..=end).step_by(step) {
+            set[value] = true;
+        }
+    }
+    Ok(set)
+}
+
+fn parse_value(value: &str, field: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} in cron field {}", value, field))
+}
+
+// Maintenance mode, switched on through the admin API. Non-critical alerts
+// are suppressed until it is switched off or `until` passes.
+#[derive(Clone, Debug, Serialize)]
+pub struct Maintenance {
+    pub since: u64,
+    pub until: Option<u64>,
+    pub reason: Option<String>,
+}
+
+impl Maintenance {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.until.is_none_or(|until| now < until)
+    }
+}
+
+// Why an alert should be recorded without being delivered, if it should.
+// Rule mute windows silence everything, maintenance mode and the global
+// windows let critical alerts through.
+pub fn suppression(state: &AppState, alert: &Alert, global: &[MuteWindow]) -> Option<String> {
+    let now = alert.timestamp;
+    if let Some(window) = alert.mute.iter().find(|w| w.is_active(now)) {
+        return Some(format!("rule mute window {}", window.cron));
+    }
+    if alert.severity >= Severity::Critical {
+        return None;
+    }
+    if state.maintenance().is_some() {
+        return Some("maintenance".to_string());
+    }
+    global
+        .iter()
+        .find(|w| w.is_active(now))
+        .map(|window| format!("mute window {}", window.cron))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, StorageConfig};
+    use crate::storage;
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    // 2024-05-01 00:00 UTC, a Wednesday
+    const T: u64 = 1_714_521_600;
+
+    fn window(cron: &str, duration_mins: u64) -> MuteWindow {
+        MuteWindow {
+            cron: cron.to_string(),
+            duration_mins,
+        }
+    }
+
+    fn alert(severity: Severity, timestamp: u64, mute: Vec<MuteWindow>) -> Alert {
+        let mut alert = Alert::new("price_move", severity, "pool".to_string(), "up 5%".to_string(), Value::Null).with_mute(mute);
+        alert.timestamp = timestamp;
+        alert
+    }
+
+    #[test]
+    fn window_covers_its_duration() {
+        let nightly = window("0 2 * * *", 60);
+        assert!(!nightly.is_active(T + 2 * 3600 - 1));
+        assert!(nightly.is_active(T + 2 * 3600));
+        assert!(nightly.is_active(T + 2 * 3600 + 59 * 60 + 59));
+        assert!(!nightly.is_active(T + 3 * 3600));
+        // Weekdays only, and Wednesday is 3
+        assert!(window("0 2 * * 1-5", 60).is_active(T + 2 * 3600 + 1800));
+        assert!(!window("0 2 * * 0,6", 60).is_active(T + 2 * 3600 + 1800));
+        // Opened the day before
+        assert!(window("0 23 * * *", 180).is_active(T + 3600));
+    }
+
+    #[test]
+    fn suppression_in_windows_and_maintenance() {
+        let state = AppState::new(Config::default(), PathBuf::new(), storage::open(&StorageConfig::Memory).unwrap());
+        let global = [window("0 2 * * *", 60)];
+        let inside = T + 2 * 3600 + 600;
+        let outside = T + 4 * 3600;
+
+        assert_eq!(suppression(&state, &alert(Severity::Warning, outside, Vec::new()), &global), None);
+        assert_eq!(
+            suppression(&state, &alert(Severity::Warning, inside, Vec::new()), &global).as_deref(),
+            Some("mute window 0 2 * * *")
+        );
+        // Global windows let critical alerts through, rule windows don't
+        assert_eq!(suppression(&state, &alert(Severity::Critical, inside, Vec::new()), &global), None);
+        let rule = vec![window("*/30 4 * * *", 5)];
+        assert_eq!(
+            suppression(&state, &alert(Severity::Critical, outside + 120, rule.clone()), &global).as_deref(),
+            Some("rule mute window */30 4 * * *")
+        );
+        assert_eq!(suppression(&state, &alert(Severity::Critical, outside + 600, rule), &global), None);
+
+        state.set_maintenance(Some(Maintenance {
+            since: T,
+            until: None,
+            reason: None,
+        }));
+        assert_eq!(
+            suppression(&state, &alert(Severity::Warning, outside, Vec::new()), &global).as_deref(),
+            Some("maintenance")
+        );
+        assert_eq!(suppression(&state, &alert(Severity::Critical, outside, Vec::new()), &global), None);
+    }
+}
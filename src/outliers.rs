@@ -0,0 +1,115 @@
+use crate::address::ValidatedPubkey;
+use crate::history::PoolSnapshot;
+use crate::state::AppState;
+use actix_web::{get, web, HttpResponse};
+use serde::Serialize;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// A snapshot kept out of a pool's price series.
+#[derive(Clone, Serialize)]
+pub struct Outlier {
+    pub slot: u64,
+    pub timestamp: u64,
+    pub price: f64,
+    // Median of the pool's last prices it was measured against.
+    pub median: f64,
+    pub deviation_pct: f64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[derive(Default)]
+struct Series {
+    // Last prices seen, rejected ones included.
+    prices: VecDeque<f64>,
+    rejected: VecDeque<Outlier>,
+}
+
+// Median filter state per pool, see `OutlierConfig`.
+#[derive(Default)]
+pub struct Outliers {
+    pools: Mutex<HashMap<Pubkey, Series>>,
+    pub rejected: AtomicU64,
+}
+
+impl Outliers {
+    pub fn rejected(&self, pool: &Pubkey) -> Vec<Outlier> {
+        self.pools.lock().unwrap().get(pool).map_or_else(Vec::new, |series| series.rejected.iter().cloned().collect())
+    }
+}
+
+fn median(prices: &VecDeque<f64>) -> Option<f64> {
+    let mut sorted: Vec<f64> = prices.iter().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((sorted[middle - 1] + sorted[middle]) / 2.0),
+        _ => Some(sorted[middle]),
+    }
+}
+
+// Run a new snapshot of `pool` through the median filter. False for an
+// outlier, which is logged and kept for `GET /pool/{id}/outliers`.
+pub fn accept(state: &AppState, pool: Pubkey, snapshot: &PoolSnapshot) -> bool {
+    let config = state.config.read().unwrap().outliers.clone();
+    if config.window == 0 {
+        return true;
+    }
+    let mut pools = state.outliers.pools.lock().unwrap();
+    let series = pools.entry(pool).or_default();
+    // Picks up from the recorded history after a restart
+    if series.prices.is_empty() {
+        series.prices.extend(state.history.recent(&pool, config.window).iter().map(|snapshot| snapshot.price));
+    }
+    let median = median(&series.prices);
+    // Rejected prices count too, so a move that holds gets through
+    series.prices.push_back(snapshot.price);
+    while series.prices.len() > config.window {
+        series.prices.pop_front();
+    }
+
+    let Some(median) = median.filter(|median| *median > 0.0) else {
+        return true;
+    };
+    let deviation_pct = (snapshot.price - median) / median * 100.0;
+    if deviation_pct.abs() <= config.max_deviation_pct {
+        return true;
+    }
+    eprintln!(
+        "Rejected outlier price {} of pool {} at slot {}, {:+.1}% from the median {}",
+        snapshot.price, pool, snapshot.slot, deviation_pct, median
+    );
+    state.outliers.rejected.fetch_add(1, Ordering::Relaxed);
+    series.rejected.push_back(Outlier {
+        slot: snapshot.slot,
+        timestamp: snapshot.timestamp,
+        price: snapshot.price,
+        median,
+        deviation_pct,
+        reserve_a: snapshot.reserve_a,
+        reserve_b: snapshot.reserve_b,
+    });
+    while series.rejected.len() > config.keep {
+        series.rejected.pop_front();
+    }
+    false
+}
+
+// Snapshots of a pool the median filter kept out of its history, oldest
+// first.
+#[get("/pool/{pool_id}/outliers")]
+async fn get_pool_outliers(state: web::Data<AppState>, pool_id: web::Path<ValidatedPubkey>) -> HttpResponse {
+    let pool = pool_id.0;
+    let config = state.config.read().unwrap().outliers.clone();
+    HttpResponse::Ok().json(json!({
+        "pool": pool.to_string(),
+        "window": config.window,
+        "max_deviation_pct": config.max_deviation_pct,
+        "outliers": state.outliers.rejected(&pool),
+    }))
+}
@@ -0,0 +1,60 @@
+use crate::alerts::Alert;
+use crate::config::PagerDutyChannel;
+use serde_json::{json, Value};
+
+// Open or update the incident for an alert. Alerts below the channel's
+// minimum severity are skipped, not failed.
+pub async fn trigger(client: &reqwest::Client, channel: &PagerDutyChannel, alert: &Alert) -> Result<(), String> {
+    if alert.severity < channel.min_severity {
+        return Ok(());
+    }
+    send_event(
+        client,
+        channel,
+        json!({
+            "routing_key": channel.routing_key,
+            "event_action": "trigger",
+            "dedup_key": alert.key,
+            "payload": {
+                "summary": alert.message,
+                "source": alert.pool,
+                "severity": alert.severity.as_str(),
+                "component": alert.kind,
+                "custom_details": {
+                    "alert_id": alert.id,
+                    "timestamp": alert.timestamp,
+                    "details": alert.details,
+                },
+            },
+        }),
+    )
+    .await
+}
+
+// Acknowledge or resolve the incident opened for `key`. PagerDuty ignores
+// keys without an open incident, so this is safe for alerts never paged.
+pub async fn update(client: &reqwest::Client, channel: &PagerDutyChannel, key: &str, action: &str) -> Result<(), String> {
+    send_event(
+        client,
+        channel,
+        json!({
+            "routing_key": channel.routing_key,
+            "event_action": action,
+            "dedup_key": key,
+        }),
+    )
+    .await
+}
+
+async fn send_event(client: &reqwest::Client, channel: &PagerDutyChannel, event: Value) -> Result<(), String> {
+    let response = client
+        .post(&channel.url)
+        .json(&event)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("PagerDuty returned {}", response.status()));
+    }
+    Ok(())
+}
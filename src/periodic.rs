@@ -0,0 +1,49 @@
+// Lists, keys and deploys refreshed from elsewhere on an interval.
+
+use crate::state::AppState;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// Longest a fetch of one list or key set may take.
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+// A failed refresh is tried again after this long rather than waiting a
+// full interval.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+// Least time between a refresh and one woken early, so e.g. tokens naming
+// made up key IDs can't hammer the identity provider.
+const MIN_WAKE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Run `refresh` now, again after the interval it returns and whenever the
+// config is reloaded or `wake` is notified. None from it means nothing is
+// configured, so it only runs again on a reload. A failure is logged as
+// `what` failing and what the last refresh loaded stays in use.
+pub async fn every<F>(state: &AppState, what: &str, wake: Option<&Notify>, mut refresh: impl FnMut() -> F)
+where
+    F: Future<Output = Option<(Duration, Result<(), String>)>>,
+{
+    loop {
+        let Some((interval, result)) = refresh().await else {
+            state.reloaded.notified().await;
+            continue;
+        };
+        let delay = match result {
+            Ok(()) => interval,
+            Err(e) => {
+                eprintln!("{} failed: {}", what, e);
+                RETRY_DELAY.min(interval)
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = state.reloaded.notified() => {}
+            _ = async {
+                tokio::time::sleep(MIN_WAKE_INTERVAL).await;
+                match wake {
+                    Some(wake) => wake.notified().await,
+                    None => std::future::pending().await,
+                }
+            } => {}
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use crate::audit;
+use crate::cluster::Cluster;
+use crate::dex::{self, DecodeWarning, DecodedPool};
+use crate::events;
+use crate::fixed;
+use crate::history::{Decimals, PoolSnapshot};
+use crate::limits::Priority;
+use crate::migration;
+use crate::outliers;
+use crate::portfolio;
+use crate::pricing;
+use crate::pubsub;
+use crate::rewards;
+use crate::rules;
+use crate::schedule::{self, PollTier};
+use crate::state::{unix_now, AppState, CachedAccount, RpcHandle};
+use crate::tasks;
+use crate::upgrades;
+use crate::token::{self, MintInfo};
+use futures::FutureExt;
+use serde::Serialize;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::client_error::ClientError;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Background loop that keeps the cache warm for every pool on the watchlist,
+// polling each once its tier's interval has passed.
+pub async fn run(state: Arc<AppState>) {
+    let mut last_pruned = 0;
+    loop {
+        let config = state.config();
+        let mut next_wake = unix_now() + config.poll_interval_secs.max(1);
+        if !state.is_paused() {
+            let watchlist = state.watchlist();
+            for (pool, tier) in &watchlist {
+                if let Some(due) = state.schedule.next_poll_at(pool).filter(|due| *due > unix_now()) {
+                    next_wake = next_wake.min(due);
+                    continue;
+                }
+                if *tier == PollTier::Dormant && config.polling.dormant_interval_secs == 0 {
+                    continue;
+                }
+                let slot = state.slots.current();
+                // Nothing can have changed while the chain stood still
+                if slot.is_some_and(|slot| !state.schedule.advanced_since_poll(pool, slot)) {
+                    state.slots.polls_skipped.fetch_add(1, Ordering::Relaxed);
+                    let next = unix_now() + tier.interval_secs(&config);
+                    state.schedule.set_next_poll(*pool, next);
+                    next_wake = next_wake.min(next);
+                    continue;
+                }
+                // Errors are also recorded in the poller status, and so is a
+                // panic, which only fails this pool's poll
+                let result = AssertUnwindSafe(refresh_pool(&state, *pool, Priority::Background))
+                    .catch_unwind()
+                    .await
+                    .unwrap_or_else(|panic| {
+                        let e = format!("Poll panicked: {}", tasks::panic_message(panic));
+                        eprintln!("Poller error for {}: {}", pool, e);
+                        let result = Err(e);
+                        record_poll(&state, *pool, &result);
+                        result
+                    });
+                schedule::update_dormancy(&state, *pool, &result).await;
+                if let Some(slot) = slot {
+                    state.schedule.set_polled_at_slot(*pool, slot);
+                }
+                let next = unix_now() + tier.interval_secs(&config);
+                state.schedule.set_next_poll(*pool, next);
+                next_wake = next_wake.min(next);
+            }
+            state.schedule.retain(&watchlist.iter().map(|(pool, _)| *pool).collect());
+            if unix_now() >= last_pruned + config.poll_interval_secs {
+                last_pruned = unix_now();
+                let cutoff = last_pruned.saturating_sub(config.history_retention_secs);
+                if let Err(e) = state.with_store(move |store| store.prune_snapshots(cutoff)).await {
+                    eprintln!("Failed to prune stored history: {}", e);
+                }
+            }
+        }
+        let sleep = Duration::from_secs(next_wake.saturating_sub(unix_now()).max(1));
+        tokio::select! {
+            _ = tokio::time::sleep(sleep) => {}
+            // New tiers and intervals apply immediately
+            _ = state.reloaded.notified() => state.schedule.reset(),
+            _ = state.schedule.wake.notified() => {}
+        }
+    }
+}
+
+// Cached pool state while it is younger than one poll interval, otherwise a
+// fresh fetch. Pools of other clusters are cached apart and never recorded.
+pub async fn get_pool(state: &AppState, cluster: &Cluster, pool: Pubkey) -> Result<Arc<CachedAccount>, String> {
+    if cluster.is_overridden() {
+        return load_pool(state.cluster_rpc_client(cluster).await, pool).await.map(Arc::new);
+    }
+    let max_age = state.config().poll_interval_secs;
+    // Following slots, a pool expires as the chain moves on rather than with
+    // time, and never while it stands still
+    let fresh = |cached: &CachedAccount| match state.slots.current().filter(|_| cluster.is_default()) {
+        Some(slot) => slot.saturating_sub(cached.slot) < pubsub::slots_in(max_age),
+        None => unix_now().saturating_sub(cached.fetched_at) < max_age,
+    };
+    match state.with_cache(cluster, |cache| cache.get(&pool)) {
+        Some(cached) if fresh(&cached) => Ok(cached),
+        _ if cluster.is_default() => fetch_pool(state, pool, Priority::Interactive).await,
+        _ => {
+            let mut cached = load_pool(state.cluster_rpc_client(cluster).await, pool).await?;
+            cached.encode();
+            let cached = Arc::new(cached);
+            let cache = state.cluster_caches.entry(cluster.name.clone()).or_default().clone();
+            cache.insert(pool, cached.clone());
+            Ok(cached)
+        }
+    }
+}
+
+// Fetch a pool, store it in the cache and history, and record the outcome.
+pub async fn refresh_pool(state: &AppState, pool: Pubkey, priority: Priority) -> Result<Arc<CachedAccount>, String> {
+    let result = fetch_pool(state, pool, priority).await;
+    record_poll(state, pool, &result);
+
+    match &result {
+        Ok(cached) => {
+            // Kept out of history and alerts until the layout is trusted again,
+            // and so are prices out of line with the pool's last ones
+            let snapshot = cached.snapshot.as_ref().filter(|_| !cached.has_layout_warnings());
+            if let Some(snapshot) = snapshot.filter(|snapshot| outliers::accept(state, pool, snapshot)) {
+                let retention = state.config.read().unwrap().history_retention_secs;
+                let previous = state.history.latest(&pool);
+                state.history.record(pool, snapshot.clone(), retention);
+                let saved = snapshot.clone();
+                if let Err(e) = state.with_store(move |store| store.save_snapshot(&pool, &saved)).await {
+                    eprintln!("Failed to store snapshot for {}: {}", pool, e);
+                }
+                let decimals = cached.pool.as_ref().map(Decimals::of).unwrap_or_default();
+                events::publish_snapshot(state, pool, decimals, previous.as_ref(), snapshot);
+                rules::evaluate(state, pool, snapshot.timestamp);
+                if let (Some(previous), Some(decoded)) = (&previous, &cached.pool) {
+                    migration::check_snapshot(state, pool, (decoded.mint_a, decoded.mint_b), previous, snapshot).await;
+                }
+                if let Some(decoded) = &cached.pool {
+                    portfolio::revalue(state, &pool, decoded, snapshot);
+                    rewards::check_expiry(state, &pool, decoded, snapshot.timestamp);
+                }
+            }
+        }
+        Err(e) => eprintln!("Poller error for {}: {}", pool, e),
+    }
+    result
+}
+
+// Count a poll in the pool's poller status. The entry is only held for the
+// update, never while the poll's outcome is processed.
+fn record_poll(state: &AppState, pool: Pubkey, result: &Result<Arc<CachedAccount>, String>) {
+    let mut status = state.pollers.entry(pool).or_default();
+    status.polls += 1;
+    match result {
+        Ok(cached) => {
+            status.consecutive_failures = 0;
+            status.last_success = Some(cached.fetched_at);
+        }
+        Err(e) => {
+            status.failures += 1;
+            status.consecutive_failures += 1;
+            status.last_error = Some(e.clone());
+            status.last_error_at = Some(unix_now());
+        }
+    }
+}
+
+// Fetch a pool of the default cluster and put it in the cache, keeping the
+// raw accounts for `?slot=` queries.
+pub async fn fetch_pool(state: &AppState, pool: Pubkey, priority: Priority) -> Result<Arc<CachedAccount>, String> {
+    let rpc_client = match priority {
+        Priority::Interactive => state.rpc_client().await,
+        Priority::Background => state.background_rpc_client().await,
+    };
+    let accounts = fetch_accounts(rpc_client, pool, None).await?;
+    let max_slots = state.config.read().unwrap().account_cache_slots;
+    state.accounts.record(pool, accounts.slot, accounts.fetched_at, accounts.account.clone(), max_slots);
+    for (key, account) in &accounts.related {
+        if let Some(account) = account {
+            state.accounts.record(*key, accounts.slot, accounts.fetched_at, account.clone(), max_slots);
+        }
+    }
+    state.observe_slot(accounts.slot);
+    let mut cached = build_pool(pool, &accounts)?;
+    check_price_band(state, pool, &mut cached);
+    upgrades::flag(state, &mut cached);
+    cached.encode();
+    let cached = Arc::new(cached);
+    let previous = state.cache.insert(pool, cached.clone());
+    let codes = |cached: &CachedAccount| cached.decode_warnings.iter().map(|w| w.code).collect::<Vec<_>>();
+    if previous.as_deref().map_or(Vec::new(), codes) != codes(&cached) {
+        for warning in &cached.decode_warnings {
+            eprintln!("Decode warning for pool {}: {} ({})", pool, warning.message, warning.code);
+        }
+    }
+    // Fields read from the wrong offsets would show up as changes
+    if let Some(previous) = previous.filter(|previous| !previous.has_layout_warnings() && !cached.has_layout_warnings()) {
+        if let (Some(old), Some(new)) = (&previous.pool, &cached.pool) {
+            audit::record_changes(state, pool, cached.slot, old, new);
+        }
+        audit::record_authority_changes(state, pool, &previous, &cached);
+    }
+    Ok(cached)
+}
+
+// Flag a pool priced far from the other cached pools of its pair, the
+// closest thing to an oracle there is for arbitrary tokens.
+fn check_price_band(state: &AppState, pool: Pubkey, cached: &mut CachedAccount) {
+    let config = state.config();
+    let band = config.decoding.price_band_pct;
+    let (Some(decoded), Some(snapshot)) = (&cached.pool, &cached.snapshot) else {
+        return;
+    };
+    if band <= 0.0 || snapshot.price <= 0.0 || cached.has_layout_warnings() {
+        return;
+    }
+    let pool_id = pool.to_string();
+    let others: Vec<_> = pricing::pool_quotes(state, &Cluster::default_for(&config), &decoded.mint_a, &decoded.mint_b)
+        .into_iter()
+        .filter(|quote| quote.pool_id != pool_id && !quote.stale)
+        .collect();
+    let Some(reference) = pricing::weighted_median(&others) else {
+        return;
+    };
+    let deviation = (snapshot.price - reference) / reference * 100.0;
+    if deviation.abs() > band {
+        cached.decode_warnings.push(DecodeWarning::value(
+            "price_out_of_band",
+            format!("Price {} is {:.1}% off {} from {} other pools", snapshot.price, deviation, reference, others.len()),
+        ));
+    }
+}
+
+// Where the state of a pool at a past slot came from.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolSource {
+    Cache,
+    Archival,
+}
+
+// Pool of the default cluster as of `slot`, rebuilt from the account cache
+// or else fetched from an archival endpoint. Also returns whether that is
+// exactly the state at `slot` rather than the closest one known. None when
+// the cache doesn't reach back that far and no archival endpoint is set.
+pub async fn get_pool_at(state: &AppState, pool: Pubkey, slot: u64) -> Result<Option<(CachedAccount, PoolSource, bool)>, String> {
+    if let Some(observed) = state.accounts.at_or_before(&pool, slot) {
+        let decoded = decode_pool(&pool, &observed.account);
+        let related = decoded
+            .as_ref()
+            .map(related_keys)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|key| (key, state.accounts.at_or_before(&key, slot).map(|o| (*o.account).clone())))
+            .collect::<Vec<_>>();
+        let exact = std::iter::once(&pool)
+            .chain(related.iter().map(|(key, _)| key))
+            .all(|key| state.accounts.unchanged_across(key, slot));
+        let accounts = PoolAccounts {
+            slot: observed.slot,
+            fetched_at: observed.fetched_at,
+            account: (*observed.account).clone(),
+            decoded,
+            related,
+        };
+        return Ok(Some((build_pool(pool, &accounts)?, PoolSource::Cache, exact)));
+    }
+    let Some(rpc_client) = state.archival_rpc_client().await else {
+        return Ok(None);
+    };
+    let accounts = fetch_accounts(rpc_client, pool, Some(slot)).await?;
+    let exact = accounts.slot == slot;
+    Ok(Some((build_pool(pool, &accounts)?, PoolSource::Archival, exact)))
+}
+
+// Error for a pool account that doesn't exist, closed or never created.
+pub fn not_found(pool: &Pubkey) -> String {
+    format!("Account {} not found", pool)
+}
+
+// Raw accounts a pool is built from, as fetched at one slot.
+struct PoolAccounts {
+    slot: u64,
+    fetched_at: u64,
+    account: Account,
+    decoded: Option<DecodedPool>,
+    // The accounts from `related_keys`, when the pool decodes.
+    related: Vec<(Pubkey, Option<Account>)>,
+}
+
+// Vaults give the reserves, mints give decimals and Token-2022 extensions,
+// and some DEXes keep the fee rate in a separate account. Reward vaults and
+// mints follow, looked up by key since the fee account is optional.
+fn related_keys(decoded: &DecodedPool) -> Vec<Pubkey> {
+    let mut keys = vec![decoded.vault_a, decoded.vault_b, decoded.mint_a, decoded.mint_b];
+    keys.extend(decoded.fee_account);
+    for reward in &decoded.rewards {
+        keys.extend([reward.vault, reward.mint]);
+    }
+    keys
+}
+
+fn decode_pool(pool: &Pubkey, account: &Account) -> Option<DecodedPool> {
+    dex::decode(&account.owner, &account.data)
+        .map_err(|e| eprintln!("Failed to decode pool {}: {}", pool, e))
+        .ok()
+        .flatten()
+}
+
+// Fetch and decode a pool account when the owning DEX is supported.
+async fn load_pool(rpc_client: RpcHandle, pool: Pubkey) -> Result<CachedAccount, String> {
+    build_pool(pool, &fetch_accounts(rpc_client, pool, None).await?)
+}
+
+// Fetch a pool account and the accounts it reads from, no older than
+// `min_context_slot` when set.
+async fn fetch_accounts(rpc_client: RpcHandle, pool: Pubkey, min_context_slot: Option<u64>) -> Result<PoolAccounts, String> {
+    let fetched = tokio::task::spawn_blocking(move || {
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(rpc_client.commitment()),
+            min_context_slot,
+            data_slice: None,
+        };
+        let response = rpc_client.get_account_with_config(&pool, config.clone())?;
+        let Some(account) = response.value else {
+            return Ok(None);
+        };
+        let decoded = decode_pool(&pool, &account);
+        let keys = decoded.as_ref().map(related_keys).unwrap_or_default();
+        let related = match keys.is_empty() {
+            true => Vec::new(),
+            false => rpc_client.get_multiple_accounts_with_config(&keys, config)?.value,
+        };
+        Ok::<_, ClientError>(Some(PoolAccounts {
+            slot: response.context.slot,
+            fetched_at: unix_now(),
+            account,
+            decoded,
+            related: keys.into_iter().zip(related).collect(),
+        }))
+    })
+    .await;
+
+    match fetched {
+        Ok(Ok(Some(accounts))) => Ok(accounts),
+        Ok(Ok(None)) => Err(not_found(&pool)),
+        Ok(Err(e)) => Err(format!("Failed to get account: {}", e)),
+        Err(e) => Err(format!("Task failed: {}", e)),
+    }
+}
+
+// Decode a pool from its raw accounts and read the vault balances.
+fn build_pool(pool: Pubkey, accounts: &PoolAccounts) -> Result<CachedAccount, String> {
+    let PoolAccounts { slot, fetched_at, account, .. } = accounts;
+    let mut decoded = accounts.decoded.clone();
+    let related = |index: usize| accounts.related.get(index).and_then(|(_, account)| account.as_ref());
+    let mut warnings = Vec::new();
+    if decoded.is_none() {
+        // A supported DEX whose account no longer decodes at all
+        if let Err(e) = dex::decode(&account.owner, &account.data) {
+            warnings.push(DecodeWarning::layout("decode_failed", e));
+        }
+    }
+    let mut mint_info = |index: usize, side: &str| -> Option<MintInfo> {
+        let account = related(index)?;
+        token::decode_mint(&account.owner, &account.data)
+            .map_err(|e| warnings.push(DecodeWarning::layout("mint_undecodable", format!("Mint {}: {}", side, e))))
+            .ok()
+    };
+    let (token_a, token_b) = (mint_info(2, "a"), mint_info(3, "b"));
+
+    if let Some(decoded) = &mut decoded {
+        warnings.append(&mut decoded.warnings);
+        // The mint is authoritative for decimals, not every pool stores them
+        if let Some(info) = &token_a {
+            decoded.decimals_a = info.decimals;
+        }
+        if let Some(info) = &token_b {
+            decoded.decimals_b = info.decimals;
+        }
+        if let Some(fee_account) = related(4) {
+            if let Err(e) = dex::apply_fee_account(decoded, &fee_account.data) {
+                eprintln!("Failed to decode fee account for pool {}: {}", pool, e);
+            }
+        }
+        let by_key = |key: &Pubkey| {
+            accounts.related.iter().find(|(related, _)| related == key).and_then(|(_, account)| account.as_ref())
+        };
+        for reward in &mut decoded.rewards {
+            let mint = by_key(&reward.mint).and_then(|mint| token::decode_mint(&mint.owner, &mint.data).ok());
+            reward.decimals = mint.map(|info| info.decimals);
+            // Orca emits until the vault runs dry. Its balance includes
+            // rewards owed but not yet claimed, so this is the latest the
+            // emissions can end.
+            if reward.end_time.is_none() && reward.emissions_per_second_x64 > 0 {
+                if let Some(balance) = by_key(&reward.vault).and_then(|vault| dex::token_account_amount(&vault.data).ok()) {
+                    reward.end_time = Some(fetched_at.saturating_add((balance as f64 / reward.emissions_per_second()) as u64));
+                    reward.end_estimated = true;
+                }
+            }
+        }
+        for (index, side, mint, pending) in [
+            (0, "a", decoded.mint_a, decoded.pending_a),
+            (1, "b", decoded.mint_b, decoded.pending_b),
+        ] {
+            let Some(vault) = related(index) else {
+                continue;
+            };
+            // A token account starts with its mint
+            if dex::read_pubkey(&vault.data, 0).is_ok_and(|vault_mint| vault_mint != mint) {
+                warnings.push(DecodeWarning::layout(
+                    "vault_mint_mismatch",
+                    format!("Vault {} does not hold mint {}", side, mint),
+                ));
+            }
+            if dex::token_account_amount(&vault.data).is_ok_and(|amount| pending > amount) {
+                warnings.push(DecodeWarning::value(
+                    "pending_exceeds_vault",
+                    format!("Pending fees of {} exceed the vault balance", side),
+                ));
+            }
+        }
+    }
+    let snapshot = match &decoded {
+        Some(decoded) => Some(build_snapshot(decoded, related(0), related(1), *slot, *fetched_at)?),
+        None => None,
+    };
+    if let Some(snapshot) = &snapshot {
+        let has_reserves = snapshot.reserve_a > 0 && snapshot.reserve_b > 0;
+        if !snapshot.price.is_finite() || (has_reserves && snapshot.price <= 0.0) {
+            warnings.push(DecodeWarning::value("invalid_price", format!("Price decoded as {}", snapshot.price)));
+        }
+    }
+
+    Ok(CachedAccount {
+        lamports: account.lamports,
+        data_size: account.data.len(),
+        slot: *slot,
+        fetched_at: *fetched_at,
+        dex: dex::dex_name(&account.owner),
+        pool: decoded,
+        snapshot,
+        token_a,
+        token_b,
+        decode_warnings: warnings,
+        encoded: None,
+    })
+}
+
+fn build_snapshot(
+    decoded: &DecodedPool,
+    vault_a: Option<&Account>,
+    vault_b: Option<&Account>,
+    slot: u64,
+    timestamp: u64,
+) -> Result<PoolSnapshot, String> {
+    let vault_amount = |vault: Option<&Account>| -> Result<u64, String> {
+        let account = vault.ok_or_else(|| "Pool vault account not found".to_string())?;
+        dex::token_account_amount(&account.data)
+    };
+    let reserve_a = vault_amount(vault_a)?.saturating_sub(decoded.pending_a);
+    let reserve_b = vault_amount(vault_b)?.saturating_sub(decoded.pending_b);
+
+    Ok(PoolSnapshot {
+        slot,
+        timestamp,
+        reserve_a,
+        reserve_b,
+        price: match (decoded.sqrt_price_x64, decoded.oracle_price) {
+            (Some(sqrt_price_x64), _) => fixed::price_from_sqrt_x64(sqrt_price_x64, decoded.decimals_a, decoded.decimals_b),
+            (None, Some(price)) => price,
+            (None, None) => fixed::price_from_reserves(reserve_a, decoded.decimals_a, reserve_b, decoded.decimals_b),
+        },
+        lp_supply: decoded.lp_supply,
+        authority: decoded.authority,
+        cumulative_volume_b: decoded.cumulative_volume_b,
+        fee_growth: decoded.fee_growth_global,
+    })
+}
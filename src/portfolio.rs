@@ -0,0 +1,792 @@
+use crate::address::ValidatedPubkey;
+use crate::alerts::{self, Alert};
+use crate::blocklist;
+use crate::config::RangeAlertsConfig;
+use crate::dex::DecodedPool;
+use crate::fixed::{self, U256};
+use crate::history::PoolSnapshot;
+use crate::pricing;
+use crate::state::{random_hex, unix_now, AppState};
+use crate::trail;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+const MAX_NAME_CHARS: usize = 100;
+const MAX_POSITIONS: usize = 100;
+
+// LP positions across pools, valued together.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Portfolio {
+    pub id: String,
+    pub name: String,
+    pub positions: Vec<Position>,
+    // API key name, "jwt:<subject>", "admin_token" or "anonymous".
+    pub created_by: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// LP tokens of a constant-product pool and the pool's state when they were
+// minted, which fee income and impermanent loss are measured against. A
+// concentrated-liquidity position has a price range instead and is only
+// watched for the price leaving it, not valued.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Position {
+    #[serde(default)]
+    pub id: String,
+    pub pool: String,
+    // Raw LP token amount, zero for a range position.
+    pub lp_amount: u64,
+    pub opened_at: u64,
+    pub entry_reserve_a: u64,
+    pub entry_reserve_b: u64,
+    pub entry_lp_supply: u64,
+    pub entry_price: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<PriceRange>,
+}
+
+impl Position {
+    fn alert_key(&self, portfolio: &str) -> Option<String> {
+        self.range.map(|_| format!("position_range/{}/{}", portfolio, self.id))
+    }
+}
+
+// Prices of token a in token b between which a concentrated-liquidity
+// position earns fees.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PriceRange {
+    pub lower_price: f64,
+    pub upper_price: f64,
+    // Liquidity the position adds while in range, as the pool counts it.
+    // Zero when not given, which leaves its fees unestimated.
+    #[serde(default)]
+    pub liquidity: u128,
+}
+
+impl PriceRange {
+    pub fn contains(&self, price: f64) -> bool {
+        price >= self.lower_price && price <= self.upper_price
+    }
+}
+
+impl PriceRange {
+    fn status(&self, snapshot: &PoolSnapshot) -> RangeStatus {
+        let price = snapshot.price;
+        RangeStatus {
+            in_range: self.contains(price),
+            price,
+            to_lower_pct: percent(price - self.lower_price, price),
+            to_upper_pct: percent(self.upper_price - price, price),
+            updated_at: snapshot.timestamp,
+        }
+    }
+}
+
+// Where the price stood against a position's range at the last poll.
+#[derive(Clone, Debug, Serialize)]
+pub struct RangeStatus {
+    pub in_range: bool,
+    pub price: f64,
+    // How far the price would have to fall or rise, in percent, to reach
+    // each bound. Negative once past it.
+    pub to_lower_pct: f64,
+    pub to_upper_pct: f64,
+    pub updated_at: u64,
+}
+
+impl RangeStatus {
+    fn zone(&self, config: &RangeAlertsConfig) -> Zone {
+        if !self.in_range {
+            Zone::Outside
+        } else if self.to_lower_pct.min(self.to_upper_pct) < config.near_edge_pct {
+            Zone::NearEdge
+        } else {
+            Zone::Inside
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Zone {
+    Inside,
+    NearEdge,
+    Outside,
+}
+
+// What a position was worth at the pool's last poll, in USD.
+#[derive(Clone, Debug, Serialize)]
+pub struct Valuation {
+    pub value_usd: f64,
+    // Value of the tokens deposited had they been held instead.
+    pub hodl_value_usd: f64,
+    pub fee_income_usd: f64,
+    // Zero or negative, fees left out.
+    pub impermanent_loss_usd: f64,
+    pub impermanent_loss_pct: f64,
+    pub share_pct: f64,
+    pub price: f64,
+    pub updated_at: u64,
+}
+
+// What the poller last made of a position.
+#[derive(Clone, Default)]
+struct Tracked {
+    valuation: Option<Valuation>,
+    range: Option<RangeStatus>,
+    // Of the last poll, None until the poller has seen the position.
+    zone: Option<Zone>,
+}
+
+// Portfolios by id, loaded from storage at startup and written back on
+// every change, with what the poller last made of each position.
+#[derive(Default)]
+pub struct Portfolios {
+    portfolios: RwLock<HashMap<String, Portfolio>>,
+    // By portfolio id, one entry per position.
+    tracked: RwLock<HashMap<String, Vec<Tracked>>>,
+}
+
+impl Portfolios {
+    pub fn load(&self, portfolios: Vec<Portfolio>) {
+        let mut loaded = HashMap::new();
+        for mut portfolio in portfolios {
+            // Positions stored before they had ids get one that stays the
+            // same across restarts until the portfolio is saved again
+            for (index, position) in portfolio.positions.iter_mut().enumerate() {
+                if position.id.is_empty() {
+                    position.id = format!("{}-{}", portfolio.id, index);
+                }
+            }
+            loaded.insert(portfolio.id.clone(), portfolio);
+        }
+        *self.portfolios.write().unwrap() = loaded;
+    }
+
+    pub fn get(&self, id: &str) -> Option<Portfolio> {
+        self.portfolios.read().unwrap().get(id).cloned()
+    }
+
+    // Oldest first.
+    pub fn all(&self) -> Vec<Portfolio> {
+        let mut portfolios: Vec<Portfolio> = self.portfolios.read().unwrap().values().cloned().collect();
+        portfolios.sort_by_key(|portfolio| portfolio.created_at);
+        portfolios
+    }
+
+    fn insert(&self, portfolio: Portfolio) {
+        self.portfolios.write().unwrap().insert(portfolio.id.clone(), portfolio);
+    }
+
+    fn remove(&self, id: &str) -> Option<Portfolio> {
+        self.tracked.write().unwrap().remove(id);
+        self.portfolios.write().unwrap().remove(id)
+    }
+
+    fn tracked(&self, id: &str) -> Vec<Tracked> {
+        self.tracked.read().unwrap().get(id).cloned().unwrap_or_default()
+    }
+
+    // A position by id and the portfolio holding it.
+    pub fn position(&self, id: &str) -> Option<(Portfolio, Position)> {
+        self.portfolios.read().unwrap().values().find_map(|portfolio| {
+            let position = portfolio.positions.iter().find(|position| position.id == id)?;
+            Some((portfolio.clone(), position.clone()))
+        })
+    }
+}
+
+// Value every position in `pool` from a fresh snapshot, and alert on range
+// positions whose price left their range or came near a bound since the
+// last poll.
+pub fn revalue(state: &AppState, pool: &Pubkey, decoded: &DecodedPool, snapshot: &PoolSnapshot) {
+    let pool_id = pool.to_string();
+    let portfolios: Vec<Portfolio> = state
+        .portfolios
+        .portfolios
+        .read()
+        .unwrap()
+        .values()
+        .filter(|portfolio| portfolio.positions.iter().any(|position| position.pool == pool_id))
+        .cloned()
+        .collect();
+    let config = state.config.read().unwrap().alerts.ranges.clone();
+    let mut moved = Vec::new();
+    {
+        let mut tracked = state.portfolios.tracked.write().unwrap();
+        for portfolio in &portfolios {
+            let entry = tracked.entry(portfolio.id.clone()).or_default();
+            entry.resize(portfolio.positions.len(), Tracked::default());
+            for (position, tracked) in portfolio.positions.iter().zip(entry.iter_mut()) {
+                if position.pool != pool_id {
+                    continue;
+                }
+                tracked.valuation = value(state, decoded, position, snapshot);
+                tracked.range = position.range.map(|range| range.status(snapshot));
+                if let Some(status) = &tracked.range {
+                    let zone = status.zone(&config);
+                    // Positions not seen before alert when they start out of place
+                    if tracked.zone.unwrap_or(Zone::Inside) != zone {
+                        moved.push((portfolio, position, status.clone(), zone));
+                    }
+                    tracked.zone = Some(zone);
+                }
+            }
+        }
+    }
+    for (portfolio, position, status, zone) in moved {
+        alert_range(state, &config, portfolio, position, decoded, &status, zone);
+    }
+}
+
+fn alert_range(
+    state: &AppState,
+    config: &RangeAlertsConfig,
+    portfolio: &Portfolio,
+    position: &Position,
+    decoded: &DecodedPool,
+    status: &RangeStatus,
+    zone: Zone,
+) {
+    let (Some(range), Some(key)) = (position.range, position.alert_key(&portfolio.id)) else {
+        return;
+    };
+    let (kind, severity, message) = match zone {
+        Zone::Inside => return alerts::resolve(state, &key),
+        Zone::NearEdge => {
+            let (bound, distance) = if status.to_lower_pct < status.to_upper_pct {
+                ("lower", status.to_lower_pct)
+            } else {
+                ("upper", status.to_upper_pct)
+            };
+            let message = format!(
+                "Position in {} of portfolio {} is {:.2}% from the {} bound of its range {}-{}",
+                decoded.label(),
+                portfolio.name,
+                distance,
+                bound,
+                range.lower_price,
+                range.upper_price
+            );
+            ("position_near_range_edge", config.near_edge, message)
+        }
+        Zone::Outside => {
+            let message = format!(
+                "Position in {} of portfolio {} is out of its range {}-{} at price {}",
+                decoded.label(),
+                portfolio.name,
+                range.lower_price,
+                range.upper_price,
+                status.price
+            );
+            ("position_out_of_range", config.out_of_range, message)
+        }
+    };
+    let Some(severity) = severity else {
+        // Turned off, the other kind of alert on this position may be on
+        return alerts::resolve(state, &key);
+    };
+    alerts::fire(
+        state,
+        Alert::new(
+            kind,
+            severity,
+            position.pool.clone(),
+            message,
+            json!({
+                "portfolio": portfolio.id,
+                "portfolio_name": portfolio.name,
+                "lower_price": range.lower_price,
+                "upper_price": range.upper_price,
+                "price": status.price,
+                "to_lower_pct": status.to_lower_pct,
+                "to_upper_pct": status.to_upper_pct,
+            }),
+        )
+        .with_key(key)
+        .with_channels(config.channels.clone()),
+    );
+}
+
+// Resolve the range alerts of positions that are no longer tracked.
+fn forget_ranges(state: &AppState, portfolio: &Portfolio, kept: &[Position]) {
+    for position in &portfolio.positions {
+        let Some(key) = position.alert_key(&portfolio.id) else {
+            continue;
+        };
+        if !kept.iter().any(|kept| kept.alert_key(&portfolio.id).as_ref() == Some(&key)) {
+            alerts::resolve(state, &key);
+        }
+    }
+}
+
+// Value a portfolio's positions from the latest recorded snapshots, for
+// when it changes between polls. Range alerts wait for the next poll.
+fn revalue_all(state: &AppState, portfolio: &Portfolio) {
+    let tracked = portfolio
+        .positions
+        .iter()
+        .map(|position| {
+            let pool = position.pool.parse().ok();
+            let snapshot = pool.and_then(|pool| state.history.latest(&pool));
+            let decoded = pool.and_then(|pool| state.cache.get(&pool)?.pool.clone());
+            let valuation = match (&decoded, &snapshot) {
+                (Some(decoded), Some(snapshot)) => value(state, decoded, position, snapshot),
+                _ => None,
+            };
+            Tracked {
+                valuation,
+                range: position.range.zip(snapshot).map(|(range, snapshot)| range.status(&snapshot)),
+                zone: None,
+            }
+        })
+        .collect();
+    state.portfolios.tracked.write().unwrap().insert(portfolio.id.clone(), tracked);
+}
+
+// None for range positions, and when the pool has no LP supply or neither
+// token has a USD price.
+fn value(state: &AppState, pool: &DecodedPool, position: &Position, snapshot: &PoolSnapshot) -> Option<Valuation> {
+    if position.range.is_some() {
+        return None;
+    }
+    let lp_supply = snapshot.lp_supply.filter(|supply| *supply > 0)?;
+    let (usd_a, usd_b) = usd_prices(state, pool, snapshot)?;
+    let lp = position.lp_amount as u128;
+    // Value of the position's part of the reserves behind `supply`
+    let held_usd = |reserve_a: u64, reserve_b: u64, supply: u64| {
+        let held = |reserve: u64, decimals: u8| {
+            fixed::mul_div(lp, reserve as u128, supply as u128).map_or(0.0, |raw| fixed::to_tokens(raw, decimals))
+        };
+        held(reserve_a, pool.decimals_a) * usd_a + held(reserve_b, pool.decimals_b) * usd_b
+    };
+
+    let share = fixed::ratio(U256::from_u128(lp), U256::from_u128(lp_supply as u128));
+    let value_usd = held_usd(snapshot.reserve_a, snapshot.reserve_b, lp_supply);
+    let hodl_value_usd = held_usd(position.entry_reserve_a, position.entry_reserve_b, position.entry_lp_supply);
+
+    // Swap fees stay in the reserves, growing the constant product behind
+    // each LP token. Without them the position would be worth less by the
+    // share of that growth.
+    let invariant = |reserve_a: u64, reserve_b: u64, supply: u64| {
+        (reserve_a as f64).sqrt() * (reserve_b as f64).sqrt() / supply as f64
+    };
+    let growth = invariant(snapshot.reserve_a, snapshot.reserve_b, lp_supply)
+        / invariant(position.entry_reserve_a, position.entry_reserve_b, position.entry_lp_supply);
+    let fee_income_usd = if growth.is_finite() && growth > 1.0 { value_usd * (1.0 - 1.0 / growth) } else { 0.0 };
+    let impermanent_loss_usd = (value_usd - fee_income_usd - hodl_value_usd).min(0.0);
+
+    Some(Valuation {
+        value_usd,
+        hodl_value_usd,
+        fee_income_usd,
+        impermanent_loss_usd,
+        impermanent_loss_pct: percent(impermanent_loss_usd, hodl_value_usd),
+        share_pct: share * 100.0,
+        price: snapshot.price,
+        updated_at: snapshot.timestamp,
+    })
+}
+
+// USD price of each side of the pool, from whichever side has one and the
+// pool's own price for the other.
+pub fn usd_prices(state: &AppState, pool: &DecodedPool, snapshot: &PoolSnapshot) -> Option<(f64, f64)> {
+    if snapshot.price <= 0.0 {
+        return None;
+    }
+    if let Some(usd_b) = pricing::usd_price_at(state, &pool.mint_b, snapshot.timestamp) {
+        return Some((snapshot.price * usd_b, usd_b));
+    }
+    let usd_a = pricing::usd_price_at(state, &pool.mint_a, snapshot.timestamp)?;
+    Some((usd_a, usd_a / snapshot.price))
+}
+
+fn percent(part: f64, whole: f64) -> f64 {
+    if whole > 0.0 {
+        part / whole * 100.0
+    } else {
+        0.0
+    }
+}
+
+#[derive(Deserialize)]
+struct NewPosition {
+    pool: ValidatedPubkey,
+    // For constant-product pools.
+    #[serde(default)]
+    lp_amount: u64,
+    // Both for concentrated-liquidity pools instead, with the position's
+    // liquidity to estimate its fees.
+    lower_price: Option<f64>,
+    upper_price: Option<f64>,
+    liquidity: Option<u128>,
+    // Now when omitted.
+    opened_at: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CreatePortfolio {
+    name: String,
+    #[serde(default)]
+    positions: Vec<NewPosition>,
+}
+
+// A position entered at the recorded state of its pool at `opened_at`. The
+// pool must be watched so the poller keeps valuing it.
+fn open_position(state: &AppState, new: &NewPosition) -> Result<Position, String> {
+    let pool = new.pool.0;
+    if !state.watchlist().iter().any(|(watched, _)| *watched == pool) {
+        return Err(format!("Pool {} is not watched, add it to the watchlist first", pool));
+    }
+    if new.lower_price.is_some() || new.upper_price.is_some() {
+        return open_range(state, new);
+    }
+    if new.lp_amount == 0 {
+        return Err("lp_amount must be positive".to_string());
+    }
+    if new.liquidity.is_some() {
+        return Err("liquidity is only for concentrated-liquidity pools".to_string());
+    }
+    let now = unix_now();
+    let opened_at = new.opened_at.unwrap_or(now);
+    if opened_at > now {
+        return Err("opened_at must not be in the future".to_string());
+    }
+    let snapshot = state
+        .history
+        .at_or_before(&pool, opened_at)
+        .ok_or_else(|| format!("No recorded state of pool {} at {}", pool, opened_at))?;
+    let lp_supply = snapshot
+        .lp_supply
+        .filter(|supply| *supply > 0)
+        .ok_or_else(|| format!("Pool {} has no LP token, only constant-product pools can be tracked", pool))?;
+    if new.lp_amount > lp_supply {
+        return Err(format!("lp_amount is more than the {} LP tokens pool {} had", lp_supply, pool));
+    }
+    Ok(Position {
+        id: random_hex(8),
+        pool: pool.to_string(),
+        lp_amount: new.lp_amount,
+        opened_at,
+        entry_reserve_a: snapshot.reserve_a,
+        entry_reserve_b: snapshot.reserve_b,
+        entry_lp_supply: lp_supply,
+        entry_price: snapshot.price,
+        range: None,
+    })
+}
+
+fn open_range(state: &AppState, new: &NewPosition) -> Result<Position, String> {
+    let pool = new.pool.0;
+    let (Some(lower_price), Some(upper_price)) = (new.lower_price, new.upper_price) else {
+        return Err("A range position needs both lower_price and upper_price".to_string());
+    };
+    if !(lower_price > 0.0 && lower_price < upper_price && upper_price.is_finite()) {
+        return Err("lower_price must be positive and below upper_price".to_string());
+    }
+    if new.lp_amount != 0 {
+        return Err("lp_amount is only for constant-product pools".to_string());
+    }
+    let concentrated = state
+        .cache
+        .get(&pool)
+        .and_then(|cached| cached.pool.as_ref().map(|decoded| decoded.sqrt_price_x64.is_some()));
+    match concentrated {
+        Some(true) => {}
+        Some(false) => {
+            return Err(format!("Pool {} is not a concentrated-liquidity pool, give lp_amount instead", pool))
+        }
+        None => return Err(format!("Pool {} has not been polled yet", pool)),
+    }
+    let now = unix_now();
+    let opened_at = new.opened_at.unwrap_or(now);
+    if opened_at > now {
+        return Err("opened_at must not be in the future".to_string());
+    }
+    let snapshot = state
+        .history
+        .at_or_before(&pool, opened_at)
+        .ok_or_else(|| format!("No recorded state of pool {} at {}", pool, opened_at))?;
+    Ok(Position {
+        id: random_hex(8),
+        pool: pool.to_string(),
+        lp_amount: 0,
+        opened_at,
+        entry_reserve_a: snapshot.reserve_a,
+        entry_reserve_b: snapshot.reserve_b,
+        entry_lp_supply: 0,
+        entry_price: snapshot.price,
+        range: Some(PriceRange {
+            lower_price,
+            upper_price,
+            liquidity: new.liquidity.unwrap_or(0),
+        }),
+    })
+}
+
+pub async fn save(state: &AppState, portfolio: Portfolio) -> Result<Portfolio, String> {
+    let saved = portfolio.clone();
+    state.with_store(move |store| store.save_portfolio(&saved)).await?;
+    state.portfolios.insert(portfolio.clone());
+    revalue_all(state, &portfolio);
+    Ok(portfolio)
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!("name must be 1 to {} characters", MAX_NAME_CHARS));
+    }
+    Ok(())
+}
+
+// The limits of `POST /portfolios`, checked on the portfolios
+// `POST /admin/import` brings in.
+pub fn validate(portfolio: &Portfolio) -> Result<(), String> {
+    check_name(&portfolio.name)?;
+    if portfolio.positions.len() > MAX_POSITIONS {
+        return Err(format!("A portfolio holds at most {} positions", MAX_POSITIONS));
+    }
+    for position in &portfolio.positions {
+        Pubkey::from_str(&position.pool).map_err(|e| format!("Invalid pool ID {}: {}", position.pool, e))?;
+    }
+    Ok(())
+}
+
+#[post("/portfolios")]
+async fn create_portfolio(state: web::Data<AppState>, body: web::Json<CreatePortfolio>, req: HttpRequest) -> HttpResponse {
+    let body = body.into_inner();
+    let name = body.name.trim().to_string();
+    if let Err(e) = check_name(&name) {
+        return HttpResponse::BadRequest().json(json!({ "error": e }));
+    }
+    if body.positions.len() > MAX_POSITIONS {
+        return too_many_positions();
+    }
+    let mut positions = Vec::new();
+    for new in &body.positions {
+        match open_position(&state, new) {
+            Ok(position) => positions.push(position),
+            Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+        }
+    }
+
+    let admin_token = state.config.read().unwrap().admin.token.clone();
+    let now = unix_now();
+    let portfolio = Portfolio {
+        id: random_hex(8),
+        name,
+        positions,
+        created_by: trail::actor(&req, admin_token.as_deref()),
+        created_at: now,
+        updated_at: now,
+    };
+    match save(&state, portfolio).await {
+        Ok(portfolio) => {
+            trail::changed(&req, target(&portfolio.id), Value::Null, &portfolio);
+            HttpResponse::Created().json(flagged(&state, &portfolio))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+}
+
+#[get("/portfolios")]
+async fn list_portfolios(state: web::Data<AppState>) -> HttpResponse {
+    let portfolios: Vec<Value> = state.portfolios.all().iter().map(|portfolio| flagged(&state, portfolio)).collect();
+    HttpResponse::Ok().json(json!({
+        "portfolios": portfolios
+    }))
+}
+
+#[get("/portfolio/{id}")]
+async fn get_portfolio(state: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    match state.portfolios.get(&id) {
+        Some(portfolio) => HttpResponse::Ok().json(flagged(&state, &portfolio)),
+        None => not_found(&id),
+    }
+}
+
+#[delete("/portfolio/{id}")]
+async fn delete_portfolio(state: web::Data<AppState>, id: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    let deleted = id.to_string();
+    match state.with_store(move |store| store.delete_portfolio(&deleted)).await {
+        Ok(true) => {}
+        Ok(false) => return not_found(&id),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+    let removed = state.portfolios.remove(&id);
+    if let Some(removed) = &removed {
+        forget_ranges(&state, removed, &[]);
+    }
+    trail::changed(&req, target(&id), removed, Value::Null);
+    HttpResponse::Ok().json(json!({
+        "deleted": id.to_string()
+    }))
+}
+
+#[post("/portfolio/{id}/positions")]
+async fn add_position(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    body: web::Json<NewPosition>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(before) = state.portfolios.get(&id) else {
+        return not_found(&id);
+    };
+    if before.positions.len() >= MAX_POSITIONS {
+        return too_many_positions();
+    }
+    let position = match open_position(&state, &body) {
+        Ok(position) => position,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    let mut portfolio = before.clone();
+    portfolio.positions.push(position);
+    portfolio.updated_at = unix_now();
+    match save(&state, portfolio).await {
+        Ok(portfolio) => {
+            trail::changed(&req, target(&id), before, &portfolio);
+            HttpResponse::Created().json(flagged(&state, &portfolio))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+}
+
+// Positions are addressed by their index in the portfolio, which shifts
+// the ones after it down.
+#[delete("/portfolio/{id}/positions/{index}")]
+async fn remove_position(
+    state: web::Data<AppState>,
+    path: web::Path<(String, usize)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (id, index) = path.into_inner();
+    let Some(before) = state.portfolios.get(&id) else {
+        return not_found(&id);
+    };
+    if index >= before.positions.len() {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("Portfolio {} has no position {}", id, index)
+        }));
+    }
+    let mut portfolio = before.clone();
+    portfolio.positions.remove(index);
+    portfolio.updated_at = unix_now();
+    match save(&state, portfolio).await {
+        Ok(portfolio) => {
+            forget_ranges(&state, &before, &portfolio.positions);
+            trail::changed(&req, target(&id), before, &portfolio);
+            HttpResponse::Ok().json(flagged(&state, &portfolio))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+}
+
+// Combined value, fee income and impermanent loss of a portfolio as of the
+// last poll of each pool. Positions without a valuation yet, or whose
+// tokens have no USD price, are listed but left out of the totals, as are
+// range positions, which come with where the price is against the range.
+#[get("/portfolio/{id}/summary")]
+async fn get_summary(state: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    let Some(portfolio) = state.portfolios.get(&id) else {
+        return not_found(&id);
+    };
+    let tracked = state.portfolios.tracked(&id);
+    let labels: HashMap<String, String> = portfolio
+        .positions
+        .iter()
+        .filter_map(|position| {
+            let label = state.cache.get(&position.pool.parse().ok()?)?.pool.as_ref()?.label();
+            Some((position.pool.clone(), label))
+        })
+        .collect();
+
+    let (mut value, mut hodl, mut fees, mut loss) = (0.0, 0.0, 0.0, 0.0);
+    let (mut unvalued, mut out_of_range) = (0, 0);
+    let mut updated_at: Option<u64> = None;
+    let mut positions = Vec::new();
+    for (index, position) in portfolio.positions.iter().enumerate() {
+        let Tracked { valuation, range, .. } = tracked.get(index).cloned().unwrap_or_default();
+        match &valuation {
+            _ if position.range.is_some() => {
+                out_of_range += usize::from(range.as_ref().is_some_and(|status| !status.in_range));
+            }
+            Some(valuation) => {
+                value += valuation.value_usd;
+                hodl += valuation.hodl_value_usd;
+                fees += valuation.fee_income_usd;
+                loss += valuation.impermanent_loss_usd;
+                updated_at = Some(updated_at.map_or(valuation.updated_at, |at| at.min(valuation.updated_at)));
+            }
+            None => unvalued += 1,
+        }
+        positions.push(json!({
+            "id": position.id,
+            "index": index,
+            "pool": position.pool,
+            "label": labels.get(&position.pool),
+            "lp_amount": position.lp_amount,
+            "opened_at": position.opened_at,
+            "valuation": valuation,
+            "range": position.range,
+            "range_status": range,
+            "blocklisted": blocklist::pool_flags(&state, &position.pool),
+        }));
+    }
+    HttpResponse::Ok().json(json!({
+        "id": portfolio.id,
+        "name": portfolio.name,
+        "total_value_usd": value,
+        "hodl_value_usd": hodl,
+        "fee_income_usd": fees,
+        "impermanent_loss_usd": loss,
+        "impermanent_loss_pct": percent(loss, hodl),
+        // Against holding the deposited tokens.
+        "net_gain_usd": value - hodl,
+        "unvalued_positions": unvalued,
+        "out_of_range_positions": out_of_range,
+        // Oldest valuation the totals use.
+        "updated_at": updated_at,
+        "positions": positions,
+    }))
+}
+
+// `portfolio` with each position flagged for its pool and mints.
+fn flagged(state: &AppState, portfolio: &Portfolio) -> Value {
+    let mut value = json!(portfolio);
+    for (position, entry) in portfolio.positions.iter().zip(value["positions"].as_array_mut().into_iter().flatten()) {
+        entry["blocklisted"] = json!(blocklist::pool_flags(state, &position.pool));
+    }
+    value
+}
+
+fn too_many_positions() -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "error": format!("A portfolio holds at most {} positions", MAX_POSITIONS)
+    }))
+}
+
+fn target(id: &str) -> String {
+    format!("portfolios/{}", id)
+}
+
+fn not_found(id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "error": format!("Portfolio {} not found", id)
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_portfolio)
+        .service(list_portfolios)
+        .service(get_portfolio)
+        .service(delete_portfolio)
+        .service(add_position)
+        .service(remove_position)
+        .service(get_summary);
+}
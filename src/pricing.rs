@@ -0,0 +1,283 @@
+use crate::address::ValidatedPubkey;
+use crate::amount::Amount;
+use crate::blocklist;
+use crate::cluster::Cluster;
+use crate::staleness;
+use crate::state::AppState;
+use crate::token;
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+pub const USDC_MINT: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+// Valued at par when converting to USD.
+const STABLECOINS: &[Pubkey] = &[USDC_MINT, pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB")];
+
+const DEFAULT_MAX_DEVIATION_PCT: f64 = 5.0;
+
+// Tokens a pair without a pool of its own is priced through, in order of
+// preference when routes are equally deep.
+const INTERMEDIATES: &[Pubkey] = &[token::NATIVE_MINT, USDC_MINT];
+
+// One pool's view of a token price.
+#[derive(Clone, Serialize)]
+pub struct PoolQuote {
+    pub pool_id: String,
+    pub dex: &'static str,
+    pub fee_bps: Option<u64>,
+    pub label: String,
+    pub price: f64,
+    // Value of both sides of the pool, an amount of the quote token.
+    pub liquidity: Amount,
+    pub age_secs: u64,
+    pub slot_lag: u64,
+    pub stale: bool,
+    pub outlier: bool,
+}
+
+// One hop of a pair route, priced from the cached pools trading it.
+#[derive(Clone, Serialize)]
+pub struct RouteLeg {
+    pub from: String,
+    pub to: String,
+    pub price: f64,
+    // Liquidity of the non-outlier pools, an amount of `to`.
+    pub liquidity: Amount,
+    pub stale: bool,
+    pub pools: Vec<String>,
+}
+
+// Price of a pair, directly or through an intermediate token. `liquidity` is
+// that of the shallowest leg as an amount of the pair's second token, which
+// bounds the size the route can take.
+#[derive(Clone, Serialize)]
+pub struct PairPrice {
+    pub price: f64,
+    pub route: Vec<String>,
+    pub direct: bool,
+    pub liquidity: Amount,
+    pub stale: bool,
+    pub legs: Vec<RouteLeg>,
+}
+
+#[derive(Deserialize)]
+struct PriceQuery {
+    quote: Option<String>,
+    max_deviation_pct: Option<f64>,
+}
+
+// Price of `mint` in `quote` from every cached pool that trades the pair.
+pub fn pool_quotes(state: &AppState, cluster: &Cluster, mint: &Pubkey, quote: &Pubkey) -> Vec<PoolQuote> {
+    state.with_cache(cluster, |cache| {
+        cache
+            .snapshot()
+            .iter()
+            // Pools that may be misread don't get a say in the price
+            .filter(|(_, cached)| !cached.has_layout_warnings())
+            .filter_map(|(pool_id, cached)| {
+                let (pool, snapshot) = (cached.pool.as_ref()?, cached.snapshot.as_ref()?);
+                let (price, quote_reserve, quote_decimals) = if pool.mint_a == *mint && pool.mint_b == *quote {
+                    (snapshot.price, snapshot.reserve_b, pool.decimals_b)
+                } else if pool.mint_b == *mint && pool.mint_a == *quote && snapshot.price > 0.0 {
+                    (1.0 / snapshot.price, snapshot.reserve_a, pool.decimals_a)
+                } else {
+                    return None;
+                };
+                let freshness = staleness::freshness(state, cluster, pool_id, cached);
+                Some(PoolQuote {
+                    pool_id: pool_id.to_string(),
+                    dex: pool.dex,
+                    fee_bps: pool.fee_bps,
+                    label: pool.label(),
+                    price,
+                    liquidity: Amount::new(2 * quote_reserve as u128, quote_decimals),
+                    age_secs: freshness.age_secs,
+                    slot_lag: freshness.slot_lag,
+                    stale: freshness.stale,
+                    outlier: false,
+                })
+            })
+            .filter(|q| q.price > 0.0)
+            .collect()
+    })
+}
+
+// USD price of `mint` at `timestamp`, from the recorded history of watched
+// pools pairing it with USDC. The pool with the most recent snapshot at that
+// time wins.
+pub fn usd_price_at(state: &AppState, mint: &Pubkey, timestamp: u64) -> Option<f64> {
+    if STABLECOINS.contains(mint) {
+        return Some(1.0);
+    }
+    let pools: Vec<(Pubkey, bool)> = state
+        .cache
+        .snapshot()
+        .iter()
+        .filter_map(|(pool_id, cached)| {
+            let pool = cached.pool.as_ref()?;
+            if pool.mint_a == *mint && pool.mint_b == USDC_MINT {
+                Some((*pool_id, false))
+            } else if pool.mint_b == *mint && pool.mint_a == USDC_MINT {
+                Some((*pool_id, true))
+            } else {
+                None
+            }
+        })
+        .collect();
+    pools
+        .into_iter()
+        .filter_map(|(pool, inverted)| {
+            let snapshot = state.history.at_or_before(&pool, timestamp)?;
+            if snapshot.price <= 0.0 {
+                return None;
+            }
+            let price = if inverted { 1.0 / snapshot.price } else { snapshot.price };
+            Some((snapshot.timestamp, price))
+        })
+        .max_by_key(|(timestamp, _)| *timestamp)
+        .map(|(_, price)| price)
+}
+
+// Median where each pool counts in proportion to its liquidity.
+pub fn weighted_median(quotes: &[PoolQuote]) -> Option<f64> {
+    let total: f64 = quotes.iter().map(|q| q.liquidity.tokens()).sum();
+    if quotes.is_empty() || total <= 0.0 {
+        return None;
+    }
+    let mut sorted: Vec<&PoolQuote> = quotes.iter().collect();
+    sorted.sort_by(|a, b| a.price.total_cmp(&b.price));
+    let mut cumulative = 0.0;
+    for quote in &sorted {
+        cumulative += quote.liquidity.tokens();
+        if cumulative >= total / 2.0 {
+            return Some(quote.price);
+        }
+    }
+    sorted.last().map(|q| q.price)
+}
+
+// Summed liquidity of pools quoted in the same token, None without any.
+fn total_liquidity<'a>(quotes: impl Iterator<Item = &'a PoolQuote>) -> Option<Amount> {
+    quotes
+        .map(|q| q.liquidity)
+        .reduce(|total, liquidity| Amount::new(total.raw + liquidity.raw, total.decimals))
+}
+
+fn route_leg(state: &AppState, cluster: &Cluster, from: &Pubkey, to: &Pubkey) -> Option<RouteLeg> {
+    let quotes = pool_quotes(state, cluster, from, to);
+    let price = weighted_median(&quotes)?;
+    let kept: Vec<&PoolQuote> = quotes
+        .iter()
+        .filter(|q| ((q.price - price) / price * 100.0).abs() <= DEFAULT_MAX_DEVIATION_PCT)
+        .collect();
+    Some(RouteLeg {
+        from: from.to_string(),
+        to: to.to_string(),
+        price,
+        liquidity: total_liquidity(kept.iter().copied())?,
+        stale: kept.iter().any(|q| q.stale),
+        pools: kept.iter().map(|q| q.pool_id.clone()).collect(),
+    })
+}
+
+// Price of `mint` in `quote`, from the pools trading the pair when there are
+// any, else through whichever intermediate token gives the deepest route.
+pub fn pair_price(state: &AppState, cluster: &Cluster, mint: &Pubkey, quote: &Pubkey) -> Option<PairPrice> {
+    if let Some(leg) = route_leg(state, cluster, mint, quote) {
+        return Some(PairPrice {
+            price: leg.price,
+            route: vec![mint.to_string(), quote.to_string()],
+            direct: true,
+            liquidity: leg.liquidity,
+            stale: leg.stale,
+            legs: vec![leg],
+        });
+    }
+    INTERMEDIATES
+        .iter()
+        .filter(|via| *via != mint && *via != quote)
+        .filter_map(|via| {
+            let first = route_leg(state, cluster, mint, via)?;
+            let second = route_leg(state, cluster, via, quote)?;
+            let converted = Amount::from_tokens(first.liquidity.tokens() * second.price, second.liquidity.decimals);
+            Some(PairPrice {
+                price: first.price * second.price,
+                route: vec![mint.to_string(), via.to_string(), quote.to_string()],
+                direct: false,
+                liquidity: if converted.raw < second.liquidity.raw { converted } else { second.liquidity },
+                stale: first.stale || second.stale,
+                legs: vec![first, second],
+            })
+        })
+        // The first of equally deep routes wins
+        .reduce(|best, route| if route.liquidity.raw > best.liquidity.raw { route } else { best })
+}
+
+#[get("/token/{mint}/price")]
+async fn get_token_price(
+    state: web::Data<AppState>,
+    cluster: Cluster,
+    mint: web::Path<ValidatedPubkey>,
+    query: web::Query<PriceQuery>,
+) -> HttpResponse {
+    let mint_pubkey = mint.0;
+    let quote_pubkey = match query.quote.as_deref().map(Pubkey::from_str) {
+        None => USDC_MINT,
+        Some(Ok(key)) => key,
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid quote mint: {}", e)
+            }));
+        }
+    };
+    let max_deviation = query.max_deviation_pct.unwrap_or(DEFAULT_MAX_DEVIATION_PCT);
+
+    let mut quotes = pool_quotes(&state, &cluster, &mint_pubkey, &quote_pubkey);
+    let Some(median) = weighted_median(&quotes) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("No known pools price {} against {}", mint_pubkey, quote_pubkey)
+        }));
+    };
+
+    for quote in &mut quotes {
+        quote.outlier = ((quote.price - median) / median * 100.0).abs() > max_deviation;
+    }
+    quotes.sort_by(|a, b| b.liquidity.tokens().total_cmp(&a.liquidity.tokens()));
+
+    // Any cached pool holding the mint tells us about its extensions
+    let risk_factors = state
+        .with_cache(&cluster, |cache| {
+            cache.snapshot().iter().find_map(|(_, cached)| {
+                let pool = cached.pool.as_ref()?;
+                let info = if pool.mint_a == mint_pubkey {
+                    cached.token_a.as_ref()
+                } else if pool.mint_b == mint_pubkey {
+                    cached.token_b.as_ref()
+                } else {
+                    None
+                };
+                info.map(|info| token::risk_factors(&mint_pubkey, info, cached.slot))
+            })
+        })
+        .unwrap_or_default();
+    let total_liquidity = total_liquidity(quotes.iter().filter(|q| !q.outlier));
+    let mut involved = vec![("mint", mint_pubkey), ("quote", quote_pubkey)];
+    involved.extend(quotes.iter().filter_map(|q| Some(("pool", Pubkey::from_str(&q.pool_id).ok()?))));
+
+    HttpResponse::Ok().json(json!({
+        "mint": mint_pubkey.to_string(),
+        "quote": quote_pubkey.to_string(),
+        "price": median,
+        // Set when a pool the price is drawn from hasn't been updated in time
+        "stale": quotes.iter().any(|q| !q.outlier && q.stale),
+        "total_liquidity": total_liquidity,
+        "pool_count": quotes.len(),
+        "outlier_count": quotes.iter().filter(|q| q.outlier).count(),
+        "risk_factors": risk_factors,
+        "blocklisted": blocklist::flags(&state, &involved),
+        "pools": quotes,
+    }))
+}
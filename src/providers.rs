@@ -0,0 +1,245 @@
+use crate::budget::RpcBudget;
+use crate::config::{redact_url, rpc_host, ProvidersConfig, RpcBudgetConfig};
+use crate::state::{unix_now, AppState};
+use crate::upstream;
+use actix_web::{get, web, HttpResponse};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::json;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY;
+use solana_client::rpc_request::{RpcError, RpcRequest};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Circuit {
+    #[default]
+    Closed,
+    // Failing, calls go elsewhere or are refused.
+    Open,
+    // Open long enough to be tried again, the next failure reopens it.
+    HalfOpen,
+}
+
+#[derive(Default)]
+struct Health {
+    consecutive_failures: u32,
+    opened_at: Option<u64>,
+    // Timestamp and success of recent calls, oldest first.
+    outcomes: VecDeque<(u64, bool)>,
+    last_error: Option<String>,
+    last_error_at: Option<u64>,
+}
+
+impl Health {
+    fn circuit(&self, config: &ProvidersConfig, now: u64) -> Circuit {
+        match self.opened_at {
+            None => Circuit::Closed,
+            Some(opened_at) if now < opened_at + config.open_secs => Circuit::Open,
+            Some(_) => Circuit::HalfOpen,
+        }
+    }
+}
+
+// Health of every external provider, keyed by RPC URL or provider name.
+// Only kept in memory, every provider starts out closed.
+#[derive(Default)]
+pub struct Providers {
+    health: Mutex<HashMap<String, Health>>,
+}
+
+impl Providers {
+    // Whether calls may go to a provider.
+    pub fn available(&self, key: &str, config: &ProvidersConfig) -> bool {
+        let health = self.health.lock().unwrap();
+        health.get(key).is_none_or(|health| health.circuit(config, unix_now()) != Circuit::Open)
+    }
+
+    pub fn record(&self, key: &str, outcome: Result<(), String>, config: &ProvidersConfig) {
+        let now = unix_now();
+        let mut all = self.health.lock().unwrap();
+        let health = all.entry(key.to_string()).or_default();
+        health.outcomes.push_back((now, outcome.is_ok()));
+        while health.outcomes.front().is_some_and(|(at, _)| *at + config.window_secs < now) {
+            health.outcomes.pop_front();
+        }
+        match outcome {
+            Ok(()) => {
+                if health.opened_at.take().is_some() {
+                    println!("Circuit of {} closed", redact_url(key));
+                }
+                health.consecutive_failures = 0;
+            }
+            Err(e) => {
+                health.consecutive_failures += 1;
+                health.last_error = Some(e);
+                health.last_error_at = Some(now);
+                let reopen = health.circuit(config, now) == Circuit::HalfOpen;
+                if reopen || (health.opened_at.is_none() && health.consecutive_failures >= config.failure_threshold) {
+                    eprintln!(
+                        "Circuit of {} opened after {} failures in a row",
+                        redact_url(key),
+                        health.consecutive_failures
+                    );
+                    health.opened_at = Some(now);
+                }
+            }
+        }
+    }
+
+    // Endpoints to spread RPC calls over: those of the lowest priority
+    // with calls allowed, or all of them when every circuit is open so
+    // there is something to try.
+    pub fn rpc_candidates<'a>(&self, rpc_urls: &'a [String], config: &ProvidersConfig) -> Vec<&'a String> {
+        let available: Vec<&String> = rpc_urls.iter().filter(|url| self.available(url, config)).collect();
+        let Some(best) = available.iter().map(|url| config.priority(url)).min() else {
+            return rpc_urls.iter().collect();
+        };
+        available.into_iter().filter(|url| config.priority(url) == best).collect()
+    }
+
+    fn status(&self, key: &str, config: &ProvidersConfig) -> ProviderStatus {
+        let now = unix_now();
+        let all = self.health.lock().unwrap();
+        let Some(health) = all.get(key) else {
+            return ProviderStatus::default();
+        };
+        let recent: Vec<bool> = health
+            .outcomes
+            .iter()
+            .filter(|(at, _)| *at + config.window_secs >= now)
+            .map(|(_, ok)| *ok)
+            .collect();
+        let errors = recent.iter().filter(|ok| !**ok).count();
+        let circuit = health.circuit(config, now);
+        ProviderStatus {
+            circuit,
+            consecutive_failures: health.consecutive_failures,
+            calls: recent.len(),
+            errors,
+            error_rate: if recent.is_empty() { 0.0 } else { errors as f64 / recent.len() as f64 },
+            opened_at: health.opened_at,
+            retry_at: health.opened_at.filter(|_| circuit == Circuit::Open).map(|at| at + config.open_secs),
+            last_error: health.last_error.clone(),
+            last_error_at: health.last_error_at,
+        }
+    }
+}
+
+#[derive(Default, Serialize)]
+struct ProviderStatus {
+    circuit: Circuit,
+    consecutive_failures: u32,
+    calls: usize,
+    errors: usize,
+    error_rate: f64,
+    opened_at: Option<u64>,
+    retry_at: Option<u64>,
+    last_error: Option<String>,
+    last_error_at: Option<u64>,
+}
+
+// Whether an RPC error says something about the endpoint rather than the
+// request, like an account that doesn't exist.
+fn provider_failure(error: &ClientError) -> Option<String> {
+    match error.kind() {
+        ClientErrorKind::Reqwest(e) => Some(describe(e)),
+        ClientErrorKind::Io(e) => Some(e.to_string()),
+        ClientErrorKind::Middleware(e) => Some(e.to_string()),
+        ClientErrorKind::SerdeJson(e) => Some(format!("Invalid response: {}", e)),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. })
+            if *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY =>
+        {
+            Some(message.clone())
+        }
+        _ => None,
+    }
+}
+
+// Without the URL, it may carry an API key.
+fn describe(error: &reqwest::Error) -> String {
+    match error.status() {
+        Some(status) => format!("HTTP {}", status),
+        None if error.is_timeout() => "Timed out".to_string(),
+        None if error.is_connect() => "Connection failed".to_string(),
+        None => "Request failed".to_string(),
+    }
+}
+
+// RPC transport recording how each call to a configured endpoint went and
+// what it cost.
+pub struct TrackedSender<S> {
+    pub inner: S,
+    pub providers: Arc<Providers>,
+    pub budget: Arc<RpcBudget>,
+    pub config: ProvidersConfig,
+    pub budget_config: RpcBudgetConfig,
+}
+
+#[async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for TrackedSender<S> {
+    async fn send(&self, request: RpcRequest, params: serde_json::Value) -> ClientResult<serde_json::Value> {
+        self.budget.charge(&self.inner.url(), &request.to_string(), &self.budget_config);
+        let result = self.inner.send(request, params).await;
+        let outcome = match &result {
+            Ok(_) => Ok(()),
+            Err(e) => provider_failure(e).map_or(Ok(()), Err),
+        };
+        self.providers.record(&self.inner.url(), outcome, &self.config);
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+// Circuit state, recent error rate and priority of every provider: the RPC
+// endpoints, Solscan, and the token lists, blocklists and JWT key set
+// fetched in the background. URLs are shown without their query string,
+// where API keys tend to be.
+#[get("/providers/status")]
+async fn get_providers_status(state: web::Data<AppState>) -> HttpResponse {
+    let config = state.config();
+    let mut providers = Vec::new();
+    for (serves, url) in config.rpc_providers() {
+        let host = rpc_host(url);
+        providers.push(json!({
+            "name": redact_url(url),
+            "kind": "rpc",
+            "serves": serves,
+            "priority": config.providers.priority(url),
+            "status": state.providers.status(url, &config.providers),
+            "credits_today": state.rpc_budget.used(&host),
+            "daily_credits": config.rpc_budget.daily_credits.get(&host),
+        }));
+    }
+    let mut http = vec![(upstream::SOLSCAN.to_string(), "token transactions".to_string())];
+    http.extend(config.token_lists.lists.iter().map(|(name, url)| (url.clone(), format!("token list {}", name))));
+    http.extend(config.blocklist.lists.iter().map(|list| (list.url.clone(), format!("blocklist {}", list.name))));
+    http.extend(config.jwt.jwks_url.iter().map(|url| (url.clone(), "JWT signing keys".to_string())));
+    for (key, serves) in http {
+        providers.push(json!({
+            "name": redact_url(&key),
+            "kind": "http",
+            "serves": serves,
+            "priority": null,
+            "status": state.providers.status(&key, &config.providers),
+        }));
+    }
+    let degraded = providers.iter().filter(|p| p["status"]["circuit"] != "closed").count();
+    HttpResponse::Ok().json(json!({
+        "window_secs": config.providers.window_secs,
+        "failure_threshold": config.providers.failure_threshold,
+        "open_secs": config.providers.open_secs,
+        "degraded": degraded,
+        "providers": providers,
+    }))
+}
@@ -0,0 +1,73 @@
+use crate::ipfilter::ClientIp;
+use crate::state::{unix_now, AppState};
+use actix_web::dev::ServiceRequest;
+use actix_web::http::Method;
+use actix_web::{HttpMessage, HttpResponse};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Windows {
+    minute: u64,
+    // Requests per client address in the current minute.
+    counts: HashMap<IpAddr, u32>,
+}
+
+// Requests without a key in public mode, see `PublicConfig`. Limited per
+// client address in fixed one-minute windows.
+#[derive(Default)]
+pub struct PublicAccess {
+    windows: Mutex<Windows>,
+    pub requests: AtomicU64,
+    pub limited: AtomicU64,
+}
+
+// Let a request without a key through in public mode, or the response
+// refusing it. Clients of a Unix socket have no address and aren't limited.
+pub fn admit(state: &AppState, req: &ServiceRequest, pattern: &str) -> Result<(), HttpResponse> {
+    let config = state.config.read().unwrap().public.clone();
+    if req.method() != Method::GET || !config.routes.iter().any(|route| route == pattern) {
+        return Err(HttpResponse::Unauthorized().json(json!({
+            "error": "This route needs an API key, send it in the X-API-Key header"
+        })));
+    }
+    // Only what the dashboards show, not any pool a client names. The path
+    // isn't matched yet this early, the pattern's segments line up with it
+    let pool = pattern.split('/').zip(req.path().split('/')).find(|(segment, _)| *segment == "{pool_id}");
+    if let Some((_, pool)) = pool {
+        let watched = Pubkey::from_str(pool).is_ok_and(|pool| state.watchlist().iter().any(|(p, _)| *p == pool));
+        if !watched {
+            return Err(HttpResponse::Unauthorized().json(json!({
+                "error": format!("Pool {} isn't public, send an API key in the X-API-Key header", pool)
+            })));
+        }
+    }
+
+    state.public.requests.fetch_add(1, Ordering::Relaxed);
+    let Some(ClientIp(ip)) = req.extensions().get::<ClientIp>().copied() else {
+        return Ok(());
+    };
+    let now = unix_now();
+    let mut windows = state.public.windows.lock().unwrap();
+    if windows.minute != now / 60 {
+        windows.minute = now / 60;
+        windows.counts.clear();
+    }
+    let count = windows.counts.entry(ip).or_default();
+    *count += 1;
+    if *count > config.requests_per_minute {
+        state.public.limited.fetch_add(1, Ordering::Relaxed);
+        let retry_after = 60 - now % 60;
+        return Err(HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after.to_string()))
+            .json(json!({
+                "error": format!("Public access is limited to {} requests a minute, send an API key for more", config.requests_per_minute)
+            })));
+    }
+    Ok(())
+}
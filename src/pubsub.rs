@@ -0,0 +1,401 @@
+use crate::config::redact_url;
+use crate::index::websocket_url;
+use crate::pubsub_client::{PubsubClient, Unsubscribe};
+use crate::schedule::PollTier;
+use crate::state::AppState;
+use crate::tasks;
+use futures::future;
+use futures::stream::{self, BoxStream, SelectAll};
+use futures::StreamExt;
+use serde::Serialize;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// How often the subscribed accounts are compared with the realtime pools,
+// vaults are only known once a pool has been polled.
+const RESUBSCRIBE_CHECK: Duration = Duration::from_secs(10);
+// Target slot time, to turn intervals into slots.
+const SLOT_MILLIS: u64 = 400;
+
+// What one connection should follow: each account with the realtime pool it
+// belongs to, and the endpoint to follow them on.
+#[derive(Clone, Default, PartialEq)]
+struct Assignment {
+    url: String,
+    accounts: HashMap<Pubkey, Pubkey>,
+}
+
+// One upstream PubSub connection as last seen.
+#[derive(Clone, Default, Serialize)]
+pub struct ConnectionStatus {
+    pub connected: bool,
+    pub assigned: usize,
+    pub subscribed: usize,
+    pub reconnects: u64,
+    pub last_error: Option<String>,
+}
+
+// Upstream `accountSubscribe` subscriptions of the realtime pools and their
+// vaults, spread over at most `polling.max_ws_connections` connections of
+// `polling.accounts_per_connection` accounts. A change to the realtime
+// pools only subscribes and unsubscribes the accounts that changed, and a
+// dropped connection resubscribes its own accounts without touching the
+// others.
+#[derive(Default)]
+pub struct AccountSubscriptions {
+    connections: Mutex<Vec<ConnectionStatus>>,
+    // Accounts with a live subscription.
+    subscribed: Mutex<HashSet<Pubkey>>,
+    // Realtime accounts left over once every connection was full.
+    unassigned: AtomicUsize,
+    pub notifications: AtomicU64,
+}
+
+impl AccountSubscriptions {
+    // Whether changes to a pool's account currently get it refreshed.
+    pub fn is_subscribed(&self, pool: &Pubkey) -> bool {
+        self.subscribed.lock().unwrap().contains(pool)
+    }
+
+    pub fn connections(&self) -> Vec<ConnectionStatus> {
+        self.connections.lock().unwrap().clone()
+    }
+
+    pub fn subscription_count(&self) -> usize {
+        self.subscribed.lock().unwrap().len()
+    }
+
+    pub fn unassigned(&self) -> usize {
+        self.unassigned.load(Ordering::Relaxed)
+    }
+
+    // Most recent error of a connection that hasn't recovered since.
+    pub fn last_error(&self) -> Option<String> {
+        self.connections.lock().unwrap().iter().rev().find_map(|status| status.last_error.clone())
+    }
+
+    fn update(&self, index: usize, f: impl FnOnce(&mut ConnectionStatus)) {
+        let mut connections = self.connections.lock().unwrap();
+        if connections.len() <= index {
+            connections.resize(index + 1, ConnectionStatus::default());
+        }
+        f(&mut connections[index]);
+    }
+}
+
+// The chain's slot as reported by `slotSubscribe` while `polling.follow_slots`
+// is set. The last slot stays current for as long as the subscription is up,
+// however long the chain stands still.
+#[derive(Default)]
+pub struct SlotClock {
+    slot: AtomicU64,
+    live: AtomicBool,
+    // Polls left out because the chain hadn't moved since the pool's last one.
+    pub polls_skipped: AtomicU64,
+}
+
+impl SlotClock {
+    // None while slots aren't followed, then the clock decides.
+    pub fn current(&self) -> Option<u64> {
+        self.live.load(Ordering::Relaxed).then(|| self.slot.load(Ordering::Relaxed))
+    }
+}
+
+// Slots the chain produces in `secs` at its target pace.
+pub fn slots_in(secs: u64) -> u64 {
+    secs * 1000 / SLOT_MILLIS
+}
+
+// Background task following the chain's slot while `polling.follow_slots`
+// is set.
+pub async fn follow_slots(state: Arc<AppState>) {
+    loop {
+        if !state.config().polling.follow_slots {
+            state.reloaded.notified().await;
+            continue;
+        }
+        let result = slot_updates(&state).await;
+        state.slots.live.store(false, Ordering::Relaxed);
+        if let Err(e) = result {
+            eprintln!("Slot subscription: {}", e);
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+}
+
+// Record every new slot. Returns once slots are no longer followed or the
+// endpoint changed.
+async fn slot_updates(state: &AppState) -> Result<(), String> {
+    let ws_url = |state: &AppState| {
+        let config = state.config.read().unwrap();
+        config.polling.ws_url.clone().unwrap_or_else(|| websocket_url(&config.rpc_urls[0]))
+    };
+    let url = ws_url(state);
+    let client = PubsubClient::connect(&state.upstream, &url)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", redact_url(&url), e))?;
+    let (mut slots, _unsubscribe) = client
+        .slot_subscribe()
+        .await
+        .map_err(|e| format!("Failed to subscribe to slots: {}", e))?;
+    println!("Following slots on {}", redact_url(&url));
+
+    loop {
+        tokio::select! {
+            update = slots.next() => {
+                let Some(update) = update else {
+                    return Err("Slot subscription closed".to_string());
+                };
+                state.slots.slot.fetch_max(update.slot, Ordering::Relaxed);
+                state.slots.live.store(true, Ordering::Relaxed);
+                state.observe_slot(update.slot);
+            }
+            _ = state.reloaded.notified() => {
+                if !state.config().polling.follow_slots || ws_url(state) != url {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// Background task assigning the realtime accounts to connections and
+// keeping one task per connection following its share.
+pub async fn run(state: Arc<AppState>) {
+    let mut connections: Vec<watch::Sender<Assignment>> = Vec::new();
+    // Accounts stay on the connection they are on while it has room
+    let mut placed: HashMap<Pubkey, usize> = HashMap::new();
+    loop {
+        let (url, max_connections, per_connection) = {
+            let config = state.config.read().unwrap();
+            let url = config.polling.ws_url.clone().unwrap_or_else(|| websocket_url(&config.rpc_urls[0]));
+            (url, config.polling.max_ws_connections, config.polling.accounts_per_connection)
+        };
+        let mut shares: Vec<HashMap<Pubkey, Pubkey>> = vec![HashMap::new(); max_connections];
+        let mut new = Vec::new();
+        let mut seen = HashSet::new();
+        for (account, pool) in realtime_accounts(&state) {
+            if !seen.insert(account) {
+                continue;
+            }
+            match placed.get(&account).filter(|index| shares.get(**index).is_some_and(|share| share.len() < per_connection)) {
+                Some(index) => {
+                    shares[*index].insert(account, pool);
+                }
+                None => new.push((account, pool)),
+            }
+        }
+        // New accounts fill the first connections up before opening more
+        let mut unassigned = 0;
+        for (account, pool) in new {
+            match shares.iter_mut().find(|share| share.len() < per_connection) {
+                Some(share) => {
+                    share.insert(account, pool);
+                }
+                None => unassigned += 1,
+            }
+        }
+        if unassigned > 0 && unassigned != state.pubsub.unassigned() {
+            eprintln!(
+                "{} realtime accounts don't fit on {} connections of {} accounts, they are only polled",
+                unassigned, max_connections, per_connection
+            );
+        }
+        state.pubsub.unassigned.store(unassigned, Ordering::Relaxed);
+        placed = shares
+            .iter()
+            .enumerate()
+            .flat_map(|(index, share)| share.keys().map(move |account| (*account, index)))
+            .collect();
+
+        while connections.len() < max_connections {
+            let (sender, receiver) = watch::channel(Assignment::default());
+            let index = connections.len();
+            tasks::supervise(state.clone(), "polling", format!("pubsub_connection_{}", index), move |state| {
+                connection(state, index, receiver.clone())
+            });
+            connections.push(sender);
+        }
+        // Connections beyond a lowered maximum are emptied and close
+        for (index, sender) in connections.iter().enumerate() {
+            let assignment = Assignment {
+                url: url.clone(),
+                accounts: shares.get(index).cloned().unwrap_or_default(),
+            };
+            state.pubsub.update(index, |status| status.assigned = assignment.accounts.len());
+            sender.send_if_modified(|current| {
+                let changed = *current != assignment;
+                *current = assignment;
+                changed
+            });
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(RESUBSCRIBE_CHECK) => {}
+            _ = state.reloaded.notified() => {}
+        }
+    }
+}
+
+// Accounts to subscribe to, each with the realtime pool it belongs to.
+fn realtime_accounts(state: &AppState) -> Vec<(Pubkey, Pubkey)> {
+    let mut accounts = Vec::new();
+    for (pool, tier) in state.watchlist() {
+        if tier != PollTier::Realtime {
+            continue;
+        }
+        accounts.push((pool, pool));
+        // Swaps move the vault balances, not always the pool account
+        if let Some(decoded) = state.cache.get(&pool).as_ref().and_then(|cached| cached.pool.as_ref()) {
+            accounts.push((decoded.vault_a, pool));
+            accounts.push((decoded.vault_b, pool));
+        }
+    }
+    accounts
+}
+
+// One upstream connection, open while it has accounts assigned and
+// reconnected after any failure.
+async fn connection(state: Arc<AppState>, index: usize, mut assignment: watch::Receiver<Assignment>) {
+    loop {
+        let url = {
+            let current = assignment.borrow_and_update();
+            (!current.accounts.is_empty()).then(|| current.url.clone())
+        };
+        let Some(url) = url else {
+            if assignment.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+        let result = follow(&state, index, &url, &mut assignment).await;
+        state.pubsub.update(index, |status| {
+            status.connected = false;
+            status.subscribed = 0;
+        });
+        if let Err(e) = result {
+            eprintln!("Realtime subscription {}: {}", index, e);
+            state.pubsub.update(index, |status| {
+                status.reconnects += 1;
+                status.last_error = Some(e);
+            });
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+}
+
+// Follow the assigned accounts over one connection and make their pools due
+// on each change, subscribing and unsubscribing as the assignment changes.
+// Returns once the connection has nothing left to follow or its endpoint
+// changed.
+async fn follow(
+    state: &AppState,
+    index: usize,
+    url: &str,
+    assignment: &mut watch::Receiver<Assignment>,
+) -> Result<(), String> {
+    let client = PubsubClient::connect(&state.upstream, url)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", redact_url(url), e))?;
+    state.pubsub.update(index, |status| status.connected = true);
+
+    // Each stream ends with a None once its subscription is gone, tagged so
+    // an account resubscribed since isn't mistaken for a lost one
+    let mut updates: SelectAll<BoxStream<'_, (Pubkey, u64, bool)>> = SelectAll::new();
+    let mut active: HashMap<Pubkey, (u64, Unsubscribe)> = HashMap::new();
+    let mut next_id = 0;
+    let result = loop {
+        let wanted = assignment.borrow_and_update().clone();
+        if wanted.url != url || wanted.accounts.is_empty() {
+            break Ok(());
+        }
+
+        let removed: Vec<Pubkey> = active.keys().filter(|account| !wanted.accounts.contains_key(account)).copied().collect();
+        for account in removed {
+            if let Some((_, unsubscribe)) = active.remove(&account) {
+                unsubscribe().await;
+            }
+            state.pubsub.subscribed.lock().unwrap().remove(&account);
+        }
+        // Subscribed in one batch rather than a round trip each
+        let added: Vec<Pubkey> = wanted.accounts.keys().filter(|account| !active.contains_key(account)).copied().collect();
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            ..RpcAccountInfoConfig::default()
+        };
+        let subscribed =
+            future::join_all(added.iter().map(|account| client.account_subscribe(account, Some(config.clone())))).await;
+        let mut failed = None;
+        for (account, result) in added.into_iter().zip(subscribed) {
+            match result {
+                Ok((stream, unsubscribe)) => {
+                    next_id += 1;
+                    let id = next_id;
+                    updates.push(
+                        stream
+                            .map(move |_| (account, id, true))
+                            .chain(stream::once(future::ready((account, id, false))))
+                            .boxed(),
+                    );
+                    active.insert(account, (id, unsubscribe));
+                    state.pubsub.subscribed.lock().unwrap().insert(account);
+                }
+                Err(e) => failed = Some(format!("Failed to subscribe to {}: {}", account, e)),
+            }
+        }
+        if let Some(e) = failed {
+            break Err(e);
+        }
+        state.pubsub.update(index, |status| {
+            status.subscribed = active.len();
+            status.last_error = None;
+        });
+
+        let closed = loop {
+            tokio::select! {
+                Some((account, id, open)) = updates.next(), if !updates.is_empty() => {
+                    let current = active.get(&account).is_some_and(|(active_id, _)| *active_id == id);
+                    match open {
+                        true => {
+                            if let Some(pool) = wanted.accounts.get(&account) {
+                                state.pubsub.notifications.fetch_add(1, Ordering::Relaxed);
+                                state.schedule.poll_now(pool);
+                            }
+                        }
+                        false if current => break Some(account),
+                        false => {}
+                    }
+                }
+                changed = assignment.changed() => match changed {
+                    Ok(()) => break None,
+                    // The manager is gone, so is everything else
+                    Err(_) => return Ok(()),
+                }
+            }
+        };
+        if let Some(account) = closed {
+            break Err(format!("Subscription to {} closed", account));
+        }
+    };
+
+    // The connection goes with them, no need to unsubscribe one by one
+    {
+        let mut subscribed = state.pubsub.subscribed.lock().unwrap();
+        for account in active.keys() {
+            subscribed.remove(account);
+        }
+    }
+    drop(updates);
+    drop(active);
+    client.shutdown().await;
+    result
+}
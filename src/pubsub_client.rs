@@ -0,0 +1,274 @@
+use crate::upstream::Upstream;
+use base64::Engine;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::future::{self, BoxFuture};
+use futures::stream::BoxStream;
+use futures::{FutureExt, SinkExt, StreamExt};
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use solana_account_decoder::UiAccount;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_response::{Response, RpcKeyedAccount, SlotInfo};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_socks::tcp::Socks5Stream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+// Keeps proxies and load balancers from dropping a quiet connection.
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+// Longest reply to a CONNECT read before giving up on the proxy.
+const MAX_CONNECT_REPLY: usize = 8192;
+
+const CLOSED: &str = "Connection closed";
+
+pub type Unsubscribe = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+type Subscribed = Result<(u64, UnboundedReceiver<Value>), String>;
+
+enum Command {
+    Subscribe {
+        method: &'static str,
+        params: Value,
+        reply: oneshot::Sender<Subscribed>,
+    },
+    Unsubscribe {
+        method: String,
+        id: u64,
+    },
+}
+
+// Solana PubSub client connecting through the configured proxy, which the
+// one in solana-pubsub-client can't. Streams end when the connection does.
+pub struct PubsubClient {
+    commands: mpsc::UnboundedSender<Command>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl PubsubClient {
+    pub async fn connect(upstream: &Upstream, url: &str) -> Result<Self, String> {
+        let parsed = Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+        let host = parsed.host_str().ok_or("URL has no host")?;
+        let port = parsed.port_or_known_default().ok_or("URL has no port")?;
+        let (proxy, timeout) = upstream.websocket_proxy(host);
+        let ws = tokio::time::timeout(timeout, async {
+            let stream = tunnel(proxy.as_ref(), host, port).await?;
+            let (ws, _) = tokio_tungstenite::client_async_tls(url, stream).await.map_err(|e| e.to_string())?;
+            Ok::<_, String>(ws)
+        })
+        .await
+        .map_err(|_| format!("Timed out after {}s", timeout.as_secs()))??;
+
+        let (commands, received) = mpsc::unbounded_channel();
+        let (shutdown, shutdown_received) = oneshot::channel();
+        Ok(PubsubClient {
+            commands,
+            shutdown,
+            task: tokio::spawn(run(ws, received, shutdown_received)),
+        })
+    }
+
+    pub async fn slot_subscribe(&self) -> Result<(BoxStream<'static, SlotInfo>, Unsubscribe), String> {
+        self.subscribe("slotSubscribe", json!([])).await
+    }
+
+    pub async fn account_subscribe(
+        &self,
+        account: &Pubkey,
+        config: Option<RpcAccountInfoConfig>,
+    ) -> Result<(BoxStream<'static, Response<UiAccount>>, Unsubscribe), String> {
+        self.subscribe("accountSubscribe", json!([account.to_string(), config])).await
+    }
+
+    pub async fn program_subscribe(
+        &self,
+        program: &Pubkey,
+        config: Option<RpcProgramAccountsConfig>,
+    ) -> Result<(BoxStream<'static, Response<RpcKeyedAccount>>, Unsubscribe), String> {
+        self.subscribe("programSubscribe", json!([program.to_string(), config])).await
+    }
+
+    // Close the connection, ending every stream.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+
+    // Notifications that don't parse as `T` are dropped.
+    async fn subscribe<T: DeserializeOwned + Send + 'static>(
+        &self,
+        method: &'static str,
+        params: Value,
+    ) -> Result<(BoxStream<'static, T>, Unsubscribe), String> {
+        let (reply, replied) = oneshot::channel();
+        self.commands
+            .send(Command::Subscribe { method, params, reply })
+            .map_err(|_| CLOSED.to_string())?;
+        let (id, notifications) = replied.await.map_err(|_| CLOSED.to_string())??;
+        let commands = self.commands.clone();
+        let unsubscribe: Unsubscribe = Box::new(move || {
+            let method = method.replace("Subscribe", "Unsubscribe");
+            let _ = commands.send(Command::Unsubscribe { method, id });
+            future::ready(()).boxed()
+        });
+        let stream = notifications.filter_map(|value| future::ready(serde_json::from_value(value).ok()));
+        Ok((stream.boxed(), unsubscribe))
+    }
+}
+
+// Send requests and route replies and notifications until the connection
+// closes or the client shuts it down.
+async fn run(
+    mut ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    mut shutdown: oneshot::Receiver<()>,
+) {
+    let mut next_request = 0u64;
+    let mut pending: HashMap<u64, oneshot::Sender<Subscribed>> = HashMap::new();
+    let mut subscriptions: HashMap<u64, UnboundedSender<Value>> = HashMap::new();
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    loop {
+        tokio::select! {
+            message = ws.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let Ok(message) = serde_json::from_str::<Value>(&text) else {
+                    continue;
+                };
+                if let Some(params) = message.get("params") {
+                    let sender = params["subscription"].as_u64().and_then(|id| subscriptions.get(&id));
+                    if let (Some(sender), Some(result)) = (sender, params.get("result")) {
+                        let _ = sender.unbounded_send(result.clone());
+                    }
+                } else if let Some(reply) = message["id"].as_u64().and_then(|id| pending.remove(&id)) {
+                    let subscribed = match message["result"].as_u64() {
+                        Some(id) => {
+                            let (sender, notifications) = unbounded();
+                            subscriptions.insert(id, sender);
+                            Ok((id, notifications))
+                        }
+                        None => Err(message["error"]["message"].as_str().unwrap_or("Subscription refused").to_string()),
+                    };
+                    let _ = reply.send(subscribed);
+                }
+            }
+            Some(command) = commands.recv() => {
+                next_request += 1;
+                let (method, params) = match command {
+                    Command::Subscribe { method, params, reply } => {
+                        pending.insert(next_request, reply);
+                        (method.to_string(), params)
+                    }
+                    Command::Unsubscribe { method, id } => {
+                        subscriptions.remove(&id);
+                        (method, json!([id]))
+                    }
+                };
+                let request = json!({"jsonrpc": "2.0", "id": next_request, "method": method, "params": params});
+                if ws.send(Message::Text(request.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            _ = ping.tick() => {
+                if ws.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            _ = &mut shutdown => {
+                let _ = ws.close(None).await;
+                break;
+            }
+        }
+    }
+}
+
+// A TCP connection to `host`, through `proxy` when given.
+async fn tunnel(proxy: Option<&Url>, host: &str, port: u16) -> Result<TcpStream, String> {
+    // IPv6 addresses come bracketed out of a URL
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let Some(proxy) = proxy else {
+        return TcpStream::connect((host, port)).await.map_err(|e| e.to_string());
+    };
+    let proxy_host = proxy.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    let credentials = (!proxy.username().is_empty()).then(|| {
+        let decode = |text| percent_decode_str(text).decode_utf8_lossy().into_owned();
+        (decode(proxy.username()), decode(proxy.password().unwrap_or_default()))
+    });
+    match proxy.scheme() {
+        "http" => {
+            let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+            let stream = TcpStream::connect((proxy_host, proxy_port))
+                .await
+                .map_err(|e| format!("Failed to connect to the proxy: {}", e))?;
+            http_connect(stream, credentials, host, port).await
+        }
+        scheme @ ("socks5" | "socks5h") => {
+            let proxy_address = (proxy_host, proxy.port().unwrap_or(1080));
+            // socks5h leaves resolving the host to the proxy
+            let target = match scheme {
+                "socks5" => tokio::net::lookup_host((host, port))
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .next()
+                    .ok_or_else(|| format!("{} has no address", host))?
+                    .to_string(),
+                _ => format!("{}:{}", host, port),
+            };
+            let connected = match &credentials {
+                Some((username, password)) => {
+                    Socks5Stream::connect_with_password(proxy_address, target.as_str(), username, password).await
+                }
+                None => Socks5Stream::connect(proxy_address, target.as_str()).await,
+            };
+            connected.map(Socks5Stream::into_inner).map_err(|e| format!("SOCKS proxy: {}", e))
+        }
+        scheme => Err(format!("WebSocket connections can't go through {} proxies", scheme)),
+    }
+}
+
+// Open a tunnel to `host` through an HTTP proxy with CONNECT.
+async fn http_connect(
+    mut stream: TcpStream,
+    credentials: Option<(String, String)>,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream, String> {
+    let target = match host.contains(':') {
+        true => format!("[{}]:{}", host, port),
+        false => format!("{}:{}", host, port),
+    };
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+    if let Some((username, password)) = credentials {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", encoded));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await.map_err(|e| format!("Proxy: {}", e))?;
+
+    // Read a byte at a time so nothing past the reply's head is taken from
+    // the tunnel
+    let mut reply = Vec::new();
+    while !reply.ends_with(b"\r\n\r\n") {
+        if reply.len() > MAX_CONNECT_REPLY {
+            return Err("Proxy sent an overlong reply".to_string());
+        }
+        reply.push(stream.read_u8().await.map_err(|e| format!("Proxy: {}", e))?);
+    }
+    let reply = String::from_utf8_lossy(&reply);
+    let status = reply.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(stream),
+        _ => Err(format!("Proxy refused the tunnel: {}", status)),
+    }
+}
@@ -0,0 +1,138 @@
+use crate::address::ValidatedPubkey;
+use crate::blocklist;
+use crate::cluster::Cluster;
+use crate::fixed;
+use crate::human::{self, Human};
+use crate::poller;
+use crate::staleness;
+use crate::state::AppState;
+use crate::token;
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Direction {
+    AToB,
+    BToA,
+}
+
+#[derive(Deserialize)]
+struct QuoteQuery {
+    // Raw input amount, before any transfer fee.
+    amount_in: u64,
+    #[serde(default = "default_direction")]
+    direction: Direction,
+}
+
+fn default_direction() -> Direction {
+    Direction::AToB
+}
+
+// Constant-product output for `amount_in` after the pool's swap fee.
+pub fn constant_product_out(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u64) -> (u64, u64) {
+    let pool_fee = fixed::mul_div_ceil(amount_in as u128, fee_bps as u128, 10_000).unwrap_or(u128::MAX).min(amount_in as u128);
+    let effective_in = amount_in as u128 - pool_fee;
+    // Below the output reserve, so it fits
+    let out = fixed::mul_div(effective_in, reserve_out as u128, (reserve_in as u128 + effective_in).max(1)).unwrap_or(0);
+    (out as u64, pool_fee as u64)
+}
+
+#[get("/pool/{pool_id}/quote")]
+async fn get_pool_quote(
+    state: web::Data<AppState>,
+    cluster: Cluster,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<QuoteQuery>,
+    human: Human,
+) -> HttpResponse {
+    let pubkey = pool_id.0;
+
+    let cached = match poller::get_pool(&state, &cluster, pubkey).await {
+        Ok(cached) => cached,
+        Err(e) => {
+            eprintln!("RPC error getting account: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": e
+            }));
+        }
+    };
+    let (Some(pool), Some(snapshot)) = (&cached.pool, &cached.snapshot) else {
+        return HttpResponse::UnprocessableEntity().json(json!({
+            "error": format!("Pool {} is not owned by a supported DEX program", pubkey)
+        }));
+    };
+
+    if pool.sqrt_price_x64.is_some() || pool.oracle_price.is_some() {
+        return HttpResponse::UnprocessableEntity().json(json!({
+            "error": format!("Quotes are only supported for constant product pools, {} is {}", pubkey, pool.dex)
+        }));
+    }
+
+    let (mint_in, mint_out, info_in, info_out, reserve_in, reserve_out) = match query.direction {
+        Direction::AToB => (pool.mint_a, pool.mint_b, &cached.token_a, &cached.token_b, snapshot.reserve_a, snapshot.reserve_b),
+        Direction::BToA => (pool.mint_b, pool.mint_a, &cached.token_b, &cached.token_a, snapshot.reserve_b, snapshot.reserve_a),
+    };
+    let (decimals_in, decimals_out) = match query.direction {
+        Direction::AToB => (pool.decimals_a, pool.decimals_b),
+        Direction::BToA => (pool.decimals_b, pool.decimals_a),
+    };
+
+    // Token-2022 transfer fees are withheld on the way into the pool and again
+    // on the way out to the trader.
+    let input_transfer_fee = info_in
+        .as_ref()
+        .map(|info| info.transfer_fee_for(query.amount_in, cached.slot))
+        .unwrap_or(0);
+    let amount_into_pool = query.amount_in - input_transfer_fee;
+    let (pool_output, pool_fee) =
+        constant_product_out(amount_into_pool, reserve_in, reserve_out, pool.fee_bps.unwrap_or(0));
+    let output_transfer_fee = info_out
+        .as_ref()
+        .map(|info| info.transfer_fee_for(pool_output, cached.slot))
+        .unwrap_or(0);
+
+    let mut risk_factors = Vec::new();
+    for (mint, info) in [(&mint_in, info_in), (&mint_out, info_out)] {
+        if let Some(info) = info {
+            risk_factors.extend(token::risk_factors(mint, info, cached.slot));
+        }
+    }
+
+    let freshness = staleness::freshness(&state, &cluster, &pubkey, &cached);
+
+    let amount_out = pool_output - output_transfer_fee;
+    let mut body = json!({
+        "pool_id": pool_id.to_string(),
+        "dex": pool.dex,
+        "label": pool.label(),
+        "slot": cached.slot,
+        "age_secs": freshness.age_secs,
+        "slot_lag": freshness.slot_lag,
+        "stale": freshness.stale,
+        "mint_in": mint_in.to_string(),
+        "mint_out": mint_out.to_string(),
+        "amount_in": query.amount_in,
+        "input_transfer_fee": input_transfer_fee,
+        "pool_fee": pool_fee,
+        "pool_output": pool_output,
+        "output_transfer_fee": output_transfer_fee,
+        "amount_out": amount_out,
+        "risk_factors": risk_factors,
+        "blocklisted": blocklist::flags(&state, &[("pool", pubkey), ("mint_in", mint_in), ("mint_out", mint_out)]),
+    });
+    if human.0 {
+        let input = |raw: u64| human::amount(raw as u128, decimals_in, &mint_in);
+        let output = |raw: u64| human::amount(raw as u128, decimals_out, &mint_out);
+        body["human"] = json!({
+            "amount_in": input(query.amount_in),
+            "input_transfer_fee": input(input_transfer_fee),
+            "pool_fee": input(pool_fee),
+            "pool_output": output(pool_output),
+            "output_transfer_fee": output(output_transfer_fee),
+            "amount_out": output(amount_out),
+        });
+    }
+    HttpResponse::Ok().json(body)
+}
@@ -0,0 +1,271 @@
+use crate::alerts::{self, Alert, Severity};
+use crate::amount::Amount;
+use crate::config::ReportPeriod;
+use crate::history::PoolSnapshot;
+use crate::state::{unix_now, AppState};
+use crate::storage::AlertFilter;
+use crate::templates;
+use crate::time::format_utc;
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+// Alerts read from the history per report, far more than a watchlist sees
+// in a week.
+const MAX_ALERTS: usize = 100_000;
+
+// Summary of one watched pool over a report's period. Prices are in the
+// pool's token b, TVL and volume are token b amounts.
+#[derive(Clone, Debug, Serialize)]
+pub struct PoolReport {
+    pub pool_id: String,
+    pub label: Option<String>,
+    pub dex: Option<&'static str>,
+    pub price_open: Option<f64>,
+    pub price_close: Option<f64>,
+    pub price_change_pct: Option<f64>,
+    pub volume: Option<Amount>,
+    pub tvl_open: Option<Amount>,
+    pub tvl_close: Option<Amount>,
+    pub tvl_change_pct: Option<f64>,
+    pub alert_count: usize,
+    // Critical alerts and liquidity migrations, oldest first.
+    pub events: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub period: ReportPeriod,
+    pub from: u64,
+    pub to: u64,
+    pub generated_at: u64,
+    // Every alert in the period, including those about no watched pool.
+    pub alert_count: usize,
+    pub pools: Vec<PoolReport>,
+}
+
+// Latest report of each period. Kept in memory only, a restart waits for the
+// next scheduled one.
+#[derive(Default)]
+pub struct Reports {
+    latest: RwLock<HashMap<&'static str, Report>>,
+}
+
+impl Reports {
+    // Newest report of `period`, or of any period.
+    pub fn latest(&self, period: Option<ReportPeriod>) -> Option<Report> {
+        let latest = self.latest.read().unwrap();
+        match period {
+            Some(period) => latest.get(period.as_str()).cloned(),
+            None => latest.values().max_by_key(|report| report.generated_at).cloned(),
+        }
+    }
+
+    fn store(&self, report: Report) {
+        self.latest.write().unwrap().insert(report.period.as_str(), report);
+    }
+}
+
+fn change_pct(open: Option<f64>, close: Option<f64>) -> Option<f64> {
+    match (open, close) {
+        (Some(open), Some(close)) if open != 0.0 => Some((close - open) / open * 100.0),
+        _ => None,
+    }
+}
+
+// Summary of `pools` between `from` and `to`.
+pub fn generate(state: &AppState, period: ReportPeriod, pools: &[Pubkey], from: u64, to: u64) -> Report {
+    let max_events = state.config.read().unwrap().reports.max_events;
+    let alerts = state
+        .store
+        .alert_history(&AlertFilter {
+            since: Some(from),
+            until: Some(to),
+            limit: MAX_ALERTS,
+            ..AlertFilter::default()
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to read alert history for report: {}", e);
+            Vec::new()
+        });
+    // Muted alerts weren't delivered, so they don't count either
+    let alerts: Vec<_> = alerts.into_iter().filter(|alert| alert.suppressed_by.is_none()).collect();
+
+    let pools = pools
+        .iter()
+        .map(|&pubkey| {
+            let pool = state.cache.get(&pubkey).and_then(|cached| cached.pool.clone());
+            let (before, after) = state.history.around(&pubkey, from, |p| p.timestamp);
+            let open = before.or(after).filter(|snapshot| snapshot.timestamp < to);
+            let close = state.history.at_or_before(&pubkey, to).filter(|_| open.is_some());
+            let amount = |raw: u128| pool.as_ref().map(|pool| Amount::new(raw, pool.decimals_b));
+            let tvl = |snapshot: &Option<PoolSnapshot>| amount(2 * snapshot.as_ref()?.reserve_b as u128);
+            let volume = match (&open, &close) {
+                (Some(open), Some(close)) => close
+                    .cumulative_volume_b
+                    .zip(open.cumulative_volume_b)
+                    .and_then(|(close, open)| amount(close.saturating_sub(open))),
+                _ => None,
+            };
+            let (price_open, price_close) = (open.as_ref().map(|s| s.price), close.as_ref().map(|s| s.price));
+            let (tvl_open, tvl_close) = (tvl(&open), tvl(&close));
+
+            let pool_alerts: Vec<_> = alerts.iter().filter(|alert| alert.pool == pubkey.to_string()).collect();
+            let mut events: Vec<(u64, String)> = pool_alerts
+                .iter()
+                .filter(|alert| alert.severity == Severity::Critical.as_str())
+                .map(|alert| (alert.timestamp, alert.message.clone()))
+                .collect();
+            match state.store.pool_links(&pubkey) {
+                Ok(links) => events.extend(
+                    links
+                        .into_iter()
+                        .filter(|link| link.old_pool == pubkey && link.drained_at >= from && link.drained_at < to)
+                        .map(|link| (link.drained_at, format!("Liquidity moved to {}", link.new_pool))),
+                ),
+                Err(e) => eprintln!("Failed to read pool links for report: {}", e),
+            }
+            events.sort_by_key(|(timestamp, _)| *timestamp);
+            events.truncate(max_events);
+
+            PoolReport {
+                pool_id: pubkey.to_string(),
+                label: pool.as_ref().map(|pool| pool.label()),
+                dex: pool.as_ref().map(|pool| pool.dex),
+                price_open,
+                price_close,
+                price_change_pct: change_pct(price_open, price_close),
+                volume,
+                tvl_open,
+                tvl_close,
+                tvl_change_pct: change_pct(tvl_open.map(|tvl| tvl.tokens()), tvl_close.map(|tvl| tvl.tokens())),
+                alert_count: pool_alerts.len(),
+                events: events.into_iter().map(|(timestamp, event)| format!("{} {}", format_utc(timestamp), event)).collect(),
+            }
+        })
+        .collect();
+
+    Report {
+        period,
+        from,
+        to,
+        generated_at: unix_now(),
+        alert_count: alerts.len(),
+        pools,
+    }
+}
+
+// Plain text version for the notification channels, the full report goes
+// along as the details.
+pub fn summary(report: &Report) -> String {
+    let pct = |value: Option<f64>| value.map_or("n/a".to_string(), |value| format!("{:+.2}%", value));
+    let mut lines = vec![format!(
+        "{} report {} to {}: {} pools, {} alerts",
+        match report.period {
+            ReportPeriod::Daily => "Daily",
+            ReportPeriod::Weekly => "Weekly",
+        },
+        format_utc(report.from),
+        format_utc(report.to),
+        report.pools.len(),
+        report.alert_count,
+    )];
+    for pool in &report.pools {
+        lines.push(format!(
+            "{}: price {}, TVL {}, volume {}, {} alerts",
+            pool.label.as_deref().unwrap_or(&pool.pool_id),
+            pct(pool.price_change_pct),
+            pct(pool.tvl_change_pct),
+            pool.volume.map_or("n/a".to_string(), |volume| format!("{:.2}", volume.tokens())),
+            pool.alert_count,
+        ));
+        lines.extend(pool.events.iter().map(|event| format!("  {}", event)));
+    }
+    lines.join("\n")
+}
+
+// Generate the report of the period ending at `to`, keep it as the latest
+// and send it out.
+pub fn publish(state: &AppState, period: ReportPeriod, to: u64) -> Report {
+    let pools: Vec<Pubkey> = state.watchlist().into_iter().map(|(pool, _)| pool).collect();
+    let report = generate(state, period, &pools, to.saturating_sub(period.secs()), to);
+    println!(
+        "Generated {} report for {} pools with {} alerts",
+        period.as_str(),
+        report.pools.len(),
+        report.alert_count
+    );
+    let channels = state.config.read().unwrap().reports.channels.clone();
+    alerts::notify(
+        state,
+        Alert::new("report", Severity::Warning, "watchlist".to_string(), summary(&report), json!(report))
+            .with_channels(channels),
+    );
+    state.reports.store(report.clone());
+    report
+}
+
+// Next `hour_utc` after `now`.
+fn next_run(hour_utc: u64, now: u64) -> u64 {
+    let at = now - now % DAY_SECS + hour_utc * 60 * 60;
+    if at <= now {
+        at + DAY_SECS
+    } else {
+        at
+    }
+}
+
+// Whether a report of `period` falls due at `at`, weekly ones on Mondays.
+pub fn is_due(period: ReportPeriod, at: u64) -> bool {
+    // Day 0 of unix time was a Thursday
+    period == ReportPeriod::Daily || (at / DAY_SECS + 3).is_multiple_of(7)
+}
+
+// Background task publishing the configured report and the scheduled
+// templates when they fall due, at `reports.hour_utc` every day. A config
+// reload reschedules it.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let config = state.config().reports;
+        let now = unix_now();
+        let at = next_run(config.hour_utc, now);
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(at - now)) => {
+                // Reports read alerts and links from the store, which blocks
+                let state = state.clone();
+                let published = tokio::task::spawn_blocking(move || {
+                    if let Some(period) = config.period.filter(|period| is_due(*period, at)) {
+                        publish(&state, period, at);
+                    }
+                    templates::publish_due(&state, at);
+                })
+                .await;
+                if let Err(e) = published {
+                    eprintln!("Task failed: {}", e);
+                }
+            }
+            _ = state.reloaded.notified() => {}
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    pub period: Option<ReportPeriod>,
+}
+
+// The newest report, of the given period or of any.
+#[get("/reports/latest")]
+async fn get_latest_report(state: web::Data<AppState>, query: web::Query<ReportQuery>) -> HttpResponse {
+    match state.reports.latest(query.period) {
+        Some(report) => HttpResponse::Ok().json(report),
+        None => HttpResponse::NotFound().json(json!({
+            "error": "No report has been generated yet"
+        })),
+    }
+}
@@ -0,0 +1,182 @@
+use crate::address::ValidatedPubkey;
+use crate::alerts::{self, Alert};
+use crate::amount::Amount;
+use crate::dex::{DecodedPool, Reward};
+use crate::fixed;
+use crate::history::{self, PoolSnapshot};
+use crate::time::parse_duration;
+use crate::portfolio;
+use crate::pricing;
+use crate::state::AppState;
+use crate::token;
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+
+const DEFAULT_WINDOW_SECS: u64 = 24 * 60 * 60;
+const DAY_SECS: u128 = 24 * 60 * 60;
+const YEAR_SECS: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+#[derive(Deserialize)]
+struct AprQuery {
+    // Volume window the fee APR is annualized from, 1d when omitted.
+    window: Option<String>,
+}
+
+// One reward's part of the pool APR. Emissions are a daily amount of the
+// reward token, null when the mint couldn't be read.
+#[derive(Serialize)]
+struct RewardApr {
+    mint: String,
+    symbol: String,
+    active: bool,
+    emissions_per_day: Option<Amount>,
+    emissions_usd_per_year: Option<f64>,
+    open_time: Option<u64>,
+    end_time: Option<u64>,
+    end_estimated: bool,
+    apr_pct: Option<f64>,
+}
+
+// Alert on rewards of a pool whose emissions end within the configured
+// warning time. Raydium AMM farms live in separate farm accounts the pool
+// doesn't reference, so only concentrated liquidity rewards are covered.
+pub fn check_expiry(state: &AppState, pool: &Pubkey, decoded: &DecodedPool, timestamp: u64) {
+    let config = state.config.read().unwrap().alerts.rewards.clone();
+    for reward in &decoded.rewards {
+        let key = format!("emissions/{}/{}", pool, reward.mint);
+        let Some(end_time) = reward.end_time else {
+            continue;
+        };
+        let expiring = reward.active(timestamp) && end_time - timestamp <= config.warn_before_secs;
+        let Some(severity) = config.expiring.filter(|_| expiring) else {
+            alerts::resolve(state, &key);
+            continue;
+        };
+        let ends = if reward.end_estimated { "are estimated to end" } else { "end" };
+        let message = format!(
+            "{} emissions of {} {} in {:.1}h",
+            token::symbol(&reward.mint),
+            decoded.label(),
+            ends,
+            (end_time - timestamp) as f64 / 3600.0
+        );
+        // The time left changes every poll, the cooldown keeps it to one
+        // alert per expiry
+        alerts::fire(
+            state,
+            Alert::new(
+                "emissions_expiring",
+                severity,
+                pool.to_string(),
+                message,
+                json!({
+                    "mint": reward.mint.to_string(),
+                    "end_time": end_time,
+                    "end_estimated": reward.end_estimated,
+                }),
+            )
+            .with_key(key)
+            .with_cooldown(config.warn_before_secs)
+            .with_channels(config.channels.clone()),
+        );
+    }
+}
+
+// USD price of a reward token, through the pool itself when it pays out one
+// of its own tokens.
+fn reward_usd_price(state: &AppState, decoded: &DecodedPool, snapshot: &PoolSnapshot, reward: &Reward) -> Option<f64> {
+    if reward.mint == decoded.mint_a || reward.mint == decoded.mint_b {
+        let (usd_a, usd_b) = portfolio::usd_prices(state, decoded, snapshot)?;
+        return Some(if reward.mint == decoded.mint_a { usd_a } else { usd_b });
+    }
+    pricing::usd_price_at(state, &reward.mint, snapshot.timestamp)
+}
+
+// APR of the pool's liquidity as a whole, split into swap fees, annualized
+// from the volume over `window`, and reward emissions at their current rate.
+// Fees are before the protocol's cut. Parts that can't be priced, or fees of
+// DEXes without a volume counter, are null and so is the total.
+#[get("/pool/{pool_id}/apr")]
+async fn get_pool_apr(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<AprQuery>,
+) -> HttpResponse {
+    let pool = pool_id.0;
+    let retention = state.config.read().unwrap().history_retention_secs;
+    let window = match query.window.as_deref().map_or(Ok(DEFAULT_WINDOW_SECS), |window| parse_duration(window, retention)) {
+        Ok(window) => window,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    let Some(decoded) = state.cache.get(&pool).and_then(|cached| cached.pool.clone()) else {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": format!("Pool {} has not been polled yet", pool)
+        }));
+    };
+    let Some(snapshot) = state.history.latest(&pool) else {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": format!("No snapshot of pool {} recorded yet", pool)
+        }));
+    };
+
+    let tokens_a = |raw: u128| fixed::to_tokens(raw, decoded.decimals_a);
+    let tokens_b = |raw: u128| fixed::to_tokens(raw, decoded.decimals_b);
+    let prices = portfolio::usd_prices(&state, &decoded, &snapshot);
+    let tvl_usd = prices
+        .map(|(usd_a, usd_b)| tokens_a(snapshot.reserve_a as u128) * usd_a + tokens_b(snapshot.reserve_b as u128) * usd_b)
+        .filter(|tvl| *tvl > 0.0);
+    let volume_usd = history::volume(&state.history, &pool, &snapshot, window)
+        .zip(prices)
+        .map(|(volume, (_, usd_b))| tokens_b(volume) * usd_b);
+    let fee_apr = match (volume_usd, decoded.fee_bps, tvl_usd) {
+        (Some(volume), Some(fee_bps), Some(tvl)) => Some(volume * fee_bps as f64 / 10_000.0 / tvl * YEAR_SECS / window as f64),
+        _ => None,
+    };
+
+    let rewards: Vec<RewardApr> = decoded
+        .rewards
+        .iter()
+        .map(|reward| {
+            let active = reward.active(snapshot.timestamp);
+            // Emissions per second are Q64.64
+            let per_day = reward.decimals.map(|decimals| {
+                Amount::new(fixed::mul_div(reward.emissions_per_second_x64, DAY_SECS, 1 << 64).unwrap_or(u128::MAX), decimals)
+            });
+            // Ended or not yet started rewards add nothing, priced or not
+            let usd_per_year = match active {
+                true => per_day
+                    .zip(reward_usd_price(&state, &decoded, &snapshot, reward))
+                    .map(|(per_day, usd)| per_day.tokens() * usd * YEAR_SECS / DAY_SECS as f64),
+                false => Some(0.0),
+            };
+            RewardApr {
+                mint: reward.mint.to_string(),
+                symbol: token::symbol(&reward.mint),
+                active,
+                emissions_per_day: per_day,
+                emissions_usd_per_year: usd_per_year,
+                open_time: reward.open_time,
+                end_time: reward.end_time,
+                end_estimated: reward.end_estimated,
+                apr_pct: usd_per_year.zip(tvl_usd).map(|(usd, tvl)| usd / tvl * 100.0),
+            }
+        })
+        .collect();
+    let emissions_apr_pct = rewards.iter().try_fold(0.0, |sum, reward| Some(sum + reward.apr_pct?));
+    let fee_apr_pct = fee_apr.map(|apr| apr * 100.0);
+
+    HttpResponse::Ok().json(json!({
+        "pool": pool.to_string(),
+        "label": decoded.label(),
+        "timestamp": snapshot.timestamp,
+        "tvl_usd": tvl_usd,
+        "window_secs": window,
+        "volume_usd": volume_usd,
+        "fee_apr_pct": fee_apr_pct,
+        "emissions_apr_pct": emissions_apr_pct,
+        "total_apr_pct": fee_apr_pct.zip(emissions_apr_pct).map(|(fees, emissions)| fees + emissions),
+        "rewards": rewards,
+    }))
+}
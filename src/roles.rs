@@ -0,0 +1,35 @@
+use crate::config::Role;
+use actix_web::http::Method;
+
+// Routes outside /admin that change state, by method and pattern.
+const WRITER_ROUTES: &[(&str, &str)] = &[
+    ("POST", "/alerts/{id}/ack"),
+    ("POST", "/pool/{pool_id}/annotations"),
+    ("DELETE", "/pool/{pool_id}/annotations/{id}"),
+    ("POST", "/subscriptions"),
+    ("DELETE", "/subscriptions/{id}"),
+    ("POST", "/subscriptions/{id}/dead-letters/redeliver"),
+    ("POST", "/reports/templates"),
+    ("DELETE", "/reports/templates/{id}"),
+    ("POST", "/solana/send"),
+    ("POST", "/portfolios"),
+    ("DELETE", "/portfolio/{id}"),
+    ("POST", "/portfolio/{id}/positions"),
+    ("DELETE", "/portfolio/{id}/positions/{index}"),
+];
+// Admin routes writers may use too.
+const WRITER_ADMIN_PREFIX: &str = "/admin/watchlist";
+
+// Least role allowed on a route.
+pub fn required_role(method: &Method, pattern: &str) -> Role {
+    if pattern.starts_with(WRITER_ADMIN_PREFIX) {
+        return Role::Writer;
+    }
+    if pattern.starts_with("/admin") {
+        return Role::Admin;
+    }
+    if WRITER_ROUTES.iter().any(|(m, p)| *m == method.as_str() && *p == pattern) {
+        return Role::Writer;
+    }
+    Role::Reader
+}
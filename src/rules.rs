@@ -0,0 +1,234 @@
+use crate::alerts::{self, Alert, Severity};
+use crate::history::Candle;
+use crate::mute::MuteWindow;
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Alert rule evaluated on closed candles of the recorded pool history, e.g.
+// {"name": "sol-ma-cross", "interval_secs": 3600,
+//  "condition": {"type": "crosses_moving_average", "period": 20}}.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    // Pools the rule applies to, every watched pool when empty.
+    #[serde(default)]
+    pub pools: Vec<String>,
+    pub interval_secs: u64,
+    #[serde(default = "default_severity")]
+    pub severity: Severity,
+    // Minimum time between two alerts from this rule for the same pool.
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    // Email addresses for this rule's alerts, instead of each email
+    // channel's `to`.
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    // Channels this rule's alerts go to by name, every channel when empty.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    // Windows in which this rule's alerts are recorded but not delivered,
+    // whatever their severity.
+    #[serde(default)]
+    pub mute: Vec<MuteWindow>,
+    pub condition: CandleCondition,
+}
+
+fn default_severity() -> Severity {
+    Severity::Warning
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CandleCondition {
+    // Close crossed the simple moving average of the last `period` closes.
+    CrossesMovingAverage {
+        period: usize,
+        #[serde(default)]
+        direction: CrossDirection,
+    },
+    // A run of `count` candles of one color, fired once when the run reaches
+    // that length.
+    ConsecutiveCandles { count: usize, color: CandleColor },
+    // Candle volume above `multiplier` times the average of the `period`
+    // candles before it.
+    VolumeSpike { multiplier: f64, period: usize },
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossDirection {
+    Above,
+    Below,
+    #[default]
+    Either,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CandleColor {
+    Red,
+    Green,
+}
+
+impl AlertRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_secs == 0 {
+            return Err(format!("alerts.rules.{}.interval_secs must be at least 1", self.name));
+        }
+        let valid = match &self.condition {
+            CandleCondition::CrossesMovingAverage { period, .. } => *period > 0,
+            CandleCondition::ConsecutiveCandles { count, .. } => *count > 0,
+            CandleCondition::VolumeSpike { multiplier, period } => *multiplier > 0.0 && *period > 0,
+        };
+        if !valid {
+            return Err(format!("alerts.rules.{} has a zero period, count or multiplier", self.name));
+        }
+        if let Some(address) = self.recipients.iter().find(|a| a.parse::<lettre::message::Mailbox>().is_err()) {
+            return Err(format!("alerts.rules.{} has an invalid recipient {}", self.name, address));
+        }
+        for window in &self.mute {
+            window.validate().map_err(|e| format!("alerts.rules.{}.mute: {}", self.name, e))?;
+        }
+        Ok(())
+    }
+
+    fn applies_to(&self, pool: &Pubkey) -> bool {
+        self.pools.is_empty() || self.pools.contains(&pool.to_string())
+    }
+}
+
+// Open time of the last candle each rule saw per pool, so a candle is only
+// evaluated once however often the pool is polled.
+#[derive(Default)]
+pub struct RuleState {
+    evaluated: Mutex<HashMap<(String, Pubkey), u64>>,
+}
+
+// Evaluate every rule for a pool against its closed candles as of `now`.
+pub fn evaluate(state: &AppState, pool: Pubkey, now: u64) {
+    let rules = state.config.read().unwrap().alerts.rules.clone();
+    for rule in rules.iter().filter(|rule| rule.applies_to(&pool)) {
+        let candles = state.history.candles(&pool, rule.interval_secs, now);
+        let Some(last) = candles.last() else {
+            continue;
+        };
+        {
+            let mut evaluated = state.rules.evaluated.lock().unwrap();
+            let seen = evaluated.entry((rule.name.clone(), pool)).or_default();
+            if *seen >= last.open_time {
+                continue;
+            }
+            *seen = last.open_time;
+        }
+        let key = format!("candle_pattern/{}/{}", rule.name, pool);
+        match check(&rule.condition, &candles) {
+            Some((message, details)) => alerts::fire(
+                state,
+                Alert::new(
+                    "candle_pattern",
+                    rule.severity,
+                    pool.to_string(),
+                    format!("Rule {} on pool {}: {}", rule.name, pool, message),
+                    json!({
+                        "rule": rule.name,
+                        "interval_secs": rule.interval_secs,
+                        "candle": last,
+                        "current": details["current"],
+                        "threshold": details["threshold"],
+                        "values": details,
+                    }),
+                )
+                .with_key(key)
+                .with_cooldown(rule.cooldown_secs)
+                .with_recipients(rule.recipients.clone())
+                .with_channels(rule.channels.clone())
+                .with_mute(rule.mute.clone()),
+            ),
+            // A closed candle without the pattern ends the alert
+            None => alerts::resolve(state, &key),
+        }
+    }
+}
+
+// Whether the newest candle meets the condition, with a description and the
+// values that triggered it, including the `current` value and the
+// `threshold` it passed.
+fn check(condition: &CandleCondition, candles: &[Candle]) -> Option<(String, serde_json::Value)> {
+    let n = candles.len();
+    match *condition {
+        CandleCondition::CrossesMovingAverage { period, direction } => {
+            if n < period + 1 {
+                return None;
+            }
+            let average = |end: usize| candles[end - period..end].iter().map(|c| c.close).sum::<f64>() / period as f64;
+            let (previous_ma, ma) = (average(n - 1), average(n));
+            let (previous, close) = (candles[n - 2].close, candles[n - 1].close);
+            let crossed_above = previous <= previous_ma && close > ma;
+            let crossed_below = previous >= previous_ma && close < ma;
+            let side = match direction {
+                CrossDirection::Above if crossed_above => "above",
+                CrossDirection::Below if crossed_below => "below",
+                CrossDirection::Either if crossed_above => "above",
+                CrossDirection::Either if crossed_below => "below",
+                _ => return None,
+            };
+            Some((
+                format!("close {} crossed {} the {}-candle moving average {}", close, side, period, ma),
+                json!({ "close": close, "moving_average": ma, "direction": side, "current": close, "threshold": ma }),
+            ))
+        }
+        CandleCondition::ConsecutiveCandles { count, color } => {
+            let matches = |candle: &Candle| match color {
+                CandleColor::Red => candle.is_red(),
+                CandleColor::Green => candle.is_green(),
+            };
+            if n < count || !candles[n - count..].iter().all(matches) {
+                return None;
+            }
+            // Only when the run reaches the count, not on every candle after
+            if n > count && matches(&candles[n - count - 1]) {
+                return None;
+            }
+            let name = match color {
+                CandleColor::Red => "red",
+                CandleColor::Green => "green",
+            };
+            Some((
+                format!("{} consecutive {} candles", count, name),
+                json!({
+                    "count": count,
+                    "color": name,
+                    "change": candles[n - 1].close - candles[n - count].open,
+                    "current": count,
+                    "threshold": count,
+                }),
+            ))
+        }
+        CandleCondition::VolumeSpike { multiplier, period } => {
+            if n < period + 1 {
+                return None;
+            }
+            let raw = candles[n - 1].volume_b?;
+            let previous: Option<Vec<u128>> = candles[n - 1 - period..n - 1].iter().map(|c| c.volume_b).collect();
+            let total = previous?.iter().sum::<u128>();
+            let (volume, average) = (raw as f64, total as f64 / period as f64);
+            if average == 0.0 || volume <= average * multiplier {
+                return None;
+            }
+            Some((
+                format!("volume {:.1}x the {}-candle average", volume / average, period),
+                json!({
+                    "volume_b": raw.to_string(),
+                    "average_volume_b": (total / period as u128).to_string(),
+                    "ratio": volume / average,
+                    "current": volume,
+                    "threshold": average * multiplier,
+                }),
+            ))
+        }
+    }
+}
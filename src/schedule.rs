@@ -0,0 +1,210 @@
+use crate::config::Config;
+use crate::migration;
+use crate::poller;
+use crate::state::{unix_now, AppState, CachedAccount};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+// Polls in a row finding no account before a pool counts as closed, one can
+// be a node lagging behind.
+const CLOSED_AFTER_POLLS: u64 = 3;
+
+// How often a watched pool is polled.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PollTier {
+    // Subscribed to over WebSocket and refreshed on every change, polled at
+    // `polling.realtime_interval_secs` as a fallback.
+    Realtime,
+    // Polled every `poll_interval_secs`.
+    #[default]
+    Standard,
+    // Polled every `polling.slow_interval_secs`.
+    Slow,
+    // Closed or inactive pools, polled every `polling.dormant_interval_secs`
+    // or not at all when that is zero.
+    Dormant,
+}
+
+impl PollTier {
+    pub fn interval_secs(&self, config: &Config) -> u64 {
+        let secs = match self {
+            PollTier::Realtime => config.polling.realtime_interval_secs,
+            PollTier::Standard => config.poll_interval_secs,
+            PollTier::Slow => config.polling.slow_interval_secs,
+            PollTier::Dormant => config.polling.dormant_interval_secs,
+        };
+        secs.max(1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DormantReason {
+    // The pool account no longer exists.
+    Closed,
+    // The reserves haven't moved for `polling.dormant_after_days`.
+    Inactive,
+}
+
+#[derive(Clone, Serialize)]
+pub struct DormantPool {
+    pub reason: DormantReason,
+    pub since: u64,
+}
+
+// When each watched pool is next polled.
+#[derive(Default)]
+pub struct Schedule {
+    // Pools missing here are due now.
+    due: Mutex<HashMap<Pubkey, u64>>,
+    // The chain's slot when each pool was last polled, while slots are
+    // followed.
+    polled_at_slot: Mutex<HashMap<Pubkey, u64>>,
+    // Kept in memory, after a restart pools are found dormant again from
+    // their stored history or within a few polls when closed.
+    dormant: Mutex<HashMap<Pubkey, DormantPool>>,
+    // Reactivated by hand, inactivity only counts from then.
+    reactivated: Mutex<HashMap<Pubkey, u64>>,
+    // Cuts the poller's sleep short when a pool became due early.
+    pub wake: Notify,
+}
+
+impl Schedule {
+    pub fn next_poll_at(&self, pool: &Pubkey) -> Option<u64> {
+        self.due.lock().unwrap().get(pool).copied()
+    }
+
+    pub fn set_next_poll(&self, pool: Pubkey, at: u64) {
+        self.due.lock().unwrap().insert(pool, at);
+    }
+
+    // Whether the chain moved on from `slot` since the pool's last poll, so
+    // it may have changed.
+    pub fn advanced_since_poll(&self, pool: &Pubkey, slot: u64) -> bool {
+        self.polled_at_slot.lock().unwrap().get(pool).is_none_or(|polled| slot > *polled)
+    }
+
+    pub fn set_polled_at_slot(&self, pool: Pubkey, slot: u64) {
+        self.polled_at_slot.lock().unwrap().insert(pool, slot);
+    }
+
+    // Make a pool due now, e.g. after its account changed.
+    pub fn poll_now(&self, pool: &Pubkey) {
+        self.due.lock().unwrap().remove(pool);
+        self.wake.notify_one();
+    }
+
+    // Make every pool due now, for when the tiers or intervals changed.
+    pub fn reset(&self) {
+        self.due.lock().unwrap().clear();
+    }
+
+    // Forget pools that are no longer watched.
+    pub fn retain(&self, pools: &HashSet<Pubkey>) {
+        self.due.lock().unwrap().retain(|pool, _| pools.contains(pool));
+        self.polled_at_slot.lock().unwrap().retain(|pool, _| pools.contains(pool));
+    }
+
+    pub fn is_dormant(&self, pool: &Pubkey) -> bool {
+        self.dormant.lock().unwrap().contains_key(pool)
+    }
+
+    pub fn dormant(&self) -> Vec<(Pubkey, DormantPool)> {
+        let mut dormant: Vec<_> = self.dormant.lock().unwrap().iter().map(|(p, d)| (*p, d.clone())).collect();
+        dormant.sort_by_key(|(_, d)| d.since);
+        dormant
+    }
+
+    // Put a dormant pool back on its tier and poll it now. False when it
+    // wasn't dormant.
+    pub fn reactivate(&self, pool: &Pubkey) -> bool {
+        if self.dormant.lock().unwrap().remove(pool).is_none() {
+            return false;
+        }
+        self.reactivated.lock().unwrap().insert(*pool, unix_now());
+        self.poll_now(pool);
+        true
+    }
+}
+
+// Demote a polled pool that is closed or hasn't traded for
+// `polling.dormant_after_days`, and bring a dormant one back once it shows
+// life again.
+pub async fn update_dormancy(state: &AppState, pool: Pubkey, result: &Result<Arc<CachedAccount>, String>) {
+    let config = state.config.read().unwrap().polling.clone();
+    let now = unix_now();
+    let reason = match result {
+        Err(e) if *e == poller::not_found(&pool) => {
+            let failures = state.pollers.get(&pool).map_or(0, |s| s.consecutive_failures);
+            if failures < CLOSED_AFTER_POLLS {
+                return;
+            }
+            Some(DormantReason::Closed)
+        }
+        // Other failures say nothing about the pool
+        Err(_) => return,
+        Ok(_) => {
+            let reactivated = state.schedule.reactivated.lock().unwrap().get(&pool).copied();
+            let quiet_since = state.history.unchanged_since(&pool).max(reactivated);
+            let inactive = config.dormant_after_days > 0
+                && quiet_since.is_some_and(|since| now.saturating_sub(since) >= config.dormant_after_days * 24 * 60 * 60);
+            inactive.then_some(DormantReason::Inactive)
+        }
+    };
+
+    let closed = {
+        let mut dormant = state.schedule.dormant.lock().unwrap();
+        match (dormant.contains_key(&pool), reason) {
+            (false, Some(reason)) => {
+                println!("Pool {} is dormant ({:?}), moving it to the dormant tier", pool, reason);
+                dormant.insert(pool, DormantPool { reason, since: now });
+                reason == DormantReason::Closed
+            }
+            (true, None) => {
+                println!("Pool {} is active again, moving it back to its tier", pool);
+                dormant.remove(&pool);
+                false
+            }
+            _ => false,
+        }
+    };
+    if closed {
+        migration::pool_closed(state, pool).await;
+    }
+}
+
+// Effective schedule of one watched pool.
+#[derive(Serialize)]
+pub struct ScheduledPool {
+    pub pool: String,
+    pub tier: PollTier,
+    pub interval_secs: u64,
+    pub subscribed: bool,
+    pub last_poll: Option<u64>,
+    // None when due now.
+    pub next_poll_at: Option<u64>,
+}
+
+// Every watched pool with when it is next polled, soonest first.
+pub fn effective(state: &AppState) -> Vec<ScheduledPool> {
+    let config = state.config();
+    let now = unix_now();
+    let mut pools: Vec<ScheduledPool> = state
+        .watchlist()
+        .into_iter()
+        .map(|(pool, tier)| ScheduledPool {
+            pool: pool.to_string(),
+            tier,
+            interval_secs: tier.interval_secs(&config),
+            subscribed: state.pubsub.is_subscribed(&pool),
+            last_poll: state.pollers.get(&pool).and_then(|status| status.last_success.max(status.last_error_at)),
+            next_poll_at: state.schedule.next_poll_at(&pool).filter(|at| *at > now),
+        })
+        .collect();
+    pools.sort_by_key(|pool| pool.next_poll_at.unwrap_or(0));
+    pools
+}
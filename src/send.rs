@@ -0,0 +1,261 @@
+use crate::cluster::Cluster;
+use crate::simulate::decode_transaction;
+use crate::state::AppState;
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+// A blockhash is valid for 150 blocks, roughly a minute, so waiting longer
+// than this never helps.
+const MAX_TIMEOUT_SECS: u64 = 90;
+const RESEND_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_WAIT_SECS: u64 = 60;
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct SendRequest {
+    // Base64-encoded, bincode-serialized (versioned) signed transaction.
+    transaction: String,
+    #[serde(default)]
+    skip_preflight: bool,
+    // How long to keep resending until the transaction is confirmed. Zero
+    // returns as soon as it is first submitted.
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct StatusQuery {
+    // Long-poll for up to this long, as "30" or "30s".
+    wait: Option<String>,
+    commitment: Option<String>,
+}
+
+enum Landing {
+    Submitted,
+    Confirmed { slot: u64, err: Option<TransactionError> },
+    Expired,
+    TimedOut,
+}
+
+#[get("/solana/blockhash")]
+async fn get_blockhash(state: web::Data<AppState>, cluster: Cluster) -> HttpResponse {
+    let rpc_client = state.cluster_rpc_client(&cluster).await;
+
+    match tokio::task::spawn_blocking(move || rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment())).await {
+        Ok(Ok((blockhash, last_valid_block_height))) => HttpResponse::Ok().json(json!({
+            "cluster": cluster.name,
+            "blockhash": blockhash.to_string(),
+            "last_valid_block_height": last_valid_block_height,
+        })),
+        Ok(Err(e)) => {
+            eprintln!("RPC error getting blockhash: {}", e);
+            HttpResponse::BadGateway().json(json!({
+                "error": format!("Failed to get latest blockhash: {}", e)
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": format!("Task failed: {}", e)
+        })),
+    }
+}
+
+// Submit a signed transaction and resend it until it is confirmed, its
+// blockhash expires or the timeout runs out. RPC nodes drop transactions
+// under load, resending is what gets them landed.
+#[post("/solana/send")]
+async fn send_transaction(state: web::Data<AppState>, cluster: Cluster, body: web::Json<SendRequest>) -> HttpResponse {
+    let transaction = match decode_transaction(&body.transaction) {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let timeout = Duration::from_secs(body.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS).min(MAX_TIMEOUT_SECS));
+
+    let rpc_client = state.cluster_rpc_client(&cluster).await;
+    let config = RpcSendTransactionConfig {
+        skip_preflight: body.skip_preflight,
+        preflight_commitment: Some(rpc_client.commitment().commitment),
+        ..RpcSendTransactionConfig::default()
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        let signature = rpc_client.send_transaction_with_config(&transaction, config)?;
+        if timeout.is_zero() {
+            return Ok((signature, Landing::Submitted));
+        }
+        let deadline = Instant::now() + timeout;
+        // Preflight already passed or was skipped on purpose
+        let resend_config = RpcSendTransactionConfig {
+            skip_preflight: true,
+            ..config
+        };
+        loop {
+            // Checked before the status so a transaction landing in between
+            // still reports as confirmed
+            let expired = !rpc_client.is_blockhash_valid(transaction.message.recent_blockhash(), rpc_client.commitment())?;
+            let status = rpc_client.get_signature_statuses(&[signature])?.value.into_iter().next().flatten();
+            if let Some(status) = status {
+                if status.satisfies_commitment(rpc_client.commitment()) {
+                    return Ok((signature, Landing::Confirmed { slot: status.slot, err: status.err }));
+                }
+            }
+            if expired {
+                return Ok((signature, Landing::Expired));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok((signature, Landing::TimedOut));
+            }
+            std::thread::sleep(RESEND_INTERVAL.min(deadline - now));
+            if let Err(e) = rpc_client.send_transaction_with_config(&transaction, resend_config) {
+                eprintln!("Failed to resend {}: {}", signature, e);
+            }
+        }
+    })
+    .await;
+
+    match result {
+        Ok(Ok((signature, landing))) => landing_response(signature, landing, timeout),
+        Ok(Err(e)) => send_error(e),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": format!("Task failed: {}", e)
+        })),
+    }
+}
+
+fn landing_response(signature: Signature, landing: Landing, timeout: Duration) -> HttpResponse {
+    let signature = signature.to_string();
+    match landing {
+        Landing::Submitted => HttpResponse::Accepted().json(json!({
+            "signature": signature,
+            "status": "submitted",
+        })),
+        Landing::Confirmed { slot, err } => HttpResponse::Ok().json(json!({
+            "signature": signature,
+            "status": if err.is_none() { "confirmed" } else { "failed" },
+            "slot": slot,
+            "error": err.map(|e| e.to_string()),
+        })),
+        Landing::Expired => HttpResponse::Gone().json(json!({
+            "signature": signature,
+            "status": "expired",
+            "error": "Blockhash expired before the transaction was confirmed, it can no longer land",
+        })),
+        Landing::TimedOut => HttpResponse::GatewayTimeout().json(json!({
+            "signature": signature,
+            "status": "pending",
+            "error": format!("Not confirmed within {}s, it may still land", timeout.as_secs()),
+        })),
+    }
+}
+
+fn send_error(e: ClientError) -> HttpResponse {
+    // Simulation failures are the caller's problem, anything else is upstream
+    if let ClientErrorKind::RpcError(RpcError::RpcResponseError {
+        message,
+        data: RpcResponseErrorData::SendTransactionPreflightFailure(simulation),
+        ..
+    }) = e.kind()
+    {
+        return HttpResponse::UnprocessableEntity().json(json!({
+            "error": message,
+            "logs": simulation.logs.clone().unwrap_or_default(),
+        }));
+    }
+    eprintln!("RPC error sending transaction: {}", e);
+    HttpResponse::BadGateway().json(json!({
+        "error": format!("Failed to send transaction: {}", e)
+    }))
+}
+
+fn parse_wait(wait: &str) -> Result<Duration, String> {
+    let secs = wait.strip_suffix('s').unwrap_or(wait);
+    secs.parse::<u64>()
+        .map(|secs| Duration::from_secs(secs.min(MAX_WAIT_SECS)))
+        .map_err(|_| format!("Invalid wait {}, expected seconds like 30s", wait))
+}
+
+fn parse_commitment(commitment: Option<&str>) -> Result<CommitmentConfig, String> {
+    match commitment.map(CommitmentLevel::from_str) {
+        None => Ok(CommitmentConfig::confirmed()),
+        Some(Ok(commitment @ (CommitmentLevel::Processed | CommitmentLevel::Confirmed | CommitmentLevel::Finalized))) => {
+            Ok(CommitmentConfig { commitment })
+        }
+        _ => Err("commitment must be processed, confirmed or finalized".to_string()),
+    }
+}
+
+// Status of a transaction by signature. With `wait` the request is held until
+// the transaction reaches `commitment` (confirmed by default) or the wait
+// runs out, whichever comes first.
+#[get("/tx/{signature}/status")]
+async fn get_transaction_status(
+    state: web::Data<AppState>,
+    cluster: Cluster,
+    signature: web::Path<String>,
+    query: web::Query<StatusQuery>,
+) -> HttpResponse {
+    let signature = match Signature::from_str(&signature) {
+        Ok(signature) => signature,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid signature: {}", e)
+            }));
+        }
+    };
+    let (wait, commitment) = match (
+        query.wait.as_deref().map(parse_wait).transpose(),
+        parse_commitment(query.commitment.as_deref()),
+    ) {
+        (Ok(wait), Ok(commitment)) => (wait.unwrap_or_default(), commitment),
+        (Err(e), _) | (_, Err(e)) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": e
+            }));
+        }
+    };
+
+    let deadline = Instant::now() + wait;
+    loop {
+        // A fresh client per poll so waiting doesn't hold an upstream slot
+        let rpc_client = state.cluster_rpc_client(&cluster).await;
+        let result = tokio::task::spawn_blocking(move || rpc_client.get_signature_statuses_with_history(&[signature])).await;
+        let status = match result {
+            Ok(Ok(response)) => response.value.into_iter().next().flatten(),
+            Ok(Err(e)) => {
+                eprintln!("RPC error getting signature status: {}", e);
+                return HttpResponse::BadGateway().json(json!({
+                    "error": format!("Failed to get signature status: {}", e)
+                }));
+            }
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(json!({
+                    "error": format!("Task failed: {}", e)
+                }));
+            }
+        };
+
+        let reached = status.as_ref().is_some_and(|s| s.satisfies_commitment(commitment));
+        if reached || Instant::now() + STATUS_POLL_INTERVAL > deadline {
+            return HttpResponse::Ok().json(json!({
+                "signature": signature.to_string(),
+                "cluster": cluster.name,
+                "found": status.is_some(),
+                "reached": reached,
+                "commitment": commitment.commitment,
+                "slot": status.as_ref().map(|s| s.slot),
+                "confirmations": status.as_ref().and_then(|s| s.confirmations),
+                "confirmation_status": status.as_ref().and_then(|s| s.confirmation_status.clone()),
+                "error": status.and_then(|s| s.err).map(|e| e.to_string()),
+            }));
+        }
+        tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+    }
+}
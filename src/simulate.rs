@@ -0,0 +1,161 @@
+use crate::blocklist;
+use crate::cluster::Cluster;
+use crate::state::AppState;
+use crate::token::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use actix_web::{post, web, HttpResponse};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::client_error::ClientError;
+use solana_client::rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig};
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+
+// Base token account layout, shared by both token programs.
+const TOKEN_ACCOUNT_LEN: usize = 165;
+
+#[derive(Deserialize)]
+struct SimulateRequest {
+    // Base64-encoded, bincode-serialized (versioned) transaction.
+    transaction: String,
+    #[serde(default)]
+    sig_verify: bool,
+    #[serde(default = "default_true")]
+    replace_recent_blockhash: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+struct TokenAccount {
+    mint: Pubkey,
+    owner: Pubkey,
+    amount: u64,
+}
+
+fn token_account(account: &Account) -> Option<TokenAccount> {
+    if account.owner != TOKEN_PROGRAM_ID && account.owner != TOKEN_2022_PROGRAM_ID {
+        return None;
+    }
+    if account.data.len() < TOKEN_ACCOUNT_LEN {
+        return None;
+    }
+    Some(TokenAccount {
+        mint: Pubkey::new_from_array(account.data[0..32].try_into().ok()?),
+        owner: Pubkey::new_from_array(account.data[32..64].try_into().ok()?),
+        amount: u64::from_le_bytes(account.data[64..72].try_into().ok()?),
+    })
+}
+
+// Base64-encoded, bincode-serialized (versioned) transaction, or a 400.
+pub fn decode_transaction(encoded: &str) -> Result<VersionedTransaction, HttpResponse> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| {
+            HttpResponse::BadRequest().json(json!({
+                "error": format!("Transaction is not valid base64: {}", e)
+            }))
+        })?;
+    bincode::deserialize(&bytes).map_err(|e| {
+        HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to deserialize transaction: {}", e)
+        }))
+    })
+}
+
+#[post("/simulate")]
+async fn simulate_transaction(
+    state: web::Data<AppState>,
+    cluster: Cluster,
+    body: web::Json<SimulateRequest>,
+) -> HttpResponse {
+    let transaction = match decode_transaction(&body.transaction) {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    if body.sig_verify && body.replace_recent_blockhash {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "sig_verify and replace_recent_blockhash cannot both be set"
+        }));
+    }
+
+    // Only statically listed accounts are tracked, lookup table entries
+    // would need another round trip to resolve.
+    let addresses: Vec<Pubkey> = transaction.message.static_account_keys().to_vec();
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: body.sig_verify,
+        replace_recent_blockhash: body.replace_recent_blockhash,
+        accounts: Some(RpcSimulateTransactionAccountsConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            addresses: addresses.iter().map(|a| a.to_string()).collect(),
+        }),
+        ..RpcSimulateTransactionConfig::default()
+    };
+
+    let rpc_client = state.cluster_rpc_client(&cluster).await;
+    let result = tokio::task::spawn_blocking(move || {
+        let before = rpc_client.get_multiple_accounts(&addresses)?;
+        let simulation = rpc_client.simulate_transaction_with_config(&transaction, config)?;
+        Ok::<_, ClientError>((addresses, before, simulation))
+    })
+    .await;
+
+    match result {
+        Ok(Ok((addresses, before, simulation))) => {
+            let simulation = simulation.value;
+            let after = simulation.accounts.unwrap_or_default();
+
+            let accounts: Vec<(&str, Pubkey)> = addresses.iter().map(|address| ("account", *address)).collect();
+            let mut balance_changes = Vec::new();
+            for (index, address) in addresses.iter().enumerate() {
+                let pre = before.get(index).and_then(|a| a.as_ref()).and_then(token_account);
+                let post = after
+                    .get(index)
+                    .and_then(|a| a.as_ref())
+                    .and_then(|a| a.decode::<Account>())
+                    .and_then(|a| token_account(&a));
+                let Some(known) = post.as_ref().or(pre.as_ref()) else {
+                    continue;
+                };
+                let pre_amount = pre.as_ref().map(|a| a.amount).unwrap_or(0);
+                let post_amount = post.as_ref().map(|a| a.amount).unwrap_or(0);
+                if pre_amount != post_amount {
+                    let involved = [("account", *address), ("mint", known.mint), ("owner", known.owner)];
+                    balance_changes.push(json!({
+                        "account": address.to_string(),
+                        "mint": known.mint.to_string(),
+                        "owner": known.owner.to_string(),
+                        "pre_amount": pre_amount,
+                        "post_amount": post_amount,
+                        "delta": post_amount as i128 - pre_amount as i128,
+                        "blocklisted": blocklist::flags(&state, &involved),
+                    }));
+                }
+            }
+
+            HttpResponse::Ok().json(json!({
+                "success": simulation.err.is_none(),
+                "error": simulation.err.map(|e| e.to_string()),
+                "logs": simulation.logs.unwrap_or_default(),
+                "units_consumed": simulation.units_consumed,
+                "token_balance_changes": balance_changes,
+                "blocklisted": blocklist::flags(&state, &accounts),
+            }))
+        }
+        Ok(Err(e)) => {
+            eprintln!("RPC error simulating transaction: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to simulate transaction: {}", e)
+            }))
+        }
+        Err(e) => {
+            eprintln!("Task error: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Task failed: {}", e)
+            }))
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::alerts::{Alert, Severity};
+use crate::config::SlackChannel;
+use crate::time::format_utc;
+use serde_json::{json, Value};
+
+pub async fn send(client: &reqwest::Client, channel: &SlackChannel, alert: &Alert) -> Result<(), String> {
+    let response = client
+        .post(&channel.webhook_url)
+        .json(&message(alert))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Slack returned {}", response.status()));
+    }
+    Ok(())
+}
+
+// Block Kit layout: severity header, the alert message, then pool, trigger
+// and, for rule alerts, the current value against the threshold it crossed.
+// `text` is the fallback for notifications.
+fn message(alert: &Alert) -> Value {
+    let icon = match alert.severity {
+        Severity::Warning => ":warning:",
+        Severity::Critical => ":rotating_light:",
+    };
+    let trigger = alert.details["rule"].as_str().unwrap_or(alert.kind);
+    let mut fields = vec![field("Pool", &format!("`{}`", alert.pool)), field("Trigger", trigger)];
+    for (label, key) in [("Current", "current"), ("Threshold", "threshold")] {
+        if let Some(value) = alert.details.get(key).filter(|v| !v.is_null()) {
+            fields.push(field(label, &value.to_string()));
+        }
+    }
+    let mut context = format_utc(alert.timestamp);
+    if let Some(id) = alert.id {
+        context = format!("Alert {} · {}", id, context);
+    }
+
+    json!({
+        "text": format!("[{}] {}", alert.severity.as_str(), alert.message),
+        "blocks": [
+            {
+                "type": "header",
+                "text": {
+                    "type": "plain_text",
+                    "text": format!("{} {} alert: {}", icon, alert.severity.as_str(), alert.kind),
+                    "emoji": true,
+                },
+            },
+            {
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": alert.message },
+            },
+            {
+                "type": "section",
+                "fields": fields,
+            },
+            {
+                "type": "context",
+                "elements": [{ "type": "mrkdwn", "text": context }],
+            },
+        ],
+    })
+}
+
+fn field(label: &str, value: &str) -> Value {
+    json!({
+        "type": "mrkdwn",
+        "text": format!("*{}*\n{}", label, value),
+    })
+}
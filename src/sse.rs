@@ -0,0 +1,251 @@
+use crate::address::ValidatedPubkey;
+use crate::blocklist;
+use crate::events::{self, EventKind, PoolEvent};
+use crate::history::{Decimals, PoolSnapshot};
+use crate::state::{unix_now, AppState};
+use crate::streaming::{ClientBuffer, StreamItem};
+use crate::usage::StreamGuard;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Comment lines keep proxies from closing idle connections.
+const HEARTBEAT_SECS: u64 = 15;
+const DEFAULT_REPLAY_SPEED: f64 = 10.0;
+const MAX_REPLAY_SPEED: f64 = 10_000.0;
+// Longest pause of a replay, quiet stretches are cut short.
+const MAX_REPLAY_WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    // Comma separated, every pool / event type when omitted.
+    pools: Option<String>,
+    events: Option<String>,
+}
+
+struct Filter {
+    pools: Vec<String>,
+    events: Vec<EventKind>,
+}
+
+impl Filter {
+    fn matches(&self, event: &PoolEvent) -> bool {
+        (self.pools.is_empty() || self.pools.contains(&event.pool))
+            && (self.events.is_empty() || self.events.contains(&event.kind))
+    }
+}
+
+fn sse_frame(event: &str, data: &serde_json::Value) -> Bytes {
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+// Wait for the next event for this client, or a heartbeat when idle.
+async fn next_frame(state: &AppState, buffer: &ClientBuffer) -> Option<Bytes> {
+    tokio::select! {
+        item = buffer.next() => match item? {
+            StreamItem::Event(event) => {
+                let mut data = json!(event);
+                data["blocklisted"] = json!(blocklist::pool_flags(state, &event.pool));
+                Some(sse_frame(event.kind.channel(), &data))
+            }
+            StreamItem::Dropped(missed) => Some(sse_frame("lagged", &json!({ "missed": missed }))),
+        },
+        _ = tokio::time::sleep(Duration::from_secs(HEARTBEAT_SECS)) => {
+            Some(Bytes::from_static(b": heartbeat\n\n"))
+        }
+    }
+}
+
+fn split(value: &Option<String>) -> Vec<String> {
+    value
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_events(value: &Option<String>) -> Result<Vec<EventKind>, HttpResponse> {
+    let mut events = Vec::new();
+    for name in split(value) {
+        match EventKind::from_channel(&name) {
+            Some(kind) => events.push(kind),
+            None => {
+                return Err(HttpResponse::BadRequest().json(json!({
+                    "error": format!("Unknown event type {}, expected price, trades or liquidity", name)
+                })));
+            }
+        }
+    }
+    Ok(events)
+}
+
+#[get("/sse/stream")]
+async fn stream(state: web::Data<AppState>, query: web::Query<StreamQuery>, req: HttpRequest) -> HttpResponse {
+    let pools = split(&query.pools);
+    for pool in &pools {
+        if let Err(e) = Pubkey::from_str(pool) {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid pool ID {}: {}", pool, e)
+            }));
+        }
+    }
+    let events = match parse_events(&query.events) {
+        Ok(events) => events,
+        Err(response) => return response,
+    };
+
+    let filter = Filter { pools, events };
+    // Metered for as long as the body stream lives
+    let guard = StreamGuard::open(&state, &req);
+    let state = state.into_inner();
+    let buffer = ClientBuffer::spawn(state.clone(), move |event| filter.matches(event));
+    let body = futures::stream::unfold((state, buffer, guard), |(state, buffer, guard)| async move {
+        let frame = next_frame(&state, &buffer).await?;
+        Some((Ok::<_, actix_web::Error>(frame), (state, buffer, guard)))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body)
+}
+
+#[derive(Deserialize)]
+struct ReplayQuery {
+    from: u64,
+    // Now when omitted.
+    to: Option<u64>,
+    // How many times faster than it happened, 10 when omitted.
+    speed: Option<f64>,
+    events: Option<String>,
+}
+
+// Where a replay is at.
+struct Replay {
+    // The `replay` frame, until sent.
+    start: Option<Bytes>,
+    pool: ValidatedPubkey,
+    decimals: Decimals,
+    snapshots: std::vec::IntoIter<PoolSnapshot>,
+    previous: Option<PoolSnapshot>,
+    pending: VecDeque<PoolEvent>,
+    events: Vec<EventKind>,
+    speed: f64,
+    sent: u64,
+    done: bool,
+}
+
+impl Replay {
+    // The next frame, after waiting out the time between snapshots.
+    async fn next_frame(&mut self) -> Option<Bytes> {
+        if let Some(start) = self.start.take() {
+            return Some(start);
+        }
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                self.sent += 1;
+                return Some(sse_frame(event.kind.channel(), &json!(event)));
+            }
+            let Some(snapshot) = self.snapshots.next() else {
+                if self.done {
+                    return None;
+                }
+                self.done = true;
+                return Some(sse_frame("end", &json!({ "events": self.sent })));
+            };
+            if let Some(previous) = &self.previous {
+                let gap = snapshot.timestamp.saturating_sub(previous.timestamp) as f64 / self.speed;
+                tokio::time::sleep(Duration::from_secs_f64(gap).min(MAX_REPLAY_WAIT)).await;
+            }
+            let events = events::derive(self.pool.0, self.decimals, self.previous.as_ref(), &snapshot);
+            self.pending
+                .extend(events.into_iter().filter(|event| self.events.is_empty() || self.events.contains(&event.kind)));
+            self.previous = Some(snapshot);
+        }
+    }
+}
+
+// Replay a pool's stored history as the events the stream would have sent,
+// `speed` times faster, with a `replay` frame first and an `end` frame last.
+// Trades and liquidity changes come from consecutive snapshots, so the first
+// snapshot only yields a price event.
+#[get("/pool/{pool_id}/replay")]
+async fn replay(
+    state: web::Data<AppState>,
+    pool_id: web::Path<ValidatedPubkey>,
+    query: web::Query<ReplayQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let pool = pool_id.into_inner();
+    let to = query.to.unwrap_or_else(unix_now);
+    if query.from > to {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "from must not be after to"
+        }));
+    }
+    let speed = query.speed.unwrap_or(DEFAULT_REPLAY_SPEED);
+    if !(speed > 0.0 && speed <= MAX_REPLAY_SPEED) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("speed must be above 0 and at most {}", MAX_REPLAY_SPEED)
+        }));
+    }
+    let events = match parse_events(&query.events) {
+        Ok(events) => events,
+        Err(response) => return response,
+    };
+    let Some(decimals) = Decimals::cached(&state, &pool.0) else {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": format!("Pool {} has not been polled yet", pool)
+        }));
+    };
+    let snapshots = state.history.range(&pool.0, query.from, to);
+    if snapshots.is_empty() {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("No stored snapshots of {} between {} and {}", pool, query.from, to)
+        }));
+    }
+
+    let start = sse_frame(
+        "replay",
+        &json!({
+            "pool": pool.to_string(),
+            "from": query.from,
+            "to": to,
+            "speed": speed,
+            "snapshots": snapshots.len(),
+        }),
+    );
+    let replay = Replay {
+        start: Some(start),
+        pool,
+        decimals,
+        snapshots: snapshots.into_iter(),
+        previous: None,
+        pending: VecDeque::new(),
+        events,
+        speed,
+        sent: 0,
+        done: false,
+    };
+    // Metered for as long as the body stream lives
+    let guard = StreamGuard::open(&state, &req);
+    let body = futures::stream::unfold((replay, guard), |(mut replay, guard)| async move {
+        let frame = replay.next_frame().await?;
+        Some((Ok::<_, actix_web::Error>(frame), (replay, guard)))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body)
+}
@@ -0,0 +1,56 @@
+use crate::cluster::Cluster;
+use crate::pubsub;
+use crate::schedule::PollTier;
+use crate::state::{unix_now, AppState, CachedAccount};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+// How old the data behind a response is. `slot_lag` is how far its slot is
+// behind the newest one any RPC endpoint returned, a lagging endpoint shows
+// up there even when the poller keeps up.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Freshness {
+    pub age_secs: u64,
+    pub slot_lag: u64,
+    pub stale: bool,
+}
+
+// Freshness of a cached pool. It is stale once it hasn't been updated for
+// `polling.stale_after_intervals` of its tier's interval, or is more than
+// `polling.max_slot_lag` slots behind. Dormant pools that are no longer
+// polled only go stale by slot. Slots are only tracked for the default
+// cluster. While `polling.follow_slots` is on, the age is counted in slots
+// the pool is behind rather than seconds.
+pub fn freshness(state: &AppState, cluster: &Cluster, pool: &Pubkey, cached: &CachedAccount) -> Freshness {
+    let config = state.config.read().unwrap();
+    let tier = if state.schedule.is_dormant(pool) {
+        PollTier::Dormant
+    } else {
+        config
+            .watchlist
+            .iter()
+            .find(|entry| Pubkey::from_str(entry.pool()).is_ok_and(|p| p == *pool))
+            .map_or(PollTier::Standard, |entry| entry.tier())
+    };
+    let age_secs = unix_now().saturating_sub(cached.fetched_at);
+    let slot_lag = if cluster.is_default() && !cluster.is_overridden() {
+        state.latest_slot().saturating_sub(cached.slot)
+    } else {
+        0
+    };
+
+    let polled = tier != PollTier::Dormant || config.polling.dormant_interval_secs > 0;
+    let max_age = tier.interval_secs(&config) * config.polling.stale_after_intervals;
+    // Following slots, time the chain stood still doesn't count
+    let too_old = match state.slots.current().filter(|_| cluster.is_default() && !cluster.is_overridden()) {
+        Some(_) => polled && slot_lag > pubsub::slots_in(max_age),
+        None => polled && age_secs > max_age,
+    };
+    let lagging = config.polling.max_slot_lag > 0 && slot_lag > config.polling.max_slot_lag;
+    Freshness {
+        age_secs,
+        slot_lag,
+        stale: too_old || lagging,
+    }
+}
@@ -0,0 +1,526 @@
+use crate::accounts::AccountCache;
+use crate::alerts::AlertTracker;
+use crate::amount::Amount;
+use crate::blocklist::Blocklist;
+use crate::budget::{RpcBudget, Standing};
+use crate::cassette::Cassette;
+use crate::audit::AuditLog;
+use crate::charts::ChartCache;
+use crate::cluster::Cluster;
+use crate::config::Config;
+use crate::creation::Creations;
+use crate::dex::{DecodeWarning, DecodedPool};
+use crate::events::PoolEvent;
+use crate::gaps::Gaps;
+use crate::history::{History, PoolSnapshot};
+use crate::images::ImageCache;
+use crate::index::PoolIndex;
+use crate::jwt::Jwks;
+use crate::keys::ApiKeys;
+use crate::limits::{Limits, Priority, RpcPermit};
+use crate::metadata::MetadataCache;
+use crate::migration::Migrations;
+use crate::mute::Maintenance;
+use crate::outliers::Outliers;
+use crate::portfolio::Portfolios;
+use crate::providers::{Providers, TrackedSender};
+use crate::public::PublicAccess;
+use crate::pubsub::{AccountSubscriptions, SlotClock};
+use crate::reports::Reports;
+use crate::rules::RuleState;
+use crate::schedule::{PollTier, Schedule};
+use crate::storage::{self, Store};
+use crate::streaming::StreamMetrics;
+use crate::subscriptions::Subscriptions;
+use crate::tasks::Tasks;
+use crate::templates::ReportTemplates;
+use crate::ticker::Ticker;
+use crate::token::{self, MintInfo};
+use crate::tokenlist::TokenLists;
+use crate::upgrades::ProgramWatch;
+use crate::upstream::Upstream;
+use crate::usage::{self, Metering};
+use bytes::Bytes;
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::json;
+use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, Notify};
+
+// Events buffered per slow receiver before it starts missing them.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+// Last fetched state of a pool account.
+#[derive(Clone)]
+pub struct CachedAccount {
+    pub lamports: u64,
+    pub data_size: usize,
+    pub slot: u64,
+    pub fetched_at: u64,
+    // Name of the owning DEX program, when it is a known one.
+    pub dex: Option<&'static str>,
+    // Only present when the owning program has a decoder.
+    pub pool: Option<DecodedPool>,
+    pub snapshot: Option<PoolSnapshot>,
+    pub token_a: Option<MintInfo>,
+    pub token_b: Option<MintInfo>,
+    pub decode_warnings: Vec<DecodeWarning>,
+    // `encode_fields`, done once when the pool is cached rather than on
+    // every request for it.
+    pub encoded: Option<Bytes>,
+}
+
+impl CachedAccount {
+    // The fields of `GET /pool/{id}` that come from this state alone, as a
+    // serialized JSON object. The handler adds the rest.
+    pub fn encode_fields(&self) -> Bytes {
+        let mut fields = json!({
+            "lamports": self.lamports,
+            "data_size": self.data_size,
+            "slot": self.slot,
+            "dex": self.dex,
+            "decode_warnings": self.decode_warnings,
+        });
+        if let (Some(pool), Some(snapshot)) = (&self.pool, &self.snapshot) {
+            let mut risk_factors = Vec::new();
+            for (mint, info) in [(&pool.mint_a, &self.token_a), (&pool.mint_b, &self.token_b)] {
+                if let Some(info) = info {
+                    risk_factors.extend(token::risk_factors(mint, info, self.slot));
+                }
+            }
+            fields["fee_bps"] = json!(pool.fee_bps);
+            fields["label"] = json!(pool.label());
+            fields["tradeable"] = json!(pool.tradeable);
+            fields["pool"] = json!(pool);
+            fields["pool"]["lp_supply"] = json!(pool.lp_supply.map(|supply| Amount::new(supply as u128, pool.lp_decimals)));
+            fields["reserve_a"] = json!(Amount::new(snapshot.reserve_a as u128, pool.decimals_a));
+            fields["reserve_b"] = json!(Amount::new(snapshot.reserve_b as u128, pool.decimals_b));
+            fields["price"] = json!(snapshot.price);
+            fields["risk_factors"] = json!(risk_factors);
+        }
+        Bytes::from(fields.to_string())
+    }
+
+    // Encode the state for the cache, see `encoded`.
+    pub fn encode(&mut self) {
+        self.encoded = Some(self.encode_fields());
+    }
+
+    // Whether the pool's fields may have been read from the wrong offsets,
+    // in which case its numbers aren't recorded or alerted on.
+    pub fn has_layout_warnings(&self) -> bool {
+        self.decode_warnings.iter().any(|warning| warning.layout)
+    }
+}
+
+// Last fetched state of every pool of one cluster. Sharded by pool so the
+// poller storing one pool only ever waits on readers of the same shard, and
+// entries are never changed in place: a fetch swaps in a new one, and readers
+// keep the one they got without holding any lock.
+#[derive(Default)]
+pub struct PoolCache {
+    pools: DashMap<Pubkey, Arc<CachedAccount>>,
+}
+
+impl PoolCache {
+    pub fn get(&self, pool: &Pubkey) -> Option<Arc<CachedAccount>> {
+        self.pools.get(pool).map(|cached| cached.clone())
+    }
+
+    pub fn insert(&self, pool: Pubkey, cached: Arc<CachedAccount>) -> Option<Arc<CachedAccount>> {
+        self.pools.insert(pool, cached)
+    }
+
+    pub fn remove(&self, pool: &Pubkey) -> Option<Arc<CachedAccount>> {
+        self.pools.remove(pool).map(|(_, cached)| cached)
+    }
+
+    // Every cached pool as of now. Only the shard locks are taken, one at a
+    // time and just long enough to copy the entries' pointers.
+    pub fn snapshot(&self) -> Vec<(Pubkey, Arc<CachedAccount>)> {
+        self.pools.iter().map(|entry| (*entry.key(), entry.value().clone())).collect()
+    }
+
+    // Empty the cache, returning how many pools it held.
+    pub fn clear(&self) -> usize {
+        let count = self.pools.len();
+        self.pools.clear();
+        count
+    }
+}
+
+// What the poller last did for a single pool.
+#[derive(Clone, Default, Serialize)]
+pub struct PollerStatus {
+    pub polls: u64,
+    pub failures: u64,
+    pub consecutive_failures: u64,
+    pub last_success: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<u64>,
+}
+
+// RPC client that holds an upstream concurrency slot until dropped, so move
+// it into the blocking task making the calls.
+pub struct RpcHandle {
+    client: RpcClient,
+    _permit: RpcPermit,
+}
+
+impl Deref for RpcHandle {
+    type Target = RpcClient;
+
+    fn deref(&self) -> &RpcClient {
+        &self.client
+    }
+}
+
+// Shared across all workers and the background poller.
+pub struct AppState {
+    pub config: RwLock<Config>,
+    pub config_path: PathBuf,
+    // Woken whenever the config is swapped so sleeping workers pick it up.
+    pub reloaded: Notify,
+    // Pools of the default cluster.
+    pub cache: PoolCache,
+    // Pools fetched for requests to other clusters, by cluster name. Only
+    // refreshed when requested again.
+    pub cluster_caches: DashMap<String, Arc<PoolCache>>,
+    // Raw accounts behind the default cluster's pools, by slot.
+    pub accounts: AccountCache,
+    pub history: History,
+    pub outliers: Outliers,
+    pub gaps: Gaps,
+    pub store: Arc<dyn Store>,
+    // Pools added at runtime, mirrored from the store so listing the
+    // watchlist doesn't hit it.
+    pub stored_watchlist: RwLock<Vec<Pubkey>>,
+    pub audit: AuditLog,
+    // Shared with alert deliveries still waiting to escalate.
+    pub alerts: Arc<AlertTracker>,
+    pub events: broadcast::Sender<PoolEvent>,
+    pub ticker: Ticker,
+    pub subscriptions: Subscriptions,
+    pub stream_metrics: StreamMetrics,
+    pub limits: Limits,
+    pub pool_index: PoolIndex,
+    pub rules: RuleState,
+    pub migrations: Migrations,
+    pub creations: Creations,
+    pub token_metadata: MetadataCache,
+    pub images: ImageCache,
+    pub token_lists: TokenLists,
+    pub blocklist: Blocklist,
+    pub program_watch: ProgramWatch,
+    pub reports: Reports,
+    pub report_templates: ReportTemplates,
+    pub charts: ChartCache,
+    pub usage: Metering,
+    pub api_keys: ApiKeys,
+    pub public: PublicAccess,
+    pub jwks: Jwks,
+    pub upstream: Upstream,
+    pub providers: Arc<Providers>,
+    pub rpc_budget: Arc<RpcBudget>,
+    pub portfolios: Portfolios,
+    pub pollers: DashMap<Pubkey, PollerStatus>,
+    pub schedule: Schedule,
+    pub pubsub: AccountSubscriptions,
+    pub slots: SlotClock,
+    pub tasks: Tasks,
+    paused: AtomicBool,
+    maintenance: Mutex<Option<Maintenance>>,
+    rpc_cursor: AtomicUsize,
+    // Newest slot any default cluster endpoint returned data for, or the slot
+    // subscription reported.
+    latest_slot: AtomicU64,
+}
+
+impl AppState {
+    pub fn new(config: Config, config_path: PathBuf, store: Arc<dyn Store>) -> Self {
+        let limits = Limits::new(&config.limits);
+        let cassette = Cassette::open(&config.recording).unwrap_or_else(|e| {
+            eprintln!("{}, recording off", e);
+            None
+        });
+        let upstream = Upstream::new(&config.http_client, cassette);
+        AppState {
+            config: RwLock::new(config),
+            config_path,
+            reloaded: Notify::new(),
+            cache: PoolCache::default(),
+            cluster_caches: DashMap::new(),
+            accounts: AccountCache::default(),
+            history: History::default(),
+            outliers: Outliers::default(),
+            gaps: Gaps::default(),
+            store,
+            stored_watchlist: RwLock::default(),
+            audit: AuditLog::default(),
+            alerts: Arc::default(),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            ticker: Ticker::default(),
+            subscriptions: Subscriptions::default(),
+            stream_metrics: StreamMetrics::default(),
+            limits,
+            pool_index: PoolIndex::default(),
+            rules: RuleState::default(),
+            migrations: Migrations::default(),
+            creations: Creations::default(),
+            token_metadata: MetadataCache::default(),
+            images: ImageCache::default(),
+            token_lists: TokenLists::default(),
+            blocklist: Blocklist::default(),
+            program_watch: ProgramWatch::default(),
+            reports: Reports::default(),
+            report_templates: ReportTemplates::default(),
+            charts: ChartCache::default(),
+            usage: Metering::default(),
+            api_keys: ApiKeys::default(),
+            public: PublicAccess::default(),
+            jwks: Jwks::default(),
+            upstream,
+            providers: Arc::default(),
+            rpc_budget: Arc::default(),
+            portfolios: Portfolios::default(),
+            pollers: DashMap::new(),
+            schedule: Schedule::default(),
+            pubsub: AccountSubscriptions::default(),
+            slots: SlotClock::default(),
+            tasks: Tasks::default(),
+            paused: AtomicBool::new(false),
+            maintenance: Mutex::new(None),
+            rpc_cursor: AtomicUsize::new(0),
+            latest_slot: AtomicU64::new(0),
+        }
+    }
+
+    pub fn config(&self) -> Config {
+        self.config.read().unwrap().clone()
+    }
+
+    // Re-read the config file and swap it in. On any error the running
+    // config is left untouched.
+    pub fn reload(&self) -> Result<Vec<&'static str>, String> {
+        let new_config = Config::load(&self.config_path)?;
+        let changed = {
+            let mut config = self.config.write().unwrap();
+            let changed = config.changed_sections(&new_config);
+            if changed.contains(&"http_client") {
+                self.upstream.rebuild(&new_config.http_client)?;
+            }
+            *config = new_config;
+            changed
+        };
+        self.reloaded.notify_waiters();
+        println!("Reloaded config from {} (changed: {:?})", self.config_path.display(), changed);
+        Ok(changed)
+    }
+
+    // Next RPC endpoint of the default cluster, once an upstream slot is free.
+    pub async fn rpc_client(&self) -> RpcHandle {
+        let rpc_urls = self.config.read().unwrap().rpc_urls.clone();
+        self.rpc_client_from(&rpc_urls, Priority::Interactive).await
+    }
+
+    // Same as `rpc_client` for work nobody is waiting on, which yields to API
+    // requests when upstream slots run short.
+    pub async fn background_rpc_client(&self) -> RpcHandle {
+        let rpc_urls = self.config.read().unwrap().rpc_urls.clone();
+        self.rpc_client_from(&rpc_urls, Priority::Background).await
+    }
+
+    // Next archival endpoint, None when there are none.
+    pub async fn archival_rpc_client(&self) -> Option<RpcHandle> {
+        let rpc_urls = self.config.read().unwrap().archival_rpc_urls.clone();
+        if rpc_urls.is_empty() {
+            return None;
+        }
+        Some(self.rpc_client_from(&rpc_urls, Priority::Interactive).await)
+    }
+
+    // Next archival endpoint for queries into the ledger history, a regular
+    // one when there are none.
+    pub async fn history_rpc_client(&self) -> RpcHandle {
+        self.history_rpc_client_with(Priority::Interactive).await
+    }
+
+    // Same as `history_rpc_client` for lookups nobody is waiting on.
+    pub async fn background_history_rpc_client(&self) -> RpcHandle {
+        self.history_rpc_client_with(Priority::Background).await
+    }
+
+    async fn history_rpc_client_with(&self, priority: Priority) -> RpcHandle {
+        let rpc_urls = {
+            let config = self.config.read().unwrap();
+            if config.archival_rpc_urls.is_empty() {
+                config.rpc_urls.clone()
+            } else {
+                config.archival_rpc_urls.clone()
+            }
+        };
+        self.rpc_client_from(&rpc_urls, priority).await
+    }
+
+    pub async fn cluster_rpc_client(&self, cluster: &Cluster) -> RpcHandle {
+        self.rpc_client_from(&cluster.rpc_urls, Priority::Interactive).await
+    }
+
+    // Hold background work back while every endpoint it could use is close
+    // to its daily RPC budget, and until the next day once all are spent.
+    async fn wait_for_budget(&self, rpc_urls: &[String]) {
+        loop {
+            let config = self.config.read().unwrap().rpc_budget.clone();
+            let best = rpc_urls
+                .iter()
+                .map(|url| self.rpc_budget.standing(url, &config))
+                .min()
+                .unwrap_or(Standing::Within);
+            if best == Standing::Within {
+                return;
+            }
+            self.rpc_budget.throttled.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_secs(config.throttle_secs)).await;
+            if best == Standing::Throttled {
+                return;
+            }
+        }
+    }
+
+    async fn rpc_client_from(&self, rpc_urls: &[String], priority: Priority) -> RpcHandle {
+        if priority == Priority::Background {
+            self.wait_for_budget(rpc_urls).await;
+        }
+        let permit = self.limits.rpc_permit(priority).await;
+        usage::charge_rpc();
+        let (providers_config, budget_config, configured) = {
+            let config = self.config.read().unwrap();
+            let configured: Vec<String> = config.rpc_providers().iter().map(|(_, url)| url.to_string()).collect();
+            (config.providers.clone(), config.rpc_budget.clone(), configured)
+        };
+        let mut candidates = self.providers.rpc_candidates(rpc_urls, &providers_config);
+        // Background work goes to endpoints with budget to spare if any
+        if priority == Priority::Background {
+            let within: Vec<&String> = candidates
+                .iter()
+                .copied()
+                .filter(|url| self.rpc_budget.standing(url, &budget_config) == Standing::Within)
+                .collect();
+            if !within.is_empty() {
+                candidates = within;
+            }
+        }
+        let url = candidates[self.rpc_cursor.fetch_add(1, Ordering::Relaxed) % candidates.len()].clone();
+        let rpc_config = RpcClientConfig::with_commitment(CommitmentConfig::confirmed());
+        let sender = self.upstream.rpc_sender(url.clone());
+        // Endpoints clients pass with X-RPC-URL aren't tracked
+        let client = if configured.contains(&url) {
+            let sender = TrackedSender {
+                inner: sender,
+                providers: self.providers.clone(),
+                budget: self.rpc_budget.clone(),
+                config: providers_config,
+                budget_config,
+            };
+            RpcClient::new_sender(sender, rpc_config)
+        } else {
+            RpcClient::new_sender(sender, rpc_config)
+        };
+        RpcHandle {
+            client,
+            _permit: permit,
+        }
+    }
+
+    // Run `call` against the store off the async workers.
+    pub async fn with_store<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&dyn Store) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, String> {
+        storage::blocking(self.store.clone(), call).await
+    }
+
+    // Config file entries followed by pools added at runtime, which are on
+    // the standard tier. Dormant pools are on the dormant tier whatever
+    // their entry says.
+    pub fn watchlist(&self) -> Vec<(Pubkey, PollTier)> {
+        let mut pools: Vec<(Pubkey, PollTier)> = Vec::new();
+        for entry in &self.config.read().unwrap().watchlist {
+            match Pubkey::from_str(entry.pool()) {
+                Ok(pool) if !pools.iter().any(|(p, _)| *p == pool) => pools.push((pool, entry.tier())),
+                Ok(_) => {}
+                Err(e) => eprintln!("Skipping invalid watchlist entry {}: {}", entry.pool(), e),
+            }
+        }
+        for pool in self.stored_watchlist.read().unwrap().iter() {
+            if !pools.iter().any(|(p, _)| p == pool) {
+                pools.push((*pool, PollTier::Standard));
+            }
+        }
+        for (pool, tier) in &mut pools {
+            if self.schedule.is_dormant(pool) {
+                *tier = PollTier::Dormant;
+            }
+        }
+        pools
+    }
+
+    // Run `f` over the cached pools of `cluster`.
+    pub fn with_cache<T>(&self, cluster: &Cluster, f: impl FnOnce(&PoolCache) -> T) -> T {
+        if cluster.is_default() {
+            return f(&self.cache);
+        }
+        let cache = self.cluster_caches.get(&cluster.name).map(|cache| cache.clone());
+        f(cache.as_deref().unwrap_or(&PoolCache::default()))
+    }
+
+    pub fn latest_slot(&self) -> u64 {
+        self.latest_slot.load(Ordering::Relaxed)
+    }
+
+    pub fn observe_slot(&self, slot: u64) {
+        self.latest_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    // Current maintenance window, switched off once its end has passed.
+    pub fn maintenance(&self) -> Option<Maintenance> {
+        let mut maintenance = self.maintenance.lock().unwrap();
+        if maintenance.as_ref().is_some_and(|m| !m.is_active(unix_now())) {
+            println!("Maintenance mode ended");
+            *maintenance = None;
+        }
+        maintenance.clone()
+    }
+
+    pub fn set_maintenance(&self, maintenance: Option<Maintenance>) {
+        *self.maintenance.lock().unwrap() = maintenance;
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Hex string of `bytes` random bytes, for ids and secrets.
+pub fn random_hex(bytes: usize) -> String {
+    (0..bytes).map(|_| format!("{:02x}", rand::random::<u8>())).collect()
+}
@@ -0,0 +1,325 @@
+use super::{
+    AlertFilter, AnnotationStore, ApiKeyStore, AlertStore, AuditFilter, AuditRecord, AuditTrailStore, BlocklistStore, PoolCreationStore,
+    PoolIndexStore, PoolLink, PoolLinkStore, PortfolioStore, ReportTemplateStore, SnapshotStore, StoredAlert, SubscriptionStore,
+    UsageStore, WatchlistStore,
+};
+use crate::alerts::Alert;
+use crate::annotations::Annotation;
+use crate::blocklist::BlockedAddress;
+use crate::creation::PoolCreation;
+use crate::history::PoolSnapshot;
+use crate::index::IndexedPool;
+use crate::keys::ApiKey;
+use crate::portfolio::Portfolio;
+use crate::subscriptions::Subscription;
+use crate::templates::ReportTemplate;
+use crate::usage::Usage;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+// Default backend and the reference for the others. Nothing survives a
+// restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    snapshots: Mutex<Vec<(Pubkey, PoolSnapshot)>>,
+    alerts: Mutex<Vec<StoredAlert>>,
+    watchlist: Mutex<BTreeSet<Pubkey>>,
+    pool_index: Mutex<HashMap<Pubkey, IndexedPool>>,
+    pool_links: Mutex<Vec<PoolLink>>,
+    pool_creations: Mutex<HashMap<Pubkey, PoolCreation>>,
+    blocklist: Mutex<HashMap<Pubkey, BlockedAddress>>,
+    usage: Mutex<HashMap<(String, String), Usage>>,
+    api_keys: Mutex<HashMap<String, ApiKey>>,
+    audit_trail: Mutex<Vec<AuditRecord>>,
+    annotations: Mutex<Vec<Annotation>>,
+    last_annotation_id: Mutex<u64>,
+    portfolios: Mutex<HashMap<String, Portfolio>>,
+    subscriptions: Mutex<HashMap<String, Subscription>>,
+    report_templates: Mutex<HashMap<String, ReportTemplate>>,
+}
+
+impl SnapshotStore for MemoryStore {
+    fn save_snapshot(&self, pool: &Pubkey, snapshot: &PoolSnapshot) -> Result<(), String> {
+        self.snapshots.lock().unwrap().push((*pool, snapshot.clone()));
+        Ok(())
+    }
+
+    fn load_snapshots(&self, since: u64) -> Result<Vec<(Pubkey, PoolSnapshot)>, String> {
+        let mut snapshots: Vec<_> = self
+            .snapshots
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, snapshot)| snapshot.timestamp >= since)
+            .cloned()
+            .collect();
+        snapshots.sort_by_key(|(_, snapshot)| (snapshot.timestamp, snapshot.slot));
+        Ok(snapshots)
+    }
+
+    fn prune_snapshots(&self, before: u64) -> Result<usize, String> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let count = snapshots.len();
+        snapshots.retain(|(_, snapshot)| snapshot.timestamp >= before);
+        Ok(count - snapshots.len())
+    }
+}
+
+impl AlertStore for MemoryStore {
+    fn save_alert(&self, alert: &Alert) -> Result<u64, String> {
+        let mut alerts = self.alerts.lock().unwrap();
+        let id = alerts.len() as u64 + 1;
+        alerts.push(StoredAlert {
+            id,
+            key: alert.key.clone(),
+            kind: alert.kind.to_string(),
+            severity: alert.severity.as_str().to_string(),
+            pool: alert.pool.clone(),
+            message: alert.message.clone(),
+            timestamp: alert.timestamp,
+            details: alert.details.clone(),
+            acked_at: None,
+            acked_by: None,
+            suppressed_by: alert.suppressed_by.clone(),
+        });
+        Ok(id)
+    }
+
+    fn alert_history(&self, filter: &AlertFilter) -> Result<Vec<StoredAlert>, String> {
+        Ok(self
+            .alerts
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|alert| alert.matches(filter))
+            .take(filter.limit)
+            .cloned()
+            .collect())
+    }
+
+    fn ack_alert(&self, id: u64, by: &str, at: u64) -> Result<Option<StoredAlert>, String> {
+        let mut alerts = self.alerts.lock().unwrap();
+        let Some(alert) = id.checked_sub(1).and_then(|i| alerts.get_mut(i as usize)) else {
+            return Ok(None);
+        };
+        if alert.acked_at.is_none() {
+            alert.acked_at = Some(at);
+            alert.acked_by = Some(by.to_string());
+        }
+        Ok(Some(alert.clone()))
+    }
+}
+
+impl WatchlistStore for MemoryStore {
+    fn watchlist(&self) -> Result<Vec<Pubkey>, String> {
+        Ok(self.watchlist.lock().unwrap().iter().copied().collect())
+    }
+
+    fn watch(&self, pool: &Pubkey) -> Result<bool, String> {
+        Ok(self.watchlist.lock().unwrap().insert(*pool))
+    }
+
+    fn unwatch(&self, pool: &Pubkey) -> Result<bool, String> {
+        Ok(self.watchlist.lock().unwrap().remove(pool))
+    }
+}
+
+impl PoolIndexStore for MemoryStore {
+    fn load_pool_index(&self) -> Result<Vec<IndexedPool>, String> {
+        Ok(self.pool_index.lock().unwrap().values().cloned().collect())
+    }
+
+    fn save_indexed_pools(&self, pools: &[IndexedPool]) -> Result<(), String> {
+        let mut index = self.pool_index.lock().unwrap();
+        index.extend(pools.iter().map(|pool| (pool.pool, pool.clone())));
+        Ok(())
+    }
+
+    fn replace_pool_index(&self, dex: &str, pools: &[IndexedPool]) -> Result<(), String> {
+        let mut index = self.pool_index.lock().unwrap();
+        index.retain(|_, pool| pool.dex != dex);
+        index.extend(pools.iter().map(|pool| (pool.pool, pool.clone())));
+        Ok(())
+    }
+}
+
+impl PoolLinkStore for MemoryStore {
+    fn save_pool_link(&self, link: &PoolLink) -> Result<bool, String> {
+        let mut links = self.pool_links.lock().unwrap();
+        if links.iter().any(|l| l.old_pool == link.old_pool && l.new_pool == link.new_pool) {
+            return Ok(false);
+        }
+        links.push(link.clone());
+        Ok(true)
+    }
+
+    fn pool_links(&self, pool: &Pubkey) -> Result<Vec<PoolLink>, String> {
+        let mut links: Vec<PoolLink> = self
+            .pool_links
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|link| link.old_pool == *pool || link.new_pool == *pool)
+            .cloned()
+            .collect();
+        links.sort_by_key(|link| link.drained_at);
+        Ok(links)
+    }
+}
+
+impl PoolCreationStore for MemoryStore {
+    fn pool_creation(&self, pool: &Pubkey) -> Result<Option<PoolCreation>, String> {
+        Ok(self.pool_creations.lock().unwrap().get(pool).cloned())
+    }
+
+    fn save_pool_creation(&self, pool: &Pubkey, creation: &PoolCreation) -> Result<(), String> {
+        self.pool_creations.lock().unwrap().insert(*pool, creation.clone());
+        Ok(())
+    }
+}
+
+impl BlocklistStore for MemoryStore {
+    fn blocklist(&self) -> Result<Vec<BlockedAddress>, String> {
+        Ok(self.blocklist.lock().unwrap().values().cloned().collect())
+    }
+
+    fn block(&self, entry: &BlockedAddress) -> Result<(), String> {
+        self.blocklist.lock().unwrap().insert(entry.address, entry.clone());
+        Ok(())
+    }
+
+    fn unblock(&self, address: &Pubkey) -> Result<bool, String> {
+        Ok(self.blocklist.lock().unwrap().remove(address).is_some())
+    }
+}
+
+impl UsageStore for MemoryStore {
+    fn usage(&self, month: &str) -> Result<Vec<(String, Usage)>, String> {
+        Ok(self
+            .usage
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((m, _), _)| m == month)
+            .map(|((_, key_name), usage)| (key_name.clone(), *usage))
+            .collect())
+    }
+
+    fn save_usage(&self, month: &str, key_name: &str, usage: &Usage) -> Result<(), String> {
+        self.usage.lock().unwrap().insert((month.to_string(), key_name.to_string()), *usage);
+        Ok(())
+    }
+}
+
+impl ApiKeyStore for MemoryStore {
+    fn api_keys(&self) -> Result<Vec<ApiKey>, String> {
+        Ok(self.api_keys.lock().unwrap().values().cloned().collect())
+    }
+
+    fn save_api_key(&self, key: &ApiKey) -> Result<(), String> {
+        self.api_keys.lock().unwrap().insert(key.id.clone(), key.clone());
+        Ok(())
+    }
+}
+
+impl AuditTrailStore for MemoryStore {
+    fn save_audit_record(&self, record: &AuditRecord) -> Result<u64, String> {
+        let mut trail = self.audit_trail.lock().unwrap();
+        let id = trail.len() as u64 + 1;
+        trail.push(AuditRecord { id, ..record.clone() });
+        Ok(id)
+    }
+
+    fn audit_trail(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, String> {
+        Ok(self
+            .audit_trail
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|record| record.matches(filter))
+            .take(filter.limit)
+            .cloned()
+            .collect())
+    }
+}
+
+impl AnnotationStore for MemoryStore {
+    fn save_annotation(&self, annotation: &Annotation) -> Result<u64, String> {
+        // Ids aren't reused after a delete
+        let mut last_id = self.last_annotation_id.lock().unwrap();
+        *last_id += 1;
+        let id = *last_id;
+        let mut annotations = self.annotations.lock().unwrap();
+        annotations.push(Annotation { id, ..annotation.clone() });
+        annotations.sort_by_key(|annotation| (annotation.timestamp, annotation.id));
+        Ok(id)
+    }
+
+    fn annotations(&self, pool: Option<&str>, from: u64, to: u64) -> Result<Vec<Annotation>, String> {
+        Ok(self
+            .annotations
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|annotation| pool.is_none_or(|pool| annotation.pool == pool))
+            .filter(|annotation| annotation.timestamp >= from && annotation.timestamp <= to)
+            .cloned()
+            .collect())
+    }
+
+    fn delete_annotation(&self, pool: &str, id: u64) -> Result<Option<Annotation>, String> {
+        let mut annotations = self.annotations.lock().unwrap();
+        let Some(index) = annotations.iter().position(|annotation| annotation.id == id && annotation.pool == pool) else {
+            return Ok(None);
+        };
+        Ok(Some(annotations.remove(index)))
+    }
+}
+
+impl PortfolioStore for MemoryStore {
+    fn portfolios(&self) -> Result<Vec<Portfolio>, String> {
+        Ok(self.portfolios.lock().unwrap().values().cloned().collect())
+    }
+
+    fn save_portfolio(&self, portfolio: &Portfolio) -> Result<(), String> {
+        self.portfolios.lock().unwrap().insert(portfolio.id.clone(), portfolio.clone());
+        Ok(())
+    }
+
+    fn delete_portfolio(&self, id: &str) -> Result<bool, String> {
+        Ok(self.portfolios.lock().unwrap().remove(id).is_some())
+    }
+}
+
+impl SubscriptionStore for MemoryStore {
+    fn subscriptions(&self) -> Result<Vec<Subscription>, String> {
+        Ok(self.subscriptions.lock().unwrap().values().cloned().collect())
+    }
+
+    fn save_subscription(&self, subscription: &Subscription) -> Result<(), String> {
+        self.subscriptions.lock().unwrap().insert(subscription.id.clone(), subscription.clone());
+        Ok(())
+    }
+
+    fn delete_subscription(&self, id: &str) -> Result<bool, String> {
+        Ok(self.subscriptions.lock().unwrap().remove(id).is_some())
+    }
+}
+
+impl ReportTemplateStore for MemoryStore {
+    fn report_templates(&self) -> Result<Vec<ReportTemplate>, String> {
+        Ok(self.report_templates.lock().unwrap().values().cloned().collect())
+    }
+
+    fn save_report_template(&self, template: &ReportTemplate) -> Result<(), String> {
+        self.report_templates.lock().unwrap().insert(template.id.clone(), template.clone());
+        Ok(())
+    }
+
+    fn delete_report_template(&self, id: &str) -> Result<bool, String> {
+        Ok(self.report_templates.lock().unwrap().remove(id).is_some())
+    }
+}
@@ -0,0 +1,303 @@
+pub mod memory;
+pub mod postgres;
+pub mod sqlite;
+
+use crate::alerts::Alert;
+use crate::annotations::Annotation;
+use crate::blocklist::BlockedAddress;
+use crate::config::StorageConfig;
+use crate::creation::PoolCreation;
+use crate::history::PoolSnapshot;
+use crate::index::IndexedPool;
+use crate::keys::ApiKey;
+use crate::portfolio::Portfolio;
+use crate::subscriptions::Subscription;
+use crate::templates::ReportTemplate;
+use crate::usage::Usage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use std::sync::Arc;
+
+// Persistence for everything that should survive a restart, split by what is
+// stored. Calls are blocking, backends are expected to be close and fast.
+pub trait SnapshotStore: Send + Sync {
+    fn save_snapshot(&self, pool: &Pubkey, snapshot: &PoolSnapshot) -> Result<(), String>;
+    // Every snapshot taken at or after `since`, oldest first.
+    fn load_snapshots(&self, since: u64) -> Result<Vec<(Pubkey, PoolSnapshot)>, String>;
+    // Delete snapshots taken before `before`, returning how many went.
+    fn prune_snapshots(&self, before: u64) -> Result<usize, String>;
+}
+
+pub trait AlertStore: Send + Sync {
+    // Returns the id the alert was stored under.
+    fn save_alert(&self, alert: &Alert) -> Result<u64, String>;
+    // Alerts matching the filter, newest first.
+    fn alert_history(&self, filter: &AlertFilter) -> Result<Vec<StoredAlert>, String>;
+    // Acknowledge an alert, returning it or None when there is no such id.
+    // Acknowledging twice keeps the first acknowledgement.
+    fn ack_alert(&self, id: u64, by: &str, at: u64) -> Result<Option<StoredAlert>, String>;
+}
+
+// A fired alert as persisted.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredAlert {
+    pub id: u64,
+    pub key: String,
+    pub kind: String,
+    pub severity: String,
+    pub pool: String,
+    pub message: String,
+    pub timestamp: u64,
+    pub details: Value,
+    pub acked_at: Option<u64>,
+    pub acked_by: Option<String>,
+    pub suppressed_by: Option<String>,
+}
+
+impl StoredAlert {
+    fn matches(&self, filter: &AlertFilter) -> bool {
+        filter.since.is_none_or(|since| self.timestamp >= since)
+            && filter.until.is_none_or(|until| self.timestamp < until)
+            && filter.pool.as_ref().is_none_or(|pool| &self.pool == pool)
+            && filter.kind.as_ref().is_none_or(|kind| &self.kind == kind)
+            && filter.acked.is_none_or(|acked| self.acked_at.is_some() == acked)
+    }
+}
+
+// Unset fields match everything.
+#[derive(Clone, Default)]
+pub struct AlertFilter {
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub pool: Option<String>,
+    pub kind: Option<String>,
+    pub acked: Option<bool>,
+    pub limit: usize,
+}
+
+pub trait WatchlistStore: Send + Sync {
+    // Pools added to the watchlist at runtime, on top of the config file.
+    fn watchlist(&self) -> Result<Vec<Pubkey>, String>;
+    // Returns false when the pool was already watched.
+    fn watch(&self, pool: &Pubkey) -> Result<bool, String>;
+    // Returns false when the pool wasn't watched.
+    fn unwatch(&self, pool: &Pubkey) -> Result<bool, String>;
+}
+
+pub trait PoolIndexStore: Send + Sync {
+    fn load_pool_index(&self) -> Result<Vec<IndexedPool>, String>;
+    // Insert pools, or update the ones already indexed.
+    fn save_indexed_pools(&self, pools: &[IndexedPool]) -> Result<(), String>;
+    // Swap everything indexed for `dex` for the result of a fresh scan.
+    fn replace_pool_index(&self, dex: &str, pools: &[IndexedPool]) -> Result<(), String>;
+}
+
+pub trait PoolLinkStore: Send + Sync {
+    // Returns false when the two pools were already linked.
+    fn save_pool_link(&self, link: &PoolLink) -> Result<bool, String>;
+    // Links with `pool` on either side, oldest first.
+    fn pool_links(&self, pool: &Pubkey) -> Result<Vec<PoolLink>, String>;
+}
+
+// A drained pool and the pool of the same pair its liquidity moved to.
+#[derive(Clone, Debug, Serialize)]
+pub struct PoolLink {
+    #[serde(serialize_with = "crate::dex::pubkey_string")]
+    pub old_pool: Pubkey,
+    #[serde(serialize_with = "crate::dex::pubkey_string")]
+    pub new_pool: Pubkey,
+    pub drained_at: u64,
+    // When the pool index first saw the new pool.
+    pub created_at: u64,
+    // Share of the old pool's reserves that was pulled, 100 when it closed.
+    pub removed_pct: f64,
+}
+
+pub trait PoolCreationStore: Send + Sync {
+    fn pool_creation(&self, pool: &Pubkey) -> Result<Option<PoolCreation>, String>;
+    fn save_pool_creation(&self, pool: &Pubkey, creation: &PoolCreation) -> Result<(), String>;
+}
+
+pub trait BlocklistStore: Send + Sync {
+    // Entries added through the admin API.
+    fn blocklist(&self) -> Result<Vec<BlockedAddress>, String>;
+    // Insert the entry, or replace the one for its address.
+    fn block(&self, entry: &BlockedAddress) -> Result<(), String>;
+    // Returns false when the address wasn't blocked.
+    fn unblock(&self, address: &Pubkey) -> Result<bool, String>;
+}
+
+pub trait UsageStore: Send + Sync {
+    // Usage of every API key in `month`, e.g. "2024-05", by key name.
+    fn usage(&self, month: &str) -> Result<Vec<(String, Usage)>, String>;
+    // Insert or replace the usage of `key_name` in `month`.
+    fn save_usage(&self, month: &str, key_name: &str, usage: &Usage) -> Result<(), String>;
+}
+
+pub trait ApiKeyStore: Send + Sync {
+    // Every key created through the admin API, revoked ones included.
+    fn api_keys(&self) -> Result<Vec<ApiKey>, String>;
+    // Insert the key, or replace the one with its id.
+    fn save_api_key(&self, key: &ApiKey) -> Result<(), String>;
+}
+
+pub trait AuditTrailStore: Send + Sync {
+    // Returns the id the record was stored under.
+    fn save_audit_record(&self, record: &AuditRecord) -> Result<u64, String>;
+    // Records matching the filter, newest first.
+    fn audit_trail(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, String>;
+}
+
+// A mutating API call. `before` and `after` are null when the handler
+// doesn't say what changed, or the thing didn't exist before or after.
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditRecord {
+    pub id: u64,
+    pub timestamp: u64,
+    // API key name, "jwt:<subject>", "admin_token" or "anonymous".
+    pub actor: String,
+    pub ip: Option<String>,
+    pub method: String,
+    pub route: String,
+    pub path: String,
+    pub status: u16,
+    // What was changed, e.g. "watchlist/<pool id>".
+    pub target: Option<String>,
+    pub before: Value,
+    pub after: Value,
+}
+
+impl AuditRecord {
+    fn matches(&self, filter: &AuditFilter) -> bool {
+        filter.since.is_none_or(|since| self.timestamp >= since)
+            && filter.until.is_none_or(|until| self.timestamp < until)
+            && filter.actor.as_ref().is_none_or(|actor| &self.actor == actor)
+            && filter.target.as_ref().is_none_or(|target| self.target.as_ref() == Some(target))
+    }
+}
+
+// Unset fields match everything.
+#[derive(Default)]
+pub struct AuditFilter {
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub limit: usize,
+}
+
+pub trait AnnotationStore: Send + Sync {
+    // Returns the id the annotation was stored under.
+    fn save_annotation(&self, annotation: &Annotation) -> Result<u64, String>;
+    // Annotations between `from` and `to` inclusive, of one pool or all of
+    // them, oldest first.
+    fn annotations(&self, pool: Option<&str>, from: u64, to: u64) -> Result<Vec<Annotation>, String>;
+    // Returns the annotation removed, None when the pool has none by that id.
+    fn delete_annotation(&self, pool: &str, id: u64) -> Result<Option<Annotation>, String>;
+}
+
+pub trait PortfolioStore: Send + Sync {
+    fn portfolios(&self) -> Result<Vec<Portfolio>, String>;
+    // Insert the portfolio, or replace the one with its id.
+    fn save_portfolio(&self, portfolio: &Portfolio) -> Result<(), String>;
+    // Returns false when there was no portfolio by that id.
+    fn delete_portfolio(&self, id: &str) -> Result<bool, String>;
+}
+
+// Delivery counts aren't stored, they start from zero on every run.
+pub trait SubscriptionStore: Send + Sync {
+    fn subscriptions(&self) -> Result<Vec<Subscription>, String>;
+    // Insert the subscription, or replace the one with its id.
+    fn save_subscription(&self, subscription: &Subscription) -> Result<(), String>;
+    // Returns false when there was no subscription by that id.
+    fn delete_subscription(&self, id: &str) -> Result<bool, String>;
+}
+
+pub trait ReportTemplateStore: Send + Sync {
+    fn report_templates(&self) -> Result<Vec<ReportTemplate>, String>;
+    // Insert the template, or replace the one with its id.
+    fn save_report_template(&self, template: &ReportTemplate) -> Result<(), String>;
+    // Returns false when there was no template by that id.
+    fn delete_report_template(&self, id: &str) -> Result<bool, String>;
+}
+
+// A complete backend, selected by the `storage` config section.
+pub trait Store:
+    SnapshotStore
+    + AlertStore
+    + WatchlistStore
+    + PoolIndexStore
+    + PoolLinkStore
+    + PoolCreationStore
+    + BlocklistStore
+    + UsageStore
+    + ApiKeyStore
+    + AuditTrailStore
+    + AnnotationStore
+    + PortfolioStore
+    + SubscriptionStore
+    + ReportTemplateStore
+{
+}
+
+impl<T> Store for T where
+    T: SnapshotStore
+        + AlertStore
+        + WatchlistStore
+        + PoolIndexStore
+        + PoolLinkStore
+        + PoolCreationStore
+        + BlocklistStore
+        + UsageStore
+        + ApiKeyStore
+        + AuditTrailStore
+        + AnnotationStore
+        + PortfolioStore
+    + SubscriptionStore
+    + ReportTemplateStore
+{
+}
+
+// Stored DEX names map back to the static names decoders use. Rows for DEXes
+// without a decoder any more are skipped.
+fn indexed_pool(pool: &str, dex: &str, mint_a: &str, mint_b: &str, slot: u64) -> Option<IndexedPool> {
+    Some(IndexedPool {
+        pool: pool.parse().ok()?,
+        dex: crate::dex::layout(dex)?.dex,
+        mint_a: mint_a.parse().ok()?,
+        mint_b: mint_b.parse().ok()?,
+        slot,
+    })
+}
+
+// u128 accumulators are stored as text, both or neither.
+fn fee_growth(a: Option<String>, b: Option<String>) -> Option<(u128, u128)> {
+    Some((a?.parse().ok()?, b?.parse().ok()?))
+}
+
+// Run `call` against `store` on the blocking pool and wait for it.
+// Backends block, and a slow one mustn't stall the async workers.
+pub async fn blocking<T: Send + 'static>(
+    store: Arc<dyn Store>,
+    call: impl FnOnce(&dyn Store) -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tokio::task::spawn_blocking(move || call(store.as_ref()))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+// Open the configured backend, bringing its schema up to date first.
+pub fn open(config: &StorageConfig) -> Result<Arc<dyn Store>, String> {
+    match config {
+        StorageConfig::Memory => Ok(Arc::new(memory::MemoryStore::default())),
+        StorageConfig::Sqlite { path } => {
+            println!("Using SQLite storage at {}", path.display());
+            Ok(Arc::new(sqlite::SqliteStore::open(path)?))
+        }
+        StorageConfig::Postgres { url } => {
+            println!("Using Postgres storage at {}", crate::config::redact_connection_string(url));
+            Ok(Arc::new(postgres::PostgresStore::open(url)?))
+        }
+    }
+}
@@ -0,0 +1,816 @@
+use super::{
+    indexed_pool, AlertFilter, AnnotationStore, ApiKeyStore, AlertStore, AuditFilter, AuditRecord, AuditTrailStore, BlocklistStore,
+    PoolCreationStore, PoolIndexStore, PoolLink, PoolLinkStore, PortfolioStore, ReportTemplateStore, SnapshotStore, StoredAlert,
+    SubscriptionStore, UsageStore, WatchlistStore,
+};
+use crate::alerts::Alert;
+use crate::annotations::Annotation;
+use crate::blocklist::{BlockKind, BlockedAddress};
+use crate::config::{Quotas, Role};
+use crate::creation::PoolCreation;
+use crate::history::PoolSnapshot;
+use crate::index::IndexedPool;
+use crate::keys::ApiKey;
+use crate::portfolio::Portfolio;
+use crate::subscriptions::Subscription;
+use crate::templates::ReportTemplate;
+use crate::usage::Usage;
+use postgres::{Client, GenericClient, NoTls};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::mpsc;
+
+mod embedded {
+    refinery::embed_migrations!("migrations/postgres");
+}
+
+type Job = Box<dyn FnOnce(&mut Client) + Send>;
+
+// The blocking postgres client drives its own runtime and panics when called
+// from inside tokio, so one dedicated thread owns the connection and runs
+// queries on behalf of callers.
+pub struct PostgresStore {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl PostgresStore {
+    pub fn open(url: &str) -> Result<Self, String> {
+        let url = url.to_string();
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (ready, opened) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut client = match connect(&url) {
+                Ok(client) => client,
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                    return;
+                }
+            };
+            let _ = ready.send(Ok(()));
+            // Ends once the store is dropped
+            for job in queue {
+                job(&mut client);
+            }
+        });
+        opened
+            .recv()
+            .map_err(|_| "Postgres worker thread exited".to_string())??;
+        Ok(PostgresStore { jobs })
+    }
+
+    // Run `query` on the connection thread and wait for its result.
+    fn with_client<T, F>(&self, query: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Client) -> Result<T, postgres::Error> + Send + 'static,
+    {
+        let (reply, result) = mpsc::channel();
+        self.jobs
+            .send(Box::new(move |client| {
+                let _ = reply.send(query(client));
+            }))
+            .map_err(|_| "Postgres worker thread exited".to_string())?;
+        result
+            .recv()
+            .map_err(|_| "Postgres worker thread exited".to_string())?
+            .map_err(|e| format!("Postgres query failed: {}", e))
+    }
+}
+
+fn connect(url: &str) -> Result<Client, String> {
+    let mut client = Client::connect(url, NoTls).map_err(|e| format!("Failed to connect to Postgres: {}", e))?;
+    let report = embedded::migrations::runner()
+        .run(&mut client)
+        .map_err(|e| format!("Failed to migrate Postgres: {}", e))?;
+    for migration in report.applied_migrations() {
+        println!("Applied migration {}", migration);
+    }
+    Ok(client)
+}
+
+fn parse_pubkey(value: String) -> Result<Pubkey, String> {
+    Pubkey::from_str(&value).map_err(|e| format!("Invalid pubkey {} in database: {}", value, e))
+}
+
+impl SnapshotStore for PostgresStore {
+    fn save_snapshot(&self, pool: &Pubkey, snapshot: &PoolSnapshot) -> Result<(), String> {
+        let pool = pool.to_string();
+        let snapshot = snapshot.clone();
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO snapshots (pool, slot, timestamp, reserve_a, reserve_b, price, lp_supply, authority, cumulative_volume_b,
+                   fee_growth_a, fee_growth_b)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+                &[
+                    &pool,
+                    &(snapshot.slot as i64),
+                    &(snapshot.timestamp as i64),
+                    &(snapshot.reserve_a as i64),
+                    &(snapshot.reserve_b as i64),
+                    &snapshot.price,
+                    &snapshot.lp_supply.map(|s| s as i64),
+                    &snapshot.authority.map(|a| a.to_string()),
+                    &snapshot.cumulative_volume_b.map(|v| v.to_string()),
+                    &snapshot.fee_growth.map(|(a, _)| a.to_string()),
+                    &snapshot.fee_growth.map(|(_, b)| b.to_string()),
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+
+    fn load_snapshots(&self, since: u64) -> Result<Vec<(Pubkey, PoolSnapshot)>, String> {
+        let rows = self.with_client(move |client| {
+            client.query(
+                "SELECT pool, slot, timestamp, reserve_a, reserve_b, price, lp_supply, authority, cumulative_volume_b,
+                   fee_growth_a, fee_growth_b
+                 FROM snapshots WHERE timestamp >= $1 ORDER BY timestamp, slot",
+                &[&(since as i64)],
+            )
+        })?;
+
+        let mut snapshots = Vec::new();
+        for row in rows {
+            snapshots.push((
+                parse_pubkey(row.get(0))?,
+                PoolSnapshot {
+                    slot: row.get::<_, i64>(1) as u64,
+                    timestamp: row.get::<_, i64>(2) as u64,
+                    reserve_a: row.get::<_, i64>(3) as u64,
+                    reserve_b: row.get::<_, i64>(4) as u64,
+                    price: row.get(5),
+                    lp_supply: row.get::<_, Option<i64>>(6).map(|s| s as u64),
+                    authority: row.get::<_, Option<String>>(7).map(parse_pubkey).transpose()?,
+                    cumulative_volume_b: row.get::<_, Option<String>>(8).and_then(|v| v.parse().ok()),
+                    fee_growth: super::fee_growth(row.get(9), row.get(10)),
+                },
+            ));
+        }
+        Ok(snapshots)
+    }
+
+    fn prune_snapshots(&self, before: u64) -> Result<usize, String> {
+        self.with_client(move |client| {
+            client.execute("DELETE FROM snapshots WHERE timestamp < $1", &[&(before as i64)])
+        })
+        .map(|deleted| deleted as usize)
+    }
+}
+
+const ALERT_COLUMNS: &str = "id, key, kind, severity, pool, message, timestamp, details, acked_at, acked_by, suppressed_by";
+
+fn stored_alert(row: &postgres::Row) -> StoredAlert {
+    StoredAlert {
+        id: row.get::<_, i64>(0) as u64,
+        key: row.get(1),
+        kind: row.get(2),
+        severity: row.get(3),
+        pool: row.get(4),
+        message: row.get(5),
+        timestamp: row.get::<_, i64>(6) as u64,
+        details: row.get(7),
+        acked_at: row.get::<_, Option<i64>>(8).map(|t| t as u64),
+        acked_by: row.get(9),
+        suppressed_by: row.get(10),
+    }
+}
+
+impl AlertStore for PostgresStore {
+    fn save_alert(&self, alert: &Alert) -> Result<u64, String> {
+        let alert = alert.clone();
+        self.with_client(move |client| {
+            client.query_one(
+                "INSERT INTO alerts (key, kind, severity, pool, message, timestamp, details, suppressed_by)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+                &[
+                    &alert.key,
+                    &alert.kind,
+                    &alert.severity.as_str(),
+                    &alert.pool,
+                    &alert.message,
+                    &(alert.timestamp as i64),
+                    &alert.details,
+                    &alert.suppressed_by,
+                ],
+            )
+        })
+        .map(|row| row.get::<_, i64>(0) as u64)
+    }
+
+    fn alert_history(&self, filter: &AlertFilter) -> Result<Vec<StoredAlert>, String> {
+        let (since, until) = (filter.since.map(|t| t as i64), filter.until.map(|t| t as i64));
+        let (pool, kind, acked) = (filter.pool.clone(), filter.kind.clone(), filter.acked);
+        let limit = filter.limit as i64;
+        let rows = self.with_client(move |client| {
+            client.query(
+                &format!(
+                    "SELECT {} FROM alerts
+                     WHERE ($1::BIGINT IS NULL OR timestamp >= $1) AND ($2::BIGINT IS NULL OR timestamp < $2)
+                       AND ($3::TEXT IS NULL OR pool = $3) AND ($4::TEXT IS NULL OR kind = $4)
+                       AND ($5::BOOLEAN IS NULL OR (acked_at IS NOT NULL) = $5)
+                     ORDER BY id DESC LIMIT $6",
+                    ALERT_COLUMNS
+                ),
+                &[&since, &until, &pool, &kind, &acked, &limit],
+            )
+        })?;
+        Ok(rows.iter().map(stored_alert).collect())
+    }
+
+    fn ack_alert(&self, id: u64, by: &str, at: u64) -> Result<Option<StoredAlert>, String> {
+        let by = by.to_string();
+        let row = self.with_client(move |client| {
+            client.execute(
+                "UPDATE alerts SET acked_at = $2, acked_by = $3 WHERE id = $1 AND acked_at IS NULL",
+                &[&(id as i64), &(at as i64), &by],
+            )?;
+            client.query_opt(
+                &format!("SELECT {} FROM alerts WHERE id = $1", ALERT_COLUMNS),
+                &[&(id as i64)],
+            )
+        })?;
+        Ok(row.as_ref().map(stored_alert))
+    }
+}
+
+impl WatchlistStore for PostgresStore {
+    fn watchlist(&self) -> Result<Vec<Pubkey>, String> {
+        let rows = self.with_client(|client| client.query("SELECT pool FROM watchlist ORDER BY pool", &[]))?;
+        rows.into_iter().map(|row| parse_pubkey(row.get(0))).collect()
+    }
+
+    fn watch(&self, pool: &Pubkey) -> Result<bool, String> {
+        let pool = pool.to_string();
+        self.with_client(move |client| {
+            client.execute("INSERT INTO watchlist (pool) VALUES ($1) ON CONFLICT DO NOTHING", &[&pool])
+        })
+        .map(|inserted| inserted > 0)
+    }
+
+    fn unwatch(&self, pool: &Pubkey) -> Result<bool, String> {
+        let pool = pool.to_string();
+        self.with_client(move |client| client.execute("DELETE FROM watchlist WHERE pool = $1", &[&pool]))
+            .map(|deleted| deleted > 0)
+    }
+}
+
+const UPSERT_INDEXED_POOL: &str = "INSERT INTO pool_index (pool, dex, mint_a, mint_b, slot) VALUES ($1, $2, $3, $4, $5)
+     ON CONFLICT (pool) DO UPDATE SET dex = excluded.dex, mint_a = excluded.mint_a, mint_b = excluded.mint_b, slot = excluded.slot";
+
+fn upsert_indexed_pools(client: &mut impl GenericClient, pools: &[IndexedPool]) -> Result<(), postgres::Error> {
+    let statement = client.prepare(UPSERT_INDEXED_POOL)?;
+    for pool in pools {
+        client.execute(
+            &statement,
+            &[
+                &pool.pool.to_string(),
+                &pool.dex,
+                &pool.mint_a.to_string(),
+                &pool.mint_b.to_string(),
+                &(pool.slot as i64),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+impl PoolIndexStore for PostgresStore {
+    fn load_pool_index(&self) -> Result<Vec<IndexedPool>, String> {
+        let rows = self.with_client(|client| client.query("SELECT pool, dex, mint_a, mint_b, slot FROM pool_index", &[]))?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                indexed_pool(row.get(0), row.get(1), row.get(2), row.get(3), row.get::<_, i64>(4) as u64)
+            })
+            .collect())
+    }
+
+    fn save_indexed_pools(&self, pools: &[IndexedPool]) -> Result<(), String> {
+        let pools = pools.to_vec();
+        self.with_client(move |client| {
+            let mut transaction = client.transaction()?;
+            upsert_indexed_pools(&mut transaction, &pools)?;
+            transaction.commit()
+        })
+    }
+
+    fn replace_pool_index(&self, dex: &str, pools: &[IndexedPool]) -> Result<(), String> {
+        let (dex, pools) = (dex.to_string(), pools.to_vec());
+        self.with_client(move |client| {
+            let mut transaction = client.transaction()?;
+            transaction.execute("DELETE FROM pool_index WHERE dex = $1", &[&dex])?;
+            upsert_indexed_pools(&mut transaction, &pools)?;
+            transaction.commit()
+        })
+    }
+}
+
+impl PoolLinkStore for PostgresStore {
+    fn save_pool_link(&self, link: &PoolLink) -> Result<bool, String> {
+        let link = link.clone();
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO pool_links (old_pool, new_pool, drained_at, created_at, removed_pct)
+                 VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+                &[
+                    &link.old_pool.to_string(),
+                    &link.new_pool.to_string(),
+                    &(link.drained_at as i64),
+                    &(link.created_at as i64),
+                    &link.removed_pct,
+                ],
+            )
+        })
+        .map(|inserted| inserted > 0)
+    }
+
+    fn pool_links(&self, pool: &Pubkey) -> Result<Vec<PoolLink>, String> {
+        let pool = pool.to_string();
+        let rows = self.with_client(move |client| {
+            client.query(
+                "SELECT old_pool, new_pool, drained_at, created_at, removed_pct FROM pool_links
+                 WHERE old_pool = $1 OR new_pool = $1 ORDER BY drained_at",
+                &[&pool],
+            )
+        })?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(PoolLink {
+                    old_pool: parse_pubkey(row.get(0))?,
+                    new_pool: parse_pubkey(row.get(1))?,
+                    drained_at: row.get::<_, i64>(2) as u64,
+                    created_at: row.get::<_, i64>(3) as u64,
+                    removed_pct: row.get(4),
+                })
+            })
+            .collect()
+    }
+}
+
+impl PoolCreationStore for PostgresStore {
+    fn pool_creation(&self, pool: &Pubkey) -> Result<Option<PoolCreation>, String> {
+        let pool = pool.to_string();
+        let row = self.with_client(move |client| {
+            client.query_opt(
+                "SELECT signature, slot, created_at, creator, initial_reserve_a, initial_reserve_b
+                 FROM pool_creations WHERE pool = $1",
+                &[&pool],
+            )
+        })?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        Ok(Some(PoolCreation {
+            signature: row.get(0),
+            slot: row.get::<_, i64>(1) as u64,
+            created_at: row.get::<_, Option<i64>>(2).map(|t| t as u64),
+            creator: parse_pubkey(row.get(3))?,
+            initial_reserve_a: row.get::<_, i64>(4) as u64,
+            initial_reserve_b: row.get::<_, i64>(5) as u64,
+        }))
+    }
+
+    fn save_pool_creation(&self, pool: &Pubkey, creation: &PoolCreation) -> Result<(), String> {
+        let (pool, creation) = (pool.to_string(), creation.clone());
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO pool_creations (pool, signature, slot, created_at, creator, initial_reserve_a, initial_reserve_b)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (pool) DO UPDATE SET signature = excluded.signature, slot = excluded.slot,
+                   created_at = excluded.created_at, creator = excluded.creator,
+                   initial_reserve_a = excluded.initial_reserve_a, initial_reserve_b = excluded.initial_reserve_b",
+                &[
+                    &pool,
+                    &creation.signature,
+                    &(creation.slot as i64),
+                    &creation.created_at.map(|t| t as i64),
+                    &creation.creator.to_string(),
+                    &(creation.initial_reserve_a as i64),
+                    &(creation.initial_reserve_b as i64),
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+}
+
+impl BlocklistStore for PostgresStore {
+    fn blocklist(&self) -> Result<Vec<BlockedAddress>, String> {
+        let rows = self.with_client(|client| {
+            client.query("SELECT address, kind, reason, added_at FROM blocklist ORDER BY address", &[])
+        })?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(BlockedAddress {
+                    address: parse_pubkey(row.get(0))?,
+                    kind: BlockKind::from_str(row.get(1))?,
+                    reason: row.get(2),
+                    source: "admin".to_string(),
+                    added_at: Some(row.get::<_, i64>(3) as u64),
+                })
+            })
+            .collect()
+    }
+
+    fn block(&self, entry: &BlockedAddress) -> Result<(), String> {
+        let entry = entry.clone();
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO blocklist (address, kind, reason, added_at) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (address) DO UPDATE SET kind = excluded.kind, reason = excluded.reason,
+                   added_at = excluded.added_at",
+                &[
+                    &entry.address.to_string(),
+                    &entry.kind.as_str(),
+                    &entry.reason,
+                    &(entry.added_at.unwrap_or(0) as i64),
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+
+    fn unblock(&self, address: &Pubkey) -> Result<bool, String> {
+        let address = address.to_string();
+        self.with_client(move |client| client.execute("DELETE FROM blocklist WHERE address = $1", &[&address]))
+            .map(|deleted| deleted > 0)
+    }
+}
+
+impl UsageStore for PostgresStore {
+    fn usage(&self, month: &str) -> Result<Vec<(String, Usage)>, String> {
+        let month = month.to_string();
+        let rows = self.with_client(move |client| {
+            client.query(
+                "SELECT key_name, requests, rpc_calls, stream_minutes FROM api_key_usage WHERE month = $1",
+                &[&month],
+            )
+        })?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.get(0),
+                    Usage {
+                        requests: row.get::<_, i64>(1) as u64,
+                        rpc_calls: row.get::<_, i64>(2) as u64,
+                        stream_minutes: row.get::<_, i64>(3) as u64,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn save_usage(&self, month: &str, key_name: &str, usage: &Usage) -> Result<(), String> {
+        let (month, key_name, usage) = (month.to_string(), key_name.to_string(), *usage);
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO api_key_usage (month, key_name, requests, rpc_calls, stream_minutes)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (month, key_name) DO UPDATE SET requests = excluded.requests,
+                   rpc_calls = excluded.rpc_calls, stream_minutes = excluded.stream_minutes",
+                &[
+                    &month,
+                    &key_name,
+                    &(usage.requests as i64),
+                    &(usage.rpc_calls as i64),
+                    &(usage.stream_minutes as i64),
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+}
+
+impl ApiKeyStore for PostgresStore {
+    fn api_keys(&self) -> Result<Vec<ApiKey>, String> {
+        let rows = self.with_client(|client| {
+            client.query(
+                "SELECT id, name, role, prefix, key_hash, previous_hash, previous_expires_at, monthly_requests,
+                   monthly_rpc_calls, monthly_stream_minutes, created_at, rotated_at, last_used_at, revoked_at
+                 FROM api_keys",
+                &[],
+            )
+        })?;
+        let optional = |value: Option<i64>| value.map(|value| value as u64);
+        rows.into_iter()
+            .map(|row| {
+                Ok(ApiKey {
+                    id: row.get(0),
+                    name: row.get(1),
+                    role: Role::from_str(row.get(2))?,
+                    prefix: row.get(3),
+                    hash: row.get(4),
+                    previous_hash: row.get(5),
+                    previous_expires_at: optional(row.get(6)),
+                    quotas: Quotas {
+                        monthly_requests: optional(row.get(7)),
+                        monthly_rpc_calls: optional(row.get(8)),
+                        monthly_stream_minutes: optional(row.get(9)),
+                    },
+                    created_at: row.get::<_, i64>(10) as u64,
+                    rotated_at: optional(row.get(11)),
+                    last_used_at: optional(row.get(12)),
+                    revoked_at: optional(row.get(13)),
+                })
+            })
+            .collect()
+    }
+
+    fn save_api_key(&self, key: &ApiKey) -> Result<(), String> {
+        let key = key.clone();
+        let optional = |value: Option<u64>| value.map(|value| value as i64);
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO api_keys (id, name, role, prefix, key_hash, previous_hash, previous_expires_at,
+                   monthly_requests, monthly_rpc_calls, monthly_stream_minutes, created_at, rotated_at, last_used_at,
+                   revoked_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name, role = excluded.role, prefix = excluded.prefix,
+                   key_hash = excluded.key_hash, previous_hash = excluded.previous_hash,
+                   previous_expires_at = excluded.previous_expires_at, monthly_requests = excluded.monthly_requests,
+                   monthly_rpc_calls = excluded.monthly_rpc_calls, monthly_stream_minutes = excluded.monthly_stream_minutes,
+                   rotated_at = excluded.rotated_at, last_used_at = excluded.last_used_at, revoked_at = excluded.revoked_at",
+                &[
+                    &key.id,
+                    &key.name,
+                    &key.role.as_str(),
+                    &key.prefix,
+                    &key.hash,
+                    &key.previous_hash,
+                    &optional(key.previous_expires_at),
+                    &optional(key.quotas.monthly_requests),
+                    &optional(key.quotas.monthly_rpc_calls),
+                    &optional(key.quotas.monthly_stream_minutes),
+                    &(key.created_at as i64),
+                    &optional(key.rotated_at),
+                    &optional(key.last_used_at),
+                    &optional(key.revoked_at),
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+}
+
+const AUDIT_COLUMNS: &str = "id, timestamp, actor, ip, method, route, path, status, target, before, after";
+
+fn audit_record(row: &postgres::Row) -> AuditRecord {
+    AuditRecord {
+        id: row.get::<_, i64>(0) as u64,
+        timestamp: row.get::<_, i64>(1) as u64,
+        actor: row.get(2),
+        ip: row.get(3),
+        method: row.get(4),
+        route: row.get(5),
+        path: row.get(6),
+        status: row.get::<_, i32>(7) as u16,
+        target: row.get(8),
+        before: row.get(9),
+        after: row.get(10),
+    }
+}
+
+impl AuditTrailStore for PostgresStore {
+    fn save_audit_record(&self, record: &AuditRecord) -> Result<u64, String> {
+        let record = record.clone();
+        self.with_client(move |client| {
+            client.query_one(
+                "INSERT INTO audit_trail (timestamp, actor, ip, method, route, path, status, target, before, after)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id",
+                &[
+                    &(record.timestamp as i64),
+                    &record.actor,
+                    &record.ip,
+                    &record.method,
+                    &record.route,
+                    &record.path,
+                    &(record.status as i32),
+                    &record.target,
+                    &record.before,
+                    &record.after,
+                ],
+            )
+        })
+        .map(|row| row.get::<_, i64>(0) as u64)
+    }
+
+    fn audit_trail(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, String> {
+        let (since, until) = (filter.since.map(|t| t as i64), filter.until.map(|t| t as i64));
+        let (actor, target) = (filter.actor.clone(), filter.target.clone());
+        let limit = filter.limit as i64;
+        let rows = self.with_client(move |client| {
+            client.query(
+                &format!(
+                    "SELECT {} FROM audit_trail
+                     WHERE ($1::BIGINT IS NULL OR timestamp >= $1) AND ($2::BIGINT IS NULL OR timestamp < $2)
+                       AND ($3::TEXT IS NULL OR actor = $3) AND ($4::TEXT IS NULL OR target = $4)
+                     ORDER BY id DESC LIMIT $5",
+                    AUDIT_COLUMNS
+                ),
+                &[&since, &until, &actor, &target, &limit],
+            )
+        })?;
+        Ok(rows.iter().map(audit_record).collect())
+    }
+}
+
+const ANNOTATION_COLUMNS: &str = "id, pool, timestamp, label, note, created_by, created_at";
+
+fn annotation(row: &postgres::Row) -> Annotation {
+    Annotation {
+        id: row.get::<_, i64>(0) as u64,
+        pool: row.get(1),
+        timestamp: row.get::<_, i64>(2) as u64,
+        label: row.get(3),
+        note: row.get(4),
+        created_by: row.get(5),
+        created_at: row.get::<_, i64>(6) as u64,
+    }
+}
+
+impl AnnotationStore for PostgresStore {
+    fn save_annotation(&self, annotation: &Annotation) -> Result<u64, String> {
+        let annotation = annotation.clone();
+        self.with_client(move |client| {
+            client.query_one(
+                "INSERT INTO annotations (pool, timestamp, label, note, created_by, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+                &[
+                    &annotation.pool,
+                    &(annotation.timestamp as i64),
+                    &annotation.label,
+                    &annotation.note,
+                    &annotation.created_by,
+                    &(annotation.created_at as i64),
+                ],
+            )
+        })
+        .map(|row| row.get::<_, i64>(0) as u64)
+    }
+
+    fn annotations(&self, pool: Option<&str>, from: u64, to: u64) -> Result<Vec<Annotation>, String> {
+        let pool = pool.map(str::to_string);
+        let (from, to) = (from.min(i64::MAX as u64) as i64, to.min(i64::MAX as u64) as i64);
+        let rows = self.with_client(move |client| {
+            client.query(
+                &format!(
+                    "SELECT {} FROM annotations
+                     WHERE ($1::TEXT IS NULL OR pool = $1) AND timestamp >= $2 AND timestamp <= $3
+                     ORDER BY timestamp, id",
+                    ANNOTATION_COLUMNS
+                ),
+                &[&pool, &from, &to],
+            )
+        })?;
+        Ok(rows.iter().map(annotation).collect())
+    }
+
+    fn delete_annotation(&self, pool: &str, id: u64) -> Result<Option<Annotation>, String> {
+        let (pool, id) = (pool.to_string(), id as i64);
+        let row = self.with_client(move |client| {
+            client.query_opt(
+                &format!("DELETE FROM annotations WHERE pool = $1 AND id = $2 RETURNING {}", ANNOTATION_COLUMNS),
+                &[&pool, &id],
+            )
+        })?;
+        Ok(row.as_ref().map(annotation))
+    }
+}
+
+impl PortfolioStore for PostgresStore {
+    fn portfolios(&self) -> Result<Vec<Portfolio>, String> {
+        let rows = self.with_client(|client| {
+            client.query("SELECT id, name, positions, created_by, created_at, updated_at FROM portfolios", &[])
+        })?;
+        rows.into_iter()
+            .map(|row| {
+                let id: String = row.get(0);
+                let positions = serde_json::from_value(row.get(2))
+                    .map_err(|e| format!("Invalid positions of portfolio {}: {}", id, e))?;
+                Ok(Portfolio {
+                    id,
+                    name: row.get(1),
+                    positions,
+                    created_by: row.get(3),
+                    created_at: row.get::<_, i64>(4) as u64,
+                    updated_at: row.get::<_, i64>(5) as u64,
+                })
+            })
+            .collect()
+    }
+
+    fn save_portfolio(&self, portfolio: &Portfolio) -> Result<(), String> {
+        let portfolio = portfolio.clone();
+        let positions = serde_json::to_value(&portfolio.positions).map_err(|e| e.to_string())?;
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO portfolios (id, name, positions, created_by, created_at, updated_at)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name, positions = excluded.positions,
+                   updated_at = excluded.updated_at",
+                &[
+                    &portfolio.id,
+                    &portfolio.name,
+                    &positions,
+                    &portfolio.created_by,
+                    &(portfolio.created_at as i64),
+                    &(portfolio.updated_at as i64),
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+
+    fn delete_portfolio(&self, id: &str) -> Result<bool, String> {
+        let id = id.to_string();
+        self.with_client(move |client| client.execute("DELETE FROM portfolios WHERE id = $1", &[&id]))
+            .map(|deleted| deleted > 0)
+    }
+}
+
+impl SubscriptionStore for PostgresStore {
+    fn subscriptions(&self) -> Result<Vec<Subscription>, String> {
+        let rows = self.with_client(|client| {
+            client.query("SELECT id, url, pools, events, secret, created_at FROM subscriptions", &[])
+        })?;
+        rows.into_iter()
+            .map(|row| {
+                let id: String = row.get(0);
+                let invalid = |e: serde_json::Error| format!("Invalid subscription {}: {}", id, e);
+                let pools = serde_json::from_value(row.get(2)).map_err(invalid)?;
+                let events = serde_json::from_value(row.get(3)).map_err(invalid)?;
+                Ok(Subscription {
+                    id,
+                    url: row.get(1),
+                    pools,
+                    events,
+                    secret: row.get(4),
+                    created_at: row.get::<_, i64>(5) as u64,
+                    delivered: 0,
+                    failed: 0,
+                })
+            })
+            .collect()
+    }
+
+    fn save_subscription(&self, subscription: &Subscription) -> Result<(), String> {
+        let subscription = subscription.clone();
+        let pools = serde_json::to_value(&subscription.pools).map_err(|e| e.to_string())?;
+        let events = serde_json::to_value(&subscription.events).map_err(|e| e.to_string())?;
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO subscriptions (id, url, pools, events, secret, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (id) DO UPDATE SET url = excluded.url, pools = excluded.pools,
+                   events = excluded.events, secret = excluded.secret",
+                &[
+                    &subscription.id,
+                    &subscription.url,
+                    &pools,
+                    &events,
+                    &subscription.secret,
+                    &(subscription.created_at as i64),
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+
+    fn delete_subscription(&self, id: &str) -> Result<bool, String> {
+        let id = id.to_string();
+        self.with_client(move |client| client.execute("DELETE FROM subscriptions WHERE id = $1", &[&id]))
+            .map(|deleted| deleted > 0)
+    }
+}
+
+impl ReportTemplateStore for PostgresStore {
+    fn report_templates(&self) -> Result<Vec<ReportTemplate>, String> {
+        let rows = self.with_client(|client| client.query("SELECT id, definition FROM report_templates", &[]))?;
+        rows.into_iter()
+            .map(|row| {
+                let id: String = row.get(0);
+                serde_json::from_value(row.get(1)).map_err(|e| format!("Invalid report template {}: {}", id, e))
+            })
+            .collect()
+    }
+
+    fn save_report_template(&self, template: &ReportTemplate) -> Result<(), String> {
+        let template = template.clone();
+        let definition = serde_json::to_value(&template).map_err(|e| e.to_string())?;
+        self.with_client(move |client| {
+            client.execute(
+                "INSERT INTO report_templates (id, name, definition, created_at) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name, definition = excluded.definition",
+                &[&template.id, &template.name, &definition, &(template.created_at as i64)],
+            )
+        })
+        .map(|_| ())
+    }
+
+    fn delete_report_template(&self, id: &str) -> Result<bool, String> {
+        let id = id.to_string();
+        self.with_client(move |client| client.execute("DELETE FROM report_templates WHERE id = $1", &[&id]))
+            .map(|deleted| deleted > 0)
+    }
+}
@@ -0,0 +1,903 @@
+use super::{
+    indexed_pool, AlertFilter, AnnotationStore, ApiKeyStore, AlertStore, AuditFilter, AuditRecord, AuditTrailStore, BlocklistStore,
+    PoolCreationStore, PoolIndexStore, PoolLink, PoolLinkStore, PortfolioStore, ReportTemplateStore, SnapshotStore, StoredAlert,
+    SubscriptionStore, UsageStore, WatchlistStore,
+};
+use crate::alerts::Alert;
+use crate::annotations::Annotation;
+use crate::blocklist::{BlockKind, BlockedAddress};
+use crate::config::{Quotas, Role};
+use crate::creation::PoolCreation;
+use crate::history::PoolSnapshot;
+use crate::index::IndexedPool;
+use crate::keys::ApiKey;
+use crate::portfolio::Portfolio;
+use crate::subscriptions::Subscription;
+use crate::templates::ReportTemplate;
+use crate::usage::Usage;
+use rusqlite::{params, Connection, OptionalExtension};
+use solana_sdk::pubkey::Pubkey;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+mod embedded {
+    refinery::embed_migrations!("migrations/sqlite");
+}
+
+// Single file store for deployments without a database server.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut connection =
+            Connection::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        // WAL keeps readers from blocking the poller's writes
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .map_err(|e| format!("Failed to initialise {}: {}", path.display(), e))?;
+        let report = embedded::migrations::runner()
+            .run(&mut connection)
+            .map_err(|e| format!("Failed to migrate {}: {}", path.display(), e))?;
+        for migration in report.applied_migrations() {
+            println!("Applied migration {}", migration);
+        }
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+fn parse_pubkey(value: String) -> Result<Pubkey, String> {
+    Pubkey::from_str(&value).map_err(|e| format!("Invalid pubkey {} in database: {}", value, e))
+}
+
+impl SnapshotStore for SqliteStore {
+    fn save_snapshot(&self, pool: &Pubkey, snapshot: &PoolSnapshot) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO snapshots (pool, slot, timestamp, reserve_a, reserve_b, price, lp_supply, authority, cumulative_volume_b,
+                   fee_growth_a, fee_growth_b)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    pool.to_string(),
+                    snapshot.slot as i64,
+                    snapshot.timestamp as i64,
+                    snapshot.reserve_a as i64,
+                    snapshot.reserve_b as i64,
+                    snapshot.price,
+                    snapshot.lp_supply.map(|s| s as i64),
+                    snapshot.authority.map(|a| a.to_string()),
+                    snapshot.cumulative_volume_b.map(|v| v.to_string()),
+                    snapshot.fee_growth.map(|(a, _)| a.to_string()),
+                    snapshot.fee_growth.map(|(_, b)| b.to_string()),
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save snapshot: {}", e))
+    }
+
+    fn load_snapshots(&self, since: u64) -> Result<Vec<(Pubkey, PoolSnapshot)>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT pool, slot, timestamp, reserve_a, reserve_b, price, lp_supply, authority, cumulative_volume_b,
+                   fee_growth_a, fee_growth_b
+                 FROM snapshots WHERE timestamp >= ?1 ORDER BY timestamp, slot",
+            )
+            .map_err(|e| format!("Failed to load snapshots: {}", e))?;
+        let rows = statement
+            .query_map([since as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, f64>(5)?,
+                    row.get::<_, Option<i64>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                    row.get::<_, Option<String>>(8)?,
+                    super::fee_growth(row.get(9)?, row.get(10)?),
+                ))
+            })
+            .map_err(|e| format!("Failed to load snapshots: {}", e))?;
+
+        let mut snapshots = Vec::new();
+        for row in rows {
+            let (pool, slot, timestamp, reserve_a, reserve_b, price, lp_supply, authority, volume, fee_growth) =
+                row.map_err(|e| format!("Failed to read snapshot: {}", e))?;
+            snapshots.push((
+                parse_pubkey(pool)?,
+                PoolSnapshot {
+                    slot: slot as u64,
+                    timestamp: timestamp as u64,
+                    reserve_a: reserve_a as u64,
+                    reserve_b: reserve_b as u64,
+                    price,
+                    lp_supply: lp_supply.map(|s| s as u64),
+                    authority: authority.map(parse_pubkey).transpose()?,
+                    cumulative_volume_b: volume.and_then(|v| v.parse().ok()),
+                    fee_growth,
+                },
+            ));
+        }
+        Ok(snapshots)
+    }
+
+    fn prune_snapshots(&self, before: u64) -> Result<usize, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM snapshots WHERE timestamp < ?1", [before as i64])
+            .map_err(|e| format!("Failed to prune snapshots: {}", e))
+    }
+}
+
+const ALERT_COLUMNS: &str = "id, key, kind, severity, pool, message, timestamp, details, acked_at, acked_by, suppressed_by";
+
+fn stored_alert(row: &rusqlite::Row) -> rusqlite::Result<StoredAlert> {
+    Ok(StoredAlert {
+        id: row.get::<_, i64>(0)? as u64,
+        key: row.get(1)?,
+        kind: row.get(2)?,
+        severity: row.get(3)?,
+        pool: row.get(4)?,
+        message: row.get(5)?,
+        timestamp: row.get::<_, i64>(6)? as u64,
+        details: serde_json::from_str(&row.get::<_, String>(7)?).unwrap_or_default(),
+        acked_at: row.get::<_, Option<i64>>(8)?.map(|t| t as u64),
+        acked_by: row.get(9)?,
+        suppressed_by: row.get(10)?,
+    })
+}
+
+impl AlertStore for SqliteStore {
+    fn save_alert(&self, alert: &Alert) -> Result<u64, String> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                "INSERT INTO alerts (key, kind, severity, pool, message, timestamp, details, suppressed_by)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    alert.key,
+                    alert.kind,
+                    alert.severity.as_str(),
+                    alert.pool,
+                    alert.message,
+                    alert.timestamp as i64,
+                    alert.details.to_string(),
+                    alert.suppressed_by,
+                ],
+            )
+            .map(|_| connection.last_insert_rowid() as u64)
+            .map_err(|e| format!("Failed to save alert: {}", e))
+    }
+
+    fn alert_history(&self, filter: &AlertFilter) -> Result<Vec<StoredAlert>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(&format!(
+                "SELECT {} FROM alerts
+                 WHERE (?1 IS NULL OR timestamp >= ?1) AND (?2 IS NULL OR timestamp < ?2)
+                   AND (?3 IS NULL OR pool = ?3) AND (?4 IS NULL OR kind = ?4)
+                   AND (?5 IS NULL OR (acked_at IS NOT NULL) = ?5)
+                 ORDER BY id DESC LIMIT ?6",
+                ALERT_COLUMNS
+            ))
+            .map_err(|e| format!("Failed to load alert history: {}", e))?;
+        statement
+            .query_map(
+                params![
+                    filter.since.map(|t| t as i64),
+                    filter.until.map(|t| t as i64),
+                    filter.pool,
+                    filter.kind,
+                    filter.acked,
+                    filter.limit as i64,
+                ],
+                stored_alert,
+            )
+            .and_then(|rows| rows.collect())
+            .map_err(|e| format!("Failed to load alert history: {}", e))
+    }
+
+    fn ack_alert(&self, id: u64, by: &str, at: u64) -> Result<Option<StoredAlert>, String> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                "UPDATE alerts SET acked_at = ?2, acked_by = ?3 WHERE id = ?1 AND acked_at IS NULL",
+                params![id as i64, at as i64, by],
+            )
+            .and_then(|_| {
+                connection
+                    .query_row(
+                        &format!("SELECT {} FROM alerts WHERE id = ?1", ALERT_COLUMNS),
+                        [id as i64],
+                        stored_alert,
+                    )
+                    .optional()
+            })
+            .map_err(|e| format!("Failed to acknowledge alert: {}", e))
+    }
+}
+
+impl WatchlistStore for SqliteStore {
+    fn watchlist(&self) -> Result<Vec<Pubkey>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT pool FROM watchlist ORDER BY pool")
+            .map_err(|e| format!("Failed to load watchlist: {}", e))?;
+        let pools = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load watchlist: {}", e))?;
+        pools.into_iter().map(parse_pubkey).collect()
+    }
+
+    fn watch(&self, pool: &Pubkey) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("INSERT OR IGNORE INTO watchlist (pool) VALUES (?1)", [pool.to_string()])
+            .map(|inserted| inserted > 0)
+            .map_err(|e| format!("Failed to update watchlist: {}", e))
+    }
+
+    fn unwatch(&self, pool: &Pubkey) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM watchlist WHERE pool = ?1", [pool.to_string()])
+            .map(|deleted| deleted > 0)
+            .map_err(|e| format!("Failed to update watchlist: {}", e))
+    }
+}
+
+const UPSERT_INDEXED_POOL: &str = "INSERT INTO pool_index (pool, dex, mint_a, mint_b, slot) VALUES (?1, ?2, ?3, ?4, ?5)
+     ON CONFLICT (pool) DO UPDATE SET dex = excluded.dex, mint_a = excluded.mint_a, mint_b = excluded.mint_b, slot = excluded.slot";
+
+fn upsert_indexed_pools(transaction: &rusqlite::Transaction, pools: &[IndexedPool]) -> rusqlite::Result<()> {
+    let mut statement = transaction.prepare(UPSERT_INDEXED_POOL)?;
+    for pool in pools {
+        statement.execute(params![
+            pool.pool.to_string(),
+            pool.dex,
+            pool.mint_a.to_string(),
+            pool.mint_b.to_string(),
+            pool.slot as i64,
+        ])?;
+    }
+    Ok(())
+}
+
+impl PoolIndexStore for SqliteStore {
+    fn load_pool_index(&self) -> Result<Vec<IndexedPool>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT pool, dex, mint_a, mint_b, slot FROM pool_index")
+            .map_err(|e| format!("Failed to load pool index: {}", e))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(indexed_pool(
+                    &row.get::<_, String>(0)?,
+                    &row.get::<_, String>(1)?,
+                    &row.get::<_, String>(2)?,
+                    &row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)? as u64,
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load pool index: {}", e))?;
+        Ok(rows.into_iter().flatten().collect())
+    }
+
+    fn save_indexed_pools(&self, pools: &[IndexedPool]) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+        connection
+            .transaction()
+            .and_then(|transaction| {
+                upsert_indexed_pools(&transaction, pools)?;
+                transaction.commit()
+            })
+            .map_err(|e| format!("Failed to save indexed pools: {}", e))
+    }
+
+    fn replace_pool_index(&self, dex: &str, pools: &[IndexedPool]) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+        connection
+            .transaction()
+            .and_then(|transaction| {
+                transaction.execute("DELETE FROM pool_index WHERE dex = ?1", [dex])?;
+                upsert_indexed_pools(&transaction, pools)?;
+                transaction.commit()
+            })
+            .map_err(|e| format!("Failed to replace pool index: {}", e))
+    }
+}
+
+impl PoolLinkStore for SqliteStore {
+    fn save_pool_link(&self, link: &PoolLink) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR IGNORE INTO pool_links (old_pool, new_pool, drained_at, created_at, removed_pct)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    link.old_pool.to_string(),
+                    link.new_pool.to_string(),
+                    link.drained_at as i64,
+                    link.created_at as i64,
+                    link.removed_pct,
+                ],
+            )
+            .map(|inserted| inserted > 0)
+            .map_err(|e| format!("Failed to save pool link: {}", e))
+    }
+
+    fn pool_links(&self, pool: &Pubkey) -> Result<Vec<PoolLink>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT old_pool, new_pool, drained_at, created_at, removed_pct FROM pool_links
+                 WHERE old_pool = ?1 OR new_pool = ?1 ORDER BY drained_at",
+            )
+            .map_err(|e| format!("Failed to load pool links: {}", e))?;
+        let rows = statement
+            .query_map([pool.to_string()], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, i64>(3)? as u64,
+                    row.get::<_, f64>(4)?,
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load pool links: {}", e))?;
+        rows.into_iter()
+            .map(|(old_pool, new_pool, drained_at, created_at, removed_pct)| {
+                Ok(PoolLink {
+                    old_pool: parse_pubkey(old_pool)?,
+                    new_pool: parse_pubkey(new_pool)?,
+                    drained_at,
+                    created_at,
+                    removed_pct,
+                })
+            })
+            .collect()
+    }
+}
+
+impl PoolCreationStore for SqliteStore {
+    fn pool_creation(&self, pool: &Pubkey) -> Result<Option<PoolCreation>, String> {
+        let row = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT signature, slot, created_at, creator, initial_reserve_a, initial_reserve_b
+                 FROM pool_creations WHERE pool = ?1",
+                [pool.to_string()],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)? as u64,
+                        row.get::<_, Option<i64>>(2)?.map(|t| t as u64),
+                        row.get::<_, String>(3)?,
+                        row.get::<_, i64>(4)? as u64,
+                        row.get::<_, i64>(5)? as u64,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(|e| format!("Failed to load pool creation: {}", e))?;
+        let Some((signature, slot, created_at, creator, initial_reserve_a, initial_reserve_b)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(PoolCreation {
+            signature,
+            slot,
+            created_at,
+            creator: parse_pubkey(creator)?,
+            initial_reserve_a,
+            initial_reserve_b,
+        }))
+    }
+
+    fn save_pool_creation(&self, pool: &Pubkey, creation: &PoolCreation) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO pool_creations
+                 (pool, signature, slot, created_at, creator, initial_reserve_a, initial_reserve_b)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    pool.to_string(),
+                    creation.signature,
+                    creation.slot as i64,
+                    creation.created_at.map(|t| t as i64),
+                    creation.creator.to_string(),
+                    creation.initial_reserve_a as i64,
+                    creation.initial_reserve_b as i64,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save pool creation: {}", e))
+    }
+}
+
+impl BlocklistStore for SqliteStore {
+    fn blocklist(&self) -> Result<Vec<BlockedAddress>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT address, kind, reason, added_at FROM blocklist ORDER BY address")
+            .map_err(|e| format!("Failed to load blocklist: {}", e))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, i64>(3)? as u64,
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load blocklist: {}", e))?;
+        rows.into_iter()
+            .map(|(address, kind, reason, added_at)| {
+                Ok(BlockedAddress {
+                    address: parse_pubkey(address)?,
+                    kind: BlockKind::from_str(&kind)?,
+                    reason,
+                    source: "admin".to_string(),
+                    added_at: Some(added_at),
+                })
+            })
+            .collect()
+    }
+
+    fn block(&self, entry: &BlockedAddress) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO blocklist (address, kind, reason, added_at) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    entry.address.to_string(),
+                    entry.kind.as_str(),
+                    entry.reason,
+                    entry.added_at.unwrap_or(0) as i64,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to update blocklist: {}", e))
+    }
+
+    fn unblock(&self, address: &Pubkey) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM blocklist WHERE address = ?1", [address.to_string()])
+            .map(|deleted| deleted > 0)
+            .map_err(|e| format!("Failed to update blocklist: {}", e))
+    }
+}
+
+impl UsageStore for SqliteStore {
+    fn usage(&self, month: &str) -> Result<Vec<(String, Usage)>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT key_name, requests, rpc_calls, stream_minutes FROM api_key_usage WHERE month = ?1")
+            .map_err(|e| format!("Failed to load API key usage: {}", e))?;
+        statement
+            .query_map([month], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    Usage {
+                        requests: row.get::<_, i64>(1)? as u64,
+                        rpc_calls: row.get::<_, i64>(2)? as u64,
+                        stream_minutes: row.get::<_, i64>(3)? as u64,
+                    },
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load API key usage: {}", e))
+    }
+
+    fn save_usage(&self, month: &str, key_name: &str, usage: &Usage) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO api_key_usage (month, key_name, requests, rpc_calls, stream_minutes)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    month,
+                    key_name,
+                    usage.requests as i64,
+                    usage.rpc_calls as i64,
+                    usage.stream_minutes as i64,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save API key usage: {}", e))
+    }
+}
+
+impl ApiKeyStore for SqliteStore {
+    fn api_keys(&self) -> Result<Vec<ApiKey>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT id, name, role, prefix, key_hash, previous_hash, previous_expires_at, monthly_requests,
+                   monthly_rpc_calls, monthly_stream_minutes, created_at, rotated_at, last_used_at, revoked_at
+                 FROM api_keys",
+            )
+            .map_err(|e| format!("Failed to load API keys: {}", e))?;
+        let optional = |value: Option<i64>| value.map(|value| value as u64);
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(2)?,
+                    ApiKey {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        role: Role::default(),
+                        prefix: row.get(3)?,
+                        hash: row.get(4)?,
+                        previous_hash: row.get(5)?,
+                        previous_expires_at: optional(row.get(6)?),
+                        quotas: Quotas {
+                            monthly_requests: optional(row.get(7)?),
+                            monthly_rpc_calls: optional(row.get(8)?),
+                            monthly_stream_minutes: optional(row.get(9)?),
+                        },
+                        created_at: row.get::<_, i64>(10)? as u64,
+                        rotated_at: optional(row.get(11)?),
+                        last_used_at: optional(row.get(12)?),
+                        revoked_at: optional(row.get(13)?),
+                    },
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load API keys: {}", e))?;
+        rows.into_iter()
+            .map(|(role, key)| Ok(ApiKey { role: Role::from_str(&role)?, ..key }))
+            .collect()
+    }
+
+    fn save_api_key(&self, key: &ApiKey) -> Result<(), String> {
+        let optional = |value: Option<u64>| value.map(|value| value as i64);
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO api_keys (id, name, role, prefix, key_hash, previous_hash, previous_expires_at,
+                   monthly_requests, monthly_rpc_calls, monthly_stream_minutes, created_at, rotated_at, last_used_at,
+                   revoked_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                params![
+                    key.id,
+                    key.name,
+                    key.role.as_str(),
+                    key.prefix,
+                    key.hash,
+                    key.previous_hash,
+                    optional(key.previous_expires_at),
+                    optional(key.quotas.monthly_requests),
+                    optional(key.quotas.monthly_rpc_calls),
+                    optional(key.quotas.monthly_stream_minutes),
+                    key.created_at as i64,
+                    optional(key.rotated_at),
+                    optional(key.last_used_at),
+                    optional(key.revoked_at),
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save API key: {}", e))
+    }
+}
+
+const AUDIT_COLUMNS: &str = "id, timestamp, actor, ip, method, route, path, status, target, before, after";
+
+fn audit_record(row: &rusqlite::Row) -> rusqlite::Result<AuditRecord> {
+    Ok(AuditRecord {
+        id: row.get::<_, i64>(0)? as u64,
+        timestamp: row.get::<_, i64>(1)? as u64,
+        actor: row.get(2)?,
+        ip: row.get(3)?,
+        method: row.get(4)?,
+        route: row.get(5)?,
+        path: row.get(6)?,
+        status: row.get(7)?,
+        target: row.get(8)?,
+        before: serde_json::from_str(&row.get::<_, String>(9)?).unwrap_or_default(),
+        after: serde_json::from_str(&row.get::<_, String>(10)?).unwrap_or_default(),
+    })
+}
+
+impl AuditTrailStore for SqliteStore {
+    fn save_audit_record(&self, record: &AuditRecord) -> Result<u64, String> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                "INSERT INTO audit_trail (timestamp, actor, ip, method, route, path, status, target, before, after)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    record.timestamp as i64,
+                    record.actor,
+                    record.ip,
+                    record.method,
+                    record.route,
+                    record.path,
+                    record.status,
+                    record.target,
+                    record.before.to_string(),
+                    record.after.to_string(),
+                ],
+            )
+            .map(|_| connection.last_insert_rowid() as u64)
+            .map_err(|e| format!("Failed to save audit record: {}", e))
+    }
+
+    fn audit_trail(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(&format!(
+                "SELECT {} FROM audit_trail
+                 WHERE (?1 IS NULL OR timestamp >= ?1) AND (?2 IS NULL OR timestamp < ?2)
+                   AND (?3 IS NULL OR actor = ?3) AND (?4 IS NULL OR target = ?4)
+                 ORDER BY id DESC LIMIT ?5",
+                AUDIT_COLUMNS
+            ))
+            .map_err(|e| format!("Failed to load audit trail: {}", e))?;
+        statement
+            .query_map(
+                params![
+                    filter.since.map(|t| t as i64),
+                    filter.until.map(|t| t as i64),
+                    filter.actor,
+                    filter.target,
+                    filter.limit as i64,
+                ],
+                audit_record,
+            )
+            .and_then(|rows| rows.collect())
+            .map_err(|e| format!("Failed to load audit trail: {}", e))
+    }
+}
+
+const ANNOTATION_COLUMNS: &str = "id, pool, timestamp, label, note, created_by, created_at";
+
+fn annotation(row: &rusqlite::Row) -> rusqlite::Result<Annotation> {
+    Ok(Annotation {
+        id: row.get::<_, i64>(0)? as u64,
+        pool: row.get(1)?,
+        timestamp: row.get::<_, i64>(2)? as u64,
+        label: row.get(3)?,
+        note: row.get(4)?,
+        created_by: row.get(5)?,
+        created_at: row.get::<_, i64>(6)? as u64,
+    })
+}
+
+impl AnnotationStore for SqliteStore {
+    fn save_annotation(&self, annotation: &Annotation) -> Result<u64, String> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                "INSERT INTO annotations (pool, timestamp, label, note, created_by, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    annotation.pool,
+                    annotation.timestamp as i64,
+                    annotation.label,
+                    annotation.note,
+                    annotation.created_by,
+                    annotation.created_at as i64,
+                ],
+            )
+            .map(|_| connection.last_insert_rowid() as u64)
+            .map_err(|e| format!("Failed to save annotation: {}", e))
+    }
+
+    fn annotations(&self, pool: Option<&str>, from: u64, to: u64) -> Result<Vec<Annotation>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(&format!(
+                "SELECT {} FROM annotations
+                 WHERE (?1 IS NULL OR pool = ?1) AND timestamp >= ?2 AND timestamp <= ?3
+                 ORDER BY timestamp, id",
+                ANNOTATION_COLUMNS
+            ))
+            .map_err(|e| format!("Failed to load annotations: {}", e))?;
+        statement
+            .query_map(params![pool, from.min(i64::MAX as u64) as i64, to.min(i64::MAX as u64) as i64], annotation)
+            .and_then(|rows| rows.collect())
+            .map_err(|e| format!("Failed to load annotations: {}", e))
+    }
+
+    fn delete_annotation(&self, pool: &str, id: u64) -> Result<Option<Annotation>, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                &format!("DELETE FROM annotations WHERE pool = ?1 AND id = ?2 RETURNING {}", ANNOTATION_COLUMNS),
+                params![pool, id as i64],
+                annotation,
+            )
+            .optional()
+            .map_err(|e| format!("Failed to delete annotation: {}", e))
+    }
+}
+
+impl PortfolioStore for SqliteStore {
+    fn portfolios(&self) -> Result<Vec<Portfolio>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT id, name, positions, created_by, created_at, updated_at FROM portfolios")
+            .map_err(|e| format!("Failed to load portfolios: {}", e))?;
+        let rows: Vec<(Portfolio, String)> = statement
+            .query_map([], |row| {
+                let portfolio = Portfolio {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    positions: Vec::new(),
+                    created_by: row.get(3)?,
+                    created_at: row.get::<_, i64>(4)? as u64,
+                    updated_at: row.get::<_, i64>(5)? as u64,
+                };
+                Ok((portfolio, row.get(2)?))
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| format!("Failed to load portfolios: {}", e))?;
+        rows.into_iter()
+            .map(|(portfolio, positions)| {
+                let positions = serde_json::from_str(&positions)
+                    .map_err(|e| format!("Invalid positions of portfolio {}: {}", portfolio.id, e))?;
+                Ok(Portfolio { positions, ..portfolio })
+            })
+            .collect()
+    }
+
+    fn save_portfolio(&self, portfolio: &Portfolio) -> Result<(), String> {
+        let positions = serde_json::to_string(&portfolio.positions).map_err(|e| e.to_string())?;
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO portfolios (id, name, positions, created_by, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    portfolio.id,
+                    portfolio.name,
+                    positions,
+                    portfolio.created_by,
+                    portfolio.created_at as i64,
+                    portfolio.updated_at as i64,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save portfolio: {}", e))
+    }
+
+    fn delete_portfolio(&self, id: &str) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM portfolios WHERE id = ?1", params![id])
+            .map(|deleted| deleted > 0)
+            .map_err(|e| format!("Failed to delete portfolio: {}", e))
+    }
+}
+
+impl SubscriptionStore for SqliteStore {
+    fn subscriptions(&self) -> Result<Vec<Subscription>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT id, url, pools, events, secret, created_at FROM subscriptions")
+            .map_err(|e| format!("Failed to load subscriptions: {}", e))?;
+        let rows: Vec<(Subscription, String, String)> = statement
+            .query_map([], |row| {
+                let subscription = Subscription {
+                    id: row.get(0)?,
+                    url: row.get(1)?,
+                    pools: Vec::new(),
+                    events: Vec::new(),
+                    secret: row.get(4)?,
+                    created_at: row.get::<_, i64>(5)? as u64,
+                    delivered: 0,
+                    failed: 0,
+                };
+                Ok((subscription, row.get(2)?, row.get(3)?))
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| format!("Failed to load subscriptions: {}", e))?;
+        rows.into_iter()
+            .map(|(subscription, pools, events)| {
+                let invalid = |e: serde_json::Error| format!("Invalid subscription {}: {}", subscription.id, e);
+                let pools = serde_json::from_str(&pools).map_err(invalid)?;
+                let events = serde_json::from_str(&events).map_err(invalid)?;
+                Ok(Subscription { pools, events, ..subscription })
+            })
+            .collect()
+    }
+
+    fn save_subscription(&self, subscription: &Subscription) -> Result<(), String> {
+        let pools = serde_json::to_string(&subscription.pools).map_err(|e| e.to_string())?;
+        let events = serde_json::to_string(&subscription.events).map_err(|e| e.to_string())?;
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO subscriptions (id, url, pools, events, secret, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    subscription.id,
+                    subscription.url,
+                    pools,
+                    events,
+                    subscription.secret,
+                    subscription.created_at as i64,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save subscription: {}", e))
+    }
+
+    fn delete_subscription(&self, id: &str) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM subscriptions WHERE id = ?1", params![id])
+            .map(|deleted| deleted > 0)
+            .map_err(|e| format!("Failed to delete subscription: {}", e))
+    }
+}
+
+impl ReportTemplateStore for SqliteStore {
+    fn report_templates(&self) -> Result<Vec<ReportTemplate>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT id, definition FROM report_templates")
+            .map_err(|e| format!("Failed to load report templates: {}", e))?;
+        let rows: Vec<(String, String)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect())
+            .map_err(|e| format!("Failed to load report templates: {}", e))?;
+        rows.into_iter()
+            .map(|(id, definition)| {
+                serde_json::from_str(&definition).map_err(|e| format!("Invalid report template {}: {}", id, e))
+            })
+            .collect()
+    }
+
+    fn save_report_template(&self, template: &ReportTemplate) -> Result<(), String> {
+        let definition = serde_json::to_string(template).map_err(|e| e.to_string())?;
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO report_templates (id, name, definition, created_at) VALUES (?1, ?2, ?3, ?4)",
+                params![template.id, template.name, definition, template.created_at as i64],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save report template: {}", e))
+    }
+
+    fn delete_report_template(&self, id: &str) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM report_templates WHERE id = ?1", params![id])
+            .map(|deleted| deleted > 0)
+            .map_err(|e| format!("Failed to delete report template: {}", e))
+    }
+}
@@ -0,0 +1,239 @@
+use crate::events::{EventKind, PoolEvent};
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+
+// What to do when a streaming client's buffer is full.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    #[default]
+    DropOldest,
+    // Replace queued price updates with the newest one for the same pool,
+    // falling back to dropping the oldest message.
+    Coalesce,
+    Disconnect,
+}
+
+#[derive(Default)]
+pub struct StreamMetrics {
+    pub clients: AtomicI64,
+    pub dropped: AtomicU64,
+    pub coalesced: AtomicU64,
+    pub disconnected: AtomicU64,
+    pub patches: AtomicU64,
+}
+
+pub enum StreamItem {
+    Event(PoolEvent),
+    // Messages this client lost since the last item.
+    Dropped(u64),
+}
+
+// Bounded queue between the shared event channel and one client socket. The
+// socket drains it at whatever pace the client reads.
+pub struct ClientBuffer {
+    queue: Mutex<VecDeque<PoolEvent>>,
+    ready: Notify,
+    capacity: usize,
+    policy: OverflowPolicy,
+    closed: AtomicBool,
+    dropped_since_read: AtomicU64,
+    state: Arc<AppState>,
+}
+
+impl ClientBuffer {
+    // Start forwarding events accepted by `filter` into a new buffer.
+    pub fn spawn<F>(state: Arc<AppState>, filter: F) -> Arc<ClientBuffer>
+    where
+        F: Fn(&PoolEvent) -> bool + Send + 'static,
+    {
+        let config = state.config().streaming;
+        let buffer = Arc::new(ClientBuffer {
+            queue: Mutex::new(VecDeque::with_capacity(config.buffer_size)),
+            ready: Notify::new(),
+            capacity: config.buffer_size.max(1),
+            policy: config.overflow_policy,
+            closed: AtomicBool::new(false),
+            dropped_since_read: AtomicU64::new(0),
+            state: state.clone(),
+        });
+        state.stream_metrics.clients.fetch_add(1, Ordering::Relaxed);
+
+        let mut receiver = state.events.subscribe();
+        let weak = Arc::downgrade(&buffer);
+        tokio::spawn(async move {
+            loop {
+                let received = receiver.recv().await;
+                // The client went away
+                let Some(buffer) = weak.upgrade() else {
+                    return;
+                };
+                match received {
+                    Ok(event) if filter(&event) => {
+                        if !buffer.push(event) {
+                            return;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => buffer.record_dropped(missed),
+                    Err(RecvError::Closed) => {
+                        buffer.close();
+                        return;
+                    }
+                }
+            }
+        });
+        buffer
+    }
+
+    // Queue an event, applying the overflow policy. Returns false once the
+    // client has been disconnected.
+    fn push(&self, event: PoolEvent) -> bool {
+        if self.closed.load(Ordering::Relaxed) {
+            return false;
+        }
+        {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.len() >= self.capacity {
+                match self.policy {
+                    OverflowPolicy::DropOldest => {
+                        queue.pop_front();
+                        self.record_dropped(1);
+                    }
+                    OverflowPolicy::Coalesce => {
+                        // A new price update replaces the queued one for its pool,
+                        // anything else displaces the oldest queued price update
+                        let superseded = queue.iter().position(|queued| {
+                            queued.kind == EventKind::Snapshot
+                                && (event.kind != EventKind::Snapshot || queued.pool == event.pool)
+                        });
+                        match superseded {
+                            Some(index) => {
+                                queue.remove(index);
+                                self.state.stream_metrics.coalesced.fetch_add(1, Ordering::Relaxed);
+                            }
+                            None => {
+                                queue.pop_front();
+                                self.record_dropped(1);
+                            }
+                        }
+                    }
+                    OverflowPolicy::Disconnect => {
+                        drop(queue);
+                        self.state.stream_metrics.disconnected.fetch_add(1, Ordering::Relaxed);
+                        self.close();
+                        return false;
+                    }
+                }
+            }
+            queue.push_back(event);
+        }
+        self.ready.notify_one();
+        true
+    }
+
+    fn record_dropped(&self, count: u64) {
+        self.dropped_since_read.fetch_add(count, Ordering::Relaxed);
+        self.state.stream_metrics.dropped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.ready.notify_one();
+    }
+
+    // Next item for the client, None once the stream should end.
+    pub async fn next(&self) -> Option<StreamItem> {
+        loop {
+            if self.closed.load(Ordering::Relaxed) {
+                return None;
+            }
+            let dropped = self.dropped_since_read.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                return Some(StreamItem::Dropped(dropped));
+            }
+            if let Some(event) = self.queue.lock().unwrap().pop_front() {
+                return Some(StreamItem::Event(event));
+            }
+            self.ready.notified().await;
+        }
+    }
+}
+
+impl Drop for ClientBuffer {
+    fn drop(&mut self) {
+        self.state.stream_metrics.clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Price updates of one delta-encoded client, each sent as a JSON merge patch
+// (RFC 7386) against the last one sent for its pool. A full snapshot goes
+// out first and then every `keyframe_interval` updates, so a client that
+// lost track resyncs without reconnecting.
+pub struct DeltaEncoder {
+    keyframe_interval: u64,
+    // Last snapshot sent per pool and the patches sent since it.
+    sent: HashMap<String, (Value, u64)>,
+}
+
+impl DeltaEncoder {
+    pub fn new(keyframe_interval: u64) -> Self {
+        DeltaEncoder {
+            keyframe_interval,
+            sent: HashMap::new(),
+        }
+    }
+
+    // Whether `data` goes out as a keyframe, and what to send for it.
+    pub fn encode(&mut self, pool: &str, data: &Value) -> (bool, Value) {
+        match self.sent.get_mut(pool) {
+            Some((last, patches)) if *patches < self.keyframe_interval => {
+                let patch = merge_patch(last, data);
+                *last = data.clone();
+                *patches += 1;
+                (false, patch)
+            }
+            _ => {
+                self.sent.insert(pool.to_string(), (data.clone(), 0));
+                (true, data.clone())
+            }
+        }
+    }
+
+    // Start every pool over with a keyframe.
+    pub fn reset(&mut self) {
+        self.sent.clear();
+    }
+}
+
+// Merge patch turning `old` into `new`. Removed keys and fields that became
+// null are both sent as null, which the client drops.
+fn merge_patch(old: &Value, new: &Value) -> Value {
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return new.clone();
+    };
+    let mut patch = Map::new();
+    for (key, value) in new {
+        match old.get(key) {
+            Some(previous) if previous == value => {}
+            Some(previous) if previous.is_object() && value.is_object() => {
+                patch.insert(key.clone(), merge_patch(previous, value));
+            }
+            _ => {
+                patch.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    for key in old.keys() {
+        if !new.contains_key(key) {
+            patch.insert(key.clone(), Value::Null);
+        }
+    }
+    Value::Object(patch)
+}
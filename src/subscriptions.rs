@@ -0,0 +1,567 @@
+use crate::config::HttpClientConfig;
+use crate::events::{EventKind, PoolEvent};
+use crate::images;
+use crate::state::{random_hex, unix_now, AppState};
+use crate::trail;
+use crate::upstream;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+// Delivery records kept per subscription for the status endpoint.
+const MAX_DELIVERY_RECORDS: usize = 200;
+
+// Dead letters kept across all subscriptions, the oldest go first. Bounds
+// memory and the file rewritten on every change while a receiver is down.
+const MAX_DEAD_LETTERS: usize = 1000;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: String,
+    pub url: String,
+    // Empty means every pool / every event kind.
+    pub pools: Vec<String>,
+    pub events: Vec<EventKind>,
+    #[serde(skip)]
+    pub secret: String,
+    pub created_at: u64,
+    pub delivered: u64,
+    pub failed: u64,
+}
+
+impl Subscription {
+    fn matches(&self, event: &PoolEvent) -> bool {
+        (self.pools.is_empty() || self.pools.contains(&event.pool))
+            && (self.events.is_empty() || self.events.contains(&event.kind))
+    }
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Retrying,
+    Delivered,
+    Failed,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Delivery {
+    pub id: u64,
+    pub event: PoolEvent,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub last_attempt_at: Option<u64>,
+    pub next_attempt_at: Option<u64>,
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+// A delivery that used up all its attempts, kept until it is redelivered.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub delivery_id: u64,
+    pub subscription_id: String,
+    pub event: PoolEvent,
+    pub attempts: u32,
+    pub failed_at: u64,
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Subscriptions {
+    subscriptions: RwLock<HashMap<String, Subscription>>,
+    deliveries: Mutex<HashMap<String, VecDeque<Delivery>>>,
+    dead_letters: Mutex<Vec<DeadLetter>>,
+    // Bumped on every change to the dead letters, and the last one written
+    // to `dead_letter_file`, so a slow write never replaces a newer one.
+    dead_letters_version: AtomicU64,
+    dead_letters_written: Arc<Mutex<u64>>,
+    next_delivery_id: AtomicU64,
+}
+
+impl Subscriptions {
+    // Replace the subscriptions with the ones storage has.
+    pub fn load(&self, subscriptions: Vec<Subscription>) {
+        *self.subscriptions.write().unwrap() =
+            subscriptions.into_iter().map(|subscription| (subscription.id.clone(), subscription)).collect();
+    }
+
+    fn get(&self, id: &str) -> Option<Subscription> {
+        self.subscriptions.read().unwrap().get(id).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Subscription> {
+        self.subscriptions.read().unwrap().values().cloned().collect()
+    }
+
+    // Add the subscription, or replace the one with its id.
+    fn insert(&self, subscription: Subscription) {
+        self.subscriptions.write().unwrap().insert(subscription.id.clone(), subscription);
+    }
+
+    fn update_delivery(&self, subscription_id: &str, delivery: &Delivery) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let records = deliveries.entry(subscription_id.to_string()).or_default();
+        match records.iter_mut().find(|d| d.id == delivery.id) {
+            Some(existing) => *existing = delivery.clone(),
+            None => {
+                records.push_back(delivery.clone());
+                if records.len() > MAX_DELIVERY_RECORDS {
+                    records.pop_front();
+                }
+            }
+        }
+    }
+
+    // Load dead letters saved by a previous run.
+    pub fn load_dead_letters(&self, path: &Path) -> Result<(), String> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut letters: Vec<DeadLetter> = serde_json::from_str(&raw)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        // Keep delivery ids unique across restarts
+        let next_id = letters.iter().map(|l| l.delivery_id + 1).max().unwrap_or(0);
+        self.next_delivery_id.fetch_max(next_id, Ordering::Relaxed);
+        letters.drain(..letters.len().saturating_sub(MAX_DEAD_LETTERS));
+        println!("Loaded {} dead letters from {}", letters.len(), path.display());
+        *self.dead_letters.lock().unwrap() = letters;
+        Ok(())
+    }
+
+    fn save_dead_letters(&self, state: &AppState) {
+        let Some(path) = state.config().subscriptions.dead_letter_file else {
+            return;
+        };
+        let (version, letters) = {
+            let letters = self.dead_letters.lock().unwrap();
+            (self.dead_letters_version.fetch_add(1, Ordering::Relaxed) + 1, letters.clone())
+        };
+        let written = self.dead_letters_written.clone();
+        // Off the async workers, the file can be large
+        tokio::task::spawn_blocking(move || {
+            let mut written = written.lock().unwrap();
+            if *written > version {
+                return;
+            }
+            let result = serde_json::to_vec_pretty(&letters)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    // Write then rename so a crash never leaves a truncated file
+                    let tmp = path.with_extension("tmp");
+                    std::fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+                    std::fs::rename(&tmp, &path).map_err(|e| e.to_string())
+                });
+            match result {
+                Ok(()) => *written = version,
+                Err(e) => eprintln!("Failed to save dead letters to {}: {}", path.display(), e),
+            }
+        });
+    }
+
+    fn add_dead_letter(&self, state: &AppState, letter: DeadLetter) {
+        {
+            let mut letters = self.dead_letters.lock().unwrap();
+            letters.push(letter);
+            if letters.len() > MAX_DEAD_LETTERS {
+                let dropped = letters.remove(0);
+                eprintln!(
+                    "Over {} dead letters, dropping delivery {} to subscription {}",
+                    MAX_DEAD_LETTERS, dropped.delivery_id, dropped.subscription_id
+                );
+            }
+        }
+        self.save_dead_letters(state);
+    }
+
+    // Remove and return dead letters for a subscription, optionally only the
+    // given delivery ids.
+    fn take_dead_letters(&self, state: &AppState, subscription_id: &str, ids: Option<&[u64]>) -> Vec<DeadLetter> {
+        let taken: Vec<DeadLetter> = {
+            let mut letters = self.dead_letters.lock().unwrap();
+            let (taken, kept) = letters.drain(..).partition(|l| {
+                l.subscription_id == subscription_id && ids.is_none_or(|ids| ids.contains(&l.delivery_id))
+            });
+            *letters = kept;
+            taken
+        };
+        if !taken.is_empty() {
+            self.save_dead_letters(state);
+        }
+        taken
+    }
+
+    fn count_outcome(&self, subscription_id: &str, delivered: bool) {
+        if let Some(subscription) = self.subscriptions.write().unwrap().get_mut(subscription_id) {
+            if delivered {
+                subscription.delivered += 1;
+            } else {
+                subscription.failed += 1;
+            }
+        }
+    }
+}
+
+// Forward every published pool event to the subscriptions that want it.
+pub async fn run(state: Arc<AppState>) {
+    let mut events = state.events.subscribe();
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                eprintln!("Subscription dispatcher fell behind, {} events not delivered", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let matching: Vec<String> = state
+            .subscriptions
+            .subscriptions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|s| s.matches(&event))
+            .map(|s| s.id.clone())
+            .collect();
+        for subscription_id in matching {
+            let delivery_id = state.subscriptions.next_delivery_id.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(deliver(state.clone(), subscription_id, delivery_id, event.clone()));
+        }
+    }
+}
+
+// POST one event, retrying with exponential backoff until it is accepted or
+// the attempt budget runs out.
+async fn deliver(state: Arc<AppState>, subscription_id: String, delivery_id: u64, event: PoolEvent) {
+    let mut delivery = Delivery {
+        id: delivery_id,
+        event,
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        last_attempt_at: None,
+        next_attempt_at: None,
+        response_status: None,
+        last_error: None,
+    };
+    let http_config = state.config.read().unwrap().http_client.clone();
+    let client = match http_client(&http_config) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Can't deliver to subscription {}: {}", subscription_id, e);
+            return;
+        }
+    };
+
+    loop {
+        let config = state.config().subscriptions;
+        // Stop quietly if the subscription was deleted while retrying
+        let Some(subscription) = state.subscriptions.get(&subscription_id) else {
+            return;
+        };
+
+        delivery.attempts += 1;
+        delivery.last_attempt_at = Some(unix_now());
+        let result = post_signed(&client, &subscription, &delivery.event, config.timeout_secs).await;
+        match result {
+            Ok(status) => {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.response_status = Some(status);
+                delivery.next_attempt_at = None;
+                delivery.last_error = None;
+            }
+            Err((status, e)) => {
+                delivery.response_status = status;
+                delivery.last_error = Some(e);
+                if delivery.attempts >= config.max_attempts {
+                    delivery.status = DeliveryStatus::Failed;
+                    delivery.next_attempt_at = None;
+                } else {
+                    delivery.status = DeliveryStatus::Retrying;
+                    let backoff = config.retry_base_secs.saturating_mul(1 << (delivery.attempts - 1).min(16));
+                    delivery.next_attempt_at = Some(unix_now() + backoff);
+                }
+            }
+        }
+        state.subscriptions.update_delivery(&subscription_id, &delivery);
+
+        match delivery.status {
+            DeliveryStatus::Delivered => {
+                state.subscriptions.count_outcome(&subscription_id, true);
+                return;
+            }
+            DeliveryStatus::Failed => {
+                eprintln!(
+                    "Giving up on delivery {} to subscription {} after {} attempts",
+                    delivery.id, subscription_id, delivery.attempts
+                );
+                state.subscriptions.count_outcome(&subscription_id, false);
+                state.subscriptions.add_dead_letter(
+                    &state,
+                    DeadLetter {
+                        delivery_id: delivery.id,
+                        subscription_id: subscription_id.clone(),
+                        event: delivery.event,
+                        attempts: delivery.attempts,
+                        failed_at: unix_now(),
+                        response_status: delivery.response_status,
+                        last_error: delivery.last_error,
+                    },
+                );
+                return;
+            }
+            _ => {
+                let wait = delivery.next_attempt_at.unwrap_or(0).saturating_sub(unix_now());
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+            }
+        }
+    }
+}
+
+// The URL was checked to be public when the subscription was made, and so
+// are the ones it redirects to. Names are checked again once resolved, for
+// each connection, so a receiver can't move its name to a private address.
+fn http_client(http_config: &HttpClientConfig) -> Result<reqwest::Client, String> {
+    upstream::builder(http_config)?
+        .redirect(images::redirect_policy(Vec::new()))
+        .dns_resolver(images::PublicResolver::new(Vec::new()))
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+// The signature covers "<timestamp>.<body>" so receivers can reject replays.
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+async fn post_signed(
+    client: &reqwest::Client,
+    subscription: &Subscription,
+    event: &PoolEvent,
+    timeout_secs: u64,
+) -> Result<u16, (Option<u16>, String)> {
+    let body = serde_json::to_vec(event).map_err(|e| (None, e.to_string()))?;
+    let timestamp = unix_now();
+    let response = client
+        .post(&subscription.url)
+        .timeout(Duration::from_secs(timeout_secs))
+        .header("Content-Type", "application/json")
+        .header("X-Pool-Monitor-Subscription", &subscription.id)
+        .header("X-Pool-Monitor-Timestamp", timestamp.to_string())
+        .header(
+            "X-Pool-Monitor-Signature",
+            format!("sha256={}", sign(&subscription.secret, timestamp, &body)),
+        )
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16())
+    } else {
+        Err((Some(status.as_u16()), format!("Endpoint returned {}", status)))
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateSubscription {
+    url: String,
+    #[serde(default)]
+    pools: Vec<String>,
+    #[serde(default)]
+    events: Vec<EventKind>,
+}
+
+// Checks on a new subscription, also run on the ones `POST /admin/import`
+// brings in.
+pub fn validate(url: &str, pools: &[String]) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid url: {}", e))?;
+    if parsed.scheme() != "https" && parsed.scheme() != "http" {
+        return Err("url must be an http(s) URL".to_string());
+    }
+    if !images::is_public(&parsed) {
+        return Err(format!("{} is not a public address", parsed.host_str().unwrap_or(url)));
+    }
+    for pool in pools {
+        Pubkey::from_str(pool).map_err(|e| format!("Invalid pool ID {}: {}", pool, e))?;
+    }
+    Ok(())
+}
+
+// Store the subscription and start delivering to it.
+pub async fn save(state: &AppState, subscription: Subscription) -> Result<(), String> {
+    let saved = subscription.clone();
+    state.with_store(move |store| store.save_subscription(&saved)).await?;
+    state.subscriptions.insert(subscription);
+    Ok(())
+}
+
+#[post("/subscriptions")]
+async fn create_subscription(
+    state: web::Data<AppState>,
+    body: web::Json<CreateSubscription>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let body = body.into_inner();
+    if let Err(e) = validate(&body.url, &body.pools) {
+        return HttpResponse::BadRequest().json(json!({ "error": e }));
+    }
+
+    let subscription = Subscription {
+        id: random_hex(8),
+        url: body.url,
+        pools: body.pools,
+        events: body.events,
+        secret: random_hex(32),
+        created_at: unix_now(),
+        delivered: 0,
+        failed: 0,
+    };
+    if let Err(e) = save(&state, subscription.clone()).await {
+        return HttpResponse::InternalServerError().json(json!({ "error": e }));
+    }
+    trail::changed(&req, format!("subscriptions/{}", subscription.id), Value::Null, &subscription);
+
+    // The secret is only ever returned here
+    let mut response = json!(subscription);
+    response["secret"] = json!(subscription.secret);
+    HttpResponse::Created().json(response)
+}
+
+#[get("/subscriptions")]
+async fn list_subscriptions(state: web::Data<AppState>) -> HttpResponse {
+    let subscriptions = state.subscriptions.all();
+    HttpResponse::Ok().json(json!({
+        "subscriptions": subscriptions
+    }))
+}
+
+#[get("/subscriptions/{id}")]
+async fn get_subscription(state: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    match state.subscriptions.get(&id) {
+        Some(subscription) => HttpResponse::Ok().json(subscription),
+        None => not_found(&id),
+    }
+}
+
+#[delete("/subscriptions/{id}")]
+async fn delete_subscription(state: web::Data<AppState>, id: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    let deleted = id.to_string();
+    match state.with_store(move |store| store.delete_subscription(&deleted)).await {
+        Ok(true) => {}
+        Ok(false) => return not_found(&id),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+    let removed = state.subscriptions.subscriptions.write().unwrap().remove(id.as_str());
+    trail::changed(&req, format!("subscriptions/{}", id), removed, Value::Null);
+    state.subscriptions.deliveries.lock().unwrap().remove(id.as_str());
+    state.subscriptions.take_dead_letters(&state, &id, None);
+    HttpResponse::Ok().json(json!({
+        "deleted": id.to_string()
+    }))
+}
+
+#[get("/subscriptions/{id}/deliveries")]
+async fn get_deliveries(state: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    if state.subscriptions.get(&id).is_none() {
+        return not_found(&id);
+    }
+    let deliveries: Vec<Delivery> = state
+        .subscriptions
+        .deliveries
+        .lock()
+        .unwrap()
+        .get(id.as_str())
+        .map(|records| records.iter().rev().cloned().collect())
+        .unwrap_or_default();
+    HttpResponse::Ok().json(json!({
+        "subscription_id": id.to_string(),
+        "deliveries": deliveries,
+    }))
+}
+
+#[get("/subscriptions/{id}/dead-letters")]
+async fn get_dead_letters(state: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    if state.subscriptions.get(&id).is_none() {
+        return not_found(&id);
+    }
+    let letters: Vec<DeadLetter> = state
+        .subscriptions
+        .dead_letters
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|l| l.subscription_id == *id)
+        .cloned()
+        .collect();
+    HttpResponse::Ok().json(json!({
+        "subscription_id": id.to_string(),
+        "dead_letters": letters,
+    }))
+}
+
+#[derive(Deserialize, Default)]
+struct RedeliverRequest {
+    // Only these delivery ids, every dead letter when omitted.
+    ids: Option<Vec<u64>>,
+}
+
+#[post("/subscriptions/{id}/dead-letters/redeliver")]
+async fn redeliver_dead_letters(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    body: Option<web::Json<RedeliverRequest>>,
+) -> HttpResponse {
+    if state.subscriptions.get(&id).is_none() {
+        return not_found(&id);
+    }
+    let request = body.map(|b| b.into_inner()).unwrap_or_default();
+    let letters = state.subscriptions.take_dead_letters(&state, &id, request.ids.as_deref());
+
+    // Each one gets a fresh attempt budget and lands back here if it fails again
+    let redelivered: Vec<u64> = letters.iter().map(|l| l.delivery_id).collect();
+    for letter in letters {
+        tokio::spawn(deliver(state.clone().into_inner(), letter.subscription_id, letter.delivery_id, letter.event));
+    }
+    HttpResponse::Accepted().json(json!({
+        "subscription_id": id.to_string(),
+        "redelivered": redelivered,
+    }))
+}
+
+fn not_found(id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "error": format!("Subscription {} not found", id)
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_subscription)
+        .service(list_subscriptions)
+        .service(get_subscription)
+        .service(delete_subscription)
+        .service(get_deliveries)
+        .service(get_dead_letters)
+        .service(redeliver_dead_letters);
+}
@@ -0,0 +1,361 @@
+use crate::config::{RestartPolicy, TaskPolicy};
+use crate::state::{unix_now, AppState};
+use actix_web::{get, post, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::panic::PanicHookInfo;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+// A task that ran this long before panicking restarts after the shortest
+// backoff again.
+const HEALTHY_AFTER: Duration = Duration::from_secs(600);
+
+tokio::task_local! {
+    // The supervised task being polled, for the panic hook.
+    static TASK: Arc<Context>;
+}
+
+struct Context {
+    name: String,
+    // Message and location of the last panic, filled in by the hook since
+    // the payload the supervisor gets back carries no location.
+    panic: Mutex<Option<String>>,
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Running,
+    // Waiting out its backoff after panicking or, with the always policy,
+    // returning.
+    Restarting,
+    // Returned, which the background loops only do when they have nothing
+    // to do with the current config.
+    Finished,
+    // Stopped by an operator.
+    Stopped,
+    // Panicked with the never policy or past max_restarts, left down until
+    // an operator starts it.
+    Failed,
+}
+
+#[derive(Clone, Serialize)]
+struct TaskHealth {
+    subsystem: &'static str,
+    status: Status,
+    restart: RestartPolicy,
+    started_at: u64,
+    restarts: u64,
+    last_panic: Option<String>,
+    last_panic_at: Option<u64>,
+    restart_at: Option<u64>,
+    #[serde(skip)]
+    commands: UnboundedSender<Command>,
+    // Tells the supervisor of a task apart from an earlier one of the same
+    // name, like the pubsub connections started again with pubsub.
+    #[serde(skip)]
+    generation: u64,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Command {
+    Stop,
+    Start,
+    Restart,
+}
+
+// Health of every supervised background task, by name.
+#[derive(Default)]
+pub struct Tasks {
+    health: Mutex<BTreeMap<String, TaskHealth>>,
+    generations: AtomicU64,
+}
+
+impl Tasks {
+    // Replaces a task of the same name, whose supervisor then exits.
+    fn register(&self, name: &str, subsystem: &'static str, restart: RestartPolicy, commands: UnboundedSender<Command>) -> u64 {
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        self.health.lock().unwrap().insert(
+            name.to_string(),
+            TaskHealth {
+                subsystem,
+                status: Status::Running,
+                restart,
+                started_at: unix_now(),
+                restarts: 0,
+                last_panic: None,
+                last_panic_at: None,
+                restart_at: None,
+                commands,
+                generation,
+            },
+        );
+        generation
+    }
+
+    fn update(&self, name: &str, generation: u64, update: impl FnOnce(&mut TaskHealth)) {
+        if let Some(task) = self.health.lock().unwrap().get_mut(name).filter(|task| task.generation == generation) {
+            update(task);
+        }
+    }
+
+    // Running state and restarts of every task, for the metrics.
+    pub fn summary(&self) -> Vec<(String, &'static str, bool, u64)> {
+        self.health
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, task)| (name.clone(), task.subsystem, task.status == Status::Running, task.restarts))
+            .collect()
+    }
+}
+
+// Log panics of supervised tasks on one line with the task, location and,
+// with RUST_BACKTRACE set, a backtrace. Other panics keep the default
+// output.
+pub fn install_panic_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info: &PanicHookInfo| {
+        let Ok(task) = TASK.try_with(|task| task.clone()) else {
+            return default(info);
+        };
+        let location = info.location().map(|l| format!(" at {}:{}", l.file(), l.line())).unwrap_or_default();
+        let panic = format!("{}{}", message(info.payload()), location);
+        eprintln!("Task {} panicked{}: {}", task.name, location, message(info.payload()));
+        let backtrace = Backtrace::capture();
+        if backtrace.status() == BacktraceStatus::Captured {
+            eprintln!("{}", backtrace);
+        }
+        *task.panic.lock().unwrap() = Some(panic);
+    }));
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string())
+}
+
+// Panic message of a caught panic, for callers that carry on, like the
+// poller after one pool's poll panicked.
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    message(payload.as_ref())
+}
+
+// What ended a run of a task.
+enum Exit {
+    Returned,
+    Panicked(String),
+    Command(Command),
+}
+
+// Run a background task of a subsystem, restarting it as its policy in
+// `tasks` says, with a growing backoff, so one bad account can't silently
+// stop ingestion. Operators stop and start it through the admin API.
+pub fn supervise<F, Fut>(state: Arc<AppState>, subsystem: &'static str, name: impl Into<String>, task: F)
+where
+    F: Fn(Arc<AppState>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let name = name.into();
+    let (sender, mut commands) = mpsc::unbounded_channel();
+    let restart = state.config.read().unwrap().tasks.policy(&name, subsystem).restart;
+    let generation = state.tasks.register(&name, subsystem, restart, sender);
+    tokio::spawn(async move {
+        let mut backoff = MIN_BACKOFF;
+        // Times of the restarts within the policy's window.
+        let mut restarts: VecDeque<Instant> = VecDeque::new();
+        loop {
+            state.tasks.update(&name, generation, |task| {
+                task.status = Status::Running;
+                task.started_at = unix_now();
+                task.restart_at = None;
+            });
+            let started = Instant::now();
+            let context = Arc::new(Context {
+                name: name.clone(),
+                panic: Mutex::new(None),
+            });
+            let mut handle = tokio::spawn(TASK.scope(context.clone(), task(state.clone())));
+            let exit = loop {
+                tokio::select! {
+                    result = &mut handle => break match result {
+                        Ok(()) => Exit::Returned,
+                        Err(e) if e.is_panic() => Exit::Panicked(
+                            context.panic.lock().unwrap().take().unwrap_or_else(|| panic_message(e.into_panic())),
+                        ),
+                        // Cancelled, only happens when the runtime shuts down
+                        Err(_) => return,
+                    },
+                    command = commands.recv() => match command {
+                        Some(Command::Start) => continue,
+                        Some(command) => {
+                            handle.abort();
+                            break Exit::Command(command);
+                        }
+                        // Replaced by a task of the same name
+                        None => {
+                            handle.abort();
+                            return;
+                        }
+                    },
+                }
+            };
+
+            let policy = state.config.read().unwrap().tasks.policy(&name, subsystem).clone();
+            state.tasks.update(&name, generation, |task| task.restart = policy.restart);
+            let (status, panic) = match exit {
+                Exit::Command(Command::Stop) => {
+                    println!("Task {} stopped", name);
+                    (Status::Stopped, None)
+                }
+                Exit::Command(_) => {
+                    println!("Task {} restarted", name);
+                    backoff = MIN_BACKOFF;
+                    restarts.clear();
+                    continue;
+                }
+                Exit::Returned if policy.restart != RestartPolicy::Always => {
+                    println!("Task {} finished", name);
+                    (Status::Finished, None)
+                }
+                Exit::Panicked(panic) if policy.restart == RestartPolicy::Never => {
+                    eprintln!("Task {} failed, its restart policy is never", name);
+                    (Status::Failed, Some(panic))
+                }
+                Exit::Returned | Exit::Panicked(_) if exceeded(&mut restarts, &policy) => {
+                    eprintln!(
+                        "Task {} restarted {} times in {}s, giving up",
+                        name, policy.max_restarts, policy.window_secs
+                    );
+                    let panic = match exit {
+                        Exit::Panicked(panic) => Some(panic),
+                        _ => None,
+                    };
+                    (Status::Failed, panic)
+                }
+                Exit::Returned | Exit::Panicked(_) => {
+                    if started.elapsed() >= HEALTHY_AFTER {
+                        backoff = MIN_BACKOFF;
+                    }
+                    eprintln!("Restarting task {} in {}s", name, backoff.as_secs());
+                    restarts.push_back(Instant::now());
+                    state.tasks.update(&name, generation, |task| {
+                        let now = unix_now();
+                        task.status = Status::Restarting;
+                        task.restarts += 1;
+                        task.restart_at = Some(now + backoff.as_secs());
+                        if let Exit::Panicked(panic) = &exit {
+                            task.last_panic = Some(panic.clone());
+                            task.last_panic_at = Some(now);
+                        }
+                    });
+                    let delay = backoff;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    // Commands aren't held back by the backoff
+                    let stopped = tokio::select! {
+                        _ = tokio::time::sleep(delay) => false,
+                        command = commands.recv() => match command {
+                            Some(Command::Stop) => true,
+                            Some(_) => {
+                                backoff = MIN_BACKOFF;
+                                restarts.clear();
+                                false
+                            }
+                            None => return,
+                        },
+                    };
+                    if !stopped {
+                        continue;
+                    }
+                    println!("Task {} stopped", name);
+                    (Status::Stopped, None)
+                }
+            };
+
+            state.tasks.update(&name, generation, |task| {
+                task.status = status;
+                task.restart_at = None;
+                if let Some(panic) = panic {
+                    task.last_panic = Some(panic);
+                    task.last_panic_at = Some(unix_now());
+                }
+            });
+            // Down until an operator starts it again
+            loop {
+                match commands.recv().await {
+                    Some(Command::Stop) => continue,
+                    Some(_) => break,
+                    None => return,
+                }
+            }
+            println!("Task {} started", name);
+            backoff = MIN_BACKOFF;
+            restarts.clear();
+        }
+    });
+}
+
+// Whether another restart would go past the policy's max_restarts.
+fn exceeded(restarts: &mut VecDeque<Instant>, policy: &TaskPolicy) -> bool {
+    let window = Duration::from_secs(policy.window_secs);
+    while restarts.front().is_some_and(|restart| restart.elapsed() >= window) {
+        restarts.pop_front();
+    }
+    policy.max_restarts > 0 && restarts.len() >= policy.max_restarts
+}
+
+// Every supervised task with its subsystem, status, restarts and last panic.
+#[get("/tasks")]
+async fn get_tasks(state: web::Data<AppState>) -> HttpResponse {
+    let health = state.tasks.health.lock().unwrap().clone();
+    let unhealthy = health.values().filter(|task| matches!(task.status, Status::Restarting | Status::Failed)).count();
+    HttpResponse::Ok().json(json!({
+        "unhealthy": unhealthy,
+        "tasks": health,
+    }))
+}
+
+// Stop, start or restart a task, or every task of a subsystem by its name.
+// Starting brings back a stopped, failed or finished task and resets its
+// backoff, restarting also a running one.
+#[post("/tasks/{name}/{command}")]
+async fn control_task(state: web::Data<AppState>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (name, action) = path.into_inner();
+    let Ok(command) = serde_json::from_value::<Command>(Value::String(action.clone())) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("Unknown command {}, use stop, start or restart", action)
+        }));
+    };
+    let health = state.tasks.health.lock().unwrap();
+    let tasks: Vec<&String> = health
+        .iter()
+        .filter(|(task, health)| **task == name || health.subsystem == name)
+        .map(|(task, health)| {
+            let _ = health.commands.send(command);
+            task
+        })
+        .collect();
+    if tasks.is_empty() {
+        return HttpResponse::NotFound().json(json!({
+            "error": format!("No task or subsystem named {}", name)
+        }));
+    }
+    println!("{} of {} requested via admin API", action, name);
+    HttpResponse::Accepted().json(json!({
+        "command": command,
+        "tasks": tasks,
+    }))
+}
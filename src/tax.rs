@@ -0,0 +1,320 @@
+use crate::address::ValidatedPubkey;
+use crate::amount::Amount;
+use crate::blocklist;
+use crate::pricing;
+use crate::state::{unix_now, AppState};
+use crate::time::{civil, format_utc, year_start};
+use crate::token::{self, NATIVE_MINT};
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_client::client_error::ClientError;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
+use tokio::task::JoinError;
+
+// Transactions fetched per export. Each costs one RPC call, wallets beyond
+// this get a truncated report.
+const MAX_TRANSACTIONS: usize = 1000;
+const SIGNATURE_PAGE: usize = 1000;
+// SOL movements below this alongside token changes are rent for token
+// accounts opened or closed by the swap, not a side of it.
+const RENT_NOISE_LAMPORTS: i128 = 10_000_000;
+const SOL_DECIMALS: u8 = 9;
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+#[derive(Deserialize)]
+struct TaxQuery {
+    year: i32,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Serialize)]
+struct Swap {
+    signature: String,
+    timestamp: u64,
+    sent_mint: String,
+    sent_currency: String,
+    sent_amount: Amount,
+    received_mint: String,
+    received_currency: String,
+    received_amount: Amount,
+    fee_sol: Amount,
+    // Value at execution from recorded pool history, None when neither side
+    // has a USDC pool in history at that time, which is always the case
+    // before `history_retention_secs` ago.
+    usd_value: Option<f64>,
+    // Not part of the CSV export.
+    blocklisted: Vec<Value>,
+}
+
+// Every swap a wallet made in a calendar year (UTC), with USD values at
+// execution time. CSV follows the Koinly universal import layout, which most
+// tax tools accept. Prices come from the recorded pool history, which only
+// reaches back `history_retention_secs`: older swaps have no USD value, and
+// are counted in `unpriced` (the X-Export-Unpriced header of a CSV) so the
+// gap isn't mistaken for zero-value trades.
+#[get("/wallet/{address}/tax-export")]
+async fn get_tax_export(
+    state: web::Data<AppState>,
+    address: web::Path<ValidatedPubkey>,
+    query: web::Query<TaxQuery>,
+) -> HttpResponse {
+    let wallet = address.0;
+    let now = unix_now();
+    if query.year < 2020 || i64::from(query.year) > civil(now).0 {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("No Solana history for {}", query.year)
+        }));
+    }
+    let (start, end) = (year_start(query.year), year_start(query.year + 1));
+    let priced_since = now.saturating_sub(state.config.read().unwrap().history_retention_secs);
+
+    // The newest signatures are on every node, older pages and the
+    // transactions themselves may only be on archival ones. Each client is
+    // let go before the next is taken so an export never holds two upstream
+    // slots.
+    let rpc_client = state.rpc_client().await;
+    let result = tokio::task::spawn_blocking(move || {
+        let mut collected = Collected::default();
+        let before = collected.page(&rpc_client, &wallet, None, (start, end))?;
+        Ok((collected, before))
+    })
+    .await;
+    let (mut collected, mut before) = match fetched(result) {
+        Ok(fetched) => fetched,
+        Err(response) => return response,
+    };
+
+    let archival_client = state.history_rpc_client().await;
+    let result = tokio::task::spawn_blocking(move || {
+        while before.is_some() {
+            before = collected.page(&archival_client, &wallet, before, (start, end))?;
+        }
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(archival_client.commitment()),
+            max_supported_transaction_version: Some(0),
+        };
+        let mut transactions = Vec::new();
+        for signature in collected.signatures {
+            transactions.push((signature, archival_client.get_transaction_with_config(&signature, config)?));
+        }
+        Ok((transactions, collected.truncated))
+    })
+    .await;
+    let (transactions, truncated) = match fetched(result) {
+        Ok(fetched) => fetched,
+        Err(response) => return response,
+    };
+
+    // Oldest first, as tax tools expect
+    let mut swaps: Vec<Swap> = transactions
+        .iter()
+        .rev()
+        .filter_map(|(signature, tx)| swap(&state, &wallet, signature, tx))
+        .collect();
+    swaps.sort_by_key(|swap| swap.timestamp);
+    let unpriced = swaps.iter().filter(|swap| swap.usd_value.is_none()).count();
+
+    match query.format {
+        ExportFormat::Json => HttpResponse::Ok().json(json!({
+            "wallet": wallet.to_string(),
+            "year": query.year,
+            "truncated": truncated,
+            "unpriced": unpriced,
+            "priced_since": priced_since,
+            "blocklisted": blocklist::flags(&state, &[("wallet", wallet)]),
+            "swaps": swaps,
+        })),
+        ExportFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}-{}.csv\"", wallet, query.year),
+            ))
+            .insert_header(("X-Export-Truncated", truncated.to_string()))
+            .insert_header(("X-Export-Unpriced", unpriced.to_string()))
+            .body(csv(&swaps)),
+    }
+}
+
+// Signatures of the wallet's successful transactions within the export's
+// year, newest first.
+#[derive(Default)]
+struct Collected {
+    signatures: Vec<Signature>,
+    truncated: bool,
+}
+
+impl Collected {
+    // Add the page of signatures before `before`, returning where the next
+    // page starts or None once the year starts, the history ends or
+    // MAX_TRANSACTIONS is reached.
+    fn page(
+        &mut self,
+        client: &RpcClient,
+        wallet: &Pubkey,
+        before: Option<Signature>,
+        (start, end): (u64, u64),
+    ) -> Result<Option<Signature>, ClientError> {
+        let page = client.get_signatures_for_address_with_config(
+            wallet,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                limit: Some(SIGNATURE_PAGE),
+                ..GetConfirmedSignaturesForAddress2Config::default()
+            },
+        )?;
+        let next = page.last().and_then(|last| Signature::from_str(&last.signature).ok());
+        let exhausted = page.len() < SIGNATURE_PAGE;
+        for entry in page {
+            let Some(block_time) = entry.block_time.map(|t| t as u64) else {
+                continue;
+            };
+            if block_time < start {
+                return Ok(None);
+            }
+            if block_time >= end || entry.err.is_some() {
+                continue;
+            }
+            if self.signatures.len() == MAX_TRANSACTIONS {
+                self.truncated = true;
+                return Ok(None);
+            }
+            self.signatures.extend(Signature::from_str(&entry.signature).ok());
+        }
+        Ok(next.filter(|_| !exhausted))
+    }
+}
+
+// The result of a blocking RPC task, or the error response.
+fn fetched<T>(result: Result<Result<T, ClientError>, JoinError>) -> Result<T, HttpResponse> {
+    match result {
+        Ok(Ok(fetched)) => Ok(fetched),
+        Ok(Err(e)) => {
+            eprintln!("RPC error fetching wallet history: {}", e);
+            Err(HttpResponse::BadGateway().json(json!({
+                "error": format!("Failed to fetch wallet history: {}", e)
+            })))
+        }
+        Err(e) => Err(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Task failed: {}", e)
+        }))),
+    }
+}
+
+// Treat a transaction as a swap when, after folding SOL into wrapped SOL,
+// the wallet's balance fell in exactly one token and rose in exactly one.
+fn swap(
+    state: &AppState,
+    wallet: &Pubkey,
+    signature: &Signature,
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Option<Swap> {
+    let timestamp = tx.block_time? as u64;
+    let meta = tx.transaction.meta.as_ref()?;
+    let keys = tx.transaction.transaction.decode()?.message.static_account_keys().to_vec();
+
+    // Net raw change per mint across every token account the wallet owns,
+    // with the mint's decimals
+    let mut changes: HashMap<Pubkey, (i128, u8)> = HashMap::new();
+    let owner = wallet.to_string();
+    let mut apply = |balances: Option<Vec<UiTransactionTokenBalance>>, sign: i128| {
+        for balance in balances.into_iter().flatten() {
+            if Option::<String>::from(balance.owner) != Some(owner.clone()) {
+                continue;
+            }
+            let (Ok(mint), Ok(raw)) = (Pubkey::from_str(&balance.mint), balance.ui_token_amount.amount.parse::<i128>()) else {
+                continue;
+            };
+            let change = changes.entry(mint).or_insert((0, balance.ui_token_amount.decimals));
+            change.0 += sign * raw;
+        }
+    };
+    apply(meta.pre_token_balances.clone().into(), -1);
+    apply(meta.post_token_balances.clone().into(), 1);
+
+    let fee_payer = keys.first() == Some(wallet);
+    let fee = if fee_payer { meta.fee } else { 0 };
+    if let Some(index) = keys.iter().position(|key| key == wallet) {
+        let lamports = meta.post_balances.get(index)?.to_owned() as i128 - meta.pre_balances.get(index)?.to_owned() as i128;
+        let sol = lamports + fee as i128;
+        let other_tokens = changes.iter().any(|(mint, (delta, _))| *mint != NATIVE_MINT && *delta != 0);
+        if !(other_tokens && sol.abs() < RENT_NOISE_LAMPORTS) {
+            changes.entry(NATIVE_MINT).or_insert((0, SOL_DECIMALS)).0 += sol;
+        }
+    }
+
+    changes.retain(|_, (delta, _)| *delta != 0);
+    let sent: Vec<_> = changes.iter().filter(|(_, (delta, _))| *delta < 0).collect();
+    let received: Vec<_> = changes.iter().filter(|(_, (delta, _))| *delta > 0).collect();
+    let ([(sent_mint, (sent_delta, sent_decimals))], [(received_mint, (received_delta, received_decimals))]) =
+        (sent.as_slice(), received.as_slice())
+    else {
+        return None;
+    };
+    let sent_amount = Amount::new(sent_delta.unsigned_abs(), *sent_decimals);
+    let received_amount = Amount::new(received_delta.unsigned_abs(), *received_decimals);
+
+    let usd_value = pricing::usd_price_at(state, received_mint, timestamp)
+        .map(|price| price * received_amount.tokens())
+        .or_else(|| pricing::usd_price_at(state, sent_mint, timestamp).map(|price| price * sent_amount.tokens()));
+
+    Some(Swap {
+        signature: signature.to_string(),
+        timestamp,
+        sent_mint: sent_mint.to_string(),
+        sent_currency: currency(sent_mint),
+        sent_amount,
+        received_mint: received_mint.to_string(),
+        received_currency: currency(received_mint),
+        received_amount,
+        fee_sol: Amount::new(fee as u128, SOL_DECIMALS),
+        usd_value,
+        blocklisted: blocklist::flags(state, &[("sent_mint", **sent_mint), ("received_mint", **received_mint)]),
+    })
+}
+
+// Tax tools match well known tickers, anything else by full mint address.
+fn currency(mint: &Pubkey) -> String {
+    token::known_symbol(mint).map(str::to_string).unwrap_or_else(|| mint.to_string())
+}
+
+fn csv(swaps: &[Swap]) -> String {
+    let mut out = String::from(
+        "Date,Sent Amount,Sent Currency,Received Amount,Received Currency,Fee Amount,Fee Currency,\
+         Net Worth Amount,Net Worth Currency,Label,Description,TxHash\n",
+    );
+    for swap in swaps {
+        out.push_str(&format!(
+            "{},{},{},{},{},{},SOL,{},USD,swap,{}/{} swap,{}\n",
+            format_utc(swap.timestamp),
+            swap.sent_amount.value(),
+            swap.sent_currency,
+            swap.received_amount.value(),
+            swap.received_currency,
+            swap.fee_sol.value(),
+            swap.usd_value.map(|v| format!("{:.2}", v)).unwrap_or_default(),
+            token::symbol(&Pubkey::from_str(&swap.sent_mint).unwrap_or_default()),
+            token::symbol(&Pubkey::from_str(&swap.received_mint).unwrap_or_default()),
+            swap.signature,
+        ));
+    }
+    out
+}
@@ -0,0 +1,470 @@
+use crate::alerts::{self, Alert, Severity};
+use crate::config::ReportPeriod;
+use crate::reports;
+use crate::state::{random_hex, unix_now, AppState};
+use crate::time::format_utc;
+use crate::trail;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+const MAX_TEMPLATES: usize = 100;
+const MAX_POOLS: usize = 100;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Price,
+    PriceChange,
+    Volume,
+    Tvl,
+    TvlChange,
+    Alerts,
+    Events,
+}
+
+impl Metric {
+    const ALL: [Metric; 7] = [
+        Metric::Price,
+        Metric::PriceChange,
+        Metric::Volume,
+        Metric::Tvl,
+        Metric::TvlChange,
+        Metric::Alerts,
+        Metric::Events,
+    ];
+
+    // Fields of the pool report the metric is made of.
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            Metric::Price => &["price_open", "price_close"],
+            Metric::PriceChange => &["price_change_pct"],
+            Metric::Volume => &["volume"],
+            Metric::Tvl => &["tvl_open", "tvl_close"],
+            Metric::TvlChange => &["tvl_change_pct"],
+            Metric::Alerts => &["alert_count"],
+            Metric::Events => &["events"],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Html,
+    Csv,
+}
+
+// A user defined report: which pools, which metrics over which period.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReportTemplate {
+    pub id: String,
+    pub name: String,
+    // Heading of rendered reports, e.g. the client they are for.
+    pub title: Option<String>,
+    // The watchlist when empty.
+    pub pools: Vec<String>,
+    // Every metric when empty.
+    pub metrics: Vec<Metric>,
+    pub period: ReportPeriod,
+    // Format scheduled reports are sent in.
+    pub format: ReportFormat,
+    // Sent through `channels` when due at `reports.hour_utc`, besides being
+    // rendered on demand.
+    pub scheduled: bool,
+    // Every channel but PagerDuty when empty.
+    pub channels: Vec<String>,
+    pub created_at: u64,
+}
+
+// Templates as stored, loaded at startup.
+#[derive(Default)]
+pub struct ReportTemplates {
+    templates: RwLock<HashMap<String, ReportTemplate>>,
+}
+
+impl ReportTemplates {
+    pub fn load(&self, templates: Vec<ReportTemplate>) {
+        *self.templates.write().unwrap() = templates.into_iter().map(|template| (template.id.clone(), template)).collect();
+    }
+
+    fn get(&self, id: &str) -> Option<ReportTemplate> {
+        self.templates.read().unwrap().get(id).cloned()
+    }
+
+    // Oldest first.
+    pub fn all(&self) -> Vec<ReportTemplate> {
+        let mut templates: Vec<ReportTemplate> = self.templates.read().unwrap().values().cloned().collect();
+        templates.sort_by_key(|template| template.created_at);
+        templates
+    }
+
+    // Add the template, or replace the one with its id.
+    fn insert(&self, template: ReportTemplate) {
+        self.templates.write().unwrap().insert(template.id.clone(), template);
+    }
+}
+
+// A template filled in for one period.
+struct Rendered {
+    template: ReportTemplate,
+    from: u64,
+    to: u64,
+    generated_at: u64,
+    columns: Vec<&'static str>,
+    // Pool id, label and dex, then the columns.
+    rows: Vec<Map<String, Value>>,
+}
+
+impl ReportTemplate {
+    fn render(&self, state: &AppState, to: u64) -> Rendered {
+        let pools: Vec<Pubkey> = if self.pools.is_empty() {
+            state.watchlist().into_iter().map(|(pool, _)| pool).collect()
+        } else {
+            // Validated when the template was created
+            self.pools.iter().filter_map(|pool| Pubkey::from_str(pool).ok()).collect()
+        };
+        let from = to.saturating_sub(self.period.secs());
+        let report = reports::generate(state, self.period, &pools, from, to);
+        let metrics = if self.metrics.is_empty() { &Metric::ALL[..] } else { &self.metrics[..] };
+        let columns: Vec<&'static str> = metrics.iter().flat_map(|metric| metric.columns()).copied().collect();
+        let rows = report
+            .pools
+            .iter()
+            .map(|pool| {
+                let Value::Object(mut fields) = json!(pool) else {
+                    unreachable!("pool reports serialize to objects");
+                };
+                fields.retain(|key, _| ["pool_id", "label", "dex"].contains(&key.as_str()) || columns.contains(&key.as_str()));
+                fields
+            })
+            .collect();
+        Rendered {
+            template: self.clone(),
+            from,
+            to,
+            generated_at: report.generated_at,
+            columns,
+            rows,
+        }
+    }
+}
+
+impl Rendered {
+    fn heading(&self) -> String {
+        self.template.title.clone().unwrap_or_else(|| self.template.name.clone())
+    }
+
+    fn json(&self) -> Value {
+        json!({
+            "template_id": self.template.id,
+            "name": self.template.name,
+            "title": self.template.title,
+            "period": self.template.period,
+            "from": self.from,
+            "to": self.to,
+            "generated_at": self.generated_at,
+            "pools": self.rows,
+        })
+    }
+
+    fn cells(&self, row: &Map<String, Value>) -> Vec<String> {
+        let cell = |value: Option<&Value>| match value {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string).unwrap_or_else(|| item.to_string()))
+                .collect::<Vec<_>>()
+                .join("; "),
+            // Token amounts, see `Amount`
+            Some(Value::Object(amount)) if amount.contains_key("value") => {
+                amount["value"].as_str().unwrap_or_default().to_string()
+            }
+            Some(value) => value.to_string(),
+        };
+        ["pool_id", "label", "dex"]
+            .iter()
+            .chain(&self.columns)
+            .map(|column| cell(row.get(*column)))
+            .collect()
+    }
+
+    fn header(&self) -> Vec<&str> {
+        ["pool_id", "label", "dex"].iter().chain(&self.columns).copied().collect()
+    }
+
+    fn csv(&self) -> String {
+        let quote = |cell: &str| {
+            if cell.contains([',', '"', '\n']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        };
+        let mut out = self.header().join(",") + "\n";
+        for row in &self.rows {
+            out.push_str(&self.cells(row).iter().map(|cell| quote(cell)).collect::<Vec<_>>().join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn html(&self) -> String {
+        let header: String = self.header().iter().map(|column| format!("<th>{}</th>", escape(column))).collect();
+        let rows: String = self
+            .rows
+            .iter()
+            .map(|row| {
+                let cells: String = self.cells(row).iter().map(|cell| format!("<td>{}</td>", escape(cell))).collect();
+                format!("<tr>{}</tr>\n", cells)
+            })
+            .collect();
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n\
+             <h1>{title}</h1>\n<p>{period} report, {from} to {to}</p>\n\
+             <table>\n<tr>{header}</tr>\n{rows}</table>\n</body>\n</html>\n",
+            title = escape(&self.heading()),
+            period = self.template.period.as_str(),
+            from = format_utc(self.from),
+            to = format_utc(self.to),
+            header = header,
+            rows = rows,
+        )
+    }
+
+    // Plain text for the notification message.
+    fn text(&self) -> String {
+        let mut lines = vec![format!(
+            "{}: {} report {} to {}",
+            self.heading(),
+            self.template.period.as_str(),
+            format_utc(self.from),
+            format_utc(self.to)
+        )];
+        for row in &self.rows {
+            let cells = self.cells(row);
+            let values: Vec<String> = self
+                .columns
+                .iter()
+                .zip(&cells[3..])
+                .map(|(column, cell)| format!("{} {}", column, if cell.is_empty() { "n/a" } else { cell }))
+                .collect();
+            lines.push(format!("{}: {}", if cells[1].is_empty() { &cells[0] } else { &cells[1] }, values.join(", ")));
+        }
+        lines.join("\n")
+    }
+
+    fn response(&self, format: ReportFormat) -> HttpResponse {
+        match format {
+            ReportFormat::Json => HttpResponse::Ok().json(self.json()),
+            ReportFormat::Csv => HttpResponse::Ok()
+                .content_type("text/csv")
+                .insert_header((
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{}-{}.csv\"", self.template.id, self.to),
+                ))
+                .body(self.csv()),
+            ReportFormat::Html => HttpResponse::Ok().content_type("text/html; charset=utf-8").body(self.html()),
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Render and send every scheduled template whose period falls due at `at`.
+// JSON reports go out as the details, HTML and CSV as their content.
+pub fn publish_due(state: &AppState, at: u64) {
+    let templates: Vec<ReportTemplate> = state
+        .report_templates
+        .templates
+        .read()
+        .unwrap()
+        .values()
+        .filter(|template| template.scheduled && reports::is_due(template.period, at))
+        .cloned()
+        .collect();
+    for template in templates {
+        let rendered = template.render(state, at);
+        let details = match template.format {
+            ReportFormat::Json => rendered.json(),
+            ReportFormat::Csv => json!({ "format": "csv", "content": rendered.csv() }),
+            ReportFormat::Html => json!({ "format": "html", "content": rendered.html() }),
+        };
+        println!("Sending report template {} ({})", template.name, template.id);
+        alerts::notify(
+            state,
+            Alert::new("report", Severity::Warning, template.name.clone(), rendered.text(), details)
+                .with_key(format!("report/{}", template.id))
+                .with_channels(template.channels.clone()),
+        );
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateTemplate {
+    name: String,
+    title: Option<String>,
+    #[serde(default)]
+    pools: Vec<String>,
+    #[serde(default)]
+    metrics: Vec<Metric>,
+    period: ReportPeriod,
+    #[serde(default)]
+    format: ReportFormat,
+    #[serde(default)]
+    scheduled: bool,
+    #[serde(default)]
+    channels: Vec<String>,
+}
+
+// Checks on a new template, also run on the ones `POST /admin/import`
+// brings in.
+pub fn validate(state: &AppState, name: &str, pools: &[String], channels: &[String]) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if pools.len() > MAX_POOLS {
+        return Err(format!("A template covers at most {} pools", MAX_POOLS));
+    }
+    for pool in pools {
+        Pubkey::from_str(pool).map_err(|e| format!("Invalid pool ID {}: {}", pool, e))?;
+    }
+    let config = state.config.read().unwrap();
+    let escalations = config.alerts.escalations();
+    match channels.iter().find(|c| !escalations.contains_key(c.as_str())) {
+        Some(channel) => Err(format!("Unknown channel {}", channel)),
+        None => Ok(()),
+    }
+}
+
+// Whether templates with `ids` fit next to the ones there are, counting
+// those they replace once.
+pub fn check_room<'a>(state: &AppState, ids: impl IntoIterator<Item = &'a str>) -> Result<(), String> {
+    let templates = state.report_templates.templates.read().unwrap();
+    let added = ids.into_iter().filter(|id| !templates.contains_key(*id)).collect::<HashSet<_>>().len();
+    if templates.len() + added > MAX_TEMPLATES {
+        return Err(format!("At most {} report templates can be kept", MAX_TEMPLATES));
+    }
+    Ok(())
+}
+
+pub async fn save(state: &AppState, template: ReportTemplate) -> Result<(), String> {
+    let saved = template.clone();
+    state.with_store(move |store| store.save_report_template(&saved)).await?;
+    state.report_templates.insert(template);
+    Ok(())
+}
+
+#[post("/reports/templates")]
+async fn create_template(state: web::Data<AppState>, body: web::Json<CreateTemplate>, req: HttpRequest) -> HttpResponse {
+    let body = body.into_inner();
+    if let Err(e) = validate(&state, &body.name, &body.pools, &body.channels) {
+        return HttpResponse::BadRequest().json(json!({ "error": e }));
+    }
+
+    let template = ReportTemplate {
+        id: random_hex(8),
+        name: body.name,
+        title: body.title,
+        pools: body.pools,
+        metrics: body.metrics,
+        period: body.period,
+        format: body.format,
+        scheduled: body.scheduled,
+        channels: body.channels,
+        created_at: unix_now(),
+    };
+    if let Err(e) = check_room(&state, [template.id.as_str()]) {
+        return HttpResponse::BadRequest().json(json!({ "error": e }));
+    }
+    if let Err(e) = save(&state, template.clone()).await {
+        return HttpResponse::InternalServerError().json(json!({ "error": e }));
+    }
+    trail::changed(&req, format!("reports/templates/{}", template.id), Value::Null, &template);
+    HttpResponse::Created().json(template)
+}
+
+#[get("/reports/templates")]
+async fn list_templates(state: web::Data<AppState>) -> HttpResponse {
+    let templates = state.report_templates.all();
+    HttpResponse::Ok().json(json!({
+        "templates": templates
+    }))
+}
+
+#[get("/reports/templates/{id}")]
+async fn get_template(state: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    match state.report_templates.get(&id) {
+        Some(template) => HttpResponse::Ok().json(template),
+        None => not_found(&id),
+    }
+}
+
+#[delete("/reports/templates/{id}")]
+async fn delete_template(state: web::Data<AppState>, id: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    let deleted = id.to_string();
+    match state.with_store(move |store| store.delete_report_template(&deleted)).await {
+        Ok(true) => {}
+        Ok(false) => return not_found(&id),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+    }
+    let removed = state.report_templates.templates.write().unwrap().remove(id.as_str());
+    trail::changed(&req, format!("reports/templates/{}", id), removed, Value::Null);
+    HttpResponse::Ok().json(json!({
+        "deleted": id.to_string()
+    }))
+}
+
+#[derive(Deserialize)]
+struct RenderQuery {
+    // The template's own format when not given.
+    format: Option<ReportFormat>,
+    // End of the period, now when not given.
+    to: Option<u64>,
+}
+
+#[get("/reports/templates/{id}/render")]
+async fn render_template(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    query: web::Query<RenderQuery>,
+) -> HttpResponse {
+    let Some(template) = state.report_templates.get(&id) else {
+        return not_found(&id);
+    };
+    let format = query.format.unwrap_or(template.format);
+    let to = query.to.unwrap_or_else(unix_now);
+    let state = state.into_inner();
+    match tokio::task::spawn_blocking(move || template.render(&state, to)).await {
+        Ok(rendered) => rendered.response(format),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "error": format!("Task failed: {}", e)
+        })),
+    }
+}
+
+fn not_found(id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "error": format!("Report template {} not found", id)
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_template)
+        .service(list_templates)
+        .service(get_template)
+        .service(delete_template)
+        .service(render_template);
+}
@@ -0,0 +1,125 @@
+use crate::format;
+use crate::history::PoolSnapshot;
+use crate::state::{unix_now, AppState};
+use bytes::Bytes;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+// Updates are batched and sent at most this often.
+const TICK: Duration = Duration::from_secs(1);
+// Frames a slow WebSocket client may fall behind by before missing some.
+const FRAME_CHANNEL_CAPACITY: usize = 16;
+
+// What changed in a pool since the last frame.
+#[derive(Default)]
+struct Change {
+    price: Option<f64>,
+    // Raw token b volume, summed over the snapshots seen.
+    volume_b: u128,
+}
+
+// One ticker update encoded both ways, shared by every client of the
+// channel.
+#[derive(Clone)]
+pub struct Frame {
+    pub json: Bytes,
+    pub msgpack: Bytes,
+}
+
+// The `ticker` WebSocket channel: price and volume changes of every watched
+// pool, folded into one entry per pool per second for live price grids. It
+// is built and encoded once for all clients.
+pub struct Ticker {
+    pending: Mutex<HashMap<Pubkey, Change>>,
+    // Last price sent per pool, the base of the next change.
+    sent: Mutex<HashMap<Pubkey, f64>>,
+    pub frames: broadcast::Sender<Frame>,
+}
+
+impl Default for Ticker {
+    fn default() -> Self {
+        Ticker {
+            pending: Mutex::default(),
+            sent: Mutex::default(),
+            frames: broadcast::channel(FRAME_CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl Ticker {
+    pub fn record(&self, pool: Pubkey, previous: Option<&PoolSnapshot>, current: &PoolSnapshot) {
+        // Nobody is watching, don't pile up changes
+        if self.frames.receiver_count() == 0 {
+            return;
+        }
+        let volume = match (previous.and_then(|p| p.cumulative_volume_b), current.cumulative_volume_b) {
+            (Some(before), Some(after)) => after.saturating_sub(before),
+            _ => 0,
+        };
+        let mut pending = self.pending.lock().unwrap();
+        let change = pending.entry(pool).or_default();
+        change.price = Some(current.price);
+        change.volume_b += volume;
+    }
+
+    // Entries for the watched pools that changed since the last frame. Pools
+    // refreshed on demand that aren't watched are left out.
+    fn flush(&self, watched: &HashSet<Pubkey>) -> Vec<serde_json::Value> {
+        let pending: Vec<(Pubkey, Change)> = self.pending.lock().unwrap().drain().collect();
+        let mut sent = self.sent.lock().unwrap();
+        let mut entries = Vec::new();
+        for (pool, change) in pending.into_iter().filter(|(pool, _)| watched.contains(pool)) {
+            let previous = sent.get(&pool).copied();
+            let price = change.price.or(previous);
+            // Unchanged prices with no trades aren't worth an entry
+            if change.volume_b == 0 && price == previous {
+                continue;
+            }
+            if let Some(price) = price {
+                sent.insert(pool, price);
+            }
+            entries.push(json!({
+                "pool": pool.to_string(),
+                "price": price,
+                "price_change": price.zip(previous).map(|(price, previous)| price - previous),
+                "volume_b": change.volume_b.to_string(),
+            }));
+        }
+        entries
+    }
+}
+
+// Send a frame of the pools that changed every second there is one and
+// somebody subscribed.
+pub async fn run(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        let ticker = &state.ticker;
+        if ticker.frames.receiver_count() == 0 || ticker.pending.lock().unwrap().is_empty() {
+            continue;
+        }
+        let watched: HashSet<Pubkey> = state.watchlist().into_iter().map(|(pool, _)| pool).collect();
+        let pools = ticker.flush(&watched);
+        if pools.is_empty() {
+            continue;
+        }
+        let message = json!({ "type": "ticker", "timestamp": unix_now(), "pools": pools });
+        let msgpack = match format::to_msgpack(&message) {
+            Ok(msgpack) => msgpack,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        // No receivers left is fine
+        let _ = ticker.frames.send(Frame {
+            json: Bytes::from(message.to_string()),
+            msgpack: Bytes::from(msgpack),
+        });
+    }
+}
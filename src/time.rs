@@ -0,0 +1,146 @@
+// Durations as the query parameters take them, and UTC calendar dates.
+
+// Duration such as 30s, 15m, 1h or 1d, or plain seconds, of at most
+// `max_secs`.
+pub fn parse_duration(text: &str, max_secs: u64) -> Result<u64, String> {
+    let invalid = || format!("Invalid duration {}, use e.g. 15m, 1h or 1d", text);
+    let (number, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((index, _)) => text.split_at(index),
+        None => (text, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let secs = number.parse::<u64>().ok().and_then(|number| number.checked_mul(multiplier));
+    match secs {
+        Some(secs) if secs > max_secs => Err(format!("Duration {} is longer than the maximum of {}s", text, max_secs)),
+        Some(secs) if secs > 0 => Ok(secs),
+        _ => Err(invalid()),
+    }
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Unix timestamp of the first second of `year`, 0 for years before 1970.
+pub fn year_start(year: i32) -> u64 {
+    (days_from_civil(year as i64, 1, 1) * 86_400).max(0) as u64
+}
+
+// Year, month and day of a unix timestamp (UTC).
+pub fn civil(timestamp: u64) -> (i64, u32, u32) {
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+// "YYYY-MM-DD HH:MM:SS UTC"
+pub fn format_utc(timestamp: u64) -> String {
+    let (year, month, day) = civil(timestamp);
+    let secs = timestamp % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+// Unix timestamp of an RFC 3339 time such as 2024-05-01T12:00:00.000Z.
+// Fractions of a second are dropped.
+pub fn parse_rfc3339(text: &str) -> Option<u64> {
+    let (date, time) = text.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (clock, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(clock) => (clock, 0),
+        None => {
+            let (clock, offset) = time.split_at(time.rfind(['+', '-'])?);
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let (hours, minutes) = (hours.parse::<i64>().ok()?, minutes.parse::<i64>().ok()?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            (clock, sign * (hours * 3600 + minutes * 60))
+        }
+    };
+    let clock = clock.split('.').next()?;
+    let mut clock = clock.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if !(0..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..=23).contains(&hour)
+        || !(0..=59).contains(&minute)
+        || !(0..=60).contains(&second)
+    {
+        return None;
+    }
+    let timestamp = days_from_civil(year, month, day)
+        .checked_mul(86_400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?
+        .checked_sub(offset)?;
+    u64::try_from(timestamp).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90", 3600), Ok(90));
+        assert_eq!(parse_duration("15m", 3600), Ok(900));
+        assert_eq!(parse_duration("1h", 3600), Ok(3600));
+        for invalid in ["0m", "", "1w", "-1h", "4611686018427387904m", "18446744073709551616"] {
+            assert!(parse_duration(invalid, u64::MAX).is_err(), "{}", invalid);
+        }
+        assert!(parse_duration("2h", 3600).is_err());
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(year_start(1970), 0);
+        assert_eq!(year_start(2024), 1_704_067_200);
+        assert_eq!(year_start(1900), 0);
+        assert_eq!(civil(1_709_164_800), (2024, 2, 29));
+        assert_eq!(format_utc(1_714_564_800), "2024-05-01 12:00:00 UTC");
+    }
+
+    #[test]
+    fn rfc3339() {
+        assert_eq!(parse_rfc3339("2024-05-01T12:00:00.000Z"), Some(1_714_564_800));
+        assert_eq!(parse_rfc3339("2024-05-01T14:30:00+02:30"), Some(1_714_564_800));
+        for invalid in [
+            "9223372036854775807-01-01T00:00:00Z",
+            "2024-05-01T00:00:00+9223372036854775807:00",
+            "2024-05-01T-1:00:00Z",
+            "1969-12-31T23:59:59Z",
+            "2024-13-01T00:00:00Z",
+        ] {
+            assert_eq!(parse_rfc3339(invalid), None, "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use crate::dex::{optional_pubkey_string, read_pubkey, read_u64};
+use serde::Serialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+
+pub const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+pub const NATIVE_MINT: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+
+pub const METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bQ518x1s");
+
+// Symbols for well known mints, used in pair labels.
+const KNOWN_SYMBOLS: &[(Pubkey, &str)] = &[
+    (NATIVE_MINT, "SOL"),
+    (pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), "USDC"),
+    (pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"), "USDT"),
+    (pubkey!("mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So"), "mSOL"),
+    (pubkey!("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn"), "JitoSOL"),
+    (pubkey!("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263"), "BONK"),
+    (pubkey!("JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN"), "JUP"),
+    (pubkey!("4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R"), "RAY"),
+];
+
+// Mainnet epochs are a fixed number of slots, close enough to pick which of
+// the two transfer fee schedules is active without another RPC call.
+const SLOTS_PER_EPOCH: u64 = 432_000;
+
+const MINT_LEN: usize = 82;
+// Token-2022 pads mints to the token account size before the account type byte.
+const ACCOUNT_TYPE_OFFSET: usize = 165;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+const EXTENSION_TRANSFER_HOOK: u16 = 14;
+const EXTENSION_TOKEN_METADATA: u16 = 19;
+// Account type of Metaplex metadata accounts.
+const METAPLEX_METADATA_V1: u8 = 4;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TransferFee {
+    pub epoch: u64,
+    pub maximum_fee: u64,
+    pub basis_points: u16,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MintInfo {
+    pub program: &'static str,
+    pub decimals: u8,
+    pub supply: u64,
+    #[serde(serialize_with = "optional_pubkey_string")]
+    pub mint_authority: Option<Pubkey>,
+    #[serde(serialize_with = "optional_pubkey_string")]
+    pub freeze_authority: Option<Pubkey>,
+    // Token-2022 keeps two schedules so a fee change only applies from a
+    // future epoch.
+    pub older_transfer_fee: Option<TransferFee>,
+    pub newer_transfer_fee: Option<TransferFee>,
+    #[serde(serialize_with = "optional_pubkey_string")]
+    pub transfer_hook_program: Option<Pubkey>,
+}
+
+// Name, symbol and off-chain JSON URI of a mint.
+#[derive(Clone, Debug, Serialize)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    // "metaplex" or "token_2022" for the metadata extension.
+    pub source: &'static str,
+}
+
+impl MintInfo {
+    pub fn transfer_fee_at(&self, slot: u64) -> Option<&TransferFee> {
+        let epoch = slot / SLOTS_PER_EPOCH;
+        match (&self.older_transfer_fee, &self.newer_transfer_fee) {
+            (_, Some(newer)) if epoch >= newer.epoch => Some(newer),
+            (older, _) => older.as_ref(),
+        }
+    }
+
+    // Fee withheld when `amount` is transferred, zero for plain SPL mints.
+    // Never more than the amount, whatever rate a malformed mint claims.
+    pub fn transfer_fee_for(&self, amount: u64, slot: u64) -> u64 {
+        let Some(fee) = self.transfer_fee_at(slot) else {
+            return 0;
+        };
+        let raw = (amount as u128 * fee.basis_points as u128).div_ceil(10_000);
+        raw.min(fee.maximum_fee as u128).min(amount as u128) as u64
+    }
+}
+
+pub fn known_symbol(mint: &Pubkey) -> Option<&'static str> {
+    KNOWN_SYMBOLS.iter().find(|(known, _)| known == mint).map(|(_, symbol)| *symbol)
+}
+
+// Display symbol for a mint, falling back to a shortened address.
+pub fn symbol(mint: &Pubkey) -> String {
+    if let Some(symbol) = known_symbol(mint) {
+        return symbol.to_string();
+    }
+    let address = mint.to_string();
+    format!("{}..{}", &address[..4], &address[address.len() - 4..])
+}
+
+// Token-2022 features that make swap outcomes differ from a plain quote.
+pub fn risk_factors(mint: &Pubkey, info: &MintInfo, slot: u64) -> Vec<Value> {
+    let mut factors = Vec::new();
+    if let Some(program) = info.transfer_hook_program {
+        factors.push(json!({
+            "mint": mint.to_string(),
+            "factor": "transfer_hook",
+            "program": program.to_string(),
+        }));
+    }
+    if let Some(fee) = info.transfer_fee_at(slot).filter(|f| f.basis_points > 0) {
+        factors.push(json!({
+            "mint": mint.to_string(),
+            "factor": "transfer_fee",
+            "basis_points": fee.basis_points,
+            "maximum_fee": fee.maximum_fee,
+        }));
+    }
+    factors
+}
+
+pub fn decode_mint(owner: &Pubkey, data: &[u8]) -> Result<MintInfo, String> {
+    let program = if *owner == TOKEN_PROGRAM_ID {
+        "spl_token"
+    } else if *owner == TOKEN_2022_PROGRAM_ID {
+        "token_2022"
+    } else {
+        return Err(format!("Account is owned by {}, not a token program", owner));
+    };
+    if data.len() < MINT_LEN {
+        return Err(format!("Mint account is {} bytes, expected at least {}", data.len(), MINT_LEN));
+    }
+
+    let mut info = MintInfo {
+        program,
+        decimals: data[44],
+        supply: read_u64(data, 36)?,
+        mint_authority: read_coption_pubkey(data, 0)?,
+        freeze_authority: read_coption_pubkey(data, 46)?,
+        older_transfer_fee: None,
+        newer_transfer_fee: None,
+        transfer_hook_program: None,
+    };
+
+    if data.len() > ACCOUNT_TYPE_OFFSET && data[ACCOUNT_TYPE_OFFSET] == ACCOUNT_TYPE_MINT {
+        for (extension, value) in extensions(&data[ACCOUNT_TYPE_OFFSET + 1..]) {
+            match extension {
+                EXTENSION_TRANSFER_FEE_CONFIG => {
+                    // authorities (64) and withheld amount (8) come first
+                    info.older_transfer_fee = Some(read_transfer_fee(value, 72)?);
+                    info.newer_transfer_fee = Some(read_transfer_fee(value, 90)?);
+                }
+                EXTENSION_TRANSFER_HOOK => {
+                    let program_id = read_pubkey(value, 32)?;
+                    if program_id != Pubkey::default() {
+                        info.transfer_hook_program = Some(program_id);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(info)
+}
+
+// Metaplex metadata account of a mint.
+pub fn metadata_pda(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref()], &METADATA_PROGRAM_ID).0
+}
+
+// Metadata kept in a Token-2022 mint's own metadata extension.
+pub fn token_2022_metadata(data: &[u8]) -> Option<TokenMetadata> {
+    if data.len() <= ACCOUNT_TYPE_OFFSET || data[ACCOUNT_TYPE_OFFSET] != ACCOUNT_TYPE_MINT {
+        return None;
+    }
+    let (_, value) = extensions(&data[ACCOUNT_TYPE_OFFSET + 1..])
+        .into_iter()
+        .find(|(extension, _)| *extension == EXTENSION_TOKEN_METADATA)?;
+    // Update authority and mint come first
+    read_metadata_strings(value, 64, "token_2022").ok()
+}
+
+pub fn decode_metaplex_metadata(data: &[u8]) -> Result<TokenMetadata, String> {
+    if data.first() != Some(&METAPLEX_METADATA_V1) {
+        return Err("Not a Metaplex metadata account".to_string());
+    }
+    // Key, update authority and mint come first
+    read_metadata_strings(data, 65, "metaplex")
+}
+
+// Name, symbol and URI as consecutive borsh strings. Metaplex pads them
+// with NULs to a fixed length.
+fn read_metadata_strings(data: &[u8], mut offset: usize, source: &'static str) -> Result<TokenMetadata, String> {
+    let mut next = || {
+        let length = data
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| "Metadata too short".to_string())?;
+        let bytes = data.get(offset + 4..offset + 4 + length).ok_or_else(|| "Metadata too short".to_string())?;
+        offset += 4 + length;
+        Ok::<_, String>(String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string())
+    };
+    Ok(TokenMetadata {
+        name: next()?,
+        symbol: next()?,
+        uri: next()?,
+        source,
+    })
+}
+
+// Walk the type-length-value extension area that follows the account type byte.
+fn extensions(mut data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut found = Vec::new();
+    while data.len() >= 4 {
+        let extension = u16::from_le_bytes([data[0], data[1]]);
+        let length = u16::from_le_bytes([data[2], data[3]]) as usize;
+        // Type 0 is uninitialized space at the end of the account
+        if extension == 0 || data.len() < 4 + length {
+            break;
+        }
+        found.push((extension, &data[4..4 + length]));
+        data = &data[4 + length..];
+    }
+    found
+}
+
+fn read_transfer_fee(data: &[u8], offset: usize) -> Result<TransferFee, String> {
+    let basis_points = data
+        .get(offset + 16..offset + 18)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| "Transfer fee extension too short".to_string())?;
+    Ok(TransferFee {
+        epoch: read_u64(data, offset)?,
+        maximum_fee: read_u64(data, offset + 8)?,
+        basis_points,
+    })
+}
+
+// SPL's COption<Pubkey>: a u32 tag followed by the key.
+fn read_coption_pubkey(data: &[u8], offset: usize) -> Result<Option<Pubkey>, String> {
+    let tag = data
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| "Mint account too short".to_string())?;
+    if tag == 0 {
+        return Ok(None);
+    }
+    read_pubkey(data, offset + 4).map(Some)
+}
@@ -0,0 +1,108 @@
+use crate::periodic::{self, FETCH_TIMEOUT};
+use crate::state::AppState;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+#[derive(Deserialize)]
+struct ListedToken {
+    address: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// Tags of every token on a configured list, None until the lists are first
+// fetched.
+#[derive(Default)]
+pub struct TokenLists {
+    tokens: RwLock<Option<HashMap<Pubkey, Vec<String>>>>,
+}
+
+impl TokenLists {
+    pub fn is_loaded(&self) -> bool {
+        self.tokens.read().unwrap().is_some()
+    }
+
+    // Tags of `mint`, None when it is on no list.
+    pub fn tags(&self, mint: &Pubkey) -> Option<Vec<String>> {
+        self.tokens.read().unwrap().as_ref()?.get(mint).cloned()
+    }
+
+    pub fn is_listed(&self, mint: &Pubkey) -> bool {
+        self.tokens.read().unwrap().as_ref().is_some_and(|tokens| tokens.contains_key(mint))
+    }
+}
+
+// `value` with the `verified` flag and tags of `mint` added, when it is an
+// object.
+pub fn annotate(state: &AppState, mint: &Pubkey, mut value: Value) -> Value {
+    if let Value::Object(fields) = &mut value {
+        let tags = state.token_lists.tags(mint);
+        fields.insert("verified".to_string(), json!(tags.is_some()));
+        fields.insert("tags".to_string(), json!(tags.unwrap_or_default()));
+    }
+    value
+}
+
+// Background task refetching the lists every `token_lists.refresh_secs`,
+// and whenever the config is reloaded.
+pub async fn run(state: Arc<AppState>) {
+    periodic::every(&state, "Token list refresh", None, || async {
+        let config = state.config().token_lists;
+        if config.lists.is_empty() {
+            *state.token_lists.tokens.write().unwrap() = None;
+            return None;
+        }
+        let result = refresh(&state, &config.lists).await.map(|tokens| {
+            println!("Loaded {} tokens from {} token lists", tokens.len(), config.lists.len());
+            *state.token_lists.tokens.write().unwrap() = Some(tokens);
+        });
+        Some((Duration::from_secs(config.refresh_secs), result))
+    })
+    .await
+}
+
+// All lists merged, each token tagged with its own tags and the names of
+// the lists it is on. Any list failing fails the refresh so a token doesn't
+// drop out for a transient error. Each fetch counts towards the health of
+// the list's URL in `GET /providers/status`.
+async fn refresh(state: &AppState, lists: &BTreeMap<String, String>) -> Result<HashMap<Pubkey, Vec<String>>, String> {
+    let client = state.upstream.client();
+    let providers_config = state.config().providers;
+    let mut tokens: HashMap<Pubkey, Vec<String>> = HashMap::new();
+    for (name, url) in lists {
+        let listed = fetch(&client, name, url).await;
+        let outcome = listed.as_ref().map(|_| ()).map_err(|e| e.clone());
+        state.providers.record(url, outcome, &providers_config);
+        for token in listed? {
+            let Ok(mint) = Pubkey::from_str(&token.address) else {
+                continue;
+            };
+            let tags = tokens.entry(mint).or_default();
+            for tag in token.tags.into_iter().chain([name.clone()]) {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+async fn fetch(client: &reqwest::Client, name: &str, url: &str) -> Result<Vec<ListedToken>, String> {
+    client
+        .get(url)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        // The URL may carry an API key
+        .map_err(|e| format!("Failed to fetch list {}: {}", name, e.without_url()))?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse list {}: {}", name, e.without_url()))
+}
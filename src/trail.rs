@@ -0,0 +1,102 @@
+use crate::admin::constant_time_eq;
+use crate::ipfilter::ClientIp;
+use crate::keys::Resolved;
+use crate::state::{unix_now, AppState};
+use crate::storage::AuditRecord;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// POST routes that only read.
+const READ_ONLY_ROUTES: &[&str] = &[
+    "/grafana/search",
+    "/grafana/query",
+    "/grafana/annotations",
+    "/simulate",
+    "/tokens/metadata",
+];
+
+// What a handler changed, picked up by `record` once it responded.
+struct Change {
+    target: String,
+    before: Value,
+    after: Value,
+}
+
+// Note what the request being handled changed, for the audit trail.
+pub fn changed(req: &HttpRequest, target: impl Into<String>, before: impl Serialize, after: impl Serialize) {
+    req.extensions_mut().insert(Change {
+        target: target.into(),
+        before: json!(before),
+        after: json!(after),
+    });
+}
+
+// Who made a request: the API key or JWT `usage::meter` resolved, or the
+// admin token.
+pub fn actor(req: &HttpRequest, admin_token: Option<&str>) -> String {
+    if let Some(key) = req.extensions().get::<Resolved>() {
+        return key.name.clone();
+    }
+    let bearer = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (bearer, admin_token) {
+        (Some(bearer), Some(admin)) if constant_time_eq(bearer.as_bytes(), admin.as_bytes()) => "admin_token".to_string(),
+        _ => "anonymous".to_string(),
+    }
+}
+
+// Store every mutating call with who made it, how it ended and, when the
+// handler said so, what it changed. Refused calls are kept too, they show
+// who tried.
+pub async fn record(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    // Calls to unknown routes change nothing
+    let Some(pattern) = req.match_pattern() else {
+        return next.call(req).await;
+    };
+    let mutating = ![Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method());
+    if !mutating || READ_ONLY_ROUTES.contains(&pattern.as_str()) {
+        return next.call(req).await;
+    }
+    let response = next.call(req).await?;
+    let request = response.request();
+    let Some(state) = request.app_data::<web::Data<AppState>>() else {
+        return Ok(response);
+    };
+    let admin_token = state.config.read().unwrap().admin.token.clone();
+    let change = request.extensions_mut().remove::<Change>();
+    let record = AuditRecord {
+        id: 0,
+        timestamp: unix_now(),
+        actor: actor(request, admin_token.as_deref()),
+        ip: request.extensions().get::<ClientIp>().map(|client| client.0.to_string()),
+        method: request.method().to_string(),
+        route: pattern,
+        path: request.path().to_string(),
+        status: response.status().as_u16(),
+        target: change.as_ref().map(|change| change.target.clone()),
+        before: change.as_ref().map_or(Value::Null, |change| change.before.clone()),
+        after: change.map_or(Value::Null, |change| change.after),
+    };
+    let state = state.clone();
+    let saved = tokio::task::spawn_blocking(move || {
+        if let Err(e) = state.store.save_audit_record(&record) {
+            eprintln!("Failed to record {} {} by {} in the audit trail: {}", record.method, record.path, record.actor, e);
+        }
+    })
+    .await;
+    if let Err(e) = saved {
+        eprintln!("Task failed: {}", e);
+    }
+    Ok(response)
+}
@@ -0,0 +1,236 @@
+use crate::alerts::{self, Alert, Severity};
+use crate::dex::{self, optional_pubkey_string, pubkey_string, DecodeWarning};
+use crate::periodic;
+use crate::state::{unix_now, AppState, CachedAccount};
+use serde::Serialize;
+use serde_json::json;
+use solana_account_decoder::UiAccountEncoding;
+use solana_account_decoder::UiDataSliceConfig;
+use solana_client::client_error::ClientError;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Last deploy of a supported DEX program.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgramDeploy {
+    pub dex: &'static str,
+    #[serde(serialize_with = "pubkey_string")]
+    pub program_id: Pubkey,
+    pub deploy_slot: u64,
+    // None once the program was made immutable.
+    #[serde(serialize_with = "optional_pubkey_string")]
+    pub upgrade_authority: Option<Pubkey>,
+    pub checked_at: u64,
+    // Set from a detected upgrade until an operator acknowledges it.
+    pub upgrade: Option<ProgramUpgrade>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgramUpgrade {
+    // None when the deploy was already recent when first seen.
+    pub previous_slot: Option<u64>,
+    pub deploy_slot: u64,
+    pub detected_at: u64,
+}
+
+// Deploys of the programs with a decoder, by DEX name.
+#[derive(Default)]
+pub struct ProgramWatch {
+    programs: RwLock<HashMap<&'static str, ProgramDeploy>>,
+}
+
+impl ProgramWatch {
+    pub fn all(&self) -> Vec<ProgramDeploy> {
+        let mut programs: Vec<ProgramDeploy> = self.programs.read().unwrap().values().cloned().collect();
+        programs.sort_by_key(|program| program.dex);
+        programs
+    }
+
+    pub fn get(&self, dex: &str) -> Option<ProgramDeploy> {
+        self.programs.read().unwrap().get(dex).cloned()
+    }
+
+    // Unacknowledged upgrade of `dex`'s program.
+    pub fn upgrade(&self, dex: &str) -> Option<ProgramUpgrade> {
+        self.programs.read().unwrap().get(dex)?.upgrade.clone()
+    }
+
+    // Clear the upgrade of `dex` once its decoder was checked against the new
+    // program. False when there is none.
+    pub fn acknowledge(&self, dex: &str) -> bool {
+        let mut programs = self.programs.write().unwrap();
+        programs.get_mut(dex).and_then(|program| program.upgrade.take()).is_some()
+    }
+}
+
+// Warn on pools of a DEX whose program was upgraded since, their layout may
+// have changed under the decoder.
+pub fn flag(state: &AppState, cached: &mut CachedAccount) {
+    let Some(dex) = cached.pool.as_ref().map(|pool| pool.dex) else {
+        return;
+    };
+    if let Some(upgrade) = state.program_watch.upgrade(dex) {
+        cached.decode_warnings.push(DecodeWarning::value(
+            "program_upgraded",
+            format!("The {} program was redeployed at slot {}, decoding is unverified", dex, upgrade.deploy_slot),
+        ));
+    }
+}
+
+// Background task reading the deploy slot of every program with a decoder
+// every `upgrades.check_interval_secs`, and whenever the config is reloaded.
+pub async fn run(state: Arc<AppState>) {
+    periodic::every(&state, "Program upgrade check", None, || async {
+        let config = state.config().upgrades;
+        if config.check_interval_secs == 0 {
+            return None;
+        }
+        let result = check(&state, config.recent_slots).await;
+        Some((Duration::from_secs(config.check_interval_secs), result))
+    })
+    .await
+}
+
+async fn check(state: &AppState, recent_slots: u64) -> Result<(), String> {
+    let rpc_client = state.background_rpc_client().await;
+    let programs: Vec<(&'static str, Pubkey)> = dex::LAYOUTS.iter().map(|layout| (layout.dex, layout.program_id)).collect();
+    let ids: Vec<Pubkey> = programs.iter().map(|(_, id)| *id).collect();
+    let fetched = tokio::task::spawn_blocking(move || {
+        let (slot, accounts) = {
+            let response = rpc_client.get_multiple_accounts_with_config(&ids, RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(rpc_client.commitment()),
+                ..RpcAccountInfoConfig::default()
+            })?;
+            (response.context.slot, response.value)
+        };
+        let programdata: Vec<Option<Pubkey>> = accounts
+            .iter()
+            .map(|account| match account.as_ref().map(|a| bincode::deserialize(&a.data)) {
+                Some(Ok(UpgradeableLoaderState::Program { programdata_address })) => Some(programdata_address),
+                _ => None,
+            })
+            .collect();
+        let keys: Vec<Pubkey> = programdata.iter().flatten().copied().collect();
+        // Only the metadata at the start of the program data, not the binary
+        let metadata = if keys.is_empty() {
+            Vec::new()
+        } else {
+            rpc_client
+                .get_multiple_accounts_with_config(&keys, RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    data_slice: Some(UiDataSliceConfig {
+                        offset: 0,
+                        length: UpgradeableLoaderState::size_of_programdata_metadata(),
+                    }),
+                    commitment: Some(rpc_client.commitment()),
+                    min_context_slot: None,
+                })?
+                .value
+        };
+        let mut metadata = keys.into_iter().zip(metadata).collect::<HashMap<_, _>>();
+        let deploys: Vec<Option<(u64, Option<Pubkey>)>> = accounts
+            .iter()
+            .zip(programdata)
+            .map(|(account, programdata)| {
+                let account = account.as_ref()?;
+                if account.owner != bpf_loader_upgradeable::id() {
+                    return None;
+                }
+                let data = metadata.remove(&programdata?)??;
+                match bincode::deserialize(&data.data) {
+                    Ok(UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address }) => Some((slot, upgrade_authority_address)),
+                    _ => None,
+                }
+            })
+            .collect();
+        Ok::<_, ClientError>((slot, deploys))
+    })
+    .await;
+    let (current_slot, deploys) = match fetched {
+        Ok(Ok(fetched)) => fetched,
+        Ok(Err(e)) => return Err(format!("Failed to fetch program accounts: {}", e)),
+        Err(e) => return Err(format!("Task failed: {}", e)),
+    };
+
+    let now = unix_now();
+    for ((dex, program_id), deploy) in programs.into_iter().zip(deploys) {
+        // Not upgradeable, or not deployed on this cluster
+        let Some((deploy_slot, upgrade_authority)) = deploy else {
+            continue;
+        };
+        let detected = {
+            let mut programs = state.program_watch.programs.write().unwrap();
+            let (upgrade, detected) = match programs.get(dex) {
+                // Seen before and unchanged, keep any unacknowledged upgrade
+                Some(previous) if previous.deploy_slot == deploy_slot => (previous.upgrade.clone(), None),
+                Some(previous) => {
+                    let upgrade = ProgramUpgrade {
+                        previous_slot: Some(previous.deploy_slot),
+                        deploy_slot,
+                        detected_at: now,
+                    };
+                    (Some(upgrade.clone()), Some(upgrade))
+                }
+                // A deploy this recent may have happened while the service
+                // was down
+                None if current_slot.saturating_sub(deploy_slot) < recent_slots => {
+                    let upgrade = ProgramUpgrade {
+                        previous_slot: None,
+                        deploy_slot,
+                        detected_at: now,
+                    };
+                    (Some(upgrade.clone()), Some(upgrade))
+                }
+                None => (None, None),
+            };
+            programs.insert(dex, ProgramDeploy {
+                dex,
+                program_id,
+                deploy_slot,
+                upgrade_authority,
+                checked_at: now,
+                upgrade,
+            });
+            detected
+        };
+        if let Some(upgrade) = detected {
+            upgraded(state, dex, program_id, &upgrade);
+        }
+    }
+    Ok(())
+}
+
+fn upgraded(state: &AppState, dex: &'static str, program_id: Pubkey, upgrade: &ProgramUpgrade) {
+    let message = match upgrade.previous_slot {
+        Some(previous) => format!(
+            "The {} program {} was upgraded at slot {} (previously {}), check its decoder",
+            dex, program_id, upgrade.deploy_slot, previous
+        ),
+        None => format!(
+            "The {} program {} was recently deployed at slot {}, check its decoder",
+            dex, program_id, upgrade.deploy_slot
+        ),
+    };
+    println!("{}", message);
+    alerts::fire(
+        state,
+        Alert::new(
+            "program_upgraded",
+            Severity::Critical,
+            program_id.to_string(),
+            message,
+            json!({
+                "dex": dex,
+                "program_id": program_id.to_string(),
+                "previous_slot": upgrade.previous_slot,
+                "deploy_slot": upgrade.deploy_slot,
+            }),
+        )
+        .with_key(format!("program_upgraded/{}", dex)),
+    );
+}
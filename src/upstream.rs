@@ -0,0 +1,214 @@
+use crate::cassette::{self, Cassette, RecordingSender};
+use crate::config::HttpClientConfig;
+use reqwest::Url;
+use serde_json::Value;
+use solana_rpc_client::http_sender::HttpSender;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Provider name of Solscan for circuit breaking.
+pub const SOLSCAN: &str = "solscan";
+pub const SOLSCAN_TRANSFERS_URL: &str = "https://public-api.solscan.io/token/transfers";
+
+// Value of a `proxy_overrides` entry for hosts reached without a proxy.
+pub const DIRECT: &str = "direct";
+
+// HTTP client shared by every outbound call so connections are reused.
+// Parameters of third-party API calls go through `get_json`, which
+// URL-encodes them, so nothing a client sends is formatted into a URL.
+pub struct Upstream {
+    client: RwLock<reqwest::Client>,
+    settings: RwLock<Settings>,
+    // RPC calls and `get_json` lookups are recorded or replayed through it
+    // when set.
+    cassette: Option<Arc<Cassette>>,
+}
+
+// The config with its proxies parsed and certificates read, so clients
+// built per RPC handle don't read files.
+#[derive(Clone)]
+struct Settings {
+    config: HttpClientConfig,
+    proxies: Option<Proxies>,
+    certificates: Vec<reqwest::Certificate>,
+}
+
+// `proxy` and `proxy_overrides` parsed, None standing for a direct
+// connection.
+#[derive(Clone)]
+struct Proxies {
+    default: Option<Url>,
+    overrides: HashMap<String, Option<Url>>,
+}
+
+impl Proxies {
+    fn for_host(&self, host: &str) -> Option<Url> {
+        match self.overrides.get(host) {
+            Some(proxy) => proxy.clone(),
+            None => self.default.clone(),
+        }
+    }
+}
+
+impl Settings {
+    fn load(config: &HttpClientConfig) -> Result<Self, String> {
+        let mut certificates = Vec::new();
+        for path in &config.ca_certificates {
+            let pem = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let certificate = reqwest::Certificate::from_pem(&pem)
+                .map_err(|e| format!("Invalid certificate in {}: {}", path.display(), e))?;
+            certificates.push(certificate);
+        }
+        Ok(Settings {
+            config: config.clone(),
+            proxies: proxies(config)?,
+            certificates,
+        })
+    }
+
+    fn builder(&self) -> reqwest::ClientBuilder {
+        let config = &self.config;
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .user_agent(config.user_agent.as_str())
+            .pool_max_idle_per_host(config.pool_max_idle_per_host)
+            .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_secs));
+        if let Some(proxies) = self.proxies.clone() {
+            builder = builder.proxy(reqwest::Proxy::custom(move |url| proxies.for_host(url.host_str()?)));
+        }
+        for certificate in &self.certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder
+    }
+}
+
+fn parse_proxy(text: &str) -> Result<Url, String> {
+    // The proxy URL may carry a password, so it isn't repeated
+    let url = Url::parse(text).map_err(|e| format!("Invalid proxy URL: {}", e))?;
+    match url.scheme() {
+        "http" | "https" | "socks5" | "socks5h" => Ok(url),
+        scheme => Err(format!("Unsupported proxy scheme {}", scheme)),
+    }
+}
+
+// The configured proxy, with hosts in `proxy_overrides` going through
+// their own proxy or none. Without either reqwest's default applies, the
+// HTTPS_PROXY and HTTP_PROXY environment variables.
+fn proxies(config: &HttpClientConfig) -> Result<Option<Proxies>, String> {
+    if config.proxy.is_none() && config.proxy_overrides.is_empty() {
+        return Ok(None);
+    }
+    let default = config.proxy.as_deref().map(parse_proxy).transpose()?;
+    let mut overrides = HashMap::new();
+    for (host, proxy) in &config.proxy_overrides {
+        let proxy = if proxy == DIRECT {
+            None
+        } else {
+            Some(parse_proxy(proxy).map_err(|e| format!("proxy_overrides.{}: {}", host, e))?)
+        };
+        overrides.insert(host.to_ascii_lowercase(), proxy);
+    }
+    Ok(Some(Proxies { default, overrides }))
+}
+
+// Builder with the configured timeouts, user agent, proxies and CA
+// certificates, for clients that need more settings of their own.
+pub fn builder(config: &HttpClientConfig) -> Result<reqwest::ClientBuilder, String> {
+    Ok(Settings::load(config)?.builder())
+}
+
+fn build(settings: &Settings) -> Result<reqwest::Client, String> {
+    settings.builder().build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+impl Upstream {
+    // Falls back to reqwest's defaults if the config doesn't build, which
+    // `Config::validate` rules out.
+    pub fn new(config: &HttpClientConfig, cassette: Option<Arc<Cassette>>) -> Self {
+        let (client, settings) = match Settings::load(config).and_then(|settings| Ok((build(&settings)?, settings))) {
+            Ok(built) => built,
+            Err(e) => {
+                eprintln!("{}, using defaults", e);
+                let settings = Settings {
+                    config: HttpClientConfig::default(),
+                    proxies: None,
+                    certificates: Vec::new(),
+                };
+                (reqwest::Client::new(), settings)
+            }
+        };
+        Upstream {
+            client: RwLock::new(client),
+            settings: RwLock::new(settings),
+            cassette,
+        }
+    }
+
+    // Clients are cheap to clone and share their connection pool.
+    pub fn client(&self) -> reqwest::Client {
+        self.client.read().unwrap().clone()
+    }
+
+    // RPC transport for one endpoint, going through the configured proxy.
+    // It gets an HTTP client of its own since the blocking RpcClient runs
+    // it on a runtime that ends with it, taking pooled connections along.
+    pub fn rpc_sender(&self, url: String) -> RecordingSender<HttpSender> {
+        let client = self
+            .settings
+            .read()
+            .unwrap()
+            .builder()
+            .default_headers(HttpSender::default_headers())
+            .build()
+            .unwrap_or_default();
+        RecordingSender {
+            inner: HttpSender::new_with_client(url, client),
+            cassette: self.cassette.clone(),
+        }
+    }
+
+    // Proxy for a WebSocket connection to `host`, None to connect directly,
+    // and how long connecting may take. Only the configured proxies apply,
+    // not the environment variables.
+    pub fn websocket_proxy(&self, host: &str) -> (Option<Url>, Duration) {
+        let settings = self.settings.read().unwrap();
+        let proxy = settings.proxies.as_ref().and_then(|proxies| proxies.for_host(host));
+        (proxy, Duration::from_secs(settings.config.connect_timeout_secs))
+    }
+
+    // Swap in clients built from a reloaded config. Calls in flight finish
+    // on the old ones.
+    pub fn rebuild(&self, config: &HttpClientConfig) -> Result<(), String> {
+        let settings = Settings::load(config)?;
+        *self.client.write().unwrap() = build(&settings)?;
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+
+    // GET `url` with `params` as its query string, parsing a JSON reply.
+    pub async fn get_json(&self, url: &str, params: &[(&str, &str)]) -> Result<Value, String> {
+        let recorded = cassette::http_request(url, params);
+        if let Some(replayed) = self.cassette.as_ref().and_then(|cassette| cassette.replay(&recorded)) {
+            return replayed;
+        }
+        let response = self
+            .client()
+            .get(url)
+            .query(params)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to fetch {}: {}", url, e.without_url()))?;
+        let value = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse response of {}: {}", url, e.without_url()))?;
+        if let Some(cassette) = &self.cassette {
+            cassette.record(&recorded, &value);
+        }
+        Ok(value)
+    }
+}
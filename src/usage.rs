@@ -0,0 +1,358 @@
+use crate::admin::constant_time_eq;
+use crate::config::Quotas;
+use crate::jwt;
+use crate::keys::{self, Resolved};
+use crate::public;
+use crate::roles;
+use crate::state::{unix_now, AppState};
+use crate::time::{civil, days_from_civil};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{get, web, Error, HttpMessage, HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+// Open streams are counted and usage saved this often.
+const TICK_SECS: u64 = 60;
+// Admin routes have their own token and metrics scrapers rarely send
+// headers.
+const UNMETERED_ROUTES: &[&str] = &["/metrics", "/", "/dashboard/{file:.*}"];
+const STREAM_ROUTES: &[&str] = &["/ws", "/sse/stream", "/pool/{pool_id}/replay"];
+// Neither counted nor refused once a quota is used up, so clients can see
+// why.
+const QUOTA_FREE_ROUTES: &[&str] = &["/account/usage"];
+
+tokio::task_local! {
+    // Counters of the key the request being handled came with.
+    static CURRENT: Arc<Counters>;
+}
+
+// What one API key used in a month.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub rpc_calls: u64,
+    pub stream_minutes: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    rpc_calls: AtomicU64,
+    stream_minutes: AtomicU64,
+}
+
+impl Counters {
+    fn usage(&self) -> Usage {
+        Usage {
+            requests: self.requests.load(Ordering::Relaxed),
+            rpc_calls: self.rpc_calls.load(Ordering::Relaxed),
+            stream_minutes: self.stream_minutes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn from_usage(usage: Usage) -> Self {
+        Counters {
+            requests: AtomicU64::new(usage.requests),
+            rpc_calls: AtomicU64::new(usage.rpc_calls),
+            stream_minutes: AtomicU64::new(usage.stream_minutes),
+        }
+    }
+}
+
+// Usage of every API key in the current month, by key name, saved to
+// storage every minute and when the month ends.
+#[derive(Default)]
+pub struct Metering {
+    month: Mutex<String>,
+    keys: RwLock<HashMap<String, Arc<Counters>>>,
+    // Open WebSocket and SSE connections per key name.
+    streams: Mutex<HashMap<String, u64>>,
+}
+
+impl Metering {
+    fn counters(&self, name: &str) -> Arc<Counters> {
+        if let Some(counters) = self.keys.read().unwrap().get(name) {
+            return counters.clone();
+        }
+        self.keys.write().unwrap().entry(name.to_string()).or_default().clone()
+    }
+
+    pub fn usage(&self, name: &str) -> Usage {
+        self.counters(name).usage()
+    }
+
+    pub fn open_streams(&self, name: &str) -> u64 {
+        self.streams.lock().unwrap().get(name).copied().unwrap_or(0)
+    }
+
+    // Start the current month from what storage has for it, saving the
+    // month that ended first. Called at startup and then once a minute, so
+    // calls in the first minute of a month still count to the last one.
+    pub fn roll(&self, state: &AppState) {
+        let month = month_of(unix_now());
+        let mut current = self.month.lock().unwrap();
+        if *current == month {
+            return;
+        }
+        if !current.is_empty() {
+            self.save(state, &current);
+        }
+        let stored = state.store.usage(&month).unwrap_or_else(|e| {
+            eprintln!("Failed to load API key usage for {}: {}", month, e);
+            Vec::new()
+        });
+        *self.keys.write().unwrap() = stored
+            .into_iter()
+            .map(|(name, usage)| (name, Arc::new(Counters::from_usage(usage))))
+            .collect();
+        *current = month;
+    }
+
+    fn save(&self, state: &AppState, month: &str) {
+        let keys: Vec<(String, Usage)> =
+            self.keys.read().unwrap().iter().map(|(name, counters)| (name.clone(), counters.usage())).collect();
+        for (name, usage) in keys {
+            if let Err(e) = state.store.save_usage(month, &name, &usage) {
+                eprintln!("Failed to save API key usage of {}: {}", name, e);
+            }
+        }
+    }
+}
+
+// "YYYY-MM" of a unix timestamp (UTC).
+fn month_of(timestamp: u64) -> String {
+    let (year, month, _) = civil(timestamp);
+    format!("{:04}-{:02}", year, month)
+}
+
+// Start of the month after the one `timestamp` falls in, when quotas reset.
+fn next_month(timestamp: u64) -> u64 {
+    let (year, month, _) = civil(timestamp);
+    let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month as i64 + 1) };
+    (days_from_civil(year, month, 1) * 86_400) as u64
+}
+
+// Count upstream RPC use against the key of the request being handled.
+// Background work runs outside any request and isn't counted.
+pub fn charge_rpc() {
+    let _ = CURRENT.try_with(|counters| counters.rpc_calls.fetch_add(1, Ordering::Relaxed));
+}
+
+// An open stream of an API key, counted every minute until it is dropped.
+pub struct StreamGuard {
+    state: web::Data<AppState>,
+    name: String,
+}
+
+impl StreamGuard {
+    // None for requests without an API key.
+    pub fn open(state: &web::Data<AppState>, req: &HttpRequest) -> Option<StreamGuard> {
+        let name = req.extensions().get::<Resolved>()?.name.clone();
+        *state.usage.streams.lock().unwrap().entry(name.clone()).or_default() += 1;
+        Some(StreamGuard { state: state.clone(), name })
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let mut streams = self.state.usage.streams.lock().unwrap();
+        if let Some(open) = streams.get_mut(&self.name) {
+            *open = open.saturating_sub(1);
+        }
+    }
+}
+
+fn presented_key(req: &ServiceRequest) -> Option<String> {
+    if let Some(key) = req.headers().get("X-API-Key").and_then(|value| value.to_str().ok()) {
+        return Some(key.to_string());
+    }
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.get("api_key").cloned())
+}
+
+// A bearer JWT, when they are accepted. The admin token travels in the same
+// header and is left to `require_admin`.
+fn presented_jwt(req: &ServiceRequest, admin_token: Option<&str>) -> Option<String> {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))?;
+    if admin_token.is_some_and(|admin| constant_time_eq(admin.as_bytes(), token.as_bytes())) || !jwt::looks_like_jwt(token) {
+        return None;
+    }
+    Some(token.to_string())
+}
+
+// The quota `usage` has used up, as what ran out and its limit.
+fn exhausted(quotas: &Quotas, usage: &Usage, stream: bool) -> Option<(&'static str, u64)> {
+    let used_up = |limit: Option<u64>, used: u64| limit.filter(|limit| used >= *limit);
+    if let Some(limit) = used_up(quotas.monthly_requests, usage.requests) {
+        return Some(("request", limit));
+    }
+    if let Some(limit) = used_up(quotas.monthly_rpc_calls, usage.rpc_calls) {
+        return Some(("RPC call", limit));
+    }
+    match used_up(quotas.monthly_stream_minutes, usage.stream_minutes) {
+        Some(limit) if stream => Some(("streaming minute", limit)),
+        _ => None,
+    }
+}
+
+// Attribute requests to the API key or bearer JWT they came with, refuse
+// keys whose role doesn't allow the route or that used up a monthly quota.
+// Requests without either pass unmetered as `api_keys.anonymous_role` unless
+// `api_keys.required` is set, or in public mode only to the public routes.
+// Admin routes are left to `require_admin`.
+pub async fn meter(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let pattern = req.match_pattern().unwrap_or_default();
+    let admin = pattern.starts_with("/admin");
+    let unmetered = admin || UNMETERED_ROUTES.contains(&pattern.as_str());
+    let (config, jwt_config, admin_token, public) = {
+        let config = state.config.read().unwrap();
+        (config.api_keys.clone(), config.jwt.clone(), config.admin.token.clone(), config.public.clone())
+    };
+    let key = match presented_key(&req) {
+        Some(presented) => match keys::resolve(&state, &config, &presented, unix_now()) {
+            Some(key) => key,
+            None => {
+                let response = HttpResponse::Unauthorized().json(json!({ "error": "Unknown or revoked API key" }));
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+        },
+        None => match presented_jwt(&req, admin_token.as_deref()).filter(|_| jwt_config.jwks_url.is_some()) {
+            Some(token) => match jwt::validate(&state, &jwt_config, &token) {
+                Ok(key) => key,
+                Err(e) => {
+                    let response = HttpResponse::Unauthorized().json(json!({ "error": e }));
+                    return Ok(req.into_response(response).map_into_right_body());
+                }
+            },
+            None => {
+                if unmetered {
+                    return Ok(next.call(req).await?.map_into_left_body());
+                }
+                let response = if public.enabled {
+                    match public::admit(&state, &req, &pattern) {
+                        Ok(()) => return Ok(next.call(req).await?.map_into_left_body()),
+                        Err(response) => response,
+                    }
+                } else if config.required {
+                    HttpResponse::Unauthorized().json(json!({
+                        "error": "Missing API key, send it in the X-API-Key header"
+                    }))
+                } else if config.anonymous_role < roles::required_role(req.method(), &pattern) {
+                    HttpResponse::Unauthorized().json(json!({
+                        "error": "This route needs an API key, send it in the X-API-Key header"
+                    }))
+                } else {
+                    return Ok(next.call(req).await?.map_into_left_body());
+                };
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+        },
+    };
+    req.extensions_mut().insert(key.clone());
+    if unmetered {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let required = roles::required_role(req.method(), &pattern);
+    if key.role < required {
+        let response = HttpResponse::Forbidden().json(json!({
+            "error": format!("API key {} has the {} role, this route needs the {} role", key.name, key.role.as_str(), required.as_str())
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    let counters = state.usage.counters(&key.name);
+    if !QUOTA_FREE_ROUTES.contains(&pattern.as_str()) {
+        let stream = STREAM_ROUTES.contains(&pattern.as_str());
+        if let Some((what, limit)) = exhausted(&key.quotas, &counters.usage(), stream) {
+            let now = unix_now();
+            let resets_at = next_month(now);
+            let response = HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", (resets_at - now).to_string()))
+                .json(json!({
+                    "error": format!("Monthly quota of {} {}s used up", limit, what),
+                    "resets_at": resets_at,
+                }));
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(CURRENT.scope(counters, next.call(req)).await?.map_into_left_body())
+}
+
+// Background task counting a minute for every open stream and saving usage
+// and when keys were last used once a minute.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(TICK_SECS)).await;
+        let streams: Vec<(String, u64)> =
+            state.usage.streams.lock().unwrap().iter().map(|(name, open)| (name.clone(), *open)).collect();
+        for (name, open) in streams.into_iter().filter(|(_, open)| *open > 0) {
+            state.usage.counters(&name).stream_minutes.fetch_add(open, Ordering::Relaxed);
+        }
+        // The store blocks, so it is written to off the runtime
+        let state = state.clone();
+        let saved = tokio::task::spawn_blocking(move || {
+            state.usage.roll(&state);
+            let month = state.usage.month.lock().unwrap().clone();
+            state.usage.save(&state, &month);
+            for key in state.api_keys.take_used() {
+                if let Err(e) = state.store.save_api_key(&key) {
+                    eprintln!("Failed to save API key {}: {}", key.id, e);
+                }
+            }
+        })
+        .await;
+        if let Err(e) = saved {
+            eprintln!("Task failed: {}", e);
+        }
+    }
+}
+
+// This month's usage and quotas of the API key the request came with.
+#[get("/account/usage")]
+async fn get_usage(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(key) = req.extensions().get::<Resolved>().cloned() else {
+        return HttpResponse::Unauthorized().json(json!({
+            "error": "Send an API key in the X-API-Key header to see its usage"
+        }));
+    };
+    let (name, quotas) = (key.name, key.quotas);
+    let usage = state.usage.usage(&name);
+    let remaining = |limit: Option<u64>, used: u64| limit.map(|limit| limit.saturating_sub(used));
+    let now = unix_now();
+    HttpResponse::Ok().json(json!({
+        "key": name,
+        "role": key.role,
+        "month": month_of(now),
+        "resets_at": next_month(now),
+        "usage": usage,
+        "quotas": {
+            "requests": quotas.monthly_requests,
+            "rpc_calls": quotas.monthly_rpc_calls,
+            "stream_minutes": quotas.monthly_stream_minutes,
+        },
+        "remaining": {
+            "requests": remaining(quotas.monthly_requests, usage.requests),
+            "rpc_calls": remaining(quotas.monthly_rpc_calls, usage.rpc_calls),
+            "stream_minutes": remaining(quotas.monthly_stream_minutes, usage.stream_minutes),
+        },
+        "open_streams": state.usage.open_streams(&name),
+    }))
+}
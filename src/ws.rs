@@ -0,0 +1,366 @@
+use crate::blocklist;
+use crate::events::{EventKind, PoolEvent};
+use crate::format;
+use crate::state::AppState;
+use crate::streaming::{ClientBuffer, DeltaEncoder, StreamItem};
+use crate::ticker::Frame;
+use crate::usage::StreamGuard;
+use actix::{Actor, ActorContext, AsyncContext, SpawnHandle, StreamHandler};
+use actix_web::{get, web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use bytestring::ByteString;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+// Clients that haven't answered a ping in this long are dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(45);
+
+// Messages clients send, e.g. {"op":"subscribe","channel":"trades","pool":"..."}.
+// Omitting `pool` subscribes to the channel for every pool.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        channel: String,
+        pool: Option<String>,
+        id: Option<Value>,
+    },
+    Unsubscribe {
+        channel: String,
+        pool: Option<String>,
+        id: Option<Value>,
+    },
+    Ping {
+        id: Option<Value>,
+    },
+}
+
+#[derive(Deserialize)]
+struct ConnectQuery {
+    // "delta" sends price updates as merge patches, "full" (the default)
+    // sends every snapshot whole.
+    encoding: Option<String>,
+}
+
+// Channel/pool pairs a client asked for, a None pool means every pool.
+type SubscriptionSet = Arc<RwLock<HashSet<(EventKind, Option<String>)>>>;
+
+fn wants(subscriptions: &SubscriptionSet, event: &PoolEvent) -> bool {
+    let subscriptions = subscriptions.read().unwrap();
+    subscriptions.contains(&(event.kind, None)) || subscriptions.contains(&(event.kind, Some(event.pool.clone())))
+}
+
+struct WsSession {
+    state: web::Data<AppState>,
+    // Shared with the buffer's forwarder so unwanted events never get queued.
+    subscriptions: SubscriptionSet,
+    // Frames of the ticker channel, while the client is on it.
+    ticker: Option<SpawnHandle>,
+    last_heartbeat: Instant,
+    // Set for clients that asked for delta encoding.
+    deltas: Option<DeltaEncoder>,
+    // Messages go out as binary MessagePack frames rather than JSON text.
+    msgpack: bool,
+    // Meters the connection against the client's API key until it closes.
+    _usage: Option<StreamGuard>,
+}
+
+impl WsSession {
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, message: Value) {
+        if !self.msgpack {
+            ctx.text(message.to_string());
+            return;
+        }
+        match format::to_msgpack(&message) {
+            Ok(bytes) => ctx.binary(bytes),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    fn handle_message(&mut self, ctx: &mut ws::WebsocketContext<Self>, message: Result<ClientMessage, String>) {
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                self.send(ctx, json!({ "type": "error", "message": format!("Invalid message: {}", e) }));
+                return;
+            }
+        };
+
+        match message {
+            ClientMessage::Ping { id } => self.send(ctx, json!({ "type": "pong", "id": id })),
+            ClientMessage::Subscribe { channel, pool, id } => {
+                self.update_subscription(ctx, true, &channel, pool, id)
+            }
+            ClientMessage::Unsubscribe { channel, pool, id } => {
+                self.update_subscription(ctx, false, &channel, pool, id)
+            }
+        }
+    }
+
+    fn update_subscription(
+        &mut self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        subscribe: bool,
+        channel: &str,
+        pool: Option<String>,
+        id: Option<Value>,
+    ) {
+        let op = if subscribe { "subscribe" } else { "unsubscribe" };
+        if channel == "ticker" {
+            return self.update_ticker(ctx, subscribe, pool, id);
+        }
+        let Some(kind) = EventKind::from_channel(channel) else {
+            self.send(ctx, json!({
+                "type": "error",
+                "op": op,
+                "id": id,
+                "message": format!("Unknown channel {}, expected price, trades, liquidity or ticker", channel),
+            }));
+            return;
+        };
+        if let Some(pool) = &pool {
+            if let Err(e) = Pubkey::from_str(pool) {
+                self.send(ctx, json!({
+                    "type": "error",
+                    "op": op,
+                    "id": id,
+                    "message": format!("Invalid pool ID {}: {}", pool, e),
+                }));
+                return;
+            }
+        }
+
+        {
+            let mut subscriptions = self.subscriptions.write().unwrap();
+            if subscribe {
+                // The client may have dropped what it had for these pools
+                if kind == EventKind::Snapshot {
+                    if let Some(deltas) = &mut self.deltas {
+                        deltas.reset();
+                    }
+                }
+                subscriptions.insert((kind, pool.clone()));
+            } else {
+                subscriptions.remove(&(kind, pool.clone()));
+            }
+        }
+        let count = self.subscription_count();
+        self.send(ctx, json!({
+            "type": "ack",
+            "op": op,
+            "channel": kind.channel(),
+            "pool": pool,
+            "id": id,
+            "subscriptions": count,
+        }));
+    }
+
+    fn subscription_count(&self) -> usize {
+        self.subscriptions.read().unwrap().len() + self.ticker.is_some() as usize
+    }
+
+    fn update_ticker(&mut self, ctx: &mut ws::WebsocketContext<Self>, subscribe: bool, pool: Option<String>, id: Option<Value>) {
+        let op = if subscribe { "subscribe" } else { "unsubscribe" };
+        if pool.is_some() {
+            self.send(ctx, json!({
+                "type": "error",
+                "op": op,
+                "id": id,
+                "message": "The ticker channel covers every watched pool, omit pool",
+            }));
+            return;
+        }
+        if subscribe && self.ticker.is_none() {
+            let frames = futures::stream::unfold(self.state.ticker.frames.subscribe(), |mut frames| async move {
+                loop {
+                    match frames.recv().await {
+                        Ok(frame) => return Some((frame, frames)),
+                        // Each frame stands alone, a slow client just skips some
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            });
+            self.ticker = Some(ctx.add_stream(frames));
+        } else if !subscribe {
+            if let Some(handle) = self.ticker.take() {
+                ctx.cancel_future(handle);
+            }
+        }
+        self.send(ctx, json!({
+            "type": "ack",
+            "op": op,
+            "channel": "ticker",
+            "pool": null,
+            "id": id,
+            "subscriptions": self.subscription_count(),
+        }));
+    }
+}
+
+impl Actor for WsSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |session, ctx| {
+            if Instant::now().duration_since(session.last_heartbeat) > CLIENT_TIMEOUT {
+                println!("WebSocket client timed out, disconnecting");
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+
+        // The actor only pulls from the buffer while the socket keeps up, so a
+        // slow client backs up here where the overflow policy applies
+        let subscriptions = self.subscriptions.clone();
+        let buffer = ClientBuffer::spawn(self.state.clone().into_inner(), move |event| wants(&subscriptions, event));
+        let events = futures::stream::unfold(buffer, |buffer| async move {
+            let item = buffer.next().await?;
+            Some((item, buffer))
+        });
+        ctx.add_stream(events);
+    }
+}
+
+impl StreamHandler<StreamItem> for WsSession {
+    fn handle(&mut self, item: StreamItem, ctx: &mut Self::Context) {
+        match item {
+            // Trades and liquidity changes are one-off, only snapshots are patched
+            StreamItem::Event(event) => match &mut self.deltas {
+                Some(deltas) if event.kind == EventKind::Snapshot => {
+                    let (keyframe, encoded) = deltas.encode(&event.pool, &event.data);
+                    let payload = if keyframe { "data" } else { "patch" };
+                    if !keyframe {
+                        self.state.stream_metrics.patches.fetch_add(1, Ordering::Relaxed);
+                    }
+                    self.send(ctx, json!({
+                        "type": "event",
+                        "channel": event.kind.channel(),
+                        "pool": event.pool,
+                        "slot": event.slot,
+                        "timestamp": event.timestamp,
+                        "keyframe": keyframe,
+                        payload: encoded,
+                        "blocklisted": blocklist::pool_flags(&self.state, &event.pool),
+                    }))
+                }
+                _ => self.send(ctx, json!({
+                    "type": "event",
+                    "channel": event.kind.channel(),
+                    "pool": event.pool,
+                    "slot": event.slot,
+                    "timestamp": event.timestamp,
+                    "data": event.data,
+                    "blocklisted": blocklist::pool_flags(&self.state, &event.pool),
+                })),
+            },
+            StreamItem::Dropped(missed) => self.send(ctx, json!({ "type": "lagged", "missed": missed })),
+        }
+    }
+
+    // The buffer only ends when the overflow policy cut this client off
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some("Client too slow, buffer overflowed".to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
+// Ticker frames come encoded already, shared by every client on the channel.
+impl StreamHandler<Frame> for WsSession {
+    fn handle(&mut self, frame: Frame, ctx: &mut Self::Context) {
+        if self.msgpack {
+            ctx.binary(frame.msgpack);
+            return;
+        }
+        match ByteString::try_from(frame.json) {
+            Ok(text) => ctx.text(text),
+            Err(e) => eprintln!("Ticker frame is not UTF-8: {}", e),
+        }
+    }
+
+    // The ticker only ends with the server, the socket stays up
+    fn finished(&mut self, _: &mut Self::Context) {}
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
+    fn handle(&mut self, message: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match message {
+            Ok(ws::Message::Text(text)) => {
+                self.last_heartbeat = Instant::now();
+                let message = serde_json::from_str(&text).map_err(|e| e.to_string());
+                self.handle_message(ctx, message);
+            }
+            Ok(ws::Message::Ping(payload)) => {
+                self.last_heartbeat = Instant::now();
+                ctx.pong(&payload);
+            }
+            Ok(ws::Message::Pong(_)) => self.last_heartbeat = Instant::now(),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            // MessagePack clients may send their messages encoded the same way
+            Ok(ws::Message::Binary(bytes)) if self.msgpack => {
+                self.last_heartbeat = Instant::now();
+                let message = rmp_serde::from_slice(&bytes).map_err(|e| e.to_string());
+                self.handle_message(ctx, message);
+            }
+            Ok(ws::Message::Binary(_)) => {
+                self.send(ctx, json!({ "type": "error", "message": "Binary frames are only supported with format=msgpack" }));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("WebSocket protocol error: {}", e);
+                ctx.stop();
+            }
+        }
+    }
+}
+
+// `?encoding=delta` sends each price update as a JSON merge patch against
+// the last one sent for the pool, with periodic full keyframes.
+// `?format=msgpack` or an Accept header asking for it sends every message
+// as a binary MessagePack frame.
+#[get("/ws")]
+async fn connect(
+    state: web::Data<AppState>,
+    query: web::Query<ConnectQuery>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse, Error> {
+    let deltas = match query.encoding.as_deref() {
+        None | Some("full") => None,
+        Some("delta") => Some(DeltaEncoder::new(state.config().streaming.keyframe_interval)),
+        Some(other) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": format!("Unknown encoding {}, expected full or delta", other)
+            })));
+        }
+    };
+    let usage = StreamGuard::open(&state, &req);
+    ws::start(
+        WsSession {
+            state,
+            subscriptions: Arc::new(RwLock::new(HashSet::new())),
+            ticker: None,
+            last_heartbeat: Instant::now(),
+            deltas,
+            msgpack: format::wants_msgpack(&req),
+            _usage: usage,
+        },
+        &req,
+        stream,
+    )
+}